@@ -0,0 +1,383 @@
+//! Finding the functions of executables and their basic blocks
+use std::{
+    collections::{BTreeMap, BTreeSet},
+};
+
+use crate::disasm::{Arch, Disassembler, Insn, Flow, fnv, FNV_START};
+use crate::image::{Image};
+
+/// Functions found at most in one sample
+const MAX_FUNCTIONS: usize = 20_000;
+/// Instructions decoded at most in one function
+const MAX_INSNS: usize = 20_000;
+/// Functions starting before an address tried at most to find the one
+/// holding it
+pub const MAX_CANDIDATES: usize = 16;
+
+/// Bytes functions often start with, and whether they only count right
+/// after the padding between two functions, 16 bytes aligned
+type Prologue = (&'static [u8], bool);
+/// A 32-bit instruction functions often start with, as (value, mask)
+type WordPrologue = (u32, u32);
+
+const PROLOGUES: &[Prologue] = &[
+    // push rbp; mov rbp, rsp
+    (&[0x55, 0x48, 0x89, 0xe5], false),
+    // push ebp; mov ebp, esp
+    (&[0x55, 0x89, 0xe5], false),
+    (&[0x55, 0x8b, 0xec], false),
+    // mov edi, edi; push ebp; mov ebp, esp
+    (&[0x8b, 0xff, 0x55, 0x8b, 0xec], false),
+    // endbr64, endbr32
+    (&[0xf3, 0x0f, 0x1e, 0xfa], true),
+    (&[0xf3, 0x0f, 0x1e, 0xfb], true),
+    // sub rsp, imm
+    (&[0x48, 0x83, 0xec], true),
+    (&[0x48, 0x81, 0xec], true),
+];
+/// Instructions AArch64 functions start with when they are built with
+/// pointer authentication or branch target identification
+const ARM64_PROLOGUES: &[Prologue] = &[
+    // paciasp
+    (&[0x3f, 0x23, 0x03, 0xd5], false),
+    // bti c
+    (&[0x5f, 0x24, 0x03, 0xd5], false),
+];
+/// Instructions functions of the other architectures start with: making
+/// room on the stack
+const MIPS_PROLOGUES: &[WordPrologue] = &[
+    // addiu $sp, $sp, -n; daddiu $sp, $sp, -n
+    (0x27bd_8000, 0xffff_8000),
+    (0x67bd_8000, 0xffff_8000),
+];
+const PPC_PROLOGUES: &[WordPrologue] = &[
+    // stwu r1, -n(r1); stdu r1, -n(r1)
+    (0x9421_8000, 0xffff_8000),
+    (0xf821_8001, 0xffff_8003),
+];
+const RISCV_PROLOGUES: &[WordPrologue] = &[
+    // addi sp, sp, -n
+    (0x8001_0113, 0x800f_ffff),
+];
+/// Bytes compilers pad functions with: int3, nop and the ret ending them
+const PADDING: &[u8] = &[0xcc, 0x90, 0xc3];
+
+/// Instructions run one after the other, entered at the start and left at
+/// the end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u64,
+    pub insns: Vec<Insn>,
+    /// Blocks the execution can go to next
+    pub succs: Vec<u64>,
+}
+
+impl Block {
+    /// Address following the last instruction
+    pub fn end(&self) -> u64 {
+        self.insns.last().map_or(self.start, |insn| insn.end())
+    }
+
+    /// Instruction deciding where the execution goes after the block: the
+    /// last one, or the one before when the last runs in its delay slot
+    pub fn exit(&self) -> &Insn {
+        match &self.insns[..] {
+            [.., exit, _] if matches!(exit.flow, Flow::Branch(_)
+                | Flow::Jump(_) | Flow::Return) => exit,
+            [.., last] => last,
+            [] => unreachable!("blocks have at least one instruction"),
+        }
+    }
+
+    /// Hash of the normalized instructions
+    pub fn hash(&self) -> u64 {
+        self.insns.iter()
+            .fold(FNV_START, |hash, insn| fnv(hash, &insn.norm.to_le_bytes()))
+    }
+}
+
+/// A function and its control flow graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub address: u64,
+    /// Offset of the start in the file
+    pub offset: usize,
+    /// Name from the symbols, or made up from the address
+    pub name: String,
+    /// Blocks by address, the first one is the entry
+    pub blocks: Vec<Block>,
+    /// Targets of the direct calls
+    pub calls: Vec<u64>,
+}
+
+/// Size of the control flow graph of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shape {
+    pub blocks: usize,
+    pub edges: usize,
+    pub insns: usize,
+}
+
+impl Function {
+    /// Instructions of all the blocks, by address
+    pub fn insns(&self) -> impl Iterator<Item = &Insn> {
+        self.blocks.iter().flat_map(|block| block.insns.iter())
+    }
+
+    /// Hash of the normalized instructions, equal for the same code at
+    /// another address
+    pub fn hash(&self) -> u64 {
+        self.blocks.iter()
+            .fold(FNV_START, |hash, block| fnv(hash, &block.hash().to_le_bytes()))
+    }
+
+    pub fn shape(&self) -> Shape {
+        Shape {
+            blocks: self.blocks.len(),
+            edges: self.blocks.iter().map(|block| block.succs.len()).sum(),
+            insns: self.insns().count(),
+        }
+    }
+
+    /// Index of the block holding the instruction at `va`
+    pub fn block_at(&self, va: u64) -> Option<usize> {
+        self.blocks.iter()
+            .position(|block| block.start <= va && va < block.end())
+    }
+
+    /// Whether the name comes from the sample rather than from the address
+    pub fn is_named(&self) -> bool {
+        !self.name.starts_with("sub_")
+    }
+}
+
+/// Name of the function at `address` starting with `blocks`: its symbol,
+/// the import it jumps to for stubs, or made up
+fn name_of(image: &Image, address: u64, blocks: &[Block]) -> String {
+    if let Some(name) = image.symbol(address) {
+        return name.to_string();
+    }
+    let stub = match blocks {
+        [block] => match &block.insns[..] {
+            [insn] if insn.flow == Flow::Jump(None) => insn.mem,
+            _ => None,
+        },
+        _ => None,
+    };
+    let import = stub.and_then(|slot| image.imports.iter()
+        .find(|import| import.slot == Some(slot)));
+    match import {
+        Some(import) => format!("j_{}", import.name),
+        None if Some(address) == image.entry => "entry".to_string(),
+        None => format!("sub_{:x}", address),
+    }
+}
+
+/// Follow the code from `start` to find the blocks of the function there
+fn function(image: &Image, data: &[u8], disasm: &mut Disassembler,
+        start: u64) -> Function {
+    let mut insns: BTreeMap<u64, Insn> = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    leaders.insert(start);
+    let mut work = vec![start];
+    let delay = disasm.arch().has_delay_slot();
+
+    while let Some(mut address) = work.pop() {
+        while insns.len() < MAX_INSNS {
+            if insns.contains_key(&address) {
+                // Falls through into code decoded from another path
+                leaders.insert(address);
+                break;
+            }
+            let insn = match image.code_at(data, address)
+                    .and_then(|code| disasm.decode(code, address)) {
+                Some(insn) => insn,
+                None => break,
+            };
+            let (mut next, flow) = (insn.end(), insn.flow);
+            insns.insert(address, insn);
+            // The instruction in the delay slot runs before the transfer
+            if delay && !matches!(flow, Flow::Next | Flow::Stop) {
+                if let Some(slot) = image.code_at(data, next)
+                        .and_then(|code| disasm.decode(code, next)) {
+                    next = slot.end();
+                    insns.insert(slot.address, slot);
+                }
+            }
+            match flow {
+                Flow::Next | Flow::Call(_) => {},
+                Flow::Branch(target) => {
+                    leaders.insert(target);
+                    leaders.insert(next);
+                    work.push(target);
+                },
+                Flow::Jump(Some(target)) => {
+                    leaders.insert(target);
+                    work.push(target);
+                    break;
+                },
+                Flow::Jump(None) | Flow::Return | Flow::Stop => break,
+            }
+            address = next;
+        }
+    }
+
+    // Cut the instructions into blocks at the leaders and after the jumps,
+    // or after their delay slots
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut calls = Vec::new();
+    for (address, insn) in insns.iter() {
+        if let Flow::Call(Some(target)) = insn.flow {
+            calls.push(target);
+        }
+        let continues = blocks.last()
+            .filter(|block| block.end() == *address
+                && !leaders.contains(address))
+            .is_some_and(|block| {
+                let last = block.insns.last().unwrap();
+                matches!(block.exit().flow, Flow::Next | Flow::Call(_))
+                    || (delay && last.flow != Flow::Stop
+                        && last.address == block.exit().address)
+            });
+        if continues {
+            blocks.last_mut().unwrap().insns.push(insn.clone());
+        } else {
+            blocks.push(Block { start: *address, insns: vec![insn.clone()],
+                succs: Vec::new() });
+        }
+    }
+    for block in blocks.iter_mut() {
+        let end = block.end();
+        let succs = match block.exit().flow {
+            Flow::Next | Flow::Call(_) => vec![end],
+            Flow::Branch(target) => vec![target, end],
+            Flow::Jump(Some(target)) => vec![target],
+            Flow::Jump(None) | Flow::Return | Flow::Stop => vec![],
+        };
+        block.succs = succs.into_iter()
+            .filter(|succ| insns.contains_key(succ))
+            .collect();
+    }
+    // The entry block first, the others by address
+    if let Some(entry) = blocks.iter().position(|block| block.start == start) {
+        let entry = blocks.remove(entry);
+        blocks.insert(0, entry);
+    }
+    calls.sort_unstable();
+    calls.dedup();
+
+    let offset = image.offset_of(start).unwrap_or(0);
+    let name = name_of(image, start, &blocks);
+    Function { address: start, offset, name, blocks, calls }
+}
+
+/// Addresses in the executable sections starting with one of the prologues
+/// of `arch`
+fn prologues(image: &Image, data: &[u8], arch: Arch) -> Vec<u64> {
+    let (prologues, words): (&[Prologue], &[WordPrologue]) = match arch {
+        Arch::X86_16 | Arch::X86 | Arch::X64 => (PROLOGUES, &[]),
+        Arch::Arm64 => (ARM64_PROLOGUES, &[]),
+        Arch::Arm | Arch::Thumb => (&[], &[]),
+        Arch::Mips | Arch::Mipsel | Arch::Mips64 | Arch::Mips64el =>
+            (&[], MIPS_PROLOGUES),
+        Arch::Ppc | Arch::Ppcle | Arch::Ppc64 | Arch::Ppc64le =>
+            (&[], PPC_PROLOGUES),
+        Arch::RiscV32 | Arch::RiscV64 => (&[], RISCV_PROLOGUES),
+    };
+    let word = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if arch.is_big_endian() {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    let mut starts = Vec::new();
+    for section in image.sections.iter().filter(|section| section.exec) {
+        let code = match data.get(section.offset..section.offset + section.size) {
+            Some(code) => code,
+            None => continue,
+        };
+        for at in (0..code.len()).step_by(arch.align()) {
+            let va = section.va.wrapping_add(at as u64);
+            let padded = at == 0 || PADDING.contains(&code[at - 1]);
+            let found = prologues.iter().any(|&(bytes, after_padding)|
+                code[at..].starts_with(bytes)
+                    && (!after_padding || (padded && va.is_multiple_of(16))))
+                || code.get(at..at + 4).map(word).is_some_and(|word| words
+                    .iter().any(|&(value, mask)| word & mask == value));
+            if found {
+                starts.push(va);
+            }
+        }
+    }
+    starts
+}
+
+/// Find the functions of the executable described by `image` in `data`,
+/// following the code from the entry point, the symbols, the exports and
+/// the targets of the calls. Then the same from the prologues found in the
+/// code no function reaches.
+pub fn functions(image: &Image, data: &[u8]) -> Result<Vec<Function>, String> {
+    let arch = image.arch.ok_or("cannot disassemble this architecture")?;
+    let mut disasm = Disassembler::new(arch);
+
+    let mut work: Vec<u64> = image.symbols.iter()
+        .map(|symbol| symbol.va)
+        .chain(image.exports.iter().map(|export| export.va))
+        .chain(image.entry)
+        .filter(|&va| image.code_at(data, va).is_some())
+        .collect();
+    work.reverse();
+    let mut found: BTreeMap<u64, Function> = BTreeMap::new();
+    // End of each block found, by start
+    let mut covered: BTreeMap<u64, u64> = BTreeMap::new();
+    let mut prologues = prologues(image, data, arch);
+    prologues.reverse();
+
+    loop {
+        while let Some(address) = work.pop() {
+            if found.contains_key(&address) || found.len() >= MAX_FUNCTIONS {
+                continue;
+            }
+            let function = function(image, data, &mut disasm, address);
+            work.extend(function.calls.iter().rev()
+                .filter(|&&target| image.code_at(data, target).is_some()));
+            covered.extend(function.blocks.iter()
+                .map(|block| (block.start, block.end())));
+            found.insert(address, function);
+        }
+        // The next prologue outside the functions found
+        let next = std::iter::from_fn(|| prologues.pop())
+            .find(|&va| covered.range(..=va).next_back()
+                .is_none_or(|(_, &end)| end <= va));
+        match next {
+            Some(va) => work.push(va),
+            None => break,
+        }
+    }
+
+    Ok(found.into_values().collect())
+}
+
+/// Find the function holding the instruction at `va`: the closest of the
+/// `starts` before it whose blocks reach it, or else a function starting at
+/// `va`. `None` if there is no code at `va`.
+pub fn function_at(image: &Image, data: &[u8], starts: &[u64], va: u64)
+        -> Option<Function> {
+    let mut disasm = Disassembler::new(image.arch?);
+    image.code_at(data, va)?;
+
+    let mut starts: Vec<u64> = starts.iter().copied()
+        .filter(|&start| start <= va && image.code_at(data, start).is_some())
+        .collect();
+    starts.sort_unstable();
+    starts.dedup();
+    for &start in starts.iter().rev().take(MAX_CANDIDATES) {
+        let function = function(image, data, &mut disasm, start);
+        if function.block_at(va).is_some() {
+            return Some(function);
+        }
+    }
+    Some(function(image, data, &mut disasm, va))
+}
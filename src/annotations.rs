@@ -0,0 +1,361 @@
+//! Notes left by the analyst on a sample: labels on offsets, comments on
+//! ranges, bookmarks and colour tags.
+//!
+//! Annotations are stored in a JSON file named after the SHA-256 of the
+//! sample, so they come back whenever the same contents are opened, wherever
+//! the file lives and whatever it is called.
+use std::{
+    fs,
+    error::Error,
+    collections::{BTreeMap},
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, Deserialize};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+
+/// Colours byte ranges can be tagged with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+/// Names of the tag colours, as typed in the `color` command
+pub const TAG_COLORS: [&str; 6] =
+    ["red", "green", "yellow", "blue", "magenta", "cyan"];
+
+impl TagColor {
+    pub fn from_name(name: &str) -> Option<TagColor> {
+        match name.to_ascii_lowercase().as_str() {
+            "red" => Some(TagColor::Red),
+            "green" => Some(TagColor::Green),
+            "yellow" => Some(TagColor::Yellow),
+            "blue" => Some(TagColor::Blue),
+            "magenta" => Some(TagColor::Magenta),
+            "cyan" => Some(TagColor::Cyan),
+            _ => None,
+        }
+    }
+
+    /// Terminal colour the tagged bytes are drawn on
+    pub fn color(self) -> Color {
+        match self {
+            TagColor::Red => Color::Red,
+            TagColor::Green => Color::Green,
+            TagColor::Yellow => Color::Yellow,
+            TagColor::Blue => Color::Blue,
+            TagColor::Magenta => Color::Magenta,
+            TagColor::Cyan => Color::Cyan,
+        }
+    }
+}
+
+/// A comment on the bytes `start..end`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comment {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// A named location to jump back to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub offset: usize,
+    pub name: String,
+}
+
+/// A colour tag on the bytes `start..end`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColorTag {
+    pub start: usize,
+    pub end: usize,
+    pub color: TagColor,
+}
+
+/// Kind of an annotation, in the order they are listed at the same offset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NoteKind {
+    Bookmark,
+    Label,
+    Comment,
+}
+
+/// One annotation of any kind, as listed by the `Bookmarks` plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub offset: usize,
+    pub kind: NoteKind,
+    pub text: String,
+}
+
+/// Every annotation of one sample
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Annotations {
+    /// SHA-256 of the sample the annotations belong to
+    pub sha256: String,
+    /// Labels by offset
+    #[serde(default)]
+    pub labels: BTreeMap<usize, String>,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    /// Colour tags, the last one wins where they overlap
+    #[serde(default)]
+    pub colors: Vec<ColorTag>,
+    /// File the annotations are saved to, kept in memory only without one
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl Annotations {
+    /// Empty annotations for the sample with the given hash
+    pub fn new(sha256: &str) -> Annotations {
+        Annotations { sha256: sha256.to_string(), ..Annotations::default() }
+    }
+
+    /// File holding the annotations of the sample with the given hash in
+    /// `dir`
+    pub fn path_in(dir: &Path, sha256: &str) -> PathBuf {
+        dir.join(format!("{}.json", sha256))
+    }
+
+    /// Read the annotations of the sample with the given hash from `dir`.
+    /// A sample that was never annotated has none.
+    pub fn load(dir: &Path, sha256: &str) -> Result<Annotations, Box<dyn Error>> {
+        let path = Annotations::path_in(dir, sha256);
+        let mut notes = if path.exists() {
+            let text = fs::read_to_string(&path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            let notes: Annotations = serde_json::from_str(&text)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            if notes.sha256 != sha256 {
+                return Err(format!("{}: annotations of another sample ({})",
+                    path.display(), notes.sha256).into());
+            }
+            notes
+        } else {
+            Annotations::new(sha256)
+        };
+
+        notes.path = Some(path);
+        Ok(notes)
+    }
+
+    /// Write the annotations to their file, if they have one
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("{}: {}", dir.display(), err))?;
+        }
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(())
+    }
+
+    /// Label `offset` with `text`, an empty text removes the label
+    pub fn set_label(&mut self, offset: usize, text: &str) {
+        if text.is_empty() {
+            self.labels.remove(&offset);
+        } else {
+            self.labels.insert(offset, text.to_string());
+        }
+    }
+
+    /// Comment the bytes `start..end`, replacing the comment on the same
+    /// range. An empty text removes it.
+    pub fn set_comment(&mut self, start: usize, end: usize, text: &str) {
+        self.comments.retain(|c| (c.start, c.end) != (start, end));
+        if !text.is_empty() {
+            self.comments.push(Comment { start, end, text: text.to_string() });
+            self.comments.sort_by_key(|c| (c.start, c.end));
+        }
+    }
+
+    /// Bookmark `offset` as `name`, renaming the bookmark already there. An
+    /// empty name removes it.
+    pub fn set_bookmark(&mut self, offset: usize, name: &str) {
+        self.bookmarks.retain(|b| b.offset != offset);
+        if !name.is_empty() {
+            self.bookmarks.push(Bookmark { offset, name: name.to_string() });
+            self.bookmarks.sort_by_key(|b| b.offset);
+        }
+    }
+
+    /// Tag the bytes `start..end` with `color`, or remove the tags
+    /// overlapping them with `None`
+    pub fn set_color(&mut self, start: usize, end: usize,
+            color: Option<TagColor>) {
+        match color {
+            Some(color) => self.colors.push(ColorTag { start, end, color }),
+            None => self.colors.retain(|t| t.end <= start || end <= t.start),
+        }
+    }
+
+    pub fn label(&self, offset: usize) -> Option<&str> {
+        self.labels.get(&offset).map(|label| label.as_str())
+    }
+
+    pub fn bookmark(&self, offset: usize) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|b| b.offset == offset)
+    }
+
+    /// Colour of the byte at `offset`, if it is tagged
+    pub fn color_at(&self, offset: usize) -> Option<TagColor> {
+        self.colors.iter().rev()
+            .find(|t| t.start <= offset && offset < t.end)
+            .map(|t| t.color)
+    }
+
+    /// Comments starting in `start..end`
+    pub fn comments_in(&self, start: usize, end: usize)
+            -> impl Iterator<Item = &Comment> {
+        self.comments.iter().filter(move |c| start <= c.start && c.start < end)
+    }
+
+    /// Labels of the offsets in `start..end`
+    pub fn labels_in(&self, start: usize, end: usize)
+            -> impl Iterator<Item = (usize, &str)> {
+        self.labels.range(start..end)
+            .map(|(offset, label)| (*offset, label.as_str()))
+    }
+
+    /// Every bookmark, label and comment sorted by offset
+    pub fn notes(&self) -> Vec<Note> {
+        let mut notes: Vec<Note> = self.bookmarks.iter()
+            .map(|b| Note { offset: b.offset, kind: NoteKind::Bookmark,
+                text: b.name.clone() })
+            .chain(self.labels.iter()
+                .map(|(offset, label)| Note { offset: *offset,
+                    kind: NoteKind::Label, text: label.clone() }))
+            .chain(self.comments.iter()
+                .map(|c| Note { offset: c.start, kind: NoteKind::Comment,
+                    text: c.text.clone() }))
+            .collect();
+        notes.sort_by_key(|note| (note.offset, note.kind));
+        notes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.comments.is_empty()
+            && self.bookmarks.is_empty() && self.colors.is_empty()
+    }
+}
+
+/// Plugin listing the bookmarks, labels and comments of the tab's sample to
+/// jump around it
+#[derive(Default)]
+pub struct Bookmarks {
+    selected: usize,
+    /// First line shown
+    scroll: usize,
+}
+
+impl RenderPlugin for Bookmarks {
+    fn get_name(&self) -> &str {
+        "Bookmarks"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let notes = ctx.notes.notes();
+        if notes.is_empty() {
+            let text = if ctx.sample.is_some() {
+                "no annotations, see the bookmark, label and comment commands"
+            } else {
+                "no sample"
+            };
+            f.render_widget(Paragraph::new(text), area);
+            return;
+        }
+
+        self.selected = self.selected.min(notes.len() - 1);
+        let rows = (area.height as usize).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let offset_style = Style::default().fg(Color::Blue);
+        let lines: Vec<Spans> = notes.iter().enumerate()
+            .skip(self.scroll)
+            .take(rows)
+            .map(|(i, note)| {
+                let (mark, style) = match note.kind {
+                    NoteKind::Bookmark => ("*", Style::default()
+                        .fg(Color::Yellow)),
+                    NoteKind::Label => (">", Style::default()
+                        .fg(Color::Green)),
+                    NoteKind::Comment => (";", Style::default()
+                        .fg(Color::Cyan)),
+                };
+                let mut spans = vec![
+                    Span::styled(format!("{:08x} ", note.offset),
+                        offset_style),
+                    Span::styled(format!("{} {}", mark, note.text), style),
+                ];
+                if i == self.selected {
+                    for span in spans.iter_mut() {
+                        span.style = span.style.add_modifier(Modifier::REVERSED);
+                    }
+                }
+                Spans::from(spans)
+            })
+            .collect();
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous annotation, `Enter` moves the cursor
+    /// of the tab to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let notes = ctx.notes.notes();
+        match key.code {
+            KeyCode::Char('j') => {
+                self.selected = (self.selected + 1)
+                    .min(notes.len().saturating_sub(1));
+            },
+            KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            },
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => {
+                self.selected = notes.len().saturating_sub(1);
+            },
+            KeyCode::Enter => {
+                if let Some(note) = notes.get(self.selected) {
+                    ctx.cursor = note.offset.min(ctx.data().len()
+                        .saturating_sub(1));
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
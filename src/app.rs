@@ -1,5 +1,10 @@
 use std::{
-    path::{Path},
+    io,
+    fs,
+    process,
+    sync::{Arc},
+    path::{Path, PathBuf},
+    borrow::{Cow},
     convert::{TryFrom},
 };
 
@@ -7,37 +12,303 @@ use tui::{
     terminal::{Frame},
     backend::{Backend},
     text::{Span, Spans},
-    style::{Style, Color},
+    style::{Style, Color, Modifier},
     layout::{Layout, Constraint, Rect, Direction},
-    widgets::{Block, Tabs, Borders, BorderType, ListItem, ListState, List},
+    widgets::{Block, Tabs, Borders, BorderType, ListItem, ListState, List,
+        Paragraph},
 };
 
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
 use crate::tabs::TabsState;
+use crate::layout::{self, PluginKind, LayoutTemplate};
+use crate::sample::{Sample, Format};
+use crate::config::{Config};
+use crate::command::{self, Command};
+use crate::keys::{KeyConfig};
+use crate::hexview::{HexView};
+use crate::search::{Search, SearchResults};
+use crate::yara::{Yara, YaraScan};
+use crate::annotations::{Annotations, Bookmarks};
+use crate::project::{Project};
+use crate::patch::{self, Patches, PatchList};
+use crate::template::{Templates, Overlay, TemplateTree};
+use crate::inspector::{Inspector};
+use crate::diff::{Diff, DiffView, Differences};
+use crate::funcdiff::{FunctionDiffView, MatchStatus};
+use crate::archive::{self, Archive};
+use crate::defang;
+use crate::quarantine;
+use crate::image::{Address, Image, RawCode};
+use crate::symbols::{Symbols, SymbolTable, ApiCategories};
+use crate::xrefs::{XrefScan, XrefPopup};
+use crate::listing::{self, Listing};
+use crate::cfg::{CfgView};
+use crate::functions::{FunctionScan, FunctionList, CallGraph};
+use crate::emulator::{Machine, EmulatorView};
 
 pub enum Plugin<'a> {
     FileManager(FileManager<'a>),
-    HexView,
+    HexView(HexView),
     Parser,
+    SearchResults(SearchResults),
+    Yara(Yara),
+    Bookmarks(Bookmarks),
+    Patches(PatchList),
+    Template(TemplateTree),
+    Inspector(Inspector),
+    Diff(DiffView),
+    Differences(Differences),
+    FunctionDiff(FunctionDiffView),
+    Symbols(Symbols),
+    Listing(Listing),
+    Cfg(CfgView),
+    Functions(FunctionList),
+    CallGraph(CallGraph),
+    Emulator(EmulatorView),
+}
+
+impl<'a> Plugin<'a> {
+    /// Name of the plugin, rendered in the title of its block
+    pub fn get_name(&self) -> &str {
+        match self {
+            Plugin::FileManager(fm) => fm.get_name(),
+            Plugin::HexView(hv) => hv.get_name(),
+            Plugin::Parser => "Parser",
+            Plugin::SearchResults(sr) => sr.get_name(),
+            Plugin::Yara(yara) => yara.get_name(),
+            Plugin::Bookmarks(bm) => bm.get_name(),
+            Plugin::Patches(pl) => pl.get_name(),
+            Plugin::Template(tt) => tt.get_name(),
+            Plugin::Inspector(di) => di.get_name(),
+            Plugin::Diff(dv) => dv.get_name(),
+            Plugin::Differences(dl) => dl.get_name(),
+            Plugin::FunctionDiff(fd) => fd.get_name(),
+            Plugin::Symbols(sy) => sy.get_name(),
+            Plugin::Listing(li) => li.get_name(),
+            Plugin::Cfg(cfg) => cfg.get_name(),
+            Plugin::Functions(fl) => fl.get_name(),
+            Plugin::CallGraph(cg) => cg.get_name(),
+            Plugin::Emulator(em) => em.get_name(),
+        }
+    }
+
+    /// Kind of the plugin, used to save layouts
+    pub fn kind(&self) -> PluginKind {
+        match self {
+            Plugin::FileManager(_) => PluginKind::FileManager,
+            Plugin::HexView(_) => PluginKind::HexView,
+            Plugin::Parser => PluginKind::Parser,
+            Plugin::SearchResults(_) => PluginKind::SearchResults,
+            Plugin::Yara(_) => PluginKind::Yara,
+            Plugin::Bookmarks(_) => PluginKind::Bookmarks,
+            Plugin::Patches(_) => PluginKind::Patches,
+            Plugin::Template(_) => PluginKind::Template,
+            Plugin::Inspector(_) => PluginKind::Inspector,
+            Plugin::Diff(_) => PluginKind::Diff,
+            Plugin::Differences(_) => PluginKind::Differences,
+            Plugin::FunctionDiff(_) => PluginKind::FunctionDiff,
+            Plugin::Symbols(_) => PluginKind::Symbols,
+            Plugin::Listing(_) => PluginKind::Listing,
+            Plugin::Cfg(_) => PluginKind::Cfg,
+            Plugin::Functions(_) => PluginKind::Functions,
+            Plugin::CallGraph(_) => PluginKind::CallGraph,
+            Plugin::Emulator(_) => PluginKind::Emulator,
+        }
+    }
+
+    /// Draw the plugin in `area`
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        match self {
+            Plugin::FileManager(fm) => fm.draw(f, area, ctx),
+            Plugin::HexView(hv) => hv.draw(f, area, ctx),
+            Plugin::Parser => {},
+            Plugin::SearchResults(sr) => sr.draw(f, area, ctx),
+            Plugin::Yara(yara) => yara.draw(f, area, ctx),
+            Plugin::Bookmarks(bm) => bm.draw(f, area, ctx),
+            Plugin::Patches(pl) => pl.draw(f, area, ctx),
+            Plugin::Template(tt) => tt.draw(f, area, ctx),
+            Plugin::Inspector(di) => di.draw(f, area, ctx),
+            Plugin::Diff(dv) => dv.draw(f, area, ctx),
+            Plugin::Differences(dl) => dl.draw(f, area, ctx),
+            Plugin::FunctionDiff(fd) => fd.draw(f, area, ctx),
+            Plugin::Symbols(sy) => sy.draw(f, area, ctx),
+            Plugin::Listing(li) => li.draw(f, area, ctx),
+            Plugin::Cfg(cfg) => cfg.draw(f, area, ctx),
+            Plugin::Functions(fl) => fl.draw(f, area, ctx),
+            Plugin::CallGraph(cg) => cg.draw(f, area, ctx),
+            Plugin::Emulator(em) => em.draw(f, area, ctx),
+        }
+    }
+
+    /// Forward a key to the plugin, returns true if the plugin used it
+    pub fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        match self {
+            Plugin::FileManager(fm) => fm.on_key(key, ctx),
+            Plugin::HexView(hv) => hv.on_key(key, ctx),
+            Plugin::Parser => false,
+            Plugin::SearchResults(sr) => sr.on_key(key, ctx),
+            Plugin::Yara(yara) => yara.on_key(key, ctx),
+            Plugin::Bookmarks(bm) => bm.on_key(key, ctx),
+            Plugin::Patches(pl) => pl.on_key(key, ctx),
+            Plugin::Template(tt) => tt.on_key(key, ctx),
+            Plugin::Inspector(di) => di.on_key(key, ctx),
+            Plugin::Diff(dv) => dv.on_key(key, ctx),
+            Plugin::Differences(dl) => dl.on_key(key, ctx),
+            Plugin::FunctionDiff(fd) => fd.on_key(key, ctx),
+            Plugin::Symbols(sy) => sy.on_key(key, ctx),
+            Plugin::Listing(li) => li.on_key(key, ctx),
+            Plugin::Cfg(cfg) => cfg.on_key(key, ctx),
+            Plugin::Functions(fl) => fl.on_key(key, ctx),
+            Plugin::CallGraph(cg) => cg.on_key(key, ctx),
+            Plugin::Emulator(em) => em.on_key(key, ctx),
+        }
+    }
+
+    /// True if the plugin takes the characters typed before the global
+    /// bindings
+    pub fn captures_keys(&self) -> bool {
+        match self {
+            Plugin::HexView(hv) => hv.captures_keys(),
+            Plugin::Emulator(em) => em.captures_keys(),
+            _ => false,
+        }
+    }
+
+    /// Forward text pasted in the terminal to the plugin
+    pub fn on_paste(&mut self, text: &str) {
+        if let Plugin::FileManager(fm) = self {
+            fm.on_paste(text)
+        }
+    }
+
+    /// Address of the item selected in the plugin, if it lists things at
+    /// addresses
+    pub fn address(&self, ctx: &TabContext) -> Option<u64> {
+        match self {
+            Plugin::Symbols(sy) => sy.address(ctx),
+            Plugin::Functions(fl) => fl.address(ctx),
+            Plugin::CallGraph(cg) => cg.address(ctx),
+            _ => None,
+        }
+    }
 }
 
-trait RenderPlugin {
+pub trait RenderPlugin {
     fn get_name(&self) -> &str;
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect);
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+        ctx: &TabContext);
+    /// Called when a key is pressed while the plugin is focused and the key
+    /// is not bound to anything else. Returns true if the key was used.
+    fn on_key(&mut self, _key: KeyEvent, _ctx: &mut TabContext) -> bool {
+        false
+    }
+    /// Called when text is pasted while the plugin is focused
+    fn on_paste(&mut self, _text: &str) {}
+    /// True if the characters typed go to the plugin before the global
+    /// bindings, e.g. while it edits. Those it does not use still run the
+    /// bindings.
+    fn captures_keys(&self) -> bool {
+        false
+    }
+    /// Address of the item selected in the plugin, for plugins listing
+    /// things at addresses. Cross-references are shown for it instead of
+    /// for the cursor.
+    fn address(&self, _ctx: &TabContext) -> Option<u64> {
+        None
+    }
 }
 
 pub struct FileManager<'a> {
     pub name: String,
-    pub curr_dir: &'a Path,
+    /// Directory listed, on disk or inside a container
+    pub curr_dir: Cow<'a, Path>,
     pub state: ListState,
+    /// Entries of `curr_dir` in the last draw, directories first
+    entries: Vec<FileEntry>,
+    /// Container `curr_dir` is in, with its path, kept between draws
+    archive: Option<(PathBuf, Archive)>,
+}
+
+/// A file or directory listed by the `FileManager`
+struct FileEntry {
+    path: PathBuf,
+    dir: bool,
+    /// Encrypted inside its container
+    encrypted: bool,
 }
 
 impl<'a> FileManager<'a> {
-    pub fn new(curr_dir: &'a str) -> FileManager {
+    pub fn new<P: Into<Cow<'a, Path>>>(curr_dir: P) -> FileManager<'a> {
         FileManager {
             name: String::from("FileManager"),
-            curr_dir: Path::new(curr_dir),
+            curr_dir: curr_dir.into(),
             state: ListState::default(),
+            entries: Vec::new(),
+            archive: None,
+        }
+    }
+
+    /// Move to `dir`, selecting its first entry
+    fn change_dir(&mut self, dir: PathBuf) {
+        self.curr_dir = Cow::Owned(dir);
+        self.state = ListState::default();
+    }
+
+    /// Read the entries of the current directory, directories first and
+    /// each group sorted by name
+    fn read_entries(&mut self) -> io::Result<Vec<FileEntry>> {
+        let mut entries: Vec<FileEntry> = if self.curr_dir.is_dir() {
+            self.archive = None;
+            self.curr_dir.read_dir()?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .map(|path| FileEntry { dir: path.is_dir(), path,
+                    encrypted: false })
+                .collect()
+        } else {
+            let dir = self.archive_dir()?;
+            let (root, archive) = self.archive.as_ref().unwrap();
+            archive.list(&dir)
+                .map(|entry| FileEntry { path: root.join(&entry.name),
+                    dir: entry.dir, encrypted: entry.encrypted })
+                .collect()
+        };
+        entries.sort_by_key(|entry| (!entry.dir, entry.path.file_name()
+            .map(|name| name.to_os_string())));
+        Ok(entries)
+    }
+
+    /// Directory inside the container of `curr_dir`, opening the
+    /// container unless it is the one of the last draw
+    fn archive_dir(&mut self) -> io::Result<String> {
+        let cached = self.archive.as_ref().and_then(|(root, archive)| {
+            let dir = archive::inner_path(self.curr_dir.strip_prefix(root)
+                .ok()?);
+            let is_dir = dir.is_empty() || archive.entry(&dir)
+                .is_some_and(|entry| entry.dir);
+            if is_dir { Some(dir) } else { None }
+        });
+        if let Some(dir) = cached {
+            return Ok(dir);
+        }
+
+        let (root, archive, dir) = archive::locate(&self.curr_dir)?;
+        // Keep the absolute path so that the container is found again
+        self.curr_dir = Cow::Owned(root.join(&dir));
+        self.archive = Some((root, archive));
+        Ok(dir)
+    }
+
+    /// Whether the file at `path` is a container we can go into
+    fn is_container(&self, path: &Path) -> bool {
+        if path.is_file() {
+            return archive::is_container(path);
         }
+        self.archive.as_ref().and_then(|(root, archive)| {
+            let name = archive::inner_path(path.strip_prefix(root).ok()?);
+            archive.read(&name).ok()
+        }).is_some_and(|data| archive::Kind::detect(&data).is_some())
     }
 }
 
@@ -46,41 +317,206 @@ impl<'a> RenderPlugin for FileManager<'a> {
         self.name.as_ref()
     }
 
-    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            _ctx: &TabContext) {
         let dir_style = Style::default().fg(Color::Blue);
         let file_style = Style::default().fg(Color::Yellow);
-        let default_style = Style::default().fg(Color::White);
 
-        let dir_entries: Vec<ListItem> = self.curr_dir
-            .read_dir().expect("read_dir call failed")
+        self.entries = match self.read_entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                self.entries.clear();
+                let text = format!("{}: {}", self.curr_dir.display(), err);
+                f.render_widget(Paragraph::new(Span::styled(text,
+                    Style::default().fg(Color::Red))), area);
+                return;
+            }
+        };
+
+        let items: Vec<ListItem> = self.entries.iter()
             .map(|entry| {
-                if let Ok(entry) = entry {
-                    if entry.path().is_dir() {
-                        ListItem::new(vec![Spans::from(vec![
-                            Span::styled(entry.path().to_str().unwrap().to_string(), dir_style)
-                        ])])
-                    } else {
-                        ListItem::new(vec![Spans::from(vec![
-                            Span::styled(entry.path().to_str().unwrap().to_string(), file_style)
-                        ])])
-                    }
+                let name = entry.path.file_name()
+                    .map(|name| defang::name(&name.to_string_lossy()))
+                    .unwrap_or_else(|| entry.path.display().to_string());
+                if entry.dir {
+                    ListItem::new(Span::styled(name + "/", dir_style))
+                } else if entry.encrypted {
+                    ListItem::new(Span::styled(name + " [encrypted]",
+                        file_style))
                 } else {
-                        ListItem::new(vec![Spans::from(vec![
-                            Span::styled(format!("fdak;l"),file_style)
-                        ])])
-
+                    ListItem::new(Span::styled(name, file_style))
                 }
             })
             .collect();
-        let entries = List::new(dir_entries);
+
+        match self.state.selected() {
+            _ if self.entries.is_empty() => self.state.select(None),
+            Some(i) if i >= self.entries.len() =>
+                self.state.select(Some(self.entries.len() - 1)),
+            Some(_) => {},
+            None => self.state.select(Some(0)),
+        }
+
+        let entries = List::new(items)
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
         f.render_stateful_widget(entries, area, &mut self.state);
     }
+
+    /// `j`/`k` select the next/previous entry, `Enter` goes into the
+    /// selected directory or container, or opens the selected file in a new
+    /// tab, `o` opens it even if it is a container, `d` compares the
+    /// selected file with the sample of the tab and `Backspace`/`h` goes to
+    /// the parent directory
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let selected = self.state.selected().unwrap_or(0);
+        let file = self.entries.get(selected)
+            .filter(|entry| !entry.dir)
+            .map(|entry| entry.path.clone());
+        match key.code {
+            KeyCode::Char('j') => self.state.select(Some((selected + 1)
+                .min(self.entries.len().saturating_sub(1)))),
+            KeyCode::Char('k') =>
+                self.state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Char('g') | KeyCode::Home => self.state.select(Some(0)),
+            KeyCode::Char('G') | KeyCode::End => self.state.select(Some(
+                self.entries.len().saturating_sub(1))),
+            KeyCode::Backspace | KeyCode::Char('h') => {
+                // `..` of a relative path like `.` has no parent to go to
+                let dir = fs::canonicalize(&self.curr_dir)
+                    .unwrap_or_else(|_| self.curr_dir.to_path_buf());
+                if let Some(parent) = dir.parent() {
+                    self.change_dir(parent.to_path_buf());
+                }
+            },
+            KeyCode::Enter => match self.entries.get(selected) {
+                Some(entry) if entry.dir => self.change_dir(entry.path.clone()),
+                Some(entry) if self.is_container(&entry.path) =>
+                    self.change_dir(entry.path.clone()),
+                Some(entry) =>
+                    ctx.actions.push(Action::OpenSample(entry.path.clone())),
+                None => {},
+            },
+            KeyCode::Char('o') => if let Some(path) = file {
+                ctx.actions.push(Action::OpenSample(path));
+            },
+            KeyCode::Char('d') => if let Some(path) = file {
+                ctx.actions.push(Action::Diff(path));
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// A pasted path to a directory or a container changes the current
+    /// directory
+    fn on_paste(&mut self, text: &str) {
+        let path = Path::new(text.trim());
+        if path.is_dir() || archive::is_container(path) {
+            self.change_dir(path.to_path_buf());
+        }
+    }
+}
+
+/// Something a plugin asks the application to do, beyond its tab
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Open the sample at the path in a new tab, or go to the tab already
+    /// holding it
+    OpenSample(PathBuf),
+    /// Compare the sample at the path with the one of the tab, or with the
+    /// next file picked if the tab has none
+    Diff(PathBuf),
+}
+
+/// State shared by all the plugins of a tab
+#[derive(Default)]
+pub struct TabContext {
+    /// The sample analysed in this tab, if any
+    pub sample: Option<Sample>,
+    /// Offset in the sample of the byte under the cursor
+    pub cursor: usize,
+    /// Last search run on the sample
+    pub search: Search,
+    /// Last YARA scan of the sample
+    pub yara: YaraScan,
+    /// Annotations of the sample
+    pub notes: Annotations,
+    /// Other end of the selection, the cursor being one end. Without an
+    /// anchor only the byte under the cursor is selected.
+    pub anchor: Option<usize>,
+    /// Actions requested by the plugins, run by `MagLabApp` after the key
+    /// that caused them
+    pub actions: Vec<Action>,
+    /// Edits made to the sample
+    pub patches: Patches,
+    /// Template decoded over the sample
+    pub overlay: Option<Overlay>,
+    /// Differences with another sample, in diff tabs
+    pub diff: Option<Diff>,
+    /// Imports and exports of the sample, listed once a `Symbols` plugin
+    /// shows them
+    pub symbols: SymbolTable,
+    /// Cross-references of the sample, indexed in the background
+    pub xrefs: XrefScan,
+    /// Functions of the sample, found in the background once a plugin
+    /// lists them
+    pub functions: FunctionScan,
+    /// Bytes of the sample disassembled as headerless code, instead of the
+    /// layout its headers tell
+    pub raw: Option<RawCode>,
+    /// Code of the sample being emulated
+    pub emulation: Option<Machine>,
+}
+
+impl TabContext {
+    /// Contents of the sample with the edits applied, empty without a
+    /// sample
+    pub fn data(&self) -> &[u8] {
+        match self.patches.data() {
+            Some(data) => data,
+            None => self.sample.as_ref()
+                .map(|sample| &sample.data[..])
+                .unwrap_or(&[]),
+        }
+    }
+
+    /// Shared contents of the sample with the edits applied, to hand to
+    /// background threads
+    pub fn contents(&self) -> Option<Arc<[u8]>> {
+        self.patches.data().cloned()
+            .or_else(|| self.sample.as_ref().map(|sample| sample.data.clone()))
+    }
+
+    /// Selected bytes as `start..end`
+    pub fn selection(&self) -> (usize, usize) {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        (anchor.min(self.cursor), anchor.max(self.cursor) + 1)
+    }
+
+    /// Disassemble the bytes of `code` instead of what the headers of the
+    /// sample tell, or lay the sample out from its headers again with `None`
+    pub fn set_raw(&mut self, code: Option<RawCode>) {
+        if let Some(sample) = self.sample.as_mut() {
+            sample.image = match &code {
+                Some(code) => Some(Arc::new(Image::raw(code))),
+                None if sample.format.is_executable() =>
+                    Image::parse(&sample.data).ok().map(Arc::new),
+                None => None,
+            };
+        }
+        self.raw = code;
+        // Analyze the code again with the new instructions
+        self.xrefs = XrefScan::default();
+        self.functions = FunctionScan::default();
+    }
 }
 
 /// Struct to hold an application for each tab
 pub struct App<'a,> {
-    pub title: &'a str,
+    pub title: String,
     pub grid: ColumnsState<'a>,
+    /// State shared by the plugins of the tab
+    pub ctx: TabContext,
 }
 
 pub struct ColumnsState<'a> {
@@ -89,7 +525,7 @@ pub struct ColumnsState<'a> {
 }
 
 impl<'a> ColumnsState<'a> {
-    pub fn new(columns: Vec<PluginsState<'a>>) -> ColumnsState {
+    pub fn new(columns: Vec<PluginsState<'a>>) -> ColumnsState<'a> {
         ColumnsState { columns, index: 0 }
     }
 
@@ -158,7 +594,7 @@ pub struct PluginsState<'a> {
 }
 
 impl<'a> PluginsState<'a> {
-    pub fn new(plugins: Vec<Plugin<'a>>) -> PluginsState {
+    pub fn new(plugins: Vec<Plugin<'a>>) -> PluginsState<'a> {
         PluginsState { plugins, index: 0 }
     }
 
@@ -176,8 +612,21 @@ impl<'a> PluginsState<'a> {
 }
 
 impl<'a> App<'a> {
-    pub fn new(title: &'a str, grid: ColumnsState<'a>) -> App<'a> {
-        App { title, grid }
+    pub fn new(title: &str, grid: ColumnsState<'a>) -> App<'a> {
+        App { title: title.to_string(), grid, ctx: TabContext::default() }
+    }
+
+    /// Create a new tab analysing `sample`, named after it
+    pub fn with_sample(sample: Sample, grid: ColumnsState<'a>) -> App<'a> {
+        let ctx = TabContext { sample: Some(sample), ..TabContext::default() };
+        App { title: ctx.sample.as_ref().unwrap().name(), grid, ctx }
+    }
+
+    /// Directory where the file managers of this tab start
+    pub fn dir(&self) -> PathBuf {
+        self.ctx.sample.as_ref()
+            .map(|sample| sample.dir())
+            .unwrap_or_else(|| PathBuf::from("."))
     }
 
     pub fn next_column(&mut self) {
@@ -234,8 +683,14 @@ impl<'a> App<'a> {
 
             // Now we render each plugin
             for (j, line) in col.plugins.iter_mut().enumerate() {
+                // Plugins draw inside the borders of their block, first so
+                // that titles follow what they drew
+                let inner = Block::default().borders(Borders::ALL)
+                    .inner(line_chunks[j]);
+                line.draw(f, inner, &self.ctx);
+
                 let mut plugin = Block::default().borders(Borders::ALL)
-                    .title(format!("Plugin{}", j));
+                    .title(line.get_name().to_string());
 
                 // If the plugin matches the selected one, we highlight it
                 if i == self.grid.index && j == col.index {
@@ -247,10 +702,6 @@ impl<'a> App<'a> {
                         .border_style(Style::default().fg(Color::White))
                 }
                 f.render_widget(plugin, line_chunks[j]);
-                match line {
-                    Plugin::FileManager(fm) => fm.draw(f, line_chunks[j]),
-                    _ => {},
-                }
             }
         }
     }
@@ -263,8 +714,22 @@ pub struct MagLabApp<'a> {
     pub title: &'a str,
     /// Status flag if the application should quit
     pub should_quit: bool,
+    /// Samples must not be modified in any way
+    pub read_only: bool,
     /// A vector of all tabs in out application
     pub tabs: TabsState<'a>,
+    /// User configuration
+    pub config: Config,
+    /// Command being typed in the prompt, if the prompt is open
+    pub prompt: Option<String>,
+    /// Message shown on the status line, e.g. the outcome of a command
+    pub status: Option<String>,
+    /// Project the samples opened are added to
+    pub project: Option<Project>,
+    /// File picked to be compared with the next one picked
+    pub diff_mark: Option<PathBuf>,
+    /// Cross-references shown over the current tab
+    pub xref_popup: Option<XrefPopup>,
 }
 
 impl<'a> MagLabApp<'a> {
@@ -272,7 +737,14 @@ impl<'a> MagLabApp<'a> {
         MagLabApp {
             title,
             should_quit: false,
+            read_only: true,
             tabs,
+            config: Config::default(),
+            prompt: None,
+            status: None,
+            project: None,
+            diff_mark: None,
+            xref_popup: None,
         }
     }
 
@@ -325,27 +797,770 @@ impl<'a> MagLabApp<'a> {
         }
     }
 
-    /// Called when pressing any other key
-    pub fn on_key(&mut self, c: char) {
-        match c {
-            'q' => {
+    /// Route pasted text to the prompt if it is open or to the focused plugin
+    pub fn on_paste(&mut self, text: &str) {
+        if let Some(prompt) = self.prompt.as_mut() {
+            // The prompt is a single line
+            prompt.extend(text.chars().filter(|c| !c.is_control()));
+            return;
+        }
+
+        let grid = &mut self.tabs.apps[self.tabs.index].grid;
+        let lines = &mut grid.columns[grid.index];
+        lines.plugins[lines.index].on_paste(text);
+    }
+
+    /// Handle a key pressed by the user
+    pub fn on_key_event(&mut self, key: KeyEvent, keys: &KeyConfig) {
+        if self.prompt.is_some() {
+            // The prompt takes every key while it is open
+            self.on_prompt_key(key);
+        } else if let Some(popup) = self.xref_popup.as_mut() {
+            // So does the popup
+            if !popup.on_key(key, &mut self.tabs.apps[self.tabs.index].ctx) {
+                self.xref_popup = None;
+            }
+        } else if self.on_typed_key(key) {
+            // The focused plugin used the character typed
+        } else if key == keys.xrefs {
+            self.open_xrefs();
+        } else if key == keys.command {
+            self.open_prompt();
+        } else if key == keys.quit {
+            self.should_quit = true;
+        } else if key == keys.tab_left {
+            self.tab_left();
+        } else if key == keys.tab_right {
+            self.tab_right();
+        } else if key == keys.focus_left {
+            self.focus_left();
+        } else if key == keys.focus_right {
+            self.focus_right();
+        } else if key == keys.focus_up {
+            self.focus_up();
+        } else if key == keys.focus_down {
+            self.focus_down();
+        } else if key == keys.new_plugin {
+            self.add_plugin(Plugin::HexView(HexView::new()));
+        } else if key == keys.remove_plugin {
+            self.remove_plugin();
+        } else if key == keys.search {
+            self.prompt = Some(String::from("search "));
+            self.status = None;
+        } else if key == keys.next_match {
+            let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+            if let Some(offset) = ctx.search.next(ctx.cursor) {
+                ctx.cursor = offset;
+            }
+        } else if key == keys.previous_match {
+            let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+            if let Some(offset) = ctx.search.previous(ctx.cursor) {
+                ctx.cursor = offset;
+            }
+        } else {
+            // Anything else goes to the focused plugin
+            let tab = &mut self.tabs.apps[self.tabs.index];
+            let lines = &mut tab.grid.columns[tab.grid.index];
+            lines.plugins[lines.index].on_key(key, &mut tab.ctx);
+            self.run_actions();
+        }
+    }
+
+    /// Give a character typed to the focused plugin first if it captures
+    /// them, returns true if it used it
+    fn on_typed_key(&mut self, key: KeyEvent) -> bool {
+        let typed = matches!(key.code, KeyCode::Char(_))
+            && !key.modifiers.intersects(KeyModifiers::CONTROL
+                | KeyModifiers::ALT);
+        let tab = &mut self.tabs.apps[self.tabs.index];
+        let lines = &mut tab.grid.columns[tab.grid.index];
+        let plugin = &mut lines.plugins[lines.index];
+        if !typed || !plugin.captures_keys() || !plugin.on_key(key,
+                &mut tab.ctx) {
+            return false;
+        }
+        self.run_actions();
+        true
+    }
+
+    /// Show the cross-references to the item selected in the focused
+    /// plugin, or to the address under the cursor
+    fn open_xrefs(&mut self) {
+        let tab = &self.tabs.apps[self.tabs.index];
+        let column = &tab.grid.columns[tab.grid.index];
+        let ctx = &tab.ctx;
+        let image = ctx.sample.as_ref().and_then(|sample| sample.image.as_ref());
+        let va = column.plugins[column.index].address(ctx)
+            .or_else(|| image.and_then(|image| image.va_of(ctx.cursor)));
+        let popup = match (va, image) {
+            (Some(va), _) => XrefPopup::new(ctx, va),
+            (None, Some(_)) => Err("the cursor is not in a section loaded in \
+                memory".to_string()),
+            (None, None) => Err("no virtual addresses in this tab".to_string()),
+        };
+        match popup {
+            Ok(popup) => self.xref_popup = Some(popup),
+            Err(err) => self.status = Some(format!("error: {}", err)),
+        }
+    }
+
+    /// Run the actions the plugins of the current tab asked for
+    fn run_actions(&mut self) {
+        let actions = std::mem::take(
+            &mut self.tabs.apps[self.tabs.index].ctx.actions);
+        for action in actions {
+            let result = match action {
+                Action::OpenSample(path) => self.open_sample(&path),
+                Action::Diff(path) => self.diff_file(&path),
+            };
+            self.status = Some(match result {
+                Ok(msg) => msg,
+                Err(err) => format!("error: {}", err),
+            });
+        }
+    }
+
+    /// Compare `sample` with `other` in a new tab
+    pub fn open_diff(&mut self, sample: Sample, other: Sample) -> String {
+        let title = format!("{} vs {}", sample.name(), other.name());
+        let diff = Diff::new(&sample, other);
+        let layout = match diff.functions.status {
+            MatchStatus::Failed(_) => "diff",
+            _ => "function-diff",
+        };
+        let grid = layout::Layout::builtin(layout).unwrap()
+            .build(&sample.dir());
+        let msg = diff.summary();
+        let mut app = App::with_sample(sample, grid);
+        app.title = title;
+        app.ctx.diff = Some(diff);
+        self.tabs.add_tab(app);
+        msg
+    }
+
+    /// Compare the file at `path` with the sample of the current tab. In a
+    /// tab without sample, the first file picked is compared with the
+    /// second one.
+    pub fn diff_file(&mut self, path: &Path) -> Result<String, String> {
+        let open = |path: &Path| Sample::open(path)
+            .map_err(|err| format!("{}: {}", path.display(), err));
+        let ctx = &self.tabs.apps[self.tabs.index].ctx;
+        let sample = match (&ctx.sample, self.diff_mark.take()) {
+            (Some(sample), _) => match ctx.contents() {
+                Some(data) if !ctx.patches.is_empty() =>
+                    Sample::from_bytes(sample.path.clone(), data),
+                _ => sample.clone(),
+            },
+            (None, Some(mark)) => open(&mark)?,
+            (None, None) => {
+                self.diff_mark = Some(path.to_path_buf());
+                return Ok(format!("picked {} to compare, pick the other file",
+                    path.display()));
+            },
+        };
+        Ok(self.open_diff(sample, open(path)?))
+    }
+
+    /// Open the sample at `path` in a new tab with the layout for its
+    /// format, or go to the tab already holding it
+    pub fn open_sample(&mut self, path: &Path) -> Result<String, String> {
+        let sample = Sample::open(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let open = self.tabs.apps.iter().position(|tab| {
+            tab.ctx.sample.as_ref().map(|s| &s.path) == Some(&sample.path)
+        });
+        if let Some(index) = open {
+            self.tabs.index = index;
+            return Ok(format!("{} is already open",
+                defang::name(&sample.name())));
+        }
+
+        let layout = self.config.layout.as_ref()
+            .and_then(|name| self.config.layout(name))
+            .unwrap_or_else(|| self.config.layout_for_format(sample.format));
+        let grid = layout.build(&sample.dir());
+        let mut app = App::with_sample(sample, grid);
+        let notes = self.load_notes(&mut app.ctx)?;
+        let name = defang::name(&app.title);
+        let msg = match notes {
+            0 => format!("opened {}", name),
+            n => format!("opened {}, {} annotations", name, n),
+        };
+        self.tabs.add_tab(app);
+        self.track_samples()?;
+        Ok(msg)
+    }
+
+    /// Add the samples of every tab to the project and to the quarantine,
+    /// if there are any
+    pub fn track_samples(&mut self) -> Result<(), String> {
+        for sample in self.tabs.apps.iter()
+                .filter_map(|tab| tab.ctx.sample.as_ref()) {
+            if let Some(project) = self.project.as_mut() {
+                project.add_sample(sample).map_err(|err| err.to_string())?;
+            }
+            if let Some(dir) = &self.config.quarantine {
+                quarantine::store(dir, sample)
+                    .map_err(|err| format!("{}: {}", dir.display(), err))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn project_mut(&mut self) -> Result<&mut Project, String> {
+        self.project.as_mut()
+            .ok_or_else(|| "no project open, start maglab with --project \
+                <dir>".to_string())
+    }
+
+    /// Load the annotations of the sample of `ctx` from the notes directory,
+    /// returns how many there are
+    fn load_notes(&self, ctx: &mut TabContext) -> Result<usize, String> {
+        let sha256 = match &ctx.sample {
+            Some(sample) => sample.sha256.clone(),
+            None => return Ok(0),
+        };
+        // Start from empty notes if they cannot be read, so that we do not
+        // try again on every draw
+        ctx.notes = Annotations::new(&sha256);
+        if let Some(dir) = &self.config.notes_dir {
+            ctx.notes = Annotations::load(dir, &sha256)
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(ctx.notes.notes().len())
+    }
+
+    /// Change the annotations of the current tab with `edit` and save them
+    fn annotate<F>(&mut self, edit: F) -> Result<(), String>
+            where F: FnOnce(&mut Annotations) {
+        let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+        if ctx.sample.is_none() {
+            return Err("no sample to annotate in this tab".to_string());
+        }
+        edit(&mut ctx.notes);
+        ctx.anchor = None;
+        ctx.notes.save().map_err(|err| err.to_string())
+    }
+
+    /// Open the command prompt
+    pub fn open_prompt(&mut self) {
+        self.prompt = Some(String::new());
+        self.status = None;
+    }
+
+    /// Handle a key pressed while the prompt is open
+    pub fn on_prompt_key(&mut self, key: KeyEvent) {
+        let prompt = match self.prompt.as_mut() {
+            Some(prompt) => prompt,
+            None => return,
+        };
+
+        match key.code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Enter => {
+                let line = self.prompt.take().unwrap_or_default();
+                if !line.trim().is_empty() {
+                    self.run_command(&line);
+                }
+            },
+            // Deleting past the start closes the prompt, like in vim
+            KeyCode::Backspace if prompt.pop().is_none() => self.prompt = None,
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL)
+                => prompt.push(c),
+            _ => {},
+        }
+    }
+
+    /// Parse and run a command typed in the prompt. The outcome is shown on
+    /// the status line.
+    pub fn run_command(&mut self, line: &str) {
+        let result = Command::parse(line).and_then(|command| {
+            self.execute(command)
+        });
+        self.status = Some(match result {
+            Ok(msg) => msg,
+            Err(err) => format!("error: {}", err),
+        });
+    }
+
+    /// Execute `command`, returning a message for the status line
+    fn execute(&mut self, command: Command) -> Result<String, String> {
+        match command {
+            Command::Layout(name) => {
+                let layout = self.config.layout(&name)
+                    .ok_or_else(|| format!("unknown layout {}, expected one \
+                        of: {}", name, self.config.layout_names().join(", ")))?;
+                let tab = &mut self.tabs.apps[self.tabs.index];
+                tab.grid = layout.build(&tab.dir());
+                Ok(format!("layout {} applied", name))
+            },
+            Command::SaveLayout { name, formats } => {
+                let tab = &self.tabs.apps[self.tabs.index];
+                let layout = layout::Layout::from_grid(&tab.grid);
+                let template = LayoutTemplate {
+                    columns: layout.columns,
+                    formats,
+                };
+                self.config.save_layout(&name, template)
+                    .map_err(|err| err.to_string())?;
+                Ok(format!("layout {} saved", name))
+            },
+            Command::Layouts => Ok(self.config.layout_names().join(", ")),
+            Command::Yara(dir) => {
+                let dir = dir.or_else(|| self.config.yara_rules.clone())
+                    .ok_or("no rules directory, set yara_rules in the \
+                        configuration or run `yara <dir>`")?;
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let data = ctx.contents()
+                    .ok_or("no sample to scan in this tab")?;
+                ctx.yara.start(dir.clone(), data);
+                Ok(format!("scanning with the rules in {}", dir.display()))
+            },
+            Command::Search(query) => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let data = ctx.contents()
+                    .ok_or("no sample to search in this tab")?;
+                ctx.search.start(&query, data)?;
+                Ok(format!("searching for {}", query))
+            },
+            Command::Open(path) => self.open_sample(&path),
+            Command::Diff(path) => {
+                if self.tabs.apps[self.tabs.index].ctx.sample.is_none() {
+                    return Err("no sample in this tab".to_string());
+                }
+                self.diff_file(&path)
+            },
+            Command::DiffTab(tab) => {
+                let sample = |index: usize| self.tabs.apps.get(index)
+                    .and_then(|app| app.ctx.sample.clone());
+                let other = sample(tab - 1)
+                    .ok_or_else(|| format!("no sample in tab {}", tab))?;
+                let sample = sample(self.tabs.index)
+                    .ok_or("no sample in this tab")?;
+                Ok(self.open_diff(sample, other))
+            },
+            Command::Label(text) => {
+                let cursor = self.tabs.apps[self.tabs.index].ctx.cursor;
+                self.annotate(|notes| notes.set_label(cursor, &text))?;
+                Ok(if text.is_empty() {
+                    format!("label removed at {:#x}", cursor)
+                } else {
+                    format!("{:#x} labelled {}", cursor, text)
+                })
+            },
+            Command::Comment(text) => {
+                let (start, end) = self.tabs.apps[self.tabs.index].ctx
+                    .selection();
+                self.annotate(|notes| notes.set_comment(start, end, &text))?;
+                Ok(if text.is_empty() {
+                    format!("comment removed at {:#x}", start)
+                } else {
+                    format!("commented {} bytes at {:#x}",
+                        end - start, start)
+                })
+            },
+            Command::Bookmark(name) => {
+                let cursor = self.tabs.apps[self.tabs.index].ctx.cursor;
+                self.annotate(|notes| notes.set_bookmark(cursor, &name))?;
+                Ok(if name.is_empty() {
+                    format!("bookmark removed at {:#x}", cursor)
+                } else {
+                    format!("bookmarked {:#x} as {}", cursor, name)
+                })
+            },
+            Command::Color(color) => {
+                let (start, end) = self.tabs.apps[self.tabs.index].ctx
+                    .selection();
+                self.annotate(|notes| notes.set_color(start, end, color))?;
+                Ok(format!("{} bytes at {:#x} {}", end - start, start,
+                    if color.is_some() { "tagged" } else { "untagged" }))
+            },
+            Command::Project => {
+                Ok(self.project_mut()?.summary())
+            },
+            Command::Note(text) => {
+                self.project_mut()?.add_note(&text)
+                    .map_err(|err| err.to_string())?;
+                Ok("note added to the journal".to_string())
+            },
+            Command::Extract(name) => {
+                let project = self.project.as_mut()
+                    .ok_or("no project open, start maglab with --project \
+                        <dir>")?;
+                let ctx = &self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_ref()
+                    .ok_or("no sample to extract from in this tab")?;
+                let (start, end) = ctx.selection();
+                let end = end.min(sample.data.len());
+                let data = sample.data.get(start..end)
+                    .ok_or("nothing selected")?;
+                let artifact = project.add_artifact(sample, start, data, &name)
+                    .map_err(|err| err.to_string())?;
+                Ok(format!("extracted {} bytes as {} ({})", artifact.len,
+                    artifact.name, artifact.sha256))
+            },
+            Command::Undo => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let offset = ctx.patches.undo().ok_or("nothing to undo")?;
+                ctx.cursor = offset.min(ctx.data().len().saturating_sub(1));
+                Ok(format!("undid the edit at {:#x}", offset))
+            },
+            Command::Redo => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let offset = ctx.patches.redo().ok_or("nothing to redo")?;
+                ctx.cursor = offset.min(ctx.data().len().saturating_sub(1));
+                Ok(format!("redid the edit at {:#x}", offset))
+            },
+            Command::PatchExport(path) => {
+                let ctx = &self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_ref()
+                    .ok_or("no sample in this tab")?;
+                if ctx.patches.is_empty() {
+                    return Err("no edits to export".to_string());
+                }
+                let what = patch::export(&path, &sample.sha256, &sample.data,
+                    &ctx.patches).map_err(|err| err.to_string())?;
+                Ok(format!("{} written to {}", what, path.display()))
+            },
+            Command::PatchWrite => {
+                if self.read_only {
+                    return Err("samples are read-only".to_string());
+                }
+                let project_dir = self.project.as_ref()
+                    .and_then(|project| fs::canonicalize(&project.dir).ok());
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let (sample, data) = match (&ctx.sample, ctx.patches.data()) {
+                    (Some(sample), Some(data)) => (sample, data.clone()),
+                    (None, _) => return Err("no sample in this tab".into()),
+                    (_, None) => return Err("no edits to write".into()),
+                };
+                if project_dir.is_some_and(|dir| sample.path.starts_with(dir)) {
+                    return Err("samples of the project are stored by hash, \
+                        use patch-export to write a patched copy".into());
+                }
+                if !sample.path.is_file() {
+                    return Err("the sample is inside a container, use \
+                        patch-export to write a patched copy".into());
+                }
+                fs::write(&sample.path, &data)
+                    .map_err(|err| format!("{}: {}", sample.path.display(),
+                        err))?;
+                // The edits are now part of the sample
+                let sample = Sample::from_bytes(sample.path.clone(), data);
+                let msg = format!("{} written, sha256 {}", sample.name(),
+                    sample.sha256);
+                ctx.sample = Some(sample);
+                ctx.patches.clear();
+                if let Some(code) = ctx.raw {
+                    ctx.set_raw(Some(code));
+                }
+                Ok(msg)
+            },
+            Command::Template { name, offset } => {
+                let templates = Templates::load(
+                    self.config.templates.as_deref())
+                    .map_err(|err| err.to_string())?;
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                if ctx.sample.is_none() {
+                    return Err("no sample in this tab".to_string());
+                }
+                let offset = offset.unwrap_or(ctx.cursor);
+                let overlay = templates.apply(&name, ctx.data(), offset)?;
+                let msg = match &overlay.error {
+                    Some(err) => format!("{} decoded partially: {}", name, err),
+                    None => format!("{} decoded at {:#x}, {} bytes", name,
+                        offset, overlay.root.len),
+                };
+                ctx.overlay = Some(overlay);
+                Ok(msg)
+            },
+            Command::ClearTemplate => {
+                self.tabs.apps[self.tabs.index].ctx.overlay = None;
+                Ok("template cleared".to_string())
+            },
+            Command::Templates => {
+                let templates = Templates::load(
+                    self.config.templates.as_deref())
+                    .map_err(|err| err.to_string())?;
+                Ok(templates.names().join(", "))
+            },
+            Command::Goto(address) => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_ref().ok_or("no sample in this tab")?;
+                let image = sample.image.as_deref();
+                let offset = match (address, image) {
+                    (Address::Offset(offset), _) => offset,
+                    (_, None) => return Err(format!("{} has no virtual \
+                        addresses", defang::name(&sample.name()))),
+                    (_, Some(image)) => image.resolve(address)
+                        .ok_or("the address is not in the file")?,
+                };
+                if offset >= ctx.data().len() {
+                    return Err(format!("offset {:#x} is past the end of the \
+                        sample", offset));
+                }
+                ctx.cursor = offset;
+                ctx.anchor = None;
+                Ok(match image.and_then(|image| image.section_of(offset)
+                        .zip(image.va_of(offset))) {
+                    Some((section, va)) => format!("offset {:#x}, va {:#x} in {}",
+                        offset, va, section.name),
+                    None => format!("offset {:#x}", offset),
+                })
+            },
+            Command::Arch(None) => {
+                let ctx = &self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_ref().ok_or("no sample in this tab")?;
+                Ok(match (ctx.raw, sample.image.as_ref()
+                        .and_then(|image| image.arch)) {
+                    (Some(code), _) => format!("{:#x} bytes of {} at offset \
+                        {:#x} are {} code loaded at {:#x}", code.size,
+                        defang::name(&sample.name()), code.offset,
+                        code.arch.name(), code.base),
+                    (None, Some(arch)) => format!("{} is {} code",
+                        defang::name(&sample.name()), arch.name()),
+                    (None, None) => format!("cannot disassemble {}, choose \
+                        an architecture with arch <name>",
+                        defang::name(&sample.name())),
+                })
+            },
+            Command::Arch(Some(arch)) => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_mut().ok_or("no sample in this tab")?;
+                let message = format!("disassembling {} as {} code",
+                    defang::name(&sample.name()), arch.name());
+                match (ctx.raw, sample.image.as_deref()) {
+                    (Some(code), _) => ctx.set_raw(Some(RawCode { arch,
+                        ..code })),
+                    (None, Some(image)) => {
+                        let image = Image { arch: Some(arch), ..image.clone() };
+                        sample.image = Some(Arc::new(image));
+                        // Analyze the code again with the new instructions
+                        ctx.xrefs = XrefScan::default();
+                        ctx.functions = FunctionScan::default();
+                    },
+                    (None, None) => {
+                        let size = sample.data.len();
+                        ctx.set_raw(Some(RawCode { arch, base: 0, offset: 0,
+                            size }));
+                    },
+                }
+                Ok(message)
+            },
+            Command::Raw { arch, base } => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_ref().ok_or("no sample in this tab")?;
+                let (offset, size) = match ctx.anchor {
+                    Some(_) => {
+                        let (start, end) = ctx.selection();
+                        (start, end.min(sample.data.len()) - start)
+                    },
+                    None => (0, sample.data.len()),
+                };
+                if size == 0 {
+                    return Err(format!("{} is empty",
+                        defang::name(&sample.name())));
+                }
+                let code = RawCode { arch, base, offset, size };
+                code.check(sample.data.len())?;
+                let message = format!("disassembling {:#x} bytes of {} at \
+                    offset {:#x} as {} code loaded at {:#x}", size,
+                    defang::name(&sample.name()), offset, arch.name(), base);
+                ctx.set_raw(Some(code));
+                ctx.cursor = offset;
+                ctx.anchor = None;
+                Ok(message)
+            },
+            Command::ClearRaw => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let sample = ctx.sample.as_ref().ok_or("no sample in this tab")?;
+                if ctx.raw.is_none() {
+                    return Err(format!("{} is not disassembled as raw code",
+                        defang::name(&sample.name())));
+                }
+                let message = if sample.format.is_executable() {
+                    format!("{} laid out from its {} headers",
+                        defang::name(&sample.name()), sample.format)
+                } else {
+                    format!("{} is no longer disassembled",
+                        defang::name(&sample.name()))
+                };
+                ctx.set_raw(None);
+                Ok(message)
+            },
+            Command::Emulate => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let image = listing::code_image(ctx)?;
+                let (start, end) = match ctx.anchor {
+                    Some(_) => {
+                        let (start, end) = ctx.selection();
+                        let va = image.va_of(start)
+                            .filter(|&va| image.code_at(ctx.data(), va).is_some())
+                            .ok_or("the selection is not in an executable \
+                                section")?;
+                        (va, Some(va.saturating_add((end - start) as u64)))
+                    },
+                    None => (listing::cursor_va(image, ctx)?, None),
+                };
+                let format = ctx.sample.as_ref().map_or(Format::Raw,
+                    |sample| sample.format);
+                let machine = Machine::new(image, ctx.data(), format, start,
+                    end)?;
+                let message = match end {
+                    Some(end) => format!("emulating {} code from {:#x} to \
+                        {:#x}", machine.arch.name(), start, end),
+                    None => format!("emulating {} code from {:#x} until it \
+                        returns", machine.arch.name(), start),
+                };
+                ctx.emulation = Some(machine);
+                Ok(message)
+            },
+            Command::EmulateMemory(at) => {
+                let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+                let machine = ctx.emulation.as_mut()
+                    .ok_or("nothing emulated in this tab")?;
+                let addr = match machine.register(&at) {
+                    Some(value) => value,
+                    None => command::parse_address(&at)?,
+                };
+                machine.view = Some(addr);
+                Ok(format!("showing the emulated memory at {:#x}", addr))
+            },
+            Command::Quarantine => {
+                let dir = self.config.quarantine.as_ref()
+                    .ok_or("no quarantine directory, set quarantine in the \
+                        configuration or start maglab with --quarantine <dir>")?;
+                let sample = self.tabs.apps[self.tabs.index].ctx.sample
+                    .as_ref().ok_or("no sample in this tab")?;
+                let path = quarantine::store(dir, sample)
+                    .map_err(|err| format!("{}: {}", dir.display(), err))?;
+                Ok(format!("quarantined as {}", path.display()))
+            },
+            Command::OpenWith { program, force } => {
+                let sample = self.tabs.apps[self.tabs.index].ctx.sample
+                    .as_ref().ok_or("no sample in this tab")?;
+                if sample.format.is_executable() && !force {
+                    return Err(format!("{} is a {} executable, run open-with! \
+                        to open it anyway", defang::name(&sample.name()),
+                        sample.format));
+                }
+                if !sample.path.is_file() {
+                    return Err("the sample is inside a container, extract it \
+                        first".to_string());
+                }
+                // The program must not draw over the terminal we are using
+                process::Command::new(&program[0])
+                    .args(&program[1..])
+                    .arg(&sample.path)
+                    .stdin(process::Stdio::null())
+                    .stdout(process::Stdio::null())
+                    .stderr(process::Stdio::null())
+                    .spawn()
+                    .map_err(|err| format!("{}: {}", program[0], err))?;
+                Ok(format!("opened {} with {}", defang::name(&sample.name()),
+                    program[0]))
+            },
+            Command::Quit => {
                 self.should_quit = true;
+                Ok(String::new())
             },
-            _ => {}
+        }
+    }
+
+    /// Called when pressing any other key
+    pub fn on_key(&mut self, c: char) {
+        if c == 'q' {
+            self.should_quit = true;
         }
     }
 
     pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>) {
+        // Collect what background searches found since the last draw
+        for app in self.tabs.apps.iter_mut() {
+            app.ctx.search.poll();
+            app.ctx.yara.poll();
+            app.ctx.xrefs.poll();
+            app.ctx.functions.poll();
+            if let Some(diff) = app.ctx.diff.as_mut() {
+                diff.functions.poll();
+            }
+        }
+
+        // Bring back the annotations of samples opened outside of
+        // `open_sample`, e.g. from the command line or a session
+        for i in 0..self.tabs.apps.len() {
+            let mut ctx = std::mem::take(&mut self.tabs.apps[i].ctx);
+            let stale = ctx.sample.as_ref()
+                .is_some_and(|sample| sample.sha256 != ctx.notes.sha256);
+            if stale {
+                if let Err(err) = self.load_notes(&mut ctx) {
+                    self.status = Some(format!("error: {}", err));
+                }
+            }
+            self.tabs.apps[i].ctx = ctx;
+        }
+
+        // Scan the sample as soon as a Yara plugin shows up in the tab
+        let tab = &mut self.tabs.apps[self.tabs.index];
+        let shows_yara = tab.grid.columns.iter()
+            .flat_map(|col| col.plugins.iter())
+            .any(|plugin| plugin.kind() == PluginKind::Yara);
+        if let (true, true, Some(dir), Some(data)) = (shows_yara,
+                tab.ctx.yara.is_idle(), &self.config.yara_rules,
+                tab.ctx.contents()) {
+            tab.ctx.yara.start(dir.clone(), data);
+        }
+
+        // Index the cross-references of the samples as they are shown
+        let ctx = &mut self.tabs.apps[self.tabs.index].ctx;
+        if let (Some(sample), Some(data)) = (&ctx.sample, ctx.contents()) {
+            if ctx.xrefs.is_stale(sample, &data) {
+                ctx.xrefs.start(sample, data);
+            }
+        }
+
+        // Find the functions as soon as a plugin listing them shows up
+        let tab = &mut self.tabs.apps[self.tabs.index];
+        let shows_functions = tab.grid.columns.iter()
+            .flat_map(|col| col.plugins.iter())
+            .any(|plugin| matches!(plugin.kind(),
+                PluginKind::Functions | PluginKind::CallGraph));
+        if let (true, Some(sample), Some(data)) = (shows_functions,
+                &tab.ctx.sample, tab.ctx.contents()) {
+            if tab.ctx.functions.is_stale(sample, &data) {
+                tab.ctx.functions.start(sample, data);
+            }
+        }
+
+        // List the imports and exports as soon as a Symbols plugin shows up
+        // in the tab
+        let tab = &mut self.tabs.apps[self.tabs.index];
+        let shows_symbols = tab.grid.columns.iter()
+            .flat_map(|col| col.plugins.iter())
+            .any(|plugin| plugin.kind() == PluginKind::Symbols);
+        if let (true, Some(sample)) = (shows_symbols, &tab.ctx.sample) {
+            if sample.sha256 != tab.ctx.symbols.sha256 {
+                tab.ctx.symbols = match ApiCategories::load(
+                        self.config.api_categories.as_deref()) {
+                    Ok(categories) => SymbolTable::new(sample, &categories),
+                    Err(err) => SymbolTable { sha256: sample.sha256.clone(),
+                        entries: Err(err.to_string()) },
+                };
+            }
+        }
+
         // Overall app layout
         let layout_constraints = vec![
             // Tabs block, at least 3 lines
             Constraint::Length(3),
             // Rest of the screen
-            Constraint::Min(0)
+            Constraint::Min(0),
+            // Prompt or status line
+            Constraint::Length(1),
         ];
 
         let chunks = Layout::default()
-            .constraints(layout_constraints.as_ref())
+            .constraints(layout_constraints)
             .split(f.size());
 
         let titles = self
@@ -353,7 +1568,8 @@ impl<'a> MagLabApp<'a> {
             .apps
             .iter()
             .map(|t| Spans::from(
-                    Span::styled(t.title, Style::default().fg(Color::White))))
+                    Span::styled(defang::name(&t.title),
+                        Style::default().fg(Color::White))))
             .collect();
 
         let tabs = Tabs::new(titles)
@@ -362,5 +1578,24 @@ impl<'a> MagLabApp<'a> {
             .select(self.tabs.index);
         f.render_widget(tabs, chunks[0]);
         self.tabs.apps[self.tabs.index].draw(f, chunks[1]);
+        if let Some(popup) = self.xref_popup.as_mut() {
+            popup.draw(f, chunks[1]);
+        }
+
+        let line = match (&self.prompt, &self.status) {
+            (Some(prompt), _) => Spans::from(vec![
+                Span::styled(":", Style::default().fg(Color::Yellow)),
+                Span::raw(prompt.as_str()),
+            ]),
+            (None, Some(status)) => Spans::from(Span::raw(status.as_str())),
+            (None, None) => Spans::default(),
+        };
+        f.render_widget(Paragraph::new(line), chunks[2]);
+        if let Some(prompt) = &self.prompt {
+            // Show where we are typing
+            let x = chunks[2].x + 1 + prompt.chars().count() as u16;
+            f.set_cursor(x.min(chunks[2].right().saturating_sub(1)),
+                chunks[2].y);
+        }
     }
 }
@@ -0,0 +1,634 @@
+//! Containers the `FileManager` browses like directories: ZIP (and JAR or
+//! APK), tar, gzip, 7z, CAB, OLE compound files and ISO images.
+//!
+//! Files inside a container are addressed by the path of the container
+//! followed by their path inside it, e.g. `mail/invoice.zip/docs/a.exe`.
+//! Nested containers work the same way. Everything is read in memory,
+//! nothing is extracted to disk.
+use std::{
+    io::{self, Read, Cursor},
+    fs,
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use miniz_oxide::inflate::{TINFLStatus, core::{self as inflate,
+    DecompressorOxide,
+    inflate_flags::{TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF}}};
+use sevenz_rust::{SevenZReader, SevenZMethod, Password};
+
+/// Password tried on encrypted entries, the one malware repositories use
+pub const PASSWORD: &str = "infected";
+
+/// Size of a sector of an ISO image
+const ISO_SECTOR: usize = 2048;
+
+/// Largest file we decompress from a container, bigger ones are refused
+/// rather than filling the memory
+const MAX_MEMBER: u64 = 256 << 20;
+
+/// Kinds of containers we can look into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// ZIP, including JAR, APK and Office Open XML files
+    Zip,
+    Tar,
+    /// Gzip compressed tar
+    TarGz,
+    /// Gzip compressed file, seen as a container of one file
+    Gzip,
+    SevenZip,
+    /// Microsoft cabinet
+    Cab,
+    /// OLE compound file, e.g. legacy Office documents and MSI packages
+    Ole,
+    /// ISO 9660 image
+    Iso,
+}
+
+impl Kind {
+    /// Detect the kind of container in `data` based on its magic
+    pub fn detect(data: &[u8]) -> Option<Kind> {
+        match data {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Some(Kind::Zip),
+            [0x1f, 0x8b, ..] => Some(Kind::Gzip),
+            [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c, ..] => Some(Kind::SevenZip),
+            [b'M', b'S', b'C', b'F', ..] => Some(Kind::Cab),
+            [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1, ..] =>
+                Some(Kind::Ole),
+            _ if data.get(257..262) == Some(b"ustar") => Some(Kind::Tar),
+            _ if data.get(0x8001..0x8006) == Some(b"CD001") => Some(Kind::Iso),
+            _ => None,
+        }
+    }
+}
+
+/// A file or a directory inside a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path inside the container, components separated by `/`
+    pub name: String,
+    /// Size of the contents once extracted
+    pub size: u64,
+    pub dir: bool,
+    /// The contents are encrypted, `PASSWORD` is tried to read them
+    pub encrypted: bool,
+    /// Position of the entry in the listing of the container, `None` for
+    /// directories only implied by the paths of the files
+    index: Option<usize>,
+}
+
+impl Entry {
+    fn new(index: usize, name: String, size: u64, dir: bool) -> Entry {
+        Entry { name, size, dir, encrypted: false, index: Some(index) }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Little endian integer of `size` bytes at `at` in `data`
+fn le(data: &[u8], at: usize, size: usize) -> io::Result<usize> {
+    let bytes = slice(data, at, size)?;
+    Ok(bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as usize))
+}
+
+/// `len` bytes at `at` in `data`
+fn slice(data: &[u8], at: usize, len: usize) -> io::Result<&[u8]> {
+    at.checked_add(len).and_then(|end| data.get(at..end))
+        .ok_or_else(|| invalid("truncated"))
+}
+
+/// Components of a path inside a container. Empty components, `.` and
+/// `..` are dropped so that entries cannot point outside of it.
+fn components(name: &str) -> impl Iterator<Item = &str> {
+    name.split(['/', '\\'])
+        .filter(|part| !matches!(*part, "" | "." | ".."))
+}
+
+/// Path inside a container from a relative `path` on the host
+pub fn inner_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    components(&path).collect::<Vec<_>>().join("/")
+}
+
+/// Directory holding the entry `name`, empty at the top of the container
+fn parent(name: &str) -> &str {
+    name.rfind('/').map(|at| &name[..at]).unwrap_or("")
+}
+
+/// A container loaded in memory with the list of its entries
+pub struct Archive {
+    pub kind: Kind,
+    /// Contents of the container, decompressed for gzip
+    data: Vec<u8>,
+    /// Entries sorted by name
+    entries: Vec<Entry>,
+}
+
+impl Archive {
+    /// Read the container at `path`
+    pub fn open(path: &Path) -> io::Result<Archive> {
+        Archive::from_bytes(fs::read(path)?)
+    }
+
+    /// List the entries of the container in `data`
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<Archive> {
+        let kind = Kind::detect(&data)
+            .ok_or_else(|| invalid("not an archive"))?;
+        let (kind, data, entries) = match kind {
+            Kind::Gzip => {
+                let mut gz = flate2::read::GzDecoder::new(&data[..]);
+                let inflated = read_limited(&mut gz, "the compressed file")?;
+                if Kind::detect(&inflated) == Some(Kind::Tar) {
+                    let entries = list_tar(&inflated)?;
+                    (Kind::TarGz, inflated, entries)
+                } else {
+                    // The name stored in the header, if any
+                    let name = gz.header()
+                        .and_then(|header| header.filename())
+                        .map(|name| String::from_utf8_lossy(name).into_owned())
+                        .unwrap_or_else(|| "data".to_string());
+                    let size = inflated.len() as u64;
+                    (kind, inflated, vec![Entry::new(0, name, size, false)])
+                }
+            },
+            Kind::Zip => {
+                let entries = list_zip(&data)?;
+                (kind, data, entries)
+            },
+            Kind::Tar | Kind::TarGz => {
+                let entries = list_tar(&data)?;
+                (kind, data, entries)
+            },
+            Kind::SevenZip => {
+                let entries = list_7z(&data)?;
+                (kind, data, entries)
+            },
+            Kind::Cab => {
+                let entries = cab_files(&data)?.1.into_iter()
+                    .enumerate()
+                    .map(|(i, file)| Entry::new(i, file.name, file.size as u64,
+                        false))
+                    .collect();
+                (kind, data, entries)
+            },
+            Kind::Ole => {
+                let entries = list_ole(&data)?;
+                (kind, data, entries)
+            },
+            Kind::Iso => {
+                let entries = iso_files(&data)?.into_iter()
+                    .map(|(entry, _)| entry)
+                    .collect();
+                (kind, data, entries)
+            },
+        };
+
+        Ok(Archive { kind, data, entries: normalize(entries) })
+    }
+
+    /// Every entry of the container, sorted by name
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The entry called `name`, if any
+    pub fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.binary_search_by(|entry| entry.name.as_str().cmp(name))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    /// Entries directly in the directory `dir`, empty for the top of the
+    /// container
+    pub fn list<'b>(&'b self, dir: &'b str) -> impl Iterator<Item = &'b Entry> {
+        self.entries.iter().filter(move |entry| parent(&entry.name) == dir)
+    }
+
+    /// Extract the contents of the file `name`
+    pub fn read(&self, name: &str) -> io::Result<Vec<u8>> {
+        let entry = self.entry(name).ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound, format!("{} not found", name)))?;
+        let index = match (entry.dir, entry.index) {
+            (false, Some(index)) => index,
+            _ => return Err(io::Error::other(
+                format!("{} is a directory", name))),
+        };
+
+        match self.kind {
+            Kind::Zip => {
+                let mut zip = zip::ZipArchive::new(
+                    Cursor::new(&self.data[..]))?;
+                let file = if entry.encrypted {
+                    zip.by_index_decrypt(index, PASSWORD.as_bytes())
+                } else {
+                    zip.by_index(index)
+                };
+                let file = file.map_err(|err| match err {
+                    zip::result::ZipError::InvalidPassword => invalid(&format!(
+                        "{} is not encrypted with {}", name, PASSWORD)),
+                    err => err.into(),
+                })?;
+                read_limited(file, name)
+            },
+            Kind::Tar | Kind::TarGz => {
+                let mut tar = tar::Archive::new(&self.data[..]);
+                let mut file = tar.entries()?.nth(index)
+                    .ok_or_else(|| invalid("truncated"))??;
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                Ok(data)
+            },
+            Kind::Gzip => Ok(self.data.clone()),
+            Kind::SevenZip => read_7z(&self.data, index, name),
+            Kind::Cab => {
+                let (folders, files) = cab_files(&self.data)?;
+                let file = &files[index];
+                let folder = cab_folder(&self.data, &folders[file.folder])?;
+                Ok(slice(&folder, file.offset, file.size)?.to_vec())
+            },
+            Kind::Ole => {
+                let mut ole = cfb::CompoundFile::open(
+                    Cursor::new(&self.data[..]))?;
+                let path = ole.walk().nth(index)
+                    .map(|entry| entry.path().to_path_buf())
+                    .ok_or_else(|| invalid("truncated"))?;
+                let mut data = Vec::new();
+                ole.open_stream(path)?.read_to_end(&mut data)?;
+                Ok(data)
+            },
+            Kind::Iso => {
+                let (_, (start, len)) = iso_files(&self.data)?
+                    .swap_remove(index);
+                Ok(slice(&self.data, start, len)?.to_vec())
+            },
+        }
+    }
+}
+
+/// Decompress `reader` into memory, refusing to go past `MAX_MEMBER`
+fn read_limited(reader: impl Read, name: &str) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(MAX_MEMBER + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_MEMBER {
+        return Err(invalid(&format!("{} is larger than {} MiB", name,
+            MAX_MEMBER >> 20)));
+    }
+    Ok(data)
+}
+
+/// Clean up the names of `entries` listed by a container, add the
+/// directories only implied by their paths and sort them by name
+fn normalize(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut sorted: BTreeMap<String, Entry> = BTreeMap::new();
+    for mut entry in entries {
+        entry.name = inner_path(Path::new(&entry.name));
+        if entry.name.is_empty() {
+            continue;
+        }
+        let mut dir = parent(&entry.name);
+        while !dir.is_empty() && !sorted.contains_key(dir) {
+            sorted.insert(dir.to_string(), Entry {
+                name: dir.to_string(), size: 0, dir: true, encrypted: false,
+                index: None,
+            });
+            dir = parent(dir);
+        }
+        // A directory listed after its files replaces the implied one
+        sorted.insert(entry.name.clone(), entry);
+    }
+    sorted.into_values().collect()
+}
+
+fn list_zip(data: &[u8]) -> io::Result<Vec<Entry>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut entries = Vec::new();
+    for index in 0..zip.len() {
+        // Raw access does not need the password
+        let file = zip.by_index_raw(index)?;
+        let mut entry = Entry::new(index, file.name().to_string(), file.size(),
+            file.is_dir());
+        entry.encrypted = file.encrypted();
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn list_tar(data: &[u8]) -> io::Result<Vec<Entry>> {
+    let mut tar = tar::Archive::new(data);
+    let mut entries = Vec::new();
+    for (index, file) in tar.entries()?.enumerate() {
+        let file = file?;
+        let kind = file.header().entry_type();
+        // Links and devices have nothing to show
+        if kind.is_file() || kind.is_dir() {
+            let name = file.path()?.to_string_lossy().into_owned();
+            entries.push(Entry::new(index, name, file.size(), kind.is_dir()));
+        }
+    }
+    Ok(entries)
+}
+
+fn sevenz_error(err: sevenz_rust::Error) -> io::Error {
+    invalid(&err.to_string())
+}
+
+fn list_7z(data: &[u8]) -> io::Result<Vec<Entry>> {
+    let reader = SevenZReader::new(Cursor::new(data), data.len() as u64,
+        Password::from(PASSWORD)).map_err(sevenz_error)?;
+    let archive = reader.archive();
+    Ok(archive.files.iter().enumerate().map(|(index, file)| {
+        let mut entry = Entry::new(index, file.name().to_string(), file.size(),
+            file.is_directory());
+        entry.encrypted = archive.stream_map.file_folder_index[index]
+            .is_some_and(|folder| archive.folders[folder].coders.iter()
+                .any(|coder| coder.decompression_method_id()
+                    == SevenZMethod::ID_AES256SHA256));
+        entry
+    }).collect())
+}
+
+fn read_7z(data: &[u8], index: usize, name: &str) -> io::Result<Vec<u8>> {
+    let mut reader = SevenZReader::new(Cursor::new(data), data.len() as u64,
+        Password::from(PASSWORD)).map_err(sevenz_error)?;
+    let stored = reader.archive().files[index].name().to_string();
+    let mut contents = None;
+    let mut skipped = 0;
+    reader.for_each_entries(|file, stream| {
+        if file.name() != stored {
+            // Solid archives decompress every file before the one we want,
+            // which counts against the same limit
+            skipped += io::copy(&mut stream.take(MAX_MEMBER + 1 - skipped),
+                &mut io::sink())?;
+            if skipped > MAX_MEMBER {
+                contents = Some(Err(invalid(&format!("the files before {} \
+                    are larger than {} MiB", name, MAX_MEMBER >> 20))));
+                return Ok(false);
+            }
+            return Ok(true);
+        }
+        contents = Some(read_limited(stream, name));
+        Ok(false)
+    }).map_err(sevenz_error)?;
+    contents.ok_or_else(|| invalid("truncated"))?
+}
+
+fn list_ole(data: &[u8]) -> io::Result<Vec<Entry>> {
+    let ole = cfb::CompoundFile::open(Cursor::new(data))?;
+    Ok(ole.walk().enumerate()
+        .filter(|(_, entry)| !entry.is_root())
+        .map(|(index, entry)| {
+            // Streams like `\x05SummaryInformation` start with a control
+            // character, show it as `[5]SummaryInformation`
+            let name = entry.path().to_string_lossy().chars()
+                .map(|c| if c.is_control() { format!("[{}]", c as u32) }
+                         else { c.to_string() })
+                .collect();
+            Entry::new(index, name, entry.len(), entry.is_storage())
+        })
+        .collect())
+}
+
+/// A folder of a cabinet: a run of data blocks compressed together
+struct CabFolder {
+    /// Offset of the first data block
+    start: usize,
+    blocks: usize,
+    compression: usize,
+    /// Size of the reserved area in each data block
+    reserve: usize,
+}
+
+/// A file of a cabinet, stored at `offset` in the decompressed `folder`
+struct CabFile {
+    name: String,
+    size: usize,
+    folder: usize,
+    offset: usize,
+}
+
+/// Parse the folders and files of the cabinet in `data`
+fn cab_files(data: &[u8]) -> io::Result<(Vec<CabFolder>, Vec<CabFile>)> {
+    let nfolders = le(data, 26, 2)?;
+    let nfiles = le(data, 28, 2)?;
+    let flags = le(data, 30, 2)?;
+
+    // Optional reserved areas, then the names of the previous and next
+    // cabinets of a set
+    let mut at = 36;
+    let (folder_reserve, data_reserve) = if flags & 4 != 0 {
+        let header_reserve = le(data, 36, 2)?;
+        at = 40 + header_reserve;
+        (le(data, 38, 1)?, le(data, 39, 1)?)
+    } else {
+        (0, 0)
+    };
+    let strings = (flags & 1 != 0) as usize * 2 + (flags & 2 != 0) as usize * 2;
+    for _ in 0..strings {
+        let len = data.get(at..).and_then(|rest| rest.iter()
+            .position(|&b| b == 0)).ok_or_else(|| invalid("truncated"))?;
+        at += len + 1;
+    }
+
+    let mut folders = Vec::new();
+    for _ in 0..nfolders {
+        folders.push(CabFolder {
+            start: le(data, at, 4)?,
+            blocks: le(data, at + 4, 2)?,
+            compression: le(data, at + 6, 2)?,
+            reserve: data_reserve,
+        });
+        at += 8 + folder_reserve;
+    }
+
+    let mut files = Vec::new();
+    at = le(data, 16, 4)?;
+    for _ in 0..nfiles {
+        let size = le(data, at, 4)?;
+        let offset = le(data, at + 4, 4)?;
+        let folder = le(data, at + 8, 2)?;
+        let name = data.get(at + 16..).and_then(|rest| rest.iter()
+            .position(|&b| b == 0).map(|len| &rest[..len]))
+            .ok_or_else(|| invalid("truncated"))?;
+        at += 16 + name.len() + 1;
+        // Files continued from or to another cabinet of a set are not
+        // entirely in this one
+        if folder < folders.len() {
+            let name = String::from_utf8_lossy(name).into_owned();
+            files.push(CabFile { name, size, folder, offset });
+        }
+    }
+    Ok((folders, files))
+}
+
+/// Decompress the data blocks of `folder`
+fn cab_folder(data: &[u8], folder: &CabFolder) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut at = folder.start;
+    for _ in 0..folder.blocks {
+        let compressed = le(data, at + 4, 2)?;
+        let size = le(data, at + 6, 2)?;
+        let block = slice(data, at + 8 + folder.reserve, compressed)?;
+        at += 8 + folder.reserve + compressed;
+
+        match folder.compression & 0xf {
+            0 => out.extend_from_slice(block),
+            1 => {
+                // MSZIP: a deflate stream per block, which can refer to
+                // the output of the previous block
+                let block = block.strip_prefix(b"CK")
+                    .ok_or_else(|| invalid("bad MSZIP block"))?;
+                let start = out.len();
+                if (start + size) as u64 > MAX_MEMBER {
+                    return Err(invalid(&format!("folder is larger than {} \
+                        MiB", MAX_MEMBER >> 20)));
+                }
+                out.resize(start + size, 0);
+                let (status, _, written) = inflate::decompress(
+                    &mut DecompressorOxide::new(), block, &mut out, start,
+                    TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF);
+                if status != TINFLStatus::Done || written != size {
+                    return Err(invalid("bad MSZIP block"));
+                }
+            },
+            2 => return Err(invalid("Quantum compression is not supported")),
+            _ => return Err(invalid("LZX compression is not supported")),
+        }
+    }
+    Ok(out)
+}
+
+/// Entries of the ISO image in `data`, with the offset and size of their
+/// contents
+fn iso_files(data: &[u8]) -> io::Result<Vec<(Entry, (usize, usize))>> {
+    // Root directory record of the primary volume, or of the Joliet one
+    // which keeps long names
+    let mut root = None;
+    let mut joliet = false;
+    for sector in 16.. {
+        let desc = slice(data, sector * ISO_SECTOR, ISO_SECTOR)?;
+        if &desc[1..6] != b"CD001" {
+            return Err(invalid("bad volume descriptor"));
+        }
+        match desc[0] {
+            1 if !joliet => root = Some(&desc[156..190]),
+            2 if matches!(&desc[88..91], b"%/@" | b"%/C" | b"%/E") => {
+                root = Some(&desc[156..190]);
+                joliet = true;
+            },
+            255 => break,
+            _ => {},
+        }
+    }
+    let root = root.ok_or_else(|| invalid("no primary volume descriptor"))?;
+
+    let mut files = Vec::new();
+    // Directories to list with their path and depth, which bounds loops
+    let mut dirs = vec![(String::new(), le(root, 2, 4)?, le(root, 10, 4)?, 0)];
+    // Extents of the directories listed, crafted images point several
+    // records to the same one
+    let mut listed = HashSet::new();
+    while let Some((path, extent, len, depth)) = dirs.pop() {
+        if !listed.insert(extent) {
+            continue;
+        }
+        let records = slice(data, extent * ISO_SECTOR, len)?;
+        let mut at = 0;
+        while at < records.len() {
+            let record_len = records[at] as usize;
+            if record_len == 0 {
+                // Records do not cross sectors, the rest is padding
+                at = (at / ISO_SECTOR + 1) * ISO_SECTOR;
+                continue;
+            }
+            let record = slice(records, at, record_len)?;
+            at += record_len;
+
+            let name_len = le(record, 32, 1)?;
+            let name = slice(record, 33, name_len)?;
+            // `.` and `..`
+            if name == [0] || name == [1] {
+                continue;
+            }
+            let name = if joliet {
+                let units: Vec<u16> = name.chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            } else {
+                String::from_utf8_lossy(name).into_owned()
+            };
+            // Drop the version, `README.TXT;1`, and the dot of names
+            // without extension
+            let name = name.split(';').next().unwrap_or("")
+                .trim_end_matches('.');
+            let name = if path.is_empty() { name.to_string() }
+                       else { format!("{}/{}", path, name) };
+
+            let extent = le(record, 2, 4)?;
+            let len = le(record, 10, 4)?;
+            let dir = record[25] & 2 != 0;
+            if dir && depth < 32 {
+                dirs.push((name.clone(), extent, len, depth + 1));
+            }
+            let size = if dir { 0 } else { len as u64 };
+            let entry = Entry::new(files.len(), name, size, dir);
+            files.push((entry, (extent * ISO_SECTOR, len)));
+        }
+    }
+    Ok(files)
+}
+
+/// Find the container `path` goes into. Returns the path of the innermost
+/// container, the container and the directory inside it, empty for its
+/// top.
+pub fn locate(path: &Path) -> io::Result<(PathBuf, Archive, String)> {
+    let file = path.ancestors().find(|path| path.is_file())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+            format!("{}: no such file or directory", path.display())))?;
+    let mut root = fs::canonicalize(file)?;
+    let mut archive = Archive::open(&root)?;
+    let mut dir = String::new();
+
+    let rest = path.strip_prefix(file).unwrap_or(Path::new(""));
+    for part in components(&rest.to_string_lossy()) {
+        let name = if dir.is_empty() { part.to_string() }
+                   else { format!("{}/{}", dir, part) };
+        match archive.entry(&name) {
+            Some(entry) if entry.dir => dir = name,
+            // Going into a container inside the container
+            Some(_) => {
+                archive = Archive::from_bytes(archive.read(&name)?)?;
+                root = root.join(&name);
+                dir.clear();
+            },
+            None => return Err(io::Error::new(io::ErrorKind::NotFound,
+                format!("{} not found in {}", name, root.display()))),
+        }
+    }
+    Ok((root, archive, dir))
+}
+
+/// Extract the file at `path` from the container it is in. Returns its
+/// absolute path and its contents.
+pub fn read(path: &Path) -> io::Result<(PathBuf, Vec<u8>)> {
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+            format!("{}: no such file", path.display())))?
+        .to_string_lossy().into_owned();
+    let (root, archive, dir) = locate(path.parent().unwrap_or(Path::new("")))?;
+    let name = if dir.is_empty() { name } else { format!("{}/{}", dir, name) };
+    let data = archive.read(&name)?;
+    Ok((root.join(name), data))
+}
+
+/// Whether the file at `path` on disk is a container
+pub fn is_container(path: &Path) -> bool {
+    // The magic of ISO images is the furthest from the start
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| file.take(0x8006).read_to_end(&mut head))
+        .is_ok_and(|_| Kind::detect(&head).is_some())
+}
@@ -0,0 +1,711 @@
+//! Control flow graph of the function under the tab's cursor, drawn with
+//! box drawing characters. Blocks are put in layers going down from the
+//! entry, the edges to the next layer go through the rows between the
+//! layers and the others around the graph, on its right.
+use std::{
+    collections::{HashMap},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::analysis::{self, Function};
+use crate::annotations::{Annotations, Note};
+use crate::app::{RenderPlugin, TabContext};
+use crate::disasm::{Flow};
+use crate::image::{Image};
+use crate::listing::{code_image, cursor_va, insn_notes};
+
+/// Columns of the widest block, borders included
+const MAX_WIDTH: usize = 40;
+/// Rows between two layers: one for the edges to go sideways, one for the
+/// arrows
+const GAP_Y: usize = 2;
+/// Columns between two blocks of a layer
+const GAP_X: usize = 3;
+/// Columns the view moves by with `h`/`l`, rows with `j`/`k`
+const PAN_X: usize = 4;
+const PAN_Y: usize = 2;
+
+/// Directions a line leaves a cell in
+const UP: u8 = 1;
+const DOWN: u8 = 2;
+const LEFT: u8 = 4;
+const RIGHT: u8 = 8;
+
+/// How much of each block is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zoom {
+    /// All the instructions
+    Full,
+    /// The instruction deciding where the execution goes next
+    Compact,
+    /// The address only
+    Overview,
+}
+
+impl Zoom {
+    fn zoom_in(self) -> Zoom {
+        match self {
+            Zoom::Full | Zoom::Compact => Zoom::Full,
+            Zoom::Overview => Zoom::Compact,
+        }
+    }
+
+    fn zoom_out(self) -> Zoom {
+        match self {
+            Zoom::Full => Zoom::Compact,
+            Zoom::Compact | Zoom::Overview => Zoom::Overview,
+        }
+    }
+}
+
+/// A block placed in the graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    /// Address of the block, in the title
+    pub address: u64,
+    pub layer: usize,
+    /// Column and row of the top left corner
+    pub x: usize,
+    pub y: usize,
+    /// Size, borders included
+    pub width: usize,
+    pub height: usize,
+    /// Text drawn in the block, one instruction per line with the labels
+    /// of the user above them
+    pub lines: Vec<String>,
+    /// Line of each instruction in `lines`, when they are all drawn
+    pub rows: Vec<usize>,
+}
+
+/// An edge between two blocks, from the bottom of one to the top of the
+/// other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// Corners of the path, starting on the bottom border of the block
+    /// left and ending above the block entered
+    pub points: Vec<(usize, usize)>,
+    pub color: Color,
+}
+
+/// The blocks of a function laid out in layers, and the edges between them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graph {
+    /// Nodes in the order of the blocks of the function
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Graph {
+    /// Lay out the blocks of `function`, showing as much of them as `zoom`
+    /// says along with the `notes` of the user on the bytes of `image`
+    pub fn new(function: &Function, zoom: Zoom, image: &Image,
+            notes: &Annotations) -> Graph {
+        let blocks = &function.blocks;
+        let index: HashMap<u64, usize> = blocks.iter().enumerate()
+            .map(|(i, block)| (block.start, i))
+            .collect();
+
+        // Taken branches are green, the fall through of branches red and
+        // the rest blue
+        let mut edges = Vec::new();
+        for (from, block) in blocks.iter().enumerate() {
+            let branch = matches!(block.exit().flow, Flow::Branch(_));
+            for (i, succ) in block.succs.iter().enumerate() {
+                let color = match (branch, i) {
+                    (false, _) => Color::Blue,
+                    (true, 0) => Color::Green,
+                    (true, _) => Color::Red,
+                };
+                edges.push(Edge { from, to: index[succ], points: Vec::new(),
+                    color });
+            }
+        }
+        // Edges leaving and entering each block
+        let mut out = vec![Vec::new(); blocks.len()];
+        let mut into = vec![Vec::new(); blocks.len()];
+        for (i, edge) in edges.iter().enumerate() {
+            out[edge.from].push(i);
+            into[edge.to].push(i);
+        }
+
+        // Edges going back to a block on the current path are loops, the
+        // others go down: each block is a layer below its lowest
+        // predecessor
+        let order = Graph::postorder(&out, &edges);
+        let mut rank = vec![0; blocks.len()];
+        for (i, &node) in order.iter().enumerate() {
+            rank[node] = i;
+        }
+        let down = |edge: &Edge| rank[edge.from] > rank[edge.to];
+        let mut layer = vec![0; blocks.len()];
+        for &node in order.iter().rev() {
+            for edge in out[node].iter().map(|&i| &edges[i])
+                    .filter(|edge| down(edge)) {
+                layer[edge.to] = layer[edge.to].max(layer[node] + 1);
+            }
+        }
+
+        // Blocks of a layer from left to right by the position of their
+        // predecessors in the layer above
+        let count = layer.iter().max().map_or(0, |&last| last + 1);
+        let mut layers: Vec<Vec<usize>> = vec![Vec::new(); count];
+        for &node in order.iter().rev() {
+            layers[layer[node]].push(node);
+        }
+        let mut position = vec![0.0; blocks.len()];
+        for nodes in layers.iter_mut() {
+            let key = |node: usize| {
+                let above: Vec<f64> = into[node].iter().map(|&i| &edges[i])
+                    .filter(|edge| down(edge)
+                        && layer[edge.from] + 1 == layer[node])
+                    .map(|edge| position[edge.from])
+                    .collect();
+                if above.is_empty() {
+                    f64::MAX
+                } else {
+                    above.iter().sum::<f64>() / above.len() as f64
+                }
+            };
+            let mut keyed: Vec<(f64, usize)> = nodes.iter()
+                .map(|&node| (key(node), node))
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            *nodes = keyed.into_iter().map(|(_, node)| node).collect();
+            for (i, &node) in nodes.iter().enumerate() {
+                position[node] = i as f64;
+            }
+        }
+
+        let mut nodes: Vec<Node> = blocks.iter().enumerate()
+            .map(|(i, block)| {
+                let text = |insn| {
+                    let (_, comments) = insn_notes(image, notes, insn);
+                    std::iter::once(insn.text.clone())
+                        .chain(comments.iter().map(|c| format!("; {}", c)))
+                        .collect::<Vec<_>>()
+                        .join("  ")
+                };
+                let (mut lines, mut rows) = (Vec::new(), Vec::new());
+                match zoom {
+                    Zoom::Full => for insn in block.insns.iter() {
+                        if let Some(label) = insn_notes(image, notes, insn).0 {
+                            lines.push(format!("{}:", label));
+                        }
+                        rows.push(lines.len());
+                        lines.push(text(insn));
+                    },
+                    Zoom::Compact => lines.push(text(block.exit())),
+                    Zoom::Overview => {},
+                }
+                let title = format!("{:x}", block.start).len() + 4;
+                let width = lines.iter()
+                    .map(|line| line.chars().count() + 2)
+                    .max().unwrap_or(0)
+                    .max(title).min(MAX_WIDTH);
+                Node { address: block.start, layer: layer[i], x: 0, y: 0,
+                    width, height: lines.len() + 2, lines, rows }
+            })
+            .collect();
+
+        // Layers centered under each other, with room above the first one
+        // for the loops going back to it
+        let widths: Vec<usize> = layers.iter()
+            .map(|layer| layer.iter().map(|&node| nodes[node].width)
+                .sum::<usize>() + GAP_X * layer.len().saturating_sub(1))
+            .collect();
+        let heights: Vec<usize> = layers.iter()
+            .map(|layer| layer.iter().map(|&node| nodes[node].height)
+                .max().unwrap_or(0))
+            .collect();
+        let width = widths.iter().copied().max().unwrap_or(0);
+        let mut y = if edges.iter().any(|edge| layer[edge.to] == 0) {
+            GAP_Y
+        } else {
+            0
+        };
+        let mut tops = Vec::with_capacity(count);
+        for (l, layer) in layers.iter().enumerate() {
+            let mut x = (width - widths[l]) / 2;
+            for &node in layer.iter() {
+                nodes[node].x = x;
+                nodes[node].y = y;
+                x += nodes[node].width + GAP_X;
+            }
+            tops.push(y);
+            y += heights[l] + GAP_Y;
+        }
+
+        // Edges to the next layer go sideways in the row under the layer
+        // they leave. The others go there to a lane of their own on the
+        // right, then through the lane to the row above the layer they
+        // enter.
+        let next = |edge: &Edge| down(edge)
+            && layer[edge.to] == layer[edge.from] + 1;
+        let center = |node: usize| nodes[node].x + nodes[node].width / 2;
+        let mut lanes = 0;
+        let lane: Vec<usize> = edges.iter()
+            .map(|edge| if next(edge) {
+                0
+            } else {
+                lanes += 1;
+                width + 2 * lanes - 1
+            })
+            .collect();
+        // Where each edge leaves and enters its blocks, spread over their
+        // borders in the order of the other ends
+        let spread = |node: &Node, k: usize, n: usize| {
+            node.x + 1 + (k + 1) * (node.width - 2) / (n + 1)
+        };
+        let mut exits = vec![0; edges.len()];
+        let mut entries = vec![0; edges.len()];
+        for node in 0..nodes.len() {
+            let mut out = out[node].clone();
+            out.sort_by_key(|&i| if next(&edges[i]) {
+                center(edges[i].to)
+            } else {
+                usize::MAX
+            });
+            for (k, &i) in out.iter().enumerate() {
+                exits[i] = spread(&nodes[node], k, out.len());
+            }
+            let mut into = into[node].clone();
+            into.sort_by_key(|&i| if next(&edges[i]) {
+                center(edges[i].from)
+            } else {
+                lane[i]
+            });
+            for (k, &i) in into.iter().enumerate() {
+                entries[i] = spread(&nodes[node], k, into.len());
+            }
+        }
+        for (i, edge) in edges.iter_mut().enumerate() {
+            let (from, to) = (&nodes[edge.from], &nodes[edge.to]);
+            let below = tops[from.layer] + heights[from.layer];
+            let above = to.y - 1;
+            let (exit, entry) = (exits[i], entries[i]);
+            edge.points = if next(edge) {
+                vec![(exit, from.y + from.height - 1), (exit, below),
+                    (entry, below), (entry, above)]
+            } else {
+                let lane = lane[i];
+                vec![(exit, from.y + from.height - 1), (exit, below),
+                    (lane, below), (lane, above - 1), (entry, above - 1),
+                    (entry, above)]
+            };
+        }
+
+        let height = nodes.iter().map(|node| node.y + node.height)
+            .chain(edges.iter().flat_map(|edge| edge.points.iter()
+                .map(|&(_, y)| y + 1)))
+            .max().unwrap_or(0);
+        let width = width + 2 * lanes;
+        Graph { nodes, edges, width, height }
+    }
+
+    /// Nodes in the order a depth first walk from the entry leaves them,
+    /// followed by the nodes it does not reach. `out` holds the edges
+    /// leaving each node.
+    fn postorder(out: &[Vec<usize>], edges: &[Edge]) -> Vec<usize> {
+        let mut seen = vec![false; out.len()];
+        let mut order = Vec::with_capacity(out.len());
+        for root in 0..out.len() {
+            if seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            let mut stack = vec![(root, 0)];
+            while let Some((node, next)) = stack.last_mut() {
+                let succ = out[*node].get(*next).map(|&i| edges[i].to);
+                *next += 1;
+                match succ {
+                    Some(succ) if !seen[succ] => {
+                        seen[succ] = true;
+                        stack.push((succ, 0));
+                    },
+                    Some(_) => {},
+                    None => {
+                        order.push(*node);
+                        stack.pop();
+                    },
+                }
+            }
+        }
+        order
+    }
+
+    /// Characters and styles of the part of the graph in `view`, a row of
+    /// spans per line. The borders of the node `selected` are highlighted,
+    /// and the line `cursor` of it.
+    fn render(&self, view: Rect, selected: Option<usize>,
+            cursor: Option<usize>) -> Vec<Spans<'static>> {
+        let (left, top) = (view.x as usize, view.y as usize);
+        let (width, height) = (view.width as usize, view.height as usize);
+        let mut cells = vec![(' ', Style::default()); width * height];
+        let mut lines = vec![0u8; width * height];
+        let cell = |x: usize, y: usize| (x >= left && x < left + width
+            && y >= top && y < top + height)
+            .then(|| (y - top) * width + x - left);
+
+        // Edges first, the blocks are drawn over their ends
+        for edge in self.edges.iter() {
+            let style = Style::default().fg(edge.color);
+            for segment in edge.points.windows(2) {
+                let ((x0, y0), (x1, y1)) = (segment[0], segment[1]);
+                let ((x0, x1), (y0, y1)) = ((x0.min(x1), x0.max(x1)),
+                    (y0.min(y1), y0.max(y1)));
+                for y in y0.max(top.saturating_sub(1))..=y1.min(top + height) {
+                    for x in x0.max(left.saturating_sub(1))
+                            ..=x1.min(left + width) {
+                        if let Some(i) = cell(x, y) {
+                            lines[i] |= if y0 == y1 {
+                                (if x > x0 { LEFT } else { 0 })
+                                    | (if x < x1 { RIGHT } else { 0 })
+                            } else {
+                                (if y > y0 { UP } else { 0 })
+                                    | (if y < y1 { DOWN } else { 0 })
+                            };
+                            cells[i].1 = style;
+                        }
+                    }
+                }
+            }
+        }
+        for (i, &line) in lines.iter().enumerate() {
+            if line != 0 {
+                cells[i].0 = line_char(line);
+            }
+        }
+
+        for (n, node) in self.nodes.iter().enumerate() {
+            if node.x >= left + width || node.x + node.width <= left
+                    || node.y >= top + height || node.y + node.height <= top {
+                continue;
+            }
+            let border = if selected == Some(n) {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let title = format!("{:x}", node.address);
+            let (right, bottom) = (node.x + node.width - 1,
+                node.y + node.height - 1);
+            for y in node.y..=bottom {
+                for x in node.x..=right {
+                    let i = match cell(x, y) {
+                        Some(i) => i,
+                        None => continue,
+                    };
+                    let (dx, dy) = (x - node.x, y - node.y);
+                    cells[i] = match (x == node.x, x == right, y == node.y,
+                            y == bottom) {
+                        (true, _, true, _) => ('┌', border),
+                        (_, true, true, _) => ('┐', border),
+                        (true, _, _, true) => ('└', border),
+                        (_, true, _, true) => ('┘', border),
+                        (_, _, true, _) | (_, _, _, true) => {
+                            match title.chars().nth(dx - 1) {
+                                Some(c) if y == node.y => (c, border
+                                    .fg(Color::Blue)),
+                                _ => ('─', border),
+                            }
+                        },
+                        (true, _, _, _) | (_, true, _, _) => ('│', border),
+                        _ => {
+                            let text = &node.lines[dy - 1];
+                            let c = text.chars().nth(dx - 1).unwrap_or(' ');
+                            let style = if selected == Some(n)
+                                    && cursor == Some(dy - 1) {
+                                Style::default()
+                                    .add_modifier(Modifier::REVERSED)
+                            } else {
+                                Style::default()
+                            };
+                            // Lines too long for the block end with a `…`
+                            if dx == node.width - 2
+                                    && text.chars().count() > node.width - 2 {
+                                ('…', style)
+                            } else {
+                                (c, style)
+                            }
+                        },
+                    };
+                }
+            }
+        }
+
+        // Where the edges leave and enter the blocks
+        for edge in self.edges.iter() {
+            let style = Style::default().fg(edge.color);
+            let (first, last) = (edge.points[0], edge.points[edge.points.len() - 1]);
+            if let Some(i) = cell(first.0, first.1) {
+                cells[i] = ('┬', style);
+            }
+            if let Some(i) = cell(last.0, last.1) {
+                cells[i] = ('▼', style);
+            }
+        }
+
+        cells.chunks(width.max(1))
+            .map(|row| {
+                let mut spans: Vec<Span> = Vec::new();
+                let mut text = String::new();
+                let mut style = row[0].1;
+                for &(c, cell_style) in row.iter() {
+                    if cell_style != style {
+                        spans.push(Span::styled(std::mem::take(&mut text),
+                            style));
+                        style = cell_style;
+                    }
+                    text.push(c);
+                }
+                spans.push(Span::styled(text, style));
+                Spans::from(spans)
+            })
+            .collect()
+    }
+
+}
+
+/// Box drawing character joining the directions of `line`
+fn line_char(line: u8) -> char {
+    match line {
+        l if l == UP | DOWN | LEFT | RIGHT => '┼',
+        l if l == UP | DOWN | RIGHT => '├',
+        l if l == UP | DOWN | LEFT => '┤',
+        l if l == DOWN | LEFT | RIGHT => '┬',
+        l if l == UP | LEFT | RIGHT => '┴',
+        l if l == DOWN | RIGHT => '┌',
+        l if l == DOWN | LEFT => '┐',
+        l if l == UP | RIGHT => '└',
+        l if l == UP | LEFT => '┘',
+        l if l & (UP | DOWN) != 0 => '│',
+        _ => '─',
+    }
+}
+
+/// Plugin drawing the control flow graph of the function holding the
+/// cursor of the tab. The block holding the cursor is selected, so the
+/// graph follows the other plugins and the other plugins follow the
+/// blocks selected in the graph.
+pub struct CfgView {
+    title: String,
+    /// Function shown, with the SHA-256 of its sample and whether the
+    /// cross-references and the functions were indexed when it was found
+    function: Option<(String, (bool, bool), Function)>,
+    /// Layout of the function at the current zoom
+    graph: Option<Graph>,
+    zoom: Zoom,
+    /// Column and row of the graph at the top left of the view
+    pan: (usize, usize),
+    /// Block holding the cursor
+    selected: Option<usize>,
+    /// Block the view last moved to
+    shown: Option<usize>,
+    /// Size of the view in the last draw
+    view: (usize, usize),
+    /// Annotations of the sample drawn in the graph
+    notes: Vec<Note>,
+}
+
+impl Default for CfgView {
+    fn default() -> Self {
+        CfgView { title: "CFG".to_string(), function: None, graph: None,
+            zoom: Zoom::Full, pan: (0, 0), selected: None, shown: None,
+            view: (1, 1), notes: Vec::new() }
+    }
+}
+
+impl CfgView {
+    /// Move the view to the node `n` if it is not in it, or always with
+    /// `center`
+    fn scroll_to(&mut self, n: usize, center: bool) {
+        let node = match &self.graph {
+            Some(graph) => &graph.nodes[n],
+            None => return,
+        };
+        let (width, height) = self.view;
+        let (left, top) = self.pan;
+        let visible = node.x >= left && node.x + node.width <= left + width
+            && node.y >= top && node.y + node.height <= top + height;
+        if center || !visible {
+            self.pan = ((node.x + node.width / 2).saturating_sub(width / 2),
+                (node.y + node.height / 2).saturating_sub(height / 2)
+                    .min(node.y.saturating_sub(1)));
+        }
+    }
+
+    /// Find the function holding `va` unless it is the one shown
+    fn update(&mut self, ctx: &TabContext, va: u64) {
+        // Checked by the caller
+        let sample = ctx.sample.as_ref().unwrap();
+        let image = sample.image.as_deref().unwrap();
+        let indexed = (ctx.xrefs.index().is_ok(),
+            ctx.functions.index().is_ok());
+        let current = self.function.as_ref()
+            .is_some_and(|(sha256, was_indexed, function)|
+                *sha256 == sample.sha256 && *was_indexed == indexed
+                    && function.block_at(va).is_some());
+        if current {
+            return;
+        }
+
+        let mut starts: Vec<u64> = image.symbols.iter()
+            .map(|symbol| symbol.va)
+            .chain(image.exports.iter().map(|export| export.va))
+            .chain(image.entry)
+            .collect();
+        if let Ok(index) = ctx.xrefs.index() {
+            starts.extend(index.calls());
+        }
+        if let Ok(index) = ctx.functions.index() {
+            starts.extend(index.functions.iter().map(|f| f.address));
+        }
+        self.function = analysis::function_at(image, ctx.data(), &starts, va)
+            .map(|function| (sample.sha256.clone(), indexed, function));
+        self.graph = None;
+        self.pan = (0, 0);
+    }
+}
+
+impl RenderPlugin for CfgView {
+    fn get_name(&self) -> &str {
+        &self.title
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let va = code_image(ctx).and_then(|image| cursor_va(image, ctx));
+        if let Ok(va) = va {
+            self.update(ctx, va);
+        }
+        let (va, function) = match (va, &self.function) {
+            (Ok(va), Some((_, _, function))) => (va, function),
+            (Err(err), _) => {
+                self.title = "CFG".to_string();
+                f.render_widget(Paragraph::new(err), area);
+                return;
+            },
+            (Ok(va), None) => {
+                self.title = "CFG".to_string();
+                f.render_widget(Paragraph::new(format!("no code at {:#x}",
+                    va)), area);
+                return;
+            },
+        };
+        self.title = format!("CFG {}", function.name);
+        self.view = (area.width as usize, area.height as usize);
+        // Drawn again when the user annotates the code
+        let notes = ctx.notes.notes();
+        if self.graph.is_none() || notes != self.notes {
+            // Checked by update
+            let image = code_image(ctx).unwrap();
+            self.graph = Some(Graph::new(function, self.zoom, image,
+                &ctx.notes));
+            self.notes = notes;
+            self.shown = None;
+        }
+
+        let selected = function.block_at(va);
+        let graph = self.graph.as_ref().unwrap();
+        let cursor = selected.filter(|_| self.zoom == Zoom::Full)
+            .and_then(|n| function.blocks[n].insns.iter()
+                .position(|insn| insn.address <= va && va < insn.end())
+                .map(|i| graph.nodes[n].rows[i]));
+        self.selected = selected;
+        if let (Some(n), true) = (selected, selected != self.shown) {
+            self.scroll_to(n, false);
+        }
+        self.shown = selected;
+
+        let view = Rect::new(self.pan.0 as u16, self.pan.1 as u16,
+            area.width, area.height);
+        let lines = self.graph.as_ref().unwrap()
+            .render(view, selected, cursor);
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `h`/`j`/`k`/`l` move the view, or by half of it in capitals. `+`/`-`
+    /// show more or less of the blocks. `Tab`/`BackTab` move the cursor of
+    /// the tab to the next/previous block by address, and `c` centers the
+    /// view on the block holding the cursor.
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return false;
+        }
+        let (width, height) = self.graph.as_ref()
+            .map_or((0, 0), |graph| (graph.width, graph.height));
+        let (view_x, view_y) = self.view;
+        let (x, y) = self.pan;
+        match key.code {
+            KeyCode::Char('h') => self.pan.0 = x.saturating_sub(PAN_X),
+            KeyCode::Char('l') => self.pan.0 = (x + PAN_X)
+                .min(width.saturating_sub(1)),
+            KeyCode::Char('k') => self.pan.1 = y.saturating_sub(PAN_Y),
+            KeyCode::Char('j') => self.pan.1 = (y + PAN_Y)
+                .min(height.saturating_sub(1)),
+            KeyCode::Char('H') => self.pan.0 = x.saturating_sub(view_x / 2),
+            KeyCode::Char('L') => self.pan.0 = (x + view_x / 2)
+                .min(width.saturating_sub(1)),
+            KeyCode::Char('K') => self.pan.1 = y.saturating_sub(view_y / 2),
+            KeyCode::Char('J') => self.pan.1 = (y + view_y / 2)
+                .min(height.saturating_sub(1)),
+            KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char('-') => {
+                let zoom = if key.code == KeyCode::Char('-') {
+                    self.zoom.zoom_out()
+                } else {
+                    self.zoom.zoom_in()
+                };
+                if zoom != self.zoom {
+                    self.zoom = zoom;
+                    self.graph = None;
+                }
+            },
+            KeyCode::Char('c') => {
+                if let Some(n) = self.selected {
+                    self.scroll_to(n, true);
+                }
+            },
+            KeyCode::Tab | KeyCode::BackTab => {
+                let function = match &self.function {
+                    Some((_, _, function)) => function,
+                    None => return true,
+                };
+                let mut starts: Vec<u64> = function.blocks.iter()
+                    .map(|block| block.start)
+                    .collect();
+                starts.sort_unstable();
+                let current = self.selected
+                    .and_then(|n| starts.iter()
+                        .position(|&start| start == function.blocks[n].start));
+                let count = starts.len();
+                let next = match (current, key.code) {
+                    (None, _) => 0,
+                    (Some(i), KeyCode::Tab) => (i + 1) % count,
+                    (Some(i), _) => (i + count - 1) % count,
+                };
+                let offset = code_image(ctx).ok()
+                    .and_then(|image| image.offset_of(starts[next]));
+                if let Some(offset) = offset {
+                    ctx.cursor = offset.min(ctx.data().len()
+                        .saturating_sub(1));
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
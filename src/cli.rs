@@ -0,0 +1,111 @@
+//! Command line arguments
+use std::{
+    path::{PathBuf},
+    time::{Duration},
+};
+
+/// Usage message printed for `--help` and on errors
+pub const USAGE: &str = "\
+Usage: maglab [OPTIONS] [SAMPLE]...
+
+Open each SAMPLE in its own tab, with a layout picked based on its format.
+
+Options:
+  -l, --layout <name>     Layout used for the sample tabs
+  -s, --session <file>    Restore the tabs saved in <file> and save them back
+                          on exit
+  -p, --project <dir>     Open the project in <dir>, created if needed. The
+                          samples opened are added to it.
+  -c, --config <file>     Read the configuration from <file>
+  -r, --read-only         Never modify the samples, the default unless the
+                          configuration sets read_only = false
+  -w, --writable          Let patch-write modify the samples
+  -q, --quarantine <dir>  Copy every sample opened to <dir>, named after its
+                          SHA-256 and without execute permissions
+  -t, --tick-rate <ms>    Milliseconds between two ticks of the event loop
+  -h, --help              Print this message
+  -V, --version           Print the version";
+
+/// Options given on the command line
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    /// Files to open, one per tab
+    pub samples: Vec<PathBuf>,
+    /// Name of the layout to use instead of the format default
+    pub layout: Option<String>,
+    /// Session file to restore from and save to
+    pub session: Option<PathBuf>,
+    /// Project directory to open
+    pub project: Option<PathBuf>,
+    /// Configuration file to use instead of the default one
+    pub config: Option<PathBuf>,
+    /// Open samples read-only, whatever the configuration says
+    pub read_only: bool,
+    /// Let samples be modified, unless `read_only` is set
+    pub writable: bool,
+    /// Directory where samples are quarantined
+    pub quarantine: Option<PathBuf>,
+    /// Time between two ticks of the event loop
+    pub tick_rate: Option<Duration>,
+    /// Print usage and exit
+    pub help: bool,
+    /// Print version and exit
+    pub version: bool,
+}
+
+impl Args {
+    /// Parse the arguments, without the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I)
+            -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+        // Everything after `--` is a sample
+        let mut only_samples = false;
+
+        while let Some(arg) = args.next() {
+            if only_samples || !arg.starts_with('-') || arg == "-" {
+                parsed.samples.push(PathBuf::from(arg));
+                continue;
+            }
+
+            // Accept both `--opt value` and `--opt=value`
+            let (opt, inline) = match arg.find('=') {
+                Some(pos) if arg.starts_with("--") =>
+                    (arg[..pos].to_string(), Some(arg[pos + 1..].to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = |name: &str| inline.clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for {}", name));
+
+            match opt.as_str() {
+                "--" => only_samples = true,
+                "-l" | "--layout" => parsed.layout = Some(value(&opt)?),
+                "-s" | "--session" =>
+                    parsed.session = Some(PathBuf::from(value(&opt)?)),
+                "-p" | "--project" =>
+                    parsed.project = Some(PathBuf::from(value(&opt)?)),
+                "-c" | "--config" =>
+                    parsed.config = Some(PathBuf::from(value(&opt)?)),
+                "-r" | "--read-only" => parsed.read_only = true,
+                "-w" | "--writable" => parsed.writable = true,
+                "-q" | "--quarantine" =>
+                    parsed.quarantine = Some(PathBuf::from(value(&opt)?)),
+                "-t" | "--tick-rate" => {
+                    let ms = value(&opt)?;
+                    let ms: u64 = ms.parse()
+                        .map_err(|_| format!("invalid tick rate: {}", ms))?;
+                    if ms == 0 {
+                        return Err("tick rate must be above 0".to_string());
+                    }
+                    parsed.tick_rate = Some(Duration::from_millis(ms));
+                },
+                "-h" | "--help" => parsed.help = true,
+                "-V" | "--version" => parsed.version = true,
+                _ => return Err(format!("unknown option: {}", arg)),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
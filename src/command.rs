@@ -0,0 +1,231 @@
+//! Commands typed in the prompt at the bottom of the screen
+use std::path::{PathBuf};
+
+use crate::sample::{Format};
+use crate::annotations::{TagColor, TAG_COLORS};
+use crate::image::{Address};
+use crate::disasm::{Arch};
+
+/// A command parsed from the prompt
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// `layout <name>`: replace the plugins of the current tab with the
+    /// layout called `name`
+    Layout(String),
+    /// `layout-save <name> [format]...`: save the arrangement of the current
+    /// tab as a template, picked by default for samples of `formats`
+    SaveLayout { name: String, formats: Vec<Format> },
+    /// `layouts`: list the layouts that can be used
+    Layouts,
+    /// `search <query>`: search the sample of the current tab, see
+    /// `search::Pattern` for the syntax of the query
+    Search(String),
+    /// `yara [dir]`: scan the sample of the current tab with the rules in
+    /// `dir`, or in the configured rules directory
+    Yara(Option<PathBuf>),
+    /// `open <path>`: open the sample at `path` in a new tab
+    Open(PathBuf),
+    /// `label [text]`: label the offset under the cursor, without text the
+    /// label is removed
+    Label(String),
+    /// `comment [text]`: comment the selected bytes, without text the
+    /// comment is removed
+    Comment(String),
+    /// `bookmark [name]`: bookmark the offset under the cursor, without name
+    /// the bookmark is removed
+    Bookmark(String),
+    /// `color <color|none>`: tag the selected bytes with a colour, `none`
+    /// removes the tags
+    Color(Option<TagColor>),
+    /// `project`: describe the open project
+    Project,
+    /// `note <text>`: add an entry to the journal of the project
+    Note(String),
+    /// `extract <name>`: store the selected bytes as an artifact of the
+    /// project
+    Extract(String),
+    /// `undo`: undo the last edit of the sample
+    Undo,
+    /// `redo`: redo the last edit undone
+    Redo,
+    /// `patch-export <path>`: write the edits as an IPS patch (`.ips`), a
+    /// JSON patch (`.json`) or a patched copy of the sample (anything else)
+    PatchExport(PathBuf),
+    /// `patch-write`: write the edits to the sample itself
+    PatchWrite,
+    /// `template <name> [offset]`: decode the template `name` at `offset`,
+    /// or at the cursor
+    Template { name: String, offset: Option<usize> },
+    /// `template-clear`: remove the template applied to the current tab
+    ClearTemplate,
+    /// `templates`: list the templates that can be applied
+    Templates,
+    /// `diff <path>`: compare the sample of the current tab with the one
+    /// at `path` in a new tab
+    Diff(PathBuf),
+    /// `diff-tab <n>`: compare the sample of the current tab with the one
+    /// of tab `n`, counting from 1
+    DiffTab(usize),
+    /// `goto [va|rva] <address>`: move the cursor to a file offset, or to
+    /// the byte loaded at a virtual address of an executable
+    Goto(Address),
+    /// `arch [name]`: disassemble the sample of the current tab as code of
+    /// the architecture `name`, instead of the one its headers tell. Files
+    /// without headers are taken as raw code loaded at 0. Without name, tell
+    /// the architecture used.
+    Arch(Option<Arch>),
+    /// `raw <arch> [base]`: disassemble the selected bytes, or the whole
+    /// sample if nothing is selected, as headerless code of `arch` loaded at
+    /// `base`, 0 by default
+    Raw { arch: Arch, base: u64 },
+    /// `raw-clear`: lay the sample of the current tab out from its headers
+    /// again
+    ClearRaw,
+    /// `emulate`: emulate the code under the cursor until it returns, or the
+    /// code selected until it runs past the selection
+    Emulate,
+    /// `emulate-mem <address|register>`: show the memory of the emulation at
+    /// an address or at the one held by a register
+    EmulateMemory(String),
+    /// `quarantine`: copy the sample of the current tab to the quarantine
+    /// directory
+    Quarantine,
+    /// `open-with[!] <program> [arg]...`: open the sample of the current tab
+    /// with an external program. Executables are refused without `!`.
+    OpenWith { program: Vec<String>, force: bool },
+    /// `quit`
+    Quit,
+}
+
+/// Parse an offset typed by the user, in hex with `0x` or in decimal
+pub fn parse_offset(text: &str) -> Result<usize, String> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    }.map_err(|_| format!("invalid offset {}", text))
+}
+
+/// Parse an address typed by the user, in hex with `0x` or in decimal
+pub fn parse_address(text: &str) -> Result<u64, String> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    }.map_err(|_| format!("invalid address {}", text))
+}
+
+/// Parse the name of an architecture, see `Arch::from_name`
+fn parse_arch(name: &str) -> Result<Arch, String> {
+    Arch::from_name(name)
+        .ok_or_else(|| format!("unknown architecture {}, expected one of {}",
+            name, Arch::ALL.iter().map(|arch| arch.name())
+                .collect::<Vec<_>>().join(", ")))
+}
+
+/// Text following the command `name` in `line`, keeping its spacing
+fn rest<'a>(line: &'a str, name: &str) -> &'a str {
+    line.trim_start()[name.len()..].trim()
+}
+
+impl Command {
+    /// Parse the text typed in the prompt
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("empty command")?;
+        let args: Vec<&str> = words.collect();
+
+        match (name, args.as_slice()) {
+            ("layout", [layout]) => Ok(Command::Layout(layout.to_string())),
+            ("layout", _) => Err("usage: layout <name>".to_string()),
+            ("layout-save", [layout, formats @ ..]) => {
+                let formats = formats.iter()
+                    .map(|f| Format::from_name(f)
+                        .ok_or_else(|| format!("unknown format {}", f)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Command::SaveLayout { name: layout.to_string(), formats })
+            },
+            ("layout-save", _) =>
+                Err("usage: layout-save <name> [format]...".to_string()),
+            ("layouts", []) => Ok(Command::Layouts),
+            ("search", []) => Err("usage: search [hex|text|utf16|re] <query>"
+                .to_string()),
+            // Keep the spacing of the query
+            ("search", _) => Ok(Command::Search(rest(line, name).to_string())),
+            ("yara", []) => Ok(Command::Yara(None)),
+            ("yara", [dir]) => Ok(Command::Yara(Some(PathBuf::from(dir)))),
+            ("yara", _) => Err("usage: yara [dir]".to_string()),
+            ("open", []) => Err("usage: open <path>".to_string()),
+            ("open", _) => Ok(Command::Open(PathBuf::from(rest(line, name)))),
+            ("diff", []) => Err("usage: diff <path>".to_string()),
+            ("diff", _) => Ok(Command::Diff(PathBuf::from(rest(line, name)))),
+            ("diff-tab", [tab]) => match tab.parse() {
+                Ok(tab) if tab > 0 => Ok(Command::DiffTab(tab)),
+                _ => Err(format!("invalid tab {}", tab)),
+            },
+            ("diff-tab", _) => Err("usage: diff-tab <n>".to_string()),
+            ("label", _) => Ok(Command::Label(rest(line, name).to_string())),
+            ("comment", _) =>
+                Ok(Command::Comment(rest(line, name).to_string())),
+            ("bookmark", _) =>
+                Ok(Command::Bookmark(rest(line, name).to_string())),
+            ("color", ["none"]) => Ok(Command::Color(None)),
+            ("color", [color]) => TagColor::from_name(color)
+                .map(|color| Command::Color(Some(color)))
+                .ok_or_else(|| format!("unknown color {}, expected one of: \
+                    {}, none", color, TAG_COLORS.join(", "))),
+            ("color", _) => Err("usage: color <color|none>".to_string()),
+            ("project", []) => Ok(Command::Project),
+            ("note", []) => Err("usage: note <text>".to_string()),
+            ("note", _) => Ok(Command::Note(rest(line, name).to_string())),
+            ("extract", [artifact]) =>
+                Ok(Command::Extract(artifact.to_string())),
+            ("extract", _) => Err("usage: extract <name>".to_string()),
+            ("undo", []) => Ok(Command::Undo),
+            ("redo", []) => Ok(Command::Redo),
+            ("patch-export", []) =>
+                Err("usage: patch-export <path>".to_string()),
+            ("patch-export", _) =>
+                Ok(Command::PatchExport(PathBuf::from(rest(line, name)))),
+            ("patch-write", []) => Ok(Command::PatchWrite),
+            ("template", [template]) => Ok(Command::Template {
+                name: template.to_string(), offset: None }),
+            ("template", [template, offset]) => Ok(Command::Template {
+                name: template.to_string(),
+                offset: Some(parse_offset(offset)?) }),
+            ("template", _) =>
+                Err("usage: template <name> [offset]".to_string()),
+            ("template-clear", []) => Ok(Command::ClearTemplate),
+            ("templates", []) => Ok(Command::Templates),
+            ("goto", [offset]) =>
+                Ok(Command::Goto(Address::Offset(parse_offset(offset)?))),
+            ("goto", ["va", va]) =>
+                Ok(Command::Goto(Address::Va(parse_address(va)?))),
+            ("goto", ["rva", rva]) =>
+                Ok(Command::Goto(Address::Rva(parse_address(rva)?))),
+            ("goto", _) => Err("usage: goto [va|rva] <address>".to_string()),
+            ("arch", []) => Ok(Command::Arch(None)),
+            ("arch", [arch]) => Ok(Command::Arch(Some(parse_arch(arch)?))),
+            ("arch", _) => Err("usage: arch [name]".to_string()),
+            ("raw", [arch]) =>
+                Ok(Command::Raw { arch: parse_arch(arch)?, base: 0 }),
+            ("raw", [arch, base]) => Ok(Command::Raw { arch: parse_arch(arch)?,
+                base: parse_address(base)? }),
+            ("raw", _) => Err("usage: raw <arch> [base]".to_string()),
+            ("raw-clear", []) => Ok(Command::ClearRaw),
+            ("emulate", []) => Ok(Command::Emulate),
+            ("emulate-mem", [at]) => Ok(Command::EmulateMemory(at.to_string())),
+            ("emulate-mem", _) =>
+                Err("usage: emulate-mem <address|register>".to_string()),
+            ("quarantine", []) => Ok(Command::Quarantine),
+            ("open-with", []) | ("open-with!", []) =>
+                Err("usage: open-with[!] <program> [arg]...".to_string()),
+            ("open-with", program) | ("open-with!", program) =>
+                Ok(Command::OpenWith {
+                    program: program.iter().map(|arg| arg.to_string())
+                        .collect(),
+                    force: name.ends_with('!'),
+                }),
+            ("q", []) | ("quit", []) => Ok(Command::Quit),
+            _ => Err(format!("unknown command: {}", line.trim())),
+        }
+    }
+}
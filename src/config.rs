@@ -0,0 +1,137 @@
+//! User configuration, read from a TOML file
+use std::{
+    fs,
+    error::Error,
+    collections::{BTreeMap},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize};
+
+use crate::layout::{Layout, LayoutTemplate, SavedLayouts, BUILTIN_LAYOUTS};
+use crate::sample::{Format};
+
+/// Settings read from the configuration file. Command line options take
+/// precedence over these.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Milliseconds between two ticks of the event loop
+    pub tick_rate: Option<u64>,
+    /// Name of the layout used for new tabs instead of the one picked based
+    /// on the format of the sample
+    pub layout: Option<String>,
+    /// Open samples read-only, the default. `false` lets `patch-write`
+    /// modify them.
+    pub read_only: Option<bool>,
+    /// Directory where every sample opened is copied under its SHA-256,
+    /// without execute permissions
+    pub quarantine: Option<PathBuf>,
+    /// Directory holding the YARA rules (`*.yar`, `*.yara`) the Yara plugin
+    /// scans samples with
+    pub yara_rules: Option<PathBuf>,
+    /// Directory holding the annotations of the samples, one file per
+    /// SHA-256. Annotations are not saved without one.
+    pub notes_dir: Option<PathBuf>,
+    /// Directory holding the binary templates (`*.tpl`) applied with the
+    /// `template` command
+    pub templates: Option<PathBuf>,
+    /// TOML file mapping categories of suspicious APIs to the APIs in them,
+    /// extending the builtin mapping of the Symbols plugin
+    pub api_categories: Option<PathBuf>,
+    /// Layout templates by name
+    pub layouts: BTreeMap<String, LayoutTemplate>,
+    /// File where the templates saved from maglab are written
+    #[serde(skip)]
+    pub saved_layouts: Option<PathBuf>,
+}
+
+impl Config {
+    /// Read the configuration from the TOML file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let config = toml::from_str(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(config)
+    }
+
+    /// Read the templates saved from maglab in `path` and use it to save new
+    /// ones. Saved templates replace the configured ones with the same name.
+    pub fn load_saved_layouts(&mut self, path: PathBuf)
+            -> Result<(), Box<dyn Error>> {
+        let saved = SavedLayouts::load(&path)?;
+        self.layouts.extend(saved.layouts);
+        self.saved_layouts = Some(path);
+
+        Ok(())
+    }
+
+    /// Save `template` as `name`, replacing the template with the same name
+    pub fn save_layout(&mut self, name: &str, template: LayoutTemplate)
+            -> Result<(), Box<dyn Error>> {
+        let path = self.saved_layouts.as_ref()
+            .ok_or("no configuration directory to save layouts in")?;
+
+        // Only rewrite what was saved before, not the configured templates
+        let mut saved = SavedLayouts::load(path)?;
+        saved.layouts.insert(name.to_string(), template.clone());
+        saved.save(path)?;
+
+        self.layouts.insert(name.to_string(), template);
+
+        Ok(())
+    }
+
+    /// Get the layout called `name`, from the templates first and then from
+    /// the builtin layouts
+    pub fn layout(&self, name: &str) -> Option<Layout> {
+        self.layouts.get(name)
+            .map(|template| template.layout())
+            .or_else(|| Layout::builtin(name))
+    }
+
+    /// Layout for a new tab holding a sample of `format`: the first template
+    /// listing the format, or the builtin default
+    pub fn layout_for_format(&self, format: Format) -> Layout {
+        self.layouts.values()
+            .find(|template| template.formats.contains(&format))
+            .map(|template| template.layout())
+            .or_else(|| Layout::builtin(Layout::name_for_format(format)))
+            // The builtin names are always valid
+            .unwrap()
+    }
+
+    /// Names of all the layouts that can be used
+    pub fn layout_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = BUILTIN_LAYOUTS.iter().copied()
+            .chain(self.layouts.keys().map(|name| name.as_str()))
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    /// Default location of the configuration file:
+    /// `$XDG_CONFIG_HOME/maglab/config.toml` or
+    /// `$HOME/.config/maglab/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("maglab").join("config.toml"))
+    }
+
+    /// Default directory of the annotations: `$XDG_DATA_HOME/maglab/notes`
+    /// or `$HOME/.local/share/maglab/notes`
+    pub fn default_notes_dir() -> Option<PathBuf> {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".local").join("share")))
+            .map(|dir| dir.join("maglab").join("notes"))
+    }
+}
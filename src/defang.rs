@@ -0,0 +1,59 @@
+//! Defanging: showing names, URLs and IP addresses found in samples so that
+//! they cannot be clicked, pasted in a browser or run by accident
+use std::{
+    sync::{OnceLock},
+};
+
+use regex::{Regex, Captures};
+
+/// URLs with a scheme and dotted IPv4 addresses
+fn indicators() -> &'static Regex {
+    static INDICATORS: OnceLock<Regex> = OnceLock::new();
+    INDICATORS.get_or_init(|| Regex::new(concat!(
+        r"(?i)\b(?:(?P<scheme>https?|ftp)://(?P<rest>[^\s'\x22<>]+)",
+        r"|(?P<ip>(?:\d{1,3}\.){3}\d{1,3})\b)",
+    )).unwrap())
+}
+
+/// Characters that hide what a name really is: controls, bidirectional
+/// overrides like the right-to-left override of `exe.doc`, and invisible
+/// ones
+fn is_deceptive(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{200b}'..='\u{200f}'
+        | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{feff}')
+}
+
+/// Name of a file as shown to the user: dots become `[.]` and deceptive
+/// characters are spelled out, e.g. `invoice<U+202E>fdp[.]exe`
+pub fn name(name: &str) -> String {
+    let mut defanged = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '.' => defanged.push_str("[.]"),
+            c if is_deceptive(c) =>
+                defanged.push_str(&format!("<U+{:04X}>", c as u32)),
+            c => defanged.push(c),
+        }
+    }
+    defanged
+}
+
+/// `text` with the URLs and IP addresses in it defanged, e.g.
+/// `hxxp://evil[.]com/a` and `10[.]0[.]0[.]1`
+pub fn text(text: &str) -> String {
+    indicators().replace_all(text, |caps: &Captures| {
+        if let Some(ip) = caps.name("ip") {
+            return ip.as_str().replace('.', "[.]");
+        }
+        let scheme = match caps["scheme"].to_ascii_lowercase().as_str() {
+            "http" => "hxxp",
+            "https" => "hxxps",
+            _ => "fxp",
+        };
+        // Only the host, the path is harmless once the scheme is broken
+        let rest = &caps["rest"];
+        let host_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        format!("{}[://]{}{}", scheme, rest[..host_end].replace('.', "[.]"),
+            &rest[host_end..])
+    }).into_owned()
+}
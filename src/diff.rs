@@ -0,0 +1,631 @@
+//! Byte level diff of two samples, shown side by side
+use std::{
+    ops::{Range},
+    collections::{HashMap},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::sample::{Sample, Format};
+use crate::image::{Image, Section};
+use crate::funcdiff::{FunctionMatch};
+
+/// Bytes in the blocks used to find the parts both sides have in common
+const BLOCK: usize = 32;
+/// Bytes of both sides at most compared byte by byte between two common
+/// blocks, anything bigger is shown as replaced
+const MAX_GAP: usize = 1 << 16;
+/// Differences at most in a gap compared byte by byte
+const MAX_EDITS: usize = 1024;
+
+/// A run of bytes equal on both sides or differing between them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub equal: bool,
+    /// Range of the first sample
+    pub a: Range<usize>,
+    /// Range of the second sample
+    pub b: Range<usize>,
+}
+
+impl Hunk {
+    /// What happened to the bytes of the first sample to get the second
+    pub fn kind(&self) -> &'static str {
+        match (self.equal, self.a.is_empty(), self.b.is_empty()) {
+            (true, _, _) => "equal",
+            (false, true, _) => "insert",
+            (false, _, true) => "delete",
+            (false, false, false) => "replace",
+        }
+    }
+}
+
+/// Builds the hunks, merging the runs of the same kind
+#[derive(Default)]
+struct Hunks {
+    hunks: Vec<Hunk>,
+    a: usize,
+    b: usize,
+}
+
+impl Hunks {
+    fn push(&mut self, equal: bool, a_len: usize, b_len: usize) {
+        if a_len == 0 && b_len == 0 {
+            return;
+        }
+        let (a, b) = (self.a..self.a + a_len, self.b..self.b + b_len);
+        self.a = a.end;
+        self.b = b.end;
+        match self.hunks.last_mut() {
+            Some(last) if last.equal == equal => {
+                last.a.end = a.end;
+                last.b.end = b.end;
+            },
+            _ => self.hunks.push(Hunk { equal, a, b }),
+        }
+    }
+}
+
+/// Push the hunks of the shortest edit script from `a` to `b`, found with
+/// Myers' algorithm. Pushes nothing and returns false if it takes more than
+/// `max` insertions and deletions.
+fn myers(a: &[u8], b: &[u8], max: usize, hunks: &mut Hunks) -> bool {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let off = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    // Furthest x on each diagonal after every step, -d..=d
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    let mut found = false;
+    for d in 0..=max as isize {
+        for k in (-d..=d).step_by(2) {
+            let down = k == -d
+                || (k != d && v[(k - 1 + off) as usize] < v[(k + 1 + off) as usize]);
+            let mut x = if down { v[(k + 1 + off) as usize] }
+                        else { v[(k - 1 + off) as usize] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + off) as usize] = x;
+            if x >= n && y >= m {
+                found = true;
+                break;
+            }
+        }
+        trace.push(v[(off - d) as usize..=(off + d) as usize].to_vec());
+        if found {
+            break;
+        }
+    }
+    if !found {
+        return false;
+    }
+
+    // Walk back from the end, the steps come out reversed
+    let mut steps = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let prev = &trace[d as usize - 1];
+        let at = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let down = k == -d || (k != d && at(k - 1) < at(k + 1));
+        let prev_k = if down { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        // One byte inserted or deleted, then the common bytes up to (x, y)
+        let step_x = if down { prev_x } else { prev_x + 1 };
+        steps.push((true, (x - step_x) as usize));
+        steps.push((false, down as usize));
+        x = prev_x;
+        y = prev_x - prev_k;
+    }
+    steps.push((true, x as usize));
+
+    for (equal, n) in steps.into_iter().rev() {
+        match (equal, n) {
+            (true, n) => hunks.push(true, n, n),
+            // Going down inserts a byte of `b`, going right deletes one of
+            // `a`
+            (false, 1) => hunks.push(false, 0, 1),
+            (false, _) => hunks.push(false, 1, 0),
+        }
+    }
+    true
+}
+
+/// Diff the parts of `a` and `b` between two common blocks
+fn diff_gap(a: &[u8], b: &[u8], hunks: &mut Hunks) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y).count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix]);
+
+    hunks.push(true, prefix, prefix);
+    let small = a_mid.len() + b_mid.len() <= MAX_GAP;
+    if a_mid.is_empty() || b_mid.is_empty() || !small
+            || !myers(a_mid, b_mid, MAX_EDITS, hunks) {
+        hunks.push(false, a_mid.len(), b_mid.len());
+    }
+    hunks.push(true, suffix, suffix);
+}
+
+/// Hash of a block, rolled one byte at a time in `diff`
+fn block_hash(block: &[u8]) -> u64 {
+    block.iter().fold(0u64, |hash, &b| hash.wrapping_mul(HASH_BASE)
+        .wrapping_add(u64::from(b)))
+}
+
+const HASH_BASE: u64 = 0x100_0000_01b3;
+
+/// Align `a` and `b`: find blocks of `a` found in `b` in the same order and
+/// compare what is between them byte by byte
+pub fn diff(a: &[u8], b: &[u8]) -> Vec<Hunk> {
+    let mut hunks = Hunks::default();
+    if a.len() + b.len() <= MAX_GAP || a.len() < BLOCK || b.len() < BLOCK {
+        diff_gap(a, b, &mut hunks);
+        return hunks.hunks;
+    }
+
+    // Blocks of `a` by hash, the ones found more than once are no good to
+    // align both sides
+    let mut blocks: HashMap<u64, Option<usize>> = HashMap::new();
+    for (i, block) in a.chunks_exact(BLOCK).enumerate() {
+        blocks.entry(block_hash(block))
+            .and_modify(|offset| *offset = None)
+            .or_insert(Some(i * BLOCK));
+    }
+    // Weight of the byte leaving the block when rolling the hash
+    let out_weight = (1..BLOCK).fold(1u64, |w, _| w.wrapping_mul(HASH_BASE));
+
+    let (mut a_done, mut b_done) = (0, 0);
+    let mut j = 0;
+    let mut hash = block_hash(&b[..BLOCK]);
+    while j + BLOCK <= b.len() {
+        let found = blocks.get(&hash).copied().flatten()
+            .filter(|&i| i >= a_done && a[i..i + BLOCK] == b[j..j + BLOCK]);
+        if let Some(mut i) = found {
+            // Grow the common part both ways
+            let mut start = j;
+            while i > a_done && start > b_done && a[i - 1] == b[start - 1] {
+                i -= 1;
+                start -= 1;
+            }
+            let len = a[i..].iter().zip(&b[start..])
+                .take_while(|(x, y)| x == y).count();
+            diff_gap(&a[a_done..i], &b[b_done..start], &mut hunks);
+            hunks.push(true, len, len);
+            a_done = i + len;
+            b_done = start + len;
+            j = b_done;
+            if j + BLOCK <= b.len() {
+                hash = block_hash(&b[j..j + BLOCK]);
+            }
+            continue;
+        }
+        if j + BLOCK < b.len() {
+            hash = hash.wrapping_sub(u64::from(b[j]).wrapping_mul(out_weight))
+                .wrapping_mul(HASH_BASE)
+                .wrapping_add(u64::from(b[j + BLOCK]));
+        }
+        j += 1;
+    }
+    diff_gap(&a[a_done..], &b[b_done..], &mut hunks);
+
+    hunks.hunks
+}
+
+/// How a section changed from the first sample to the second
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionChange {
+    pub name: String,
+    pub change: String,
+}
+
+/// Compare the sections with the same name on both sides
+fn section_changes(a: &[u8], b: &[u8], hunks: &[Hunk])
+        -> Result<Vec<SectionChange>, String> {
+    // Only the sections with contents in the file
+    let sections = |data: &[u8]| Image::parse(data).map(|image| image.sections
+        .into_iter().filter(|section| section.size > 0));
+    let a_sections = sections(a)?;
+    let mut b_sections: Vec<Option<Section>> = sections(b)?
+        .map(Some).collect();
+
+    let mut changes = Vec::new();
+    for section in a_sections {
+        let other = b_sections.iter_mut()
+            .find(|other| other.as_ref()
+                .is_some_and(|other| other.name == section.name))
+            .and_then(|other| other.take());
+        let change = match other {
+            None => "removed".to_string(),
+            Some(other) => {
+                let range = section.offset..section.offset + section.size;
+                let bytes = &a[range.clone()];
+                let other_bytes = &b[other.offset..other.offset + other.size];
+                if bytes == other_bytes {
+                    "unchanged".to_string()
+                } else {
+                    // Bytes of this section replaced, deleted or inserted,
+                    // on the side where there are more
+                    let other_range = other.offset..other.offset + other.size;
+                    let overlap = |hunk: &Range<usize>, range: &Range<usize>|
+                        hunk.end.min(range.end)
+                            .saturating_sub(hunk.start.max(range.start));
+                    let changed: usize = hunks.iter()
+                        .filter(|hunk| !hunk.equal)
+                        .map(|hunk| overlap(&hunk.a, &range)
+                            .max(overlap(&hunk.b, &other_range)))
+                        .sum();
+                    let mut change = format!("changed, {} bytes", changed);
+                    if section.size != other.size {
+                        change.push_str(&format!(", size {:#x} -> {:#x}",
+                            section.size, other.size));
+                    }
+                    change
+                }
+            },
+        };
+        changes.push(SectionChange { name: section.name, change });
+    }
+    for section in b_sections.into_iter().flatten() {
+        changes.push(SectionChange { name: section.name,
+            change: "added".to_string() });
+    }
+
+    Ok(changes)
+}
+
+/// Differences between the sample of a tab and another one
+#[derive(Debug)]
+pub struct Diff {
+    /// The sample compared with the one of the tab
+    pub other: Sample,
+    pub hunks: Vec<Hunk>,
+    /// Changes of the sections, or why there are none
+    pub sections: Result<Vec<SectionChange>, String>,
+    /// Functions of both sides matched in the background, or why there are
+    /// none
+    pub functions: FunctionMatch,
+}
+
+impl Diff {
+    /// Compare `sample` with `other`
+    pub fn new(sample: &Sample, other: Sample) -> Diff {
+        let hunks = diff(&sample.data, &other.data);
+        let same_format = match (sample.format, other.format) {
+            (Format::Raw, _) | (_, Format::Raw) =>
+                Err("raw samples have no sections".to_string()),
+            (a, b) if a != b => Err(format!("formats differ, {} and {}", a, b)),
+            _ => Ok(()),
+        };
+        let sections = same_format.clone().and_then(|_|
+            section_changes(&sample.data, &other.data, &hunks)
+                .map_err(|err| format!("cannot read the sections: {}", err)));
+        let functions = match (same_format, &sample.image, &other.image) {
+            (Err(err), _, _) => FunctionMatch::failed(err),
+            (Ok(()), Some(a), Some(b)) => FunctionMatch::start(
+                (a.clone(), sample.data.clone()),
+                (b.clone(), other.data.clone())),
+            (Ok(()), _, _) => FunctionMatch::failed(
+                "cannot read the headers of both samples".to_string()),
+        };
+        Diff { other, hunks, sections, functions }
+    }
+
+    /// Hunks where both sides differ
+    pub fn changes(&self) -> impl Iterator<Item = (usize, &Hunk)> {
+        self.hunks.iter().enumerate().filter(|(_, hunk)| !hunk.equal)
+    }
+
+    /// One line summary of the differences
+    pub fn summary(&self) -> String {
+        let (mut a, mut b) = (0, 0);
+        for (_, hunk) in self.changes() {
+            a += hunk.a.len();
+            b += hunk.b.len();
+        }
+        match self.changes().count() {
+            0 => "identical".to_string(),
+            n => format!("{} differences, {} bytes on the left, {} on the \
+                right", n, a, b),
+        }
+    }
+
+    /// Index of the hunk holding `offset` of the first sample
+    pub fn hunk_at(&self, offset: usize) -> usize {
+        self.hunks.partition_point(|hunk| hunk.a.end <= offset)
+            .min(self.hunks.len().saturating_sub(1))
+    }
+}
+
+/// Plugin showing both samples of a diff side by side, aligned on their
+/// common bytes
+#[derive(Default)]
+pub struct DiffView {
+    /// Bytes on each side of a row
+    width: usize,
+    /// First row of each hunk, plus the number of rows at the end
+    starts: Vec<usize>,
+    /// Selected row
+    row: usize,
+    /// First row shown
+    scroll: usize,
+    /// Cursor of the tab in the last draw, the selection follows it
+    cursor: Option<usize>,
+}
+
+impl DiffView {
+    /// Lay the hunks out in rows of `width` bytes on each side
+    fn layout(&mut self, diff: &Diff, width: usize) {
+        if self.width == width && self.starts.len() == diff.hunks.len() + 1 {
+            return;
+        }
+        self.width = width;
+        self.starts = vec![0];
+        for hunk in diff.hunks.iter() {
+            let len = hunk.a.len().max(hunk.b.len());
+            let rows = len.div_ceil(width);
+            self.starts.push(self.starts.last().unwrap() + rows);
+        }
+    }
+
+    /// Hunk of `row` and the ranges of both sides shown in it, `None` when
+    /// there are no hunks
+    fn row(&self, diff: &Diff, row: usize)
+            -> Option<(usize, Range<usize>, Range<usize>)> {
+        let i = self.starts.partition_point(|&start| start <= row)
+            .saturating_sub(1);
+        let hunk = diff.hunks.get(i)?;
+        let skip = (row - self.starts[i]) * self.width;
+        let side = |range: &Range<usize>| {
+            let start = (range.start + skip).min(range.end);
+            start..(start + self.width).min(range.end)
+        };
+        Some((i, side(&hunk.a), side(&hunk.b)))
+    }
+
+    /// Select `row` and move the cursor of the tab to its first byte on the
+    /// left, or to the last byte of the `len` there are for an insertion at
+    /// the end
+    fn select(&mut self, row: usize, diff: &Diff, len: usize,
+            ctx_cursor: &mut usize) {
+        let rows = *self.starts.last().unwrap();
+        self.row = row.min(rows.saturating_sub(1));
+        if let Some((_, a, _)) = self.row(diff, self.row) {
+            *ctx_cursor = a.start.min(len.saturating_sub(1));
+        }
+        self.cursor = Some(*ctx_cursor);
+    }
+}
+
+/// Hex and ASCII of one side of a row, `width` bytes wide
+fn side<'a>(data: &[u8], range: Range<usize>, width: usize, changed: Color)
+        -> Vec<Span<'a>> {
+    let style = Style::default().fg(changed);
+    let mut spans = Vec::new();
+    if range.is_empty() {
+        spans.push(Span::raw(" ".repeat(9 + width * 4)));
+        return spans;
+    }
+    spans.push(Span::styled(format!("{:08x} ", range.start),
+        Style::default().fg(Color::Blue)));
+    for i in range.clone() {
+        spans.push(Span::styled(format!("{:02x} ", data[i]), style));
+    }
+    spans.push(Span::raw("   ".repeat(width - range.len())));
+    for i in range.clone() {
+        let c = if data[i].is_ascii_graphic() || data[i] == b' ' {
+            data[i] as char } else { '.' };
+        spans.push(Span::styled(c.to_string(), style));
+    }
+    spans.push(Span::raw(" ".repeat(width - range.len())));
+    spans
+}
+
+impl RenderPlugin for DiffView {
+    fn get_name(&self) -> &str {
+        "Diff"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let (diff, sample) = match (&ctx.diff, &ctx.sample) {
+            (Some(diff), Some(sample)) => (diff, sample),
+            _ => {
+                f.render_widget(Paragraph::new(
+                    "no diff, see the diff command"), area);
+                return;
+            }
+        };
+
+        // Each side takes its offset, then 4 columns per byte
+        let available = (area.width as usize).saturating_sub(3) / 2;
+        let width = [16, 8, 4, 2, 1].iter().copied()
+            .find(|width| 9 + width * 4 <= available)
+            .unwrap_or(1);
+        self.layout(diff, width);
+        let rows = *self.starts.last().unwrap();
+        if diff.hunks.is_empty() {
+            f.render_widget(Paragraph::new("both samples are empty"), area);
+            return;
+        }
+
+        // Follow the cursor when it is moved from another plugin
+        if self.cursor != Some(ctx.cursor) {
+            let i = diff.hunk_at(ctx.cursor);
+            let hunk = &diff.hunks[i];
+            self.row = self.starts[i] + ctx.cursor.saturating_sub(hunk.a.start)
+                .min(hunk.a.len().saturating_sub(1)) / width;
+            self.cursor = Some(ctx.cursor);
+        }
+        self.row = self.row.min(rows.saturating_sub(1));
+
+        let height = (area.height as usize).saturating_sub(1).max(1);
+        if self.row < self.scroll {
+            self.scroll = self.row;
+        } else if self.row >= self.scroll + height {
+            self.scroll = self.row + 1 - height;
+        }
+
+        let title = Style::default().add_modifier(Modifier::BOLD);
+        let mut lines = vec![Spans::from(vec![
+            Span::styled(format!("{:<w$}", sample.name(), w = 9 + width * 4),
+                title),
+            Span::raw(" | "),
+            Span::styled(diff.other.name(), title),
+        ])];
+        for row in (self.scroll..rows).take(height) {
+            // Rows are only laid out for hunks
+            let (i, a, b) = self.row(diff, row).unwrap();
+            let hunk = &diff.hunks[i];
+            let (left, right) = match hunk.equal {
+                true => (Color::Reset, Color::Reset),
+                false => (Color::Red, Color::Green),
+            };
+            let mut spans = side(&sample.data, a, width, left);
+            spans.push(Span::raw(" | "));
+            spans.extend(side(&diff.other.data, b, width, right));
+            if row == self.row {
+                for span in spans.iter_mut() {
+                    span.style = span.style.add_modifier(Modifier::REVERSED);
+                }
+            }
+            lines.push(Spans::from(spans));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous row, `]`/`[` go to the next/previous
+    /// difference, `g`/`G` to the start/end
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let diff = match &ctx.diff {
+            Some(diff) if !self.starts.is_empty() => diff,
+            _ => return false,
+        };
+        let hunk = self.starts.partition_point(|&start| start <= self.row) - 1;
+        let row = match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.row + 1,
+            KeyCode::Char('k') | KeyCode::Up => self.row.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => 0,
+            KeyCode::Char('G') | KeyCode::End => usize::MAX,
+            KeyCode::Char(']') => match diff.changes()
+                    .find(|&(i, _)| i > hunk) {
+                Some((i, _)) => self.starts[i],
+                None => self.row,
+            },
+            KeyCode::Char('[') => match diff.changes()
+                    .filter(|&(i, _)| self.starts[i] < self.row)
+                    .last() {
+                Some((i, _)) => self.starts[i],
+                None => self.row,
+            },
+            _ => return false,
+        };
+        let len = ctx.data().len();
+        let diff = ctx.diff.as_ref().unwrap();
+        self.select(row, diff, len, &mut ctx.cursor);
+        true
+    }
+}
+
+/// Plugin listing the differences of a diff and the changes of the sections
+#[derive(Default)]
+pub struct Differences {
+    selected: usize,
+    scroll: usize,
+}
+
+impl RenderPlugin for Differences {
+    fn get_name(&self) -> &str {
+        "Differences"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let diff = match &ctx.diff {
+            Some(diff) => diff,
+            None => {
+                f.render_widget(Paragraph::new(
+                    "no diff, see the diff command"), area);
+                return;
+            }
+        };
+
+        let mut lines = vec![Spans::from(Span::styled(diff.summary(),
+            Style::default().fg(Color::Blue)))];
+        match &diff.sections {
+            Ok(changes) => {
+                for change in changes.iter().filter(|c| c.change != "unchanged") {
+                    lines.push(Spans::from(vec![
+                        Span::styled(format!("{:<12}", change.name),
+                            Style::default().fg(Color::Cyan)),
+                        Span::raw(change.change.clone()),
+                    ]));
+                }
+            },
+            Err(err) => lines.push(Spans::from(Span::styled(err.clone(),
+                Style::default().fg(Color::DarkGray)))),
+        }
+
+        let changes: Vec<&Hunk> = diff.changes().map(|(_, hunk)| hunk)
+            .collect();
+        self.selected = self.selected.min(changes.len().saturating_sub(1));
+        let rows = (area.height as usize).saturating_sub(lines.len()).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        for (i, hunk) in changes.iter().enumerate().skip(self.scroll) {
+            let mut style = Style::default();
+            if i == self.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            lines.push(Spans::from(Span::styled(format!(
+                "{:08x} {:<7} {} -> {} bytes at {:08x}", hunk.a.start,
+                hunk.kind(), hunk.a.len(), hunk.b.len(), hunk.b.start),
+                style)));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous difference, `Enter` moves the cursor
+    /// of the tab to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let count = ctx.diff.as_ref().map_or(0, |diff| diff.changes().count());
+        match key.code {
+            KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(count.saturating_sub(1));
+            },
+            KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            },
+            KeyCode::Enter => {
+                let hunk = ctx.diff.as_ref()
+                    .and_then(|diff| diff.changes().nth(self.selected))
+                    .map(|(_, hunk)| hunk.a.start);
+                // An insertion at the end starts past the last byte
+                if let Some(offset) = hunk {
+                    ctx.cursor = offset.min(ctx.data().len().saturating_sub(1));
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
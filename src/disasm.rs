@@ -0,0 +1,560 @@
+//! Disassembly: decoding instructions and where the execution goes next.
+//! x86 is decoded with iced, RISC-V by `riscv`, the others with capstone.
+use iced_x86::{
+    Decoder, DecoderOptions, Formatter, IntelFormatter, FlowControl, Mnemonic,
+    OpKind, Register,
+};
+use capstone::{
+    Capstone, Endian, ExtraMode, Mode, InsnGroupType,
+    arch::{
+        ArchDetail, DetailsArchInsn,
+        arm::{ArmCC, ArmInsn, ArmOperandType, ArmReg},
+        arm64::{Arm64CC, Arm64Insn, Arm64OperandType, Arm64Reg},
+        mips::{MipsOperand, MipsReg},
+        ppc::{PpcOperand},
+    },
+};
+use serde::{Serialize, Deserialize};
+
+use crate::riscv;
+
+/// Architectures we can disassemble
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    /// 16-bit x86, e.g. boot sectors and DOS programs
+    #[serde(rename = "x86-16")]
+    X86_16,
+    /// 32-bit x86
+    X86,
+    /// 64-bit x86
+    X64,
+    /// 32-bit ARM, A32 instructions
+    Arm,
+    /// 32-bit ARM, Thumb and Thumb-2 instructions
+    Thumb,
+    /// 64-bit ARM, A64 instructions
+    Arm64,
+    /// 32-bit MIPS, big endian
+    Mips,
+    /// 32-bit MIPS, little endian
+    Mipsel,
+    /// 64-bit MIPS, big endian
+    Mips64,
+    /// 64-bit MIPS, little endian
+    Mips64el,
+    /// 32-bit PowerPC, big endian
+    Ppc,
+    /// 32-bit PowerPC, little endian
+    Ppcle,
+    /// 64-bit PowerPC, big endian
+    Ppc64,
+    /// 64-bit PowerPC, little endian
+    Ppc64le,
+    /// 32-bit RISC-V, with the compressed instructions
+    RiscV32,
+    /// 64-bit RISC-V, with the compressed instructions
+    RiscV64,
+}
+
+impl Arch {
+    /// Every architecture, in the order they are listed to the user
+    pub const ALL: [Arch; 16] = [Arch::X86_16, Arch::X86, Arch::X64, Arch::Arm,
+        Arch::Thumb, Arch::Arm64, Arch::Mips, Arch::Mipsel, Arch::Mips64, Arch::Mips64el,
+        Arch::Ppc, Arch::Ppcle, Arch::Ppc64, Arch::Ppc64le, Arch::RiscV32,
+        Arch::RiscV64];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Arch::X86_16 => "x86-16",
+            Arch::X86 => "x86",
+            Arch::X64 => "x64",
+            Arch::Arm => "arm",
+            Arch::Thumb => "thumb",
+            Arch::Arm64 => "arm64",
+            Arch::Mips => "mips",
+            Arch::Mipsel => "mipsel",
+            Arch::Mips64 => "mips64",
+            Arch::Mips64el => "mips64el",
+            Arch::Ppc => "ppc",
+            Arch::Ppcle => "ppcle",
+            Arch::Ppc64 => "ppc64",
+            Arch::Ppc64le => "ppc64le",
+            Arch::RiscV32 => "riscv32",
+            Arch::RiscV64 => "riscv64",
+        }
+    }
+
+    /// Get an architecture from its name as typed by the user, ignoring case
+    pub fn from_name(name: &str) -> Option<Arch> {
+        match name.to_ascii_lowercase().as_str() {
+            "8086" | "i8086" => Some(Arch::X86_16),
+            "x86" | "i386" => Some(Arch::X86),
+            "x64" | "x86_64" | "amd64" => Some(Arch::X64),
+            "arm" | "a32" => Some(Arch::Arm),
+            "thumb" | "t32" => Some(Arch::Thumb),
+            "arm64" | "aarch64" | "a64" => Some(Arch::Arm64),
+            "powerpc" => Some(Arch::Ppc),
+            "powerpc64" => Some(Arch::Ppc64),
+            "rv32" => Some(Arch::RiscV32),
+            "rv64" => Some(Arch::RiscV64),
+            name => Arch::ALL.iter().copied().find(|arch| arch.name() == name),
+        }
+    }
+
+    /// Size of the smallest instruction, which instructions are aligned to
+    pub fn align(self) -> usize {
+        match self {
+            Arch::X86_16 | Arch::X86 | Arch::X64 => 1,
+            Arch::Thumb | Arch::RiscV32 | Arch::RiscV64 => 2,
+            _ => 4,
+        }
+    }
+
+    pub fn is_big_endian(self) -> bool {
+        matches!(self, Arch::Mips | Arch::Mips64 | Arch::Ppc | Arch::Ppc64)
+    }
+
+    /// Whether the instruction following a jump, a call or a return runs
+    /// before it, in its delay slot
+    pub fn has_delay_slot(self) -> bool {
+        matches!(self, Arch::Mips | Arch::Mipsel | Arch::Mips64
+            | Arch::Mips64el)
+    }
+}
+
+/// Where the execution goes after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// To the next instruction
+    Next,
+    /// To the target, `None` if it is only known at runtime
+    Jump(Option<u64>),
+    /// To the target or to the next instruction
+    Branch(u64),
+    /// To the target, then back to the next instruction
+    Call(Option<u64>),
+    Return,
+    /// Nowhere we can follow: invalid instruction, trap or halt
+    Stop,
+}
+
+/// A decoded instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Insn {
+    pub address: u64,
+    pub len: usize,
+    /// Instruction in Intel syntax for x86, in the syntax of the vendor
+    /// for the others
+    pub text: String,
+    pub flow: Flow,
+    /// Address of the memory operand, when it is known without running the
+    /// code: absolute or relative to the instruction
+    pub mem: Option<u64>,
+    /// Immediate operand wide enough to be an address, e.g. of a string
+    /// pushed on the stack
+    pub imm: Option<u64>,
+    /// Hash of the instruction without its addresses and constants, equal
+    /// for the same code compiled at another place
+    pub norm: u64,
+}
+
+impl Insn {
+    /// Address of the instruction following this one
+    pub fn end(&self) -> u64 {
+        self.address.wrapping_add(self.len as u64)
+    }
+}
+
+/// FNV-1a of `bytes`, continuing from `hash`
+pub fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| (hash ^ u64::from(b))
+        .wrapping_mul(0x100_0000_01b3))
+}
+
+/// Starting value of `fnv`
+pub const FNV_START: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Decoder of an architecture
+enum Engine {
+    X86 { bitness: u32, formatter: Box<IntelFormatter> },
+    Capstone(Capstone),
+    RiscV { xlen: u32 },
+}
+
+/// An operand of an instruction decoded by capstone, the same for every
+/// architecture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Reg(u16),
+    Imm(u64),
+    Mem { base: u16, index: u16, disp: i64 },
+    Other,
+}
+
+/// Decodes instructions one after the other
+pub struct Disassembler {
+    arch: Arch,
+    engine: Engine,
+}
+
+impl Disassembler {
+    pub fn new(arch: Arch) -> Disassembler {
+        let (cs_arch, mode, extra) = match arch {
+            Arch::X86_16 | Arch::X86 | Arch::X64 => {
+                let mut formatter = IntelFormatter::new();
+                let options = formatter.options_mut();
+                options.set_space_after_operand_separator(true);
+                options.set_hex_prefix("0x");
+                options.set_hex_suffix("");
+                options.set_branch_leading_zeros(false);
+                let bitness = match arch {
+                    Arch::X86_16 => 16,
+                    Arch::X86 => 32,
+                    _ => 64,
+                };
+                return Disassembler { arch, engine: Engine::X86 { bitness,
+                    formatter: Box::new(formatter) } };
+            },
+            Arch::RiscV32 => return Disassembler { arch,
+                engine: Engine::RiscV { xlen: 32 } },
+            Arch::RiscV64 => return Disassembler { arch,
+                engine: Engine::RiscV { xlen: 64 } },
+            Arch::Arm => (capstone::Arch::ARM, Mode::Arm, Some(ExtraMode::V8)),
+            Arch::Thumb =>
+                (capstone::Arch::ARM, Mode::Thumb, Some(ExtraMode::V8)),
+            Arch::Arm64 => (capstone::Arch::ARM64, Mode::Arm, None),
+            Arch::Mips | Arch::Mipsel => (capstone::Arch::MIPS, Mode::Mips32, None),
+            Arch::Mips64 | Arch::Mips64el =>
+                (capstone::Arch::MIPS, Mode::Mips64, None),
+            Arch::Ppc | Arch::Ppcle => (capstone::Arch::PPC, Mode::Mode32, None),
+            Arch::Ppc64 | Arch::Ppc64le =>
+                (capstone::Arch::PPC, Mode::Mode64, None),
+        };
+        let endian = if arch.is_big_endian() { Endian::Big } else { Endian::Little };
+        let mut cs = Capstone::new_raw(cs_arch, mode, extra.into_iter(),
+                Some(endian))
+            .expect("capstone is built with every architecture we decode");
+        cs.set_detail(true).expect("capstone is built with details");
+        Disassembler { arch, engine: Engine::Capstone(cs) }
+    }
+
+    pub fn arch(&self) -> Arch {
+        self.arch
+    }
+
+    /// Decode the instruction at the start of `code`, loaded at `address`.
+    /// Bytes that are no instruction decode as a `(bad)` as long as the
+    /// smallest instruction.
+    pub fn decode(&mut self, code: &[u8], address: u64) -> Option<Insn> {
+        if code.is_empty() {
+            return None;
+        }
+        let (bitness, formatter) = match &mut self.engine {
+            Engine::X86 { bitness, formatter } => (*bitness, formatter),
+            Engine::Capstone(cs) => return Some(decode_capstone(cs, self.arch,
+                code, address)),
+            Engine::RiscV { xlen } => return Some(riscv::decode(code, address,
+                *xlen)),
+        };
+        let insn = Decoder::with_ip(bitness, code, address,
+            DecoderOptions::NONE).decode();
+        if insn.is_invalid() {
+            return Some(Insn { address, len: 1, text: "(bad)".to_string(),
+                flow: Flow::Stop, mem: None, imm: None,
+                norm: fnv(FNV_START, &[code[0]]) });
+        }
+
+        let near_target = || match insn.op_kind(0) {
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 =>
+                Some(insn.near_branch_target()),
+            _ => None,
+        };
+        let flow = match insn.flow_control() {
+            FlowControl::Next | FlowControl::XbeginXabortXend => Flow::Next,
+            FlowControl::UnconditionalBranch => Flow::Jump(near_target()),
+            FlowControl::IndirectBranch => Flow::Jump(None),
+            FlowControl::ConditionalBranch => match near_target() {
+                Some(target) => Flow::Branch(target),
+                None => Flow::Next,
+            },
+            FlowControl::Call => Flow::Call(near_target()),
+            FlowControl::IndirectCall => Flow::Call(None),
+            FlowControl::Return => Flow::Return,
+            // System calls come back, breakpoints do not
+            FlowControl::Interrupt if insn.mnemonic() != Mnemonic::Int3 =>
+                Flow::Next,
+            FlowControl::Interrupt | FlowControl::Exception => Flow::Stop,
+        };
+        let flow = match insn.mnemonic() {
+            Mnemonic::Hlt => Flow::Stop,
+            _ => flow,
+        };
+
+        // Mnemonic, operand kinds and registers, leaving out immediates,
+        // displacements and branch targets
+        let mut norm = fnv(FNV_START, &(insn.mnemonic() as u16).to_le_bytes());
+        for i in 0..insn.op_count() {
+            let kind = insn.op_kind(i);
+            norm = fnv(norm, &[kind as u8]);
+            match kind {
+                OpKind::Register => norm = fnv(norm,
+                    &[insn.op_register(i) as u8]),
+                OpKind::Memory => norm = fnv(norm, &[
+                    insn.memory_base() as u8, insn.memory_index() as u8,
+                    insn.memory_index_scale() as u8]),
+                _ => {},
+            }
+        }
+
+        let has_mem = (0..insn.op_count())
+            .any(|i| insn.op_kind(i) == OpKind::Memory);
+        let mem = if !has_mem || insn.memory_index() != Register::None {
+            None
+        } else if insn.is_ip_rel_memory_operand() {
+            Some(insn.ip_rel_memory_address())
+        } else if insn.memory_base() == Register::None {
+            Some(insn.memory_displacement64())
+        } else {
+            None
+        };
+
+        // Addresses of 16-bit code fit in 16 bits
+        let imm = (0..insn.op_count())
+            .find(|&i| match insn.op_kind(i) {
+                OpKind::Immediate32 | OpKind::Immediate64
+                | OpKind::Immediate32to64 => true,
+                OpKind::Immediate16 => bitness == 16,
+                _ => false,
+            })
+            .map(|i| insn.immediate(i));
+
+        let mut text = String::new();
+        formatter.format(&insn, &mut text);
+        Some(Insn { address, len: insn.len(), text, flow, mem, imm, norm })
+    }
+}
+
+/// The `(bad)` instruction at the start of `code`, as long as the smallest
+/// instruction of `arch`
+pub fn bad(arch: Arch, code: &[u8], address: u64) -> Insn {
+    let len = arch.align().min(code.len());
+    Insn { address, len, text: "(bad)".to_string(), flow: Flow::Stop,
+        mem: None, imm: None, norm: fnv(FNV_START, &code[..len]) }
+}
+
+/// Decode the instruction at the start of `code` with `cs`
+fn decode_capstone(cs: &Capstone, arch: Arch, code: &[u8], address: u64)
+        -> Insn {
+    let insns = match cs.disasm_count(code, address, 1) {
+        Ok(insns) => insns,
+        Err(_) => return bad(arch, code, address),
+    };
+    let insn = match insns.iter().next() {
+        Some(insn) => insn,
+        None => return bad(arch, code, address),
+    };
+    let detail = match cs.insn_detail(&insn) {
+        Ok(detail) => detail,
+        Err(_) => return bad(arch, code, address),
+    };
+    let text = match (insn.mnemonic(), insn.op_str()) {
+        (Some(mnemonic), Some(ops)) if !ops.is_empty() =>
+            format!("{} {}", mnemonic, ops),
+        (Some(mnemonic), _) => mnemonic.to_string(),
+        _ => return bad(arch, code, address),
+    };
+    let id = insn.id().0;
+    let mnemonic = insn.mnemonic().unwrap_or("");
+
+    // The operands, and whether the instruction only runs on a condition
+    let (ops, conditional): (Vec<Operand>, bool) = match detail.arch_detail() {
+        ArchDetail::ArmDetail(detail) => (detail.operands()
+            .map(|op| match op.op_type {
+                ArmOperandType::Reg(reg) => Operand::Reg(reg.0),
+                ArmOperandType::Imm(imm) => Operand::Imm(imm as u32 as u64),
+                ArmOperandType::Mem(mem) => Operand::Mem {
+                    base: mem.base().0, index: mem.index().0,
+                    disp: mem.disp() as i64 },
+                _ => Operand::Other,
+            }).collect(),
+            !matches!(detail.cc(), ArmCC::ARM_CC_AL | ArmCC::ARM_CC_INVALID)),
+        ArchDetail::Arm64Detail(detail) => (detail.operands()
+            .map(|op| match op.op_type {
+                Arm64OperandType::Reg(reg) => Operand::Reg(reg.0),
+                Arm64OperandType::Imm(imm) => Operand::Imm(imm as u64),
+                Arm64OperandType::Mem(mem) => Operand::Mem {
+                    base: mem.base().0, index: mem.index().0,
+                    disp: i64::from(mem.disp()) },
+                _ => Operand::Other,
+            }).collect(),
+            !matches!(detail.cc(), Arm64CC::ARM64_CC_AL | Arm64CC::ARM64_CC_NV
+                | Arm64CC::ARM64_CC_INVALID)),
+        ArchDetail::MipsDetail(detail) => (detail.operands()
+            .map(|op| match op {
+                MipsOperand::Reg(reg) => Operand::Reg(reg.0),
+                MipsOperand::Imm(imm) => Operand::Imm(imm as u64),
+                MipsOperand::Mem(mem) => Operand::Mem { base: mem.base().0,
+                    index: 0, disp: mem.disp() },
+                _ => Operand::Other,
+            }).collect(), false),
+        ArchDetail::PpcDetail(detail) => (detail.operands()
+            .map(|op| match op {
+                PpcOperand::Reg(reg) => Operand::Reg(reg.0),
+                PpcOperand::Imm(imm) => Operand::Imm(imm as u64),
+                PpcOperand::Mem(mem) => Operand::Mem { base: mem.base().0,
+                    index: 0, disp: i64::from(mem.disp()) },
+                _ => Operand::Other,
+            }).collect(), false),
+        _ => return bad(arch, code, address),
+    };
+
+    // Branch targets and literals are the last immediate
+    let target = ops.iter().rev().find_map(|op| match *op {
+        Operand::Imm(imm) => Some(imm),
+        _ => None,
+    });
+    let in_group = |group: InsnGroupType::Type| detail.groups()
+        .any(|g| u32::from(g.0) == group);
+    let (flow, mem) = match arch {
+        Arch::Arm | Arch::Thumb | Arch::Arm64 => arm_flow(arch, address, id,
+            &ops, conditional, target, in_group),
+        Arch::Mips | Arch::Mipsel | Arch::Mips64 | Arch::Mips64el =>
+            (mips_flow(mnemonic, &ops, target), None),
+        _ => (ppc_flow(mnemonic, target), None),
+    };
+    let imm = target.filter(|_| flow == Flow::Next && mem.is_none());
+
+    // Instruction and registers, leaving out immediates, displacements and
+    // branch targets
+    let mut norm = fnv(FNV_START, &id.to_le_bytes());
+    for op in ops.iter() {
+        norm = match *op {
+            Operand::Reg(reg) => fnv(fnv(norm, &[1]), &reg.to_le_bytes()),
+            Operand::Imm(_) => fnv(norm, &[2]),
+            Operand::Mem { base, index, .. } => fnv(fnv(fnv(norm, &[3]),
+                &base.to_le_bytes()), &index.to_le_bytes()),
+            Operand::Other => fnv(norm, &[4]),
+        };
+    }
+    Insn { address, len: insn.bytes().len(), text, flow, mem, imm, norm }
+}
+
+/// Flow and memory operand of an ARM instruction
+fn arm_flow(arch: Arch, address: u64, id: u32, ops: &[Operand],
+        conditional: bool, target: Option<u64>,
+        in_group: impl Fn(InsnGroupType::Type) -> bool)
+        -> (Flow, Option<u64>) {
+    let (compare_branch, stop, pc, lr) = if arch == Arch::Arm64 {
+        ([Arm64Insn::ARM64_INS_CBZ, Arm64Insn::ARM64_INS_CBNZ,
+            Arm64Insn::ARM64_INS_TBZ, Arm64Insn::ARM64_INS_TBNZ]
+            .iter().any(|&other| other as u32 == id),
+        [Arm64Insn::ARM64_INS_BRK, Arm64Insn::ARM64_INS_HLT]
+            .iter().any(|&other| other as u32 == id),
+        None, Arm64Reg::ARM64_REG_X30 as u16)
+    } else {
+        ([ArmInsn::ARM_INS_CBZ, ArmInsn::ARM_INS_CBNZ]
+            .iter().any(|&other| other as u32 == id),
+        [ArmInsn::ARM_INS_BKPT, ArmInsn::ARM_INS_UDF, ArmInsn::ARM_INS_HLT]
+            .iter().any(|&other| other as u32 == id),
+        Some(ArmReg::ARM_REG_PC as u16), ArmReg::ARM_REG_LR as u16)
+    };
+
+    let is_pc = |op: &Operand| Some(*op) == pc.map(Operand::Reg);
+    // `pop {..., pc}`, `ldm sp!, {..., pc}`, `ldr pc, [...]`, `mov pc, lr`
+    let loads_pc = ([ArmInsn::ARM_INS_POP, ArmInsn::ARM_INS_LDM]
+            .iter().any(|&other| other as u32 == id)
+            && ops.iter().any(is_pc))
+        || ops.first().is_some_and(is_pc);
+    let returns = in_group(InsnGroupType::CS_GRP_RET)
+        || (ops.len() == 1 && ops[0] == Operand::Reg(lr)
+            && in_group(InsnGroupType::CS_GRP_JUMP))
+        || (loads_pc && (ops.contains(&Operand::Reg(lr))
+            || ops.first().is_some_and(|op| !is_pc(op))));
+
+    let flow = if stop {
+        Flow::Stop
+    } else if returns {
+        if conditional { Flow::Next } else { Flow::Return }
+    } else if in_group(InsnGroupType::CS_GRP_CALL) {
+        Flow::Call(target)
+    } else if in_group(InsnGroupType::CS_GRP_JUMP) || loads_pc {
+        let target = target.filter(|_| !loads_pc);
+        match target {
+            Some(target) if conditional || compare_branch =>
+                Flow::Branch(target),
+            _ if conditional || compare_branch => Flow::Next,
+            _ => Flow::Jump(target),
+        }
+    } else {
+        Flow::Next
+    };
+
+    // Loads relative to the instruction: to `pc` on A32, which reads as
+    // the instruction 8 bytes ahead (4 on Thumb, aligned); a literal on A64
+    let mem = ops.iter().find_map(|op| match *op {
+        Operand::Mem { base, index: 0, disp } if Some(base) == pc => {
+            let pc = match arch {
+                Arch::Thumb => address.wrapping_add(4) & !3,
+                _ => address.wrapping_add(8),
+            };
+            Some(pc.wrapping_add(disp as u64))
+        },
+        _ => None,
+    }).or_else(|| {
+        let literal = arch == Arch::Arm64 && [Arm64Insn::ARM64_INS_LDR,
+                Arm64Insn::ARM64_INS_LDRSW]
+            .iter().any(|&other| other as u32 == id);
+        target.filter(|_| literal)
+    });
+    (flow, mem)
+}
+
+/// Flow of a MIPS instruction, from its mnemonic: capstone does not group
+/// the jumps of MIPS
+fn mips_flow(mnemonic: &str, ops: &[Operand], target: Option<u64>) -> Flow {
+    let ra = Operand::Reg(MipsReg::MIPS_REG_RA as u16);
+    match mnemonic {
+        "jr" | "jr.hb" | "jrc" if ops.first() == Some(&ra) => Flow::Return,
+        "jr" | "jr.hb" | "jrc" => Flow::Jump(None),
+        "jalr" | "jalr.hb" | "jalrc" | "jialc" => Flow::Call(None),
+        "jal" | "jalx" | "bal" | "balc" => Flow::Call(target),
+        "j" | "b" | "bc" => Flow::Jump(target),
+        "break" | "sdbbp" => Flow::Stop,
+        "eret" | "deret" => Flow::Return,
+        _ if mnemonic.starts_with('b') => match target {
+            // bgezal, bltzal: calls on a condition, which return
+            Some(target) if mnemonic.contains("al") => Flow::Call(Some(target)),
+            Some(target) => Flow::Branch(target),
+            None => Flow::Next,
+        },
+        _ => Flow::Next,
+    }
+}
+
+/// Flow of a PowerPC instruction, from its mnemonic: `blr` returns through
+/// the link register, `bctr` jumps through the count register and the `l`
+/// suffix makes a call
+fn ppc_flow(mnemonic: &str, target: Option<u64>) -> Flow {
+    let mnemonic = mnemonic.trim_end_matches(['+', '-']);
+    match mnemonic {
+        "blr" => Flow::Return,
+        "bctr" => Flow::Jump(None),
+        "bctrl" | "blrl" => Flow::Call(None),
+        "bl" | "bla" => Flow::Call(target),
+        "b" | "ba" => Flow::Jump(target),
+        "trap" => Flow::Stop,
+        // Returns and jumps through the count register on a condition
+        _ if mnemonic.starts_with('b') && (mnemonic.ends_with("lr")
+            || mnemonic.ends_with("ctr")) => Flow::Next,
+        _ if mnemonic.starts_with('b') && mnemonic.ends_with("lrl")
+            || mnemonic.ends_with("ctrl") => Flow::Call(None),
+        _ if mnemonic.starts_with('b') => match target {
+            Some(target) if mnemonic.ends_with('l') =>
+                Flow::Call(Some(target)),
+            Some(target) => Flow::Branch(target),
+            None => Flow::Next,
+        },
+        _ => Flow::Next,
+    }
+}
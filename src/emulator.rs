@@ -0,0 +1,1576 @@
+//! Emulation of x86 and x64 code, to watch decryption routines and
+//! unpacking stubs at work without running the sample.
+//!
+//! Instructions are decoded by iced and carried out here, on memory holding
+//! the sections of the sample, a stack and stand-ins for its imports.
+//! Nothing of the sample ever runs on the host: system calls stop the
+//! emulation and the imports only do what their stand-in does, e.g.
+//! `VirtualAlloc` allocates emulated memory.
+use std::{
+    fmt,
+    collections::{BTreeMap, HashMap},
+};
+
+use iced_x86::{
+    ConditionCode, Decoder, DecoderOptions, FlowControl, Instruction, Mnemonic,
+    OpKind, Register,
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::defang;
+use crate::disasm::{Arch, Disassembler};
+use crate::image::{Image};
+use crate::sample::{Format};
+
+/// Size of the pages memory is mapped by
+const PAGE: u64 = 0x1000;
+/// Alignment of the memory allocated for the stack, the stubs and the heap
+const ALIGN: u64 = 0x1_0000;
+/// Memory is allocated below 4 GiB, where 32-bit code reaches it
+const LIMIT: u64 = 0x1_0000_0000;
+const STACK_SIZE: u64 = 0x10_0000;
+/// Bytes of the stack above the first stack pointer, where the caller would
+/// have put the arguments and the home space
+const STACK_TOP: u64 = 0x1000;
+/// Stubs standing in for the imports, the first one being where the code
+/// emulated returns to
+const STUBS: u64 = 0x1000;
+const STUB_SIZE: u64 = 0x10;
+/// Handle returned for the libraries loaded
+const MODULE: u64 = 0x7000_0000;
+/// Handle returned for the heap of the process
+const HEAP: u64 = 0x6000_0000;
+/// Bytes of the longest instruction
+const MAX_INSN: u64 = 15;
+/// Characters of the strings given to the imports shown at most
+const MAX_STRING: usize = 256;
+/// Bytes copied or set at most by one call to an import
+const MAX_COPY: u64 = 0x100_0000;
+/// Pages mapped at most, 4 GiB of the address space
+const MAX_MAPPED: u64 = 0x10_0000;
+/// Regions mapped at most, which every access looks through
+const MAX_REGIONS: usize = 0x1000;
+/// Pages written at most, 256 MiB of the memory of the host
+const MAX_WRITTEN: usize = 0x1_0000;
+/// Instructions emulated at most by `run`, so that endless loops give the
+/// control back
+pub const MAX_STEPS: usize = 1_000_000;
+
+/// Imports the emulator stands in for, with the kinds of their arguments:
+/// `x` a number, `a` an ANSI string and `w` a UTF-16 string. The last field
+/// tells whether the function removes its arguments from the stack on x86,
+/// as the Windows API does and the C runtime does not.
+const APIS: &[(&str, &str, bool)] = &[
+    ("VirtualAlloc", "xxxx", true),
+    ("VirtualFree", "xxx", true),
+    ("VirtualProtect", "xxxx", true),
+    ("GetProcessHeap", "", true),
+    ("HeapAlloc", "xxx", true),
+    ("HeapFree", "xxx", true),
+    ("LocalAlloc", "xx", true),
+    ("LocalFree", "x", true),
+    ("GlobalAlloc", "xx", true),
+    ("GlobalFree", "x", true),
+    ("RtlMoveMemory", "xxx", true),
+    ("RtlZeroMemory", "xx", true),
+    ("LoadLibraryA", "a", true),
+    ("LoadLibraryW", "w", true),
+    ("LoadLibraryExA", "axx", true),
+    ("LoadLibraryExW", "wxx", true),
+    ("GetModuleHandleA", "a", true),
+    ("GetModuleHandleW", "w", true),
+    ("GetProcAddress", "xa", true),
+    ("lstrlenA", "a", true),
+    ("lstrlenW", "w", true),
+    ("Sleep", "x", true),
+    ("GetTickCount", "", true),
+    ("GetLastError", "", true),
+    ("SetLastError", "x", true),
+    ("IsDebuggerPresent", "", true),
+    ("CloseHandle", "x", true),
+    ("OutputDebugStringA", "a", true),
+    ("OutputDebugStringW", "w", true),
+    ("MessageBoxA", "xaax", true),
+    ("MessageBoxW", "xwwx", true),
+    ("ExitProcess", "x", true),
+    ("ExitThread", "x", true),
+    ("malloc", "x", false),
+    ("calloc", "xx", false),
+    ("free", "x", false),
+    ("memcpy", "xxx", false),
+    ("memmove", "xxx", false),
+    ("memset", "xxx", false),
+    ("strlen", "a", false),
+    ("wcslen", "w", false),
+    ("exit", "x", false),
+];
+
+/// Indices in `Machine::regs`, in the order of their encoding
+const RAX: usize = 0;
+const RCX: usize = 1;
+const RDX: usize = 2;
+const RBX: usize = 3;
+const RSP: usize = 4;
+const RBP: usize = 5;
+const RSI: usize = 6;
+const RDI: usize = 7;
+
+/// Names of the registers of x64 and x86, in the order they are shown
+const REGS_64: [(&str, usize); 16] = [("rax", RAX), ("rbx", RBX),
+    ("rcx", RCX), ("rdx", RDX), ("rsi", RSI), ("rdi", RDI), ("rbp", RBP),
+    ("rsp", RSP), ("r8", 8), ("r9", 9), ("r10", 10), ("r11", 11),
+    ("r12", 12), ("r13", 13), ("r14", 14), ("r15", 15)];
+const REGS_32: [(&str, usize); 8] = [("eax", RAX), ("ebx", RBX),
+    ("ecx", RCX), ("edx", RDX), ("esi", RSI), ("edi", RDI), ("ebp", RBP),
+    ("esp", RSP)];
+
+/// Bits of a value of `size` bytes
+fn mask(size: usize) -> u64 {
+    if size >= 8 { u64::MAX } else { (1 << (size * 8)) - 1 }
+}
+
+/// Value of `size` bytes extended with its sign
+fn sext(value: u64, size: usize) -> i64 {
+    let shift = 64 - size as u32 * 8;
+    ((value << shift) as i64) >> shift
+}
+
+/// Memory of the emulated process, mapped by pages. Pages are only
+/// allocated once written, the others read as zeros.
+#[derive(Default)]
+pub struct Memory {
+    /// Ranges of pages mapped, which can overlap
+    mapped: Vec<(u64, u64)>,
+    /// Pages in `mapped`, counting the overlaps again
+    pages_mapped: u64,
+    /// Contents of the pages written
+    pages: HashMap<u64, Box<[u8]>>,
+}
+
+/// Contents of the pages mapped but never written
+static ZEROS: [u8; PAGE as usize] = [0; PAGE as usize];
+
+impl Memory {
+    /// Map zeroed pages over `addr..addr + size`, keeping the pages already
+    /// mapped. Returns false, mapping nothing, once that is too much.
+    pub fn map(&mut self, addr: u64, size: u64) -> bool {
+        if size == 0 {
+            return true;
+        }
+        // The last page of the address space ends past 2^64
+        let first = addr / PAGE;
+        let end = addr.saturating_add(size - 1) / PAGE + 1;
+        if self.mapped.len() >= MAX_REGIONS
+                || self.pages_mapped + (end - first) > MAX_MAPPED {
+            return false;
+        }
+        self.mapped.push((first, end));
+        self.pages_mapped += end - first;
+        true
+    }
+
+    pub fn is_mapped(&self, addr: u64) -> bool {
+        let page = addr / PAGE;
+        self.mapped.iter().any(|&(start, end)| start <= page && page < end)
+    }
+
+    /// Contents of the page holding `addr`, if it is mapped
+    fn page(&self, addr: u64) -> Option<&[u8]> {
+        match self.pages.get(&(addr / PAGE)) {
+            Some(page) => Some(page),
+            None if self.is_mapped(addr) => Some(&ZEROS),
+            None => None,
+        }
+    }
+
+    /// Map `size` bytes at the lowest address where they are all free
+    pub fn alloc(&mut self, size: u64) -> Option<u64> {
+        let size = (size.max(1).checked_add(PAGE - 1)? / PAGE) * PAGE;
+        let mut addr = ALIGN;
+        while addr.checked_add(size)? <= LIMIT {
+            let (first, end) = (addr / PAGE, (addr + size) / PAGE);
+            match self.mapped.iter()
+                    .filter(|&&(start, stop)| start < end && first < stop)
+                    .map(|&(_, stop)| stop)
+                    .max() {
+                Some(used) => addr = (used * PAGE).checked_add(ALIGN - 1)?
+                    / ALIGN * ALIGN,
+                None => return Some(addr).filter(|_| self.map(addr, size)),
+            }
+        }
+        None
+    }
+
+    /// Fill `buf` with the bytes at `addr`, or tell the first address that
+    /// is not mapped
+    pub fn read(&self, addr: u64, buf: &mut [u8]) -> Result<(), u64> {
+        let mut done = 0;
+        while done < buf.len() {
+            let at = addr.wrapping_add(done as u64);
+            let page = self.page(at).ok_or(at)?;
+            let start = (at % PAGE) as usize;
+            let len = (PAGE as usize - start).min(buf.len() - done);
+            buf[done..done + len].copy_from_slice(&page[start..start + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Write `bytes` at `addr`, or tell the first address that is not
+    /// mapped, or mapped but past the pages that can be written. Nothing is
+    /// written unless all of it can be.
+    pub fn write(&mut self, addr: u64, bytes: &[u8]) -> Result<(), u64> {
+        let last = addr.wrapping_add(bytes.len().saturating_sub(1) as u64);
+        if let Some(at) = [addr, last].iter().copied()
+                .chain((addr / PAGE + 1..=last / PAGE).map(|page| page * PAGE))
+                .find(|&at| !self.is_mapped(at)) {
+            return Err(at);
+        }
+        let mut new: Vec<u64> = [addr / PAGE, last / PAGE].iter().copied()
+            .chain(addr / PAGE + 1..=last / PAGE)
+            .filter(|page| !self.pages.contains_key(page))
+            .collect();
+        new.sort_unstable();
+        new.dedup();
+        let left = MAX_WRITTEN.saturating_sub(self.pages.len());
+        if let Some(&page) = new.get(left) {
+            return Err(page * PAGE);
+        }
+        let mut done = 0;
+        while done < bytes.len() {
+            let at = addr.wrapping_add(done as u64);
+            let page = self.pages.entry(at / PAGE)
+                .or_insert_with(|| vec![0; PAGE as usize].into_boxed_slice());
+            let start = (at % PAGE) as usize;
+            let len = (PAGE as usize - start).min(bytes.len() - done);
+            page[start..start + len].copy_from_slice(&bytes[done..done + len]);
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// Bytes at `addr`, `None` for the ones that are not mapped
+    pub fn peek(&self, addr: u64, len: usize) -> Vec<Option<u8>> {
+        (0..len as u64).map(|i| {
+            let at = addr.wrapping_add(i);
+            self.page(at).map(|page| page[(at % PAGE) as usize])
+        }).collect()
+    }
+}
+
+/// Status flags set by the instructions and tested by the conditions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub cf: bool,
+    pub pf: bool,
+    pub zf: bool,
+    pub sf: bool,
+    pub of: bool,
+    /// Direction of the string instructions, backwards when set
+    pub df: bool,
+}
+
+impl Flags {
+    fn test(&self, cc: ConditionCode) -> bool {
+        match cc {
+            ConditionCode::o => self.of,
+            ConditionCode::no => !self.of,
+            ConditionCode::b => self.cf,
+            ConditionCode::ae => !self.cf,
+            ConditionCode::e => self.zf,
+            ConditionCode::ne => !self.zf,
+            ConditionCode::be => self.cf || self.zf,
+            ConditionCode::a => !self.cf && !self.zf,
+            ConditionCode::s => self.sf,
+            ConditionCode::ns => !self.sf,
+            ConditionCode::p => self.pf,
+            ConditionCode::np => !self.pf,
+            ConditionCode::l => self.sf != self.of,
+            ConditionCode::ge => self.sf == self.of,
+            ConditionCode::le => self.zf || self.sf != self.of,
+            ConditionCode::g => !self.zf && self.sf == self.of,
+            ConditionCode::None => true,
+        }
+    }
+
+    /// The flags as laid out in EFLAGS
+    fn bits(&self) -> u64 {
+        u64::from(self.cf) | 0x2 | u64::from(self.pf) << 2
+            | u64::from(self.zf) << 6 | u64::from(self.sf) << 7
+            | u64::from(self.df) << 10 | u64::from(self.of) << 11
+    }
+
+    fn from_bits(bits: u64) -> Flags {
+        Flags { cf: bits & 1 != 0, pf: bits & 1 << 2 != 0,
+            zf: bits & 1 << 6 != 0, sf: bits & 1 << 7 != 0,
+            df: bits & 1 << 10 != 0, of: bits & 1 << 11 != 0 }
+    }
+
+    /// Names of the flags with whether they are set, in the order shown
+    pub fn list(&self) -> [(&'static str, bool); 6] {
+        [("cf", self.cf), ("pf", self.pf), ("zf", self.zf), ("sf", self.sf),
+            ("of", self.of), ("df", self.df)]
+    }
+}
+
+/// Why the emulation stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// Returned to where the code emulated was called from
+    Returned,
+    /// Reached the end of the range emulated
+    End,
+    /// Called `ExitProcess` or the like with this exit code
+    Exited(u64),
+    /// Ran into something it would crash on or that we cannot emulate
+    Fault(String),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Returned => f.write_str("returned"),
+            Stop::End => f.write_str("reached the end of the selection"),
+            Stop::Exited(code) => write!(f, "exited with code {:#x}", code),
+            Stop::Fault(err) => f.write_str(err),
+        }
+    }
+}
+
+/// An emulated x86 or x64 processor with the memory of its process
+pub struct Machine {
+    pub arch: Arch,
+    /// General purpose registers in the order of their encoding, `rax` to
+    /// `r15`
+    regs: [u64; 16],
+    pub rip: u64,
+    pub flags: Flags,
+    pub memory: Memory,
+    /// Where the code emulated returns to once it is done
+    ret: u64,
+    /// Address right after the range emulated
+    end: Option<u64>,
+    /// Name of the imports by the address of their stub, as
+    /// `library!name`
+    stubs: BTreeMap<u64, String>,
+    /// Address of the next stub free, for the functions looked up while
+    /// running
+    next_stub: u64,
+    stubs_end: u64,
+    stack: (u64, u64),
+    /// Base of the `fs` segment on x86 or `gs` on x64: the thread
+    /// environment block
+    teb: u64,
+    /// Load address of the sample, returned for its own module handle
+    base: u64,
+    /// The imports take their arguments as on Linux and macOS, not as on
+    /// Windows
+    sysv: bool,
+    /// Instructions emulated so far
+    pub steps: usize,
+    /// Why the emulation stopped, `None` while it can go on
+    pub stop: Option<Stop>,
+    /// Calls to the imports, e.g. `LoadLibraryA("ws2_32.dll") = 0x70000000`
+    pub calls: Vec<String>,
+    /// Address and size of the last write outside of the stack
+    pub written: Option<(u64, usize)>,
+    /// Memory shown in the Emulator plugin, following the writes when `None`
+    pub view: Option<u64>,
+}
+
+impl Machine {
+    /// Prepare to emulate the code of `image` from `start` until it returns,
+    /// or until it reaches `end`. The sections are loaded from `data`, and
+    /// the imports take their arguments as on the system of `format`.
+    pub fn new(image: &Image, data: &[u8], format: Format, start: u64,
+            end: Option<u64>) -> Result<Machine, String> {
+        let arch = image.arch.ok_or("cannot disassemble this architecture")?;
+        if !matches!(arch, Arch::X86 | Arch::X64) {
+            return Err(format!("cannot emulate {} code, only x86 and x64",
+                arch.name()));
+        }
+
+        let mut memory = Memory::default();
+        let no_memory = || "no memory left to emulate the code".to_string();
+        // The headers, which some code parses to find its own imports or
+        // exports
+        if format == Format::PE {
+            let headers = &data[..data.len().min(PAGE as usize)];
+            memory.map(image.base, PAGE);
+            let _ = memory.write(image.base, headers);
+        }
+        // ELF sections that are not loaded in memory have no address
+        for section in image.sections.iter()
+                .filter(|section| section.va != 0 || section.exec) {
+            if !memory.map(section.va, section.vsize.max(section.size as u64)) {
+                return Err(no_memory());
+            }
+            let end = (section.offset + section.size).min(data.len());
+            if let Some(bytes) = data.get(section.offset..end) {
+                let _ = memory.write(section.va, bytes);
+            }
+        }
+
+        let stack = memory.alloc(STACK_SIZE).ok_or_else(no_memory)?;
+        let stubs = memory.alloc(STUBS * STUB_SIZE).ok_or_else(no_memory)?;
+        let _ = memory.write(stubs, &vec![0xcc; (STUBS * STUB_SIZE) as usize]);
+        // Thread and process environment blocks, telling there is no
+        // debugger
+        let teb = memory.alloc(2 * PAGE).ok_or_else(no_memory)?;
+        let peb = teb + PAGE;
+        let (width, slots) = match arch {
+            Arch::X86 => (4, [(teb + 0x18, teb), (teb + 0x30, peb),
+                (peb + 0x8, image.base)]),
+            _ => (8, [(teb + 0x30, teb), (teb + 0x60, peb),
+                (peb + 0x10, image.base)]),
+        };
+        for (at, value) in slots.iter() {
+            let _ = memory.write(*at, &value.to_le_bytes()[..width]);
+        }
+
+        let mut machine = Machine {
+            arch,
+            regs: [0; 16],
+            rip: start,
+            flags: Flags::default(),
+            memory,
+            ret: stubs,
+            end,
+            stubs: BTreeMap::new(),
+            next_stub: stubs + STUB_SIZE,
+            stubs_end: stubs + STUBS * STUB_SIZE,
+            stack: (stack, stack + STACK_SIZE),
+            teb,
+            base: image.base,
+            sysv: matches!(format, Format::ELF | Format::MachO),
+            steps: 0,
+            stop: None,
+            calls: Vec::new(),
+            written: None,
+            view: None,
+        };
+        // The loader fills the slots of the imports with their address
+        for import in image.imports.iter() {
+            let name = if import.library.is_empty() {
+                import.name.clone()
+            } else {
+                format!("{}!{}", import.library, import.name)
+            };
+            let stub = machine.stub(name);
+            if let Some(slot) = import.slot.filter(|_| stub != 0) {
+                let _ = machine.memory.write(slot,
+                    &stub.to_le_bytes()[..width]);
+            }
+        }
+        machine.regs[RSP] = stack + STACK_SIZE - STACK_TOP;
+        machine.push(machine.ret).map_err(|stop| stop.to_string())?;
+        Ok(machine)
+    }
+
+    /// Bytes of the pointers and of the stack slots
+    fn width(&self) -> usize {
+        if self.arch == Arch::X86 { 4 } else { 8 }
+    }
+
+    /// `addr` wrapped around the address space
+    fn wrap(&self, addr: u64) -> u64 {
+        if self.arch == Arch::X86 { addr & 0xffff_ffff } else { addr }
+    }
+
+    /// Address of the stub of the import `name`, 0 once the stubs run out
+    fn stub(&mut self, name: String) -> u64 {
+        if let Some((&addr, _)) = self.stubs.iter()
+                .find(|(_, stub)| **stub == name) {
+            return addr;
+        }
+        if self.next_stub >= self.stubs_end {
+            return 0;
+        }
+        let addr = self.next_stub;
+        self.next_stub += STUB_SIZE;
+        self.stubs.insert(addr, name);
+        addr
+    }
+
+    /// Name of the import whose stub is at `addr`
+    pub fn import_at(&self, addr: u64) -> Option<&str> {
+        self.stubs.get(&addr).map(|name| name.as_str())
+    }
+
+    /// Registers in the order they are shown, with their names
+    pub fn registers(&self) -> Vec<(&'static str, u64)> {
+        match self.arch {
+            Arch::X86 => REGS_32.iter()
+                .map(|&(name, i)| (name, self.regs[i] & 0xffff_ffff))
+                .chain(std::iter::once(("eip", self.rip)))
+                .collect(),
+            _ => REGS_64.iter()
+                .map(|&(name, i)| (name, self.regs[i]))
+                .chain(std::iter::once(("rip", self.rip)))
+                .collect(),
+        }
+    }
+
+    /// Value of the register called `name`, e.g. `rax` or `esi`
+    pub fn register(&self, name: &str) -> Option<u64> {
+        let name = name.to_ascii_lowercase();
+        self.registers().into_iter()
+            .find(|(reg, _)| *reg == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Bytes of the instruction at `rip`, as many as mapped
+    pub fn code(&self) -> Vec<u8> {
+        let len = (0..MAX_INSN)
+            .take_while(|&i| self.memory.is_mapped(self.rip.wrapping_add(i)))
+            .count();
+        let mut code = vec![0; len];
+        let _ = self.memory.read(self.rip, &mut code);
+        code
+    }
+
+    /// Emulate the next instruction, unless the emulation stopped
+    pub fn step(&mut self) {
+        if self.stop.is_some() {
+            return;
+        }
+        if let Err(stop) = self.execute() {
+            self.stop = Some(stop);
+            return;
+        }
+        self.steps += 1;
+        if self.rip == self.ret {
+            self.stop = Some(Stop::Returned);
+        } else if Some(self.rip) == self.end {
+            self.stop = Some(Stop::End);
+        }
+    }
+
+    /// Emulate the next instruction, and the whole function it calls if it
+    /// is a call
+    pub fn step_over(&mut self) {
+        let call = self.decode().ok()
+            .filter(|insn| insn.mnemonic() == Mnemonic::Call
+                && !self.stubs.contains_key(&self.rip));
+        self.step();
+        if let Some(insn) = call {
+            // Back from the call once past the return address, which stdcall
+            // functions pop along with their arguments
+            let sp = self.regs[RSP].wrapping_add(self.width() as u64);
+            for _ in 1..MAX_STEPS {
+                if self.stop.is_some() || (self.rip == insn.next_ip()
+                        && self.regs[RSP] >= sp) {
+                    break;
+                }
+                self.step();
+            }
+        }
+    }
+
+    /// Emulate up to `max` instructions, until the emulation stops
+    pub fn run(&mut self, max: usize) {
+        for _ in 0..max {
+            if self.stop.is_some() {
+                break;
+            }
+            self.step();
+        }
+    }
+
+    fn decode(&self) -> Result<Instruction, Stop> {
+        let code = self.code();
+        if code.is_empty() {
+            return Err(Stop::Fault(format!("execution of unmapped memory at \
+                {:#x}", self.rip)));
+        }
+        let bitness = self.width() as u32 * 8;
+        let insn = Decoder::with_ip(bitness, &code, self.rip,
+            DecoderOptions::NONE).decode();
+        if insn.is_invalid() {
+            return Err(Stop::Fault(format!("invalid instruction at {:#x}",
+                self.rip)));
+        }
+        Ok(insn)
+    }
+
+    /// Carry out the instruction at `rip`, or the import whose stub it is
+    fn execute(&mut self) -> Result<(), Stop> {
+        if let Some(name) = self.stubs.get(&self.rip).cloned() {
+            return self.call_import(&name);
+        }
+        let insn = self.decode()?;
+        let rip = self.rip;
+        self.rip = insn.next_ip();
+        let result = self.exec(&insn);
+        if result.is_err() {
+            // Show the instruction that failed
+            self.rip = rip;
+        }
+        result
+    }
+
+    fn unsupported(insn: &Instruction) -> Stop {
+        Stop::Fault(format!("cannot emulate {}",
+            format!("{:?}", insn.mnemonic()).to_lowercase()))
+    }
+
+    /// Index in `regs`, bit shift and size of a general purpose register
+    fn gpr(reg: Register) -> Option<(usize, u32, usize)> {
+        if !reg.is_gpr() {
+            return None;
+        }
+        let index = reg.full_register() as usize - Register::RAX as usize;
+        let shift = match reg {
+            Register::AH | Register::CH | Register::DH | Register::BH => 8,
+            _ => 0,
+        };
+        Some((index, shift, reg.size()))
+    }
+
+    fn reg(&self, reg: Register) -> Result<u64, Stop> {
+        match Machine::gpr(reg) {
+            Some((index, shift, size)) =>
+                Ok((self.regs[index] >> shift) & mask(size)),
+            None if matches!(reg, Register::RIP | Register::EIP) =>
+                Ok(self.rip),
+            None => Err(Stop::Fault(format!("cannot emulate the register {:?}",
+                reg).to_lowercase())),
+        }
+    }
+
+    fn set_reg(&mut self, reg: Register, value: u64) -> Result<(), Stop> {
+        let (index, shift, size) = Machine::gpr(reg).ok_or_else(||
+            Stop::Fault(format!("cannot emulate the register {:?}", reg)
+                .to_lowercase()))?;
+        self.regs[index] = match size {
+            // Writing 32 bits clears the upper half
+            4 | 8 => value & mask(size),
+            _ => (self.regs[index] & !(mask(size) << shift))
+                | (value & mask(size)) << shift,
+        };
+        Ok(())
+    }
+
+    /// Accumulator and its extension for operands of `size`: `al` and `ah`,
+    /// `ax` and `dx`, ...
+    fn accumulator(size: usize) -> (Register, Register) {
+        match size {
+            1 => (Register::AL, Register::AH),
+            2 => (Register::AX, Register::DX),
+            4 => (Register::EAX, Register::EDX),
+            _ => (Register::RAX, Register::RDX),
+        }
+    }
+
+    fn load(&self, addr: u64, size: usize) -> Result<u64, Stop> {
+        let mut bytes = [0; 8];
+        self.memory.read(addr, &mut bytes[..size])
+            .map_err(|at| Stop::Fault(format!("read of unmapped memory at \
+                {:#x}", at)))?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn store(&mut self, addr: u64, bytes: &[u8]) -> Result<(), Stop> {
+        self.memory.write(addr, bytes)
+            .map_err(|at| Stop::Fault(if self.memory.is_mapped(at) {
+                "out of memory".to_string()
+            } else {
+                format!("write to unmapped memory at {:#x}", at)
+            }))?;
+        if addr < self.stack.0 || addr >= self.stack.1 {
+            self.written = Some((addr, bytes.len()));
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, value: u64) -> Result<(), Stop> {
+        let width = self.width();
+        let sp = self.wrap(self.regs[RSP].wrapping_sub(width as u64));
+        self.store(sp, &value.to_le_bytes()[..width])?;
+        self.regs[RSP] = sp;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u64, Stop> {
+        let width = self.width();
+        let value = self.load(self.regs[RSP], width)?;
+        self.regs[RSP] = self.wrap(self.regs[RSP].wrapping_add(width as u64));
+        Ok(value)
+    }
+
+    /// Address of the memory operand of `insn`, without its segment
+    fn effective(&self, insn: &Instruction) -> Result<u64, Stop> {
+        if insn.is_ip_rel_memory_operand() {
+            return Ok(insn.ip_rel_memory_address());
+        }
+        let (base, index) = (insn.memory_base(), insn.memory_index());
+        let mut addr = insn.memory_displacement64();
+        if base != Register::None {
+            addr = addr.wrapping_add(self.reg(base)?);
+        }
+        if index != Register::None {
+            addr = addr.wrapping_add(self.reg(index)?
+                .wrapping_mul(u64::from(insn.memory_index_scale())));
+        }
+        // 32-bit addressing wraps around at 4 GiB
+        let wide = base.is_gpr64() || index.is_gpr64()
+            || (base == Register::None && index == Register::None);
+        Ok(if wide { self.wrap(addr) } else { addr & 0xffff_ffff })
+    }
+
+    /// Address of the memory operand of `insn`
+    fn address(&self, insn: &Instruction) -> Result<u64, Stop> {
+        let segment = match (insn.memory_segment(), self.arch) {
+            (Register::FS, Arch::X86) | (Register::GS, Arch::X64) => self.teb,
+            _ => 0,
+        };
+        Ok(self.wrap(self.effective(insn)?.wrapping_add(segment)))
+    }
+
+    /// Size of the operand `i` of `insn`, in bytes
+    fn size(insn: &Instruction, i: u32) -> usize {
+        match insn.op_kind(i) {
+            OpKind::Register => insn.op_register(i).size(),
+            OpKind::Memory => insn.memory_size().size(),
+            OpKind::Immediate8 => 1,
+            OpKind::Immediate16 | OpKind::Immediate8to16 => 2,
+            OpKind::Immediate32 | OpKind::Immediate8to32 => 4,
+            _ => 8,
+        }
+    }
+
+    fn read(&self, insn: &Instruction, i: u32) -> Result<u64, Stop> {
+        match insn.op_kind(i) {
+            OpKind::Register => self.reg(insn.op_register(i)),
+            OpKind::Memory => match insn.memory_size().size() {
+                size @ 1..=8 => self.load(self.address(insn)?, size),
+                _ => Err(Machine::unsupported(insn)),
+            },
+            OpKind::NearBranch16 | OpKind::NearBranch32
+                | OpKind::NearBranch64 => Ok(insn.near_branch_target()),
+            OpKind::Immediate8 | OpKind::Immediate16 | OpKind::Immediate32
+                | OpKind::Immediate64 | OpKind::Immediate8to16
+                | OpKind::Immediate8to32 | OpKind::Immediate8to64
+                | OpKind::Immediate32to64 => Ok(insn.immediate(i)),
+            _ => Err(Machine::unsupported(insn)),
+        }
+    }
+
+    fn write(&mut self, insn: &Instruction, i: u32, value: u64)
+            -> Result<(), Stop> {
+        match insn.op_kind(i) {
+            OpKind::Register => self.set_reg(insn.op_register(i), value),
+            OpKind::Memory => match insn.memory_size().size() {
+                size @ 1..=8 => {
+                    let addr = self.address(insn)?;
+                    self.store(addr, &value.to_le_bytes()[..size])
+                },
+                _ => Err(Machine::unsupported(insn)),
+            },
+            _ => Err(Machine::unsupported(insn)),
+        }
+    }
+
+    /// Set the zero, sign and parity flags for `res`
+    fn set_result(&mut self, res: u64, size: usize) {
+        self.flags.zf = res & mask(size) == 0;
+        self.flags.sf = sext(res, size) < 0;
+        self.flags.pf = (res as u8).count_ones() & 1 == 0;
+    }
+
+    fn add(&mut self, a: u64, b: u64, carry: u64, size: usize) -> u64 {
+        let (a, b) = (a & mask(size), b & mask(size));
+        let wide = u128::from(a) + u128::from(b) + u128::from(carry);
+        let res = wide as u64 & mask(size);
+        self.flags.cf = wide > u128::from(mask(size));
+        self.flags.of = sext((a ^ res) & (b ^ res), size) < 0;
+        self.set_result(res, size);
+        res
+    }
+
+    fn sub(&mut self, a: u64, b: u64, borrow: u64, size: usize) -> u64 {
+        let (a, b) = (a & mask(size), b & mask(size));
+        let res = a.wrapping_sub(b).wrapping_sub(borrow) & mask(size);
+        self.flags.cf = u128::from(a) < u128::from(b) + u128::from(borrow);
+        self.flags.of = sext((a ^ b) & (a ^ res), size) < 0;
+        self.set_result(res, size);
+        res
+    }
+
+    fn logic(&mut self, res: u64, size: usize) -> u64 {
+        self.flags.cf = false;
+        self.flags.of = false;
+        self.set_result(res, size);
+        res & mask(size)
+    }
+
+    fn exec(&mut self, insn: &Instruction) -> Result<(), Stop> {
+        let size = Machine::size(insn, 0);
+        match insn.mnemonic() {
+            Mnemonic::Nop | Mnemonic::Pause | Mnemonic::Endbr32
+                | Mnemonic::Endbr64 | Mnemonic::Lfence | Mnemonic::Mfence
+                | Mnemonic::Sfence => {},
+            Mnemonic::Mov => {
+                let value = self.read(insn, 1)?;
+                self.write(insn, 0, value)?;
+            },
+            Mnemonic::Movzx => {
+                let value = self.read(insn, 1)? & mask(Machine::size(insn, 1));
+                self.write(insn, 0, value)?;
+            },
+            Mnemonic::Movsx | Mnemonic::Movsxd => {
+                let value = sext(self.read(insn, 1)?, Machine::size(insn, 1));
+                self.write(insn, 0, value as u64)?;
+            },
+            Mnemonic::Lea => {
+                let addr = self.effective(insn)?;
+                self.write(insn, 0, addr)?;
+            },
+            Mnemonic::Xchg => {
+                let (a, b) = (self.read(insn, 0)?, self.read(insn, 1)?);
+                self.write(insn, 0, b)?;
+                self.write(insn, 1, a)?;
+            },
+            m @ (Mnemonic::Add | Mnemonic::Adc | Mnemonic::Sub | Mnemonic::Sbb
+                    | Mnemonic::Cmp | Mnemonic::And | Mnemonic::Or
+                    | Mnemonic::Xor | Mnemonic::Test) => {
+                let (a, b) = (self.read(insn, 0)?, self.read(insn, 1)?);
+                let carry = u64::from(self.flags.cf);
+                let res = match m {
+                    Mnemonic::Add => self.add(a, b, 0, size),
+                    Mnemonic::Adc => self.add(a, b, carry, size),
+                    Mnemonic::Sub | Mnemonic::Cmp => self.sub(a, b, 0, size),
+                    Mnemonic::Sbb => self.sub(a, b, carry, size),
+                    Mnemonic::And | Mnemonic::Test => self.logic(a & b, size),
+                    Mnemonic::Or => self.logic(a | b, size),
+                    _ => self.logic(a ^ b, size),
+                };
+                if !matches!(m, Mnemonic::Cmp | Mnemonic::Test) {
+                    self.write(insn, 0, res)?;
+                }
+            },
+            m @ (Mnemonic::Inc | Mnemonic::Dec) => {
+                // The carry is left alone
+                let (a, cf) = (self.read(insn, 0)?, self.flags.cf);
+                let res = if m == Mnemonic::Inc {
+                    self.add(a, 1, 0, size)
+                } else {
+                    self.sub(a, 1, 0, size)
+                };
+                self.flags.cf = cf;
+                self.write(insn, 0, res)?;
+            },
+            Mnemonic::Neg => {
+                let a = self.read(insn, 0)?;
+                let res = self.sub(0, a, 0, size);
+                self.write(insn, 0, res)?;
+            },
+            Mnemonic::Not => {
+                let a = self.read(insn, 0)?;
+                self.write(insn, 0, !a)?;
+            },
+            Mnemonic::Shl | Mnemonic::Sal | Mnemonic::Shr | Mnemonic::Sar
+                | Mnemonic::Rol | Mnemonic::Ror => self.shift(insn, size)?,
+            Mnemonic::Mul => self.multiply(insn, size, false)?,
+            Mnemonic::Imul if insn.op_count() == 1 =>
+                self.multiply(insn, size, true)?,
+            Mnemonic::Imul => {
+                let (a, b) = match insn.op_count() {
+                    2 => (self.read(insn, 0)?, self.read(insn, 1)?),
+                    _ => (self.read(insn, 1)?, self.read(insn, 2)?),
+                };
+                let full = i128::from(sext(a, size)) * i128::from(sext(b, size));
+                let res = full as u64 & mask(size);
+                self.flags.cf = i128::from(sext(res, size)) != full;
+                self.flags.of = self.flags.cf;
+                self.write(insn, 0, res)?;
+            },
+            Mnemonic::Div => self.divide(insn, size, false)?,
+            Mnemonic::Idiv => self.divide(insn, size, true)?,
+            Mnemonic::Cbw => {
+                let al = self.reg(Register::AL)?;
+                self.set_reg(Register::AX, sext(al, 1) as u64)?;
+            },
+            Mnemonic::Cwde => {
+                let ax = self.reg(Register::AX)?;
+                self.set_reg(Register::EAX, sext(ax, 2) as u64)?;
+            },
+            Mnemonic::Cdqe => {
+                let eax = self.reg(Register::EAX)?;
+                self.set_reg(Register::RAX, sext(eax, 4) as u64)?;
+            },
+            m @ (Mnemonic::Cwd | Mnemonic::Cdq | Mnemonic::Cqo) => {
+                let size = match m {
+                    Mnemonic::Cwd => 2,
+                    Mnemonic::Cdq => 4,
+                    _ => 8,
+                };
+                let (acc, ext) = Machine::accumulator(size);
+                let negative = sext(self.reg(acc)?, size) < 0;
+                self.set_reg(ext, if negative { u64::MAX } else { 0 })?;
+            },
+            Mnemonic::Push => {
+                let value = self.read(insn, 0)?;
+                self.push(value)?;
+            },
+            Mnemonic::Pop => {
+                let value = self.pop()?;
+                self.write(insn, 0, value)?;
+            },
+            Mnemonic::Pushad => {
+                let sp = self.regs[RSP];
+                for &i in [RAX, RCX, RDX, RBX].iter() {
+                    self.push(self.regs[i])?;
+                }
+                self.push(sp)?;
+                for &i in [RBP, RSI, RDI].iter() {
+                    self.push(self.regs[i])?;
+                }
+            },
+            Mnemonic::Popad => {
+                for &i in [RDI, RSI, RBP].iter() {
+                    self.regs[i] = self.pop()?;
+                }
+                // The stack pointer saved is skipped
+                self.pop()?;
+                for &i in [RBX, RDX, RCX, RAX].iter() {
+                    self.regs[i] = self.pop()?;
+                }
+            },
+            Mnemonic::Pushf | Mnemonic::Pushfd | Mnemonic::Pushfq =>
+                self.push(self.flags.bits())?,
+            Mnemonic::Popf | Mnemonic::Popfd | Mnemonic::Popfq =>
+                self.flags = Flags::from_bits(self.pop()?),
+            Mnemonic::Call => {
+                let target = self.read(insn, 0)?;
+                self.push(self.rip)?;
+                self.rip = self.wrap(target);
+            },
+            Mnemonic::Jmp => self.rip = self.wrap(self.read(insn, 0)?),
+            Mnemonic::Ret => {
+                let to = self.pop()?;
+                if insn.op_count() == 1 {
+                    self.regs[RSP] = self.wrap(self.regs[RSP]
+                        .wrapping_add(insn.immediate(0)));
+                }
+                self.rip = to;
+            },
+            Mnemonic::Leave => {
+                self.regs[RSP] = self.regs[RBP];
+                self.regs[RBP] = self.pop()?;
+            },
+            m @ (Mnemonic::Loop | Mnemonic::Loope | Mnemonic::Loopne) => {
+                let count = self.wrap(self.regs[RCX].wrapping_sub(1));
+                self.regs[RCX] = count;
+                let taken = count != 0 && match m {
+                    Mnemonic::Loope => self.flags.zf,
+                    Mnemonic::Loopne => !self.flags.zf,
+                    _ => true,
+                };
+                if taken {
+                    self.rip = insn.near_branch_target();
+                }
+            },
+            Mnemonic::Jcxz | Mnemonic::Jecxz | Mnemonic::Jrcxz => {
+                let count = self.reg(insn.op_register(0))
+                    .unwrap_or(self.regs[RCX]);
+                let count = match insn.mnemonic() {
+                    Mnemonic::Jcxz => count & 0xffff,
+                    Mnemonic::Jecxz => count & 0xffff_ffff,
+                    _ => count,
+                };
+                if count == 0 {
+                    self.rip = insn.near_branch_target();
+                }
+            },
+            Mnemonic::Stosb | Mnemonic::Stosw | Mnemonic::Stosd
+                | Mnemonic::Stosq | Mnemonic::Lodsb | Mnemonic::Lodsw
+                | Mnemonic::Lodsd | Mnemonic::Lodsq | Mnemonic::Movsb
+                | Mnemonic::Movsw | Mnemonic::Movsd | Mnemonic::Movsq
+                | Mnemonic::Scasb | Mnemonic::Scasw | Mnemonic::Scasd
+                | Mnemonic::Scasq | Mnemonic::Cmpsb | Mnemonic::Cmpsw
+                | Mnemonic::Cmpsd | Mnemonic::Cmpsq
+                // `movsd` and `cmpsd` also name SSE instructions, on XMM
+                // registers
+                if (0..insn.op_count()).all(|i|
+                    insn.op_kind(i) != OpKind::Register
+                        || insn.op_register(i).is_gpr()) => self.string(insn)?,
+            Mnemonic::Cld => self.flags.df = false,
+            Mnemonic::Std => self.flags.df = true,
+            Mnemonic::Clc => self.flags.cf = false,
+            Mnemonic::Stc => self.flags.cf = true,
+            Mnemonic::Cmc => self.flags.cf = !self.flags.cf,
+            Mnemonic::Bswap => {
+                let value = self.read(insn, 0)?;
+                let swapped = match size {
+                    8 => value.swap_bytes(),
+                    _ => u64::from((value as u32).swap_bytes()),
+                };
+                self.write(insn, 0, swapped)?;
+            },
+            m @ (Mnemonic::Bt | Mnemonic::Bts | Mnemonic::Btr
+                    | Mnemonic::Btc) => {
+                let value = self.read(insn, 0)?;
+                let bit = 1 << (self.read(insn, 1)? % (size as u64 * 8));
+                self.flags.cf = value & bit != 0;
+                match m {
+                    Mnemonic::Bts => self.write(insn, 0, value | bit)?,
+                    Mnemonic::Btr => self.write(insn, 0, value & !bit)?,
+                    Mnemonic::Btc => self.write(insn, 0, value ^ bit)?,
+                    _ => {},
+                }
+            },
+            Mnemonic::Xadd => {
+                let (a, b) = (self.read(insn, 0)?, self.read(insn, 1)?);
+                let res = self.add(a, b, 0, size);
+                self.write(insn, 1, a)?;
+                self.write(insn, 0, res)?;
+            },
+            Mnemonic::Cmpxchg => {
+                let (acc, _) = Machine::accumulator(size);
+                let (a, expected) = (self.read(insn, 0)?, self.reg(acc)?);
+                self.sub(expected, a, 0, size);
+                if self.flags.zf {
+                    let value = self.read(insn, 1)?;
+                    self.write(insn, 0, value)?;
+                } else {
+                    self.set_reg(acc, a)?;
+                }
+            },
+            // The same machine every time, never in a hurry
+            Mnemonic::Rdtsc => {
+                self.set_reg(Register::EAX, self.steps as u64 & 0xffff_ffff)?;
+                self.set_reg(Register::EDX, self.steps as u64 >> 32)?;
+            },
+            Mnemonic::Cpuid => for &i in [RAX, RBX, RCX, RDX].iter() {
+                self.regs[i] = 0;
+            },
+            Mnemonic::Int3 => return Err(Stop::Fault("breakpoint".to_string())),
+            Mnemonic::Int | Mnemonic::Into | Mnemonic::Syscall
+                | Mnemonic::Sysenter => return Err(Stop::Fault(
+                    "system calls are not emulated".to_string())),
+            Mnemonic::Hlt => return Err(Stop::Fault("halted".to_string())),
+            _ if insn.condition_code() != ConditionCode::None => {
+                let taken = self.flags.test(insn.condition_code());
+                if insn.flow_control() == FlowControl::ConditionalBranch {
+                    if taken {
+                        self.rip = insn.near_branch_target();
+                    }
+                } else if insn.op_count() == 1 {
+                    // setcc
+                    self.write(insn, 0, u64::from(taken))?;
+                } else {
+                    // cmovcc, which clears the upper half of 32-bit registers
+                    // either way
+                    let value = self.read(insn, if taken { 1 } else { 0 })?;
+                    self.write(insn, 0, value)?;
+                }
+            },
+            _ => return Err(Machine::unsupported(insn)),
+        }
+        Ok(())
+    }
+
+    fn shift(&mut self, insn: &Instruction, size: usize) -> Result<(), Stop> {
+        let bits = size as u32 * 8;
+        let a = self.read(insn, 0)? & mask(size);
+        let limit = if size == 8 { 0x3f } else { 0x1f };
+        let count = (self.read(insn, 1)? & limit) as u32;
+        if count == 0 {
+            return Ok(());
+        }
+        let msb = |value: u64| (value >> (bits - 1)) & 1 != 0;
+        let res = match insn.mnemonic() {
+            Mnemonic::Shl | Mnemonic::Sal => {
+                let res = (a << count) & mask(size);
+                self.flags.cf = count <= bits && (a >> (bits - count)) & 1 != 0;
+                self.flags.of = msb(res) != self.flags.cf;
+                self.set_result(res, size);
+                res
+            },
+            Mnemonic::Shr => {
+                let res = a >> count;
+                self.flags.cf = (a >> (count - 1)) & 1 != 0;
+                self.flags.of = msb(a);
+                self.set_result(res, size);
+                res
+            },
+            Mnemonic::Sar => {
+                let signed = sext(a, size);
+                let res = (signed >> count) as u64 & mask(size);
+                self.flags.cf = (signed >> (count - 1)) & 1 != 0;
+                self.flags.of = false;
+                self.set_result(res, size);
+                res
+            },
+            Mnemonic::Rol => {
+                let count = count % bits;
+                let res = if count == 0 { a } else {
+                    ((a << count) | (a >> (bits - count))) & mask(size)
+                };
+                self.flags.cf = res & 1 != 0;
+                self.flags.of = msb(res) != self.flags.cf;
+                res
+            },
+            _ => {
+                let count = count % bits;
+                let res = if count == 0 { a } else {
+                    ((a >> count) | (a << (bits - count))) & mask(size)
+                };
+                self.flags.cf = msb(res);
+                self.flags.of = msb(res) != ((res >> (bits - 2)) & 1 != 0);
+                res
+            },
+        };
+        self.write(insn, 0, res)
+    }
+
+    /// `mul` and `imul` with one operand, into the accumulator and its
+    /// extension
+    fn multiply(&mut self, insn: &Instruction, size: usize, signed: bool)
+            -> Result<(), Stop> {
+        let (acc, ext) = Machine::accumulator(size);
+        let (a, b) = (self.reg(acc)?, self.read(insn, 0)? & mask(size));
+        let full = if signed {
+            (i128::from(sext(a, size)) * i128::from(sext(b, size))) as u128
+        } else {
+            u128::from(a) * u128::from(b)
+        };
+        let low = full as u64 & mask(size);
+        let high = (full >> (size * 8)) as u64 & mask(size);
+        self.flags.cf = if signed {
+            i128::from(sext(low, size)) != full as i128
+        } else {
+            high != 0
+        };
+        self.flags.of = self.flags.cf;
+        self.set_reg(acc, low)?;
+        self.set_reg(ext, high)
+    }
+
+    /// `div` and `idiv` of the accumulator and its extension, `ax` for
+    /// bytes
+    fn divide(&mut self, insn: &Instruction, size: usize, signed: bool)
+            -> Result<(), Stop> {
+        let divisor = self.read(insn, 0)? & mask(size);
+        if divisor == 0 {
+            return Err(Stop::Fault("division by zero".to_string()));
+        }
+        let (acc, ext) = Machine::accumulator(size);
+        let bits = size as u32 * 8;
+        let dividend = if size == 1 {
+            u128::from(self.reg(Register::AX)?)
+        } else {
+            u128::from(self.reg(ext)?) << bits | u128::from(self.reg(acc)?)
+        };
+        let (quotient, remainder) = if signed {
+            let shift = 128 - 2 * bits;
+            let dividend = ((dividend << shift) as i128) >> shift;
+            let divisor = i128::from(sext(divisor, size));
+            // The most negative 64-bit dividend over -1 is past i128 too
+            let overflow = || Stop::Fault("division overflow".to_string());
+            let quotient = dividend.checked_div(divisor).ok_or_else(overflow)?;
+            if quotient != i128::from(sext(quotient as u64, size)) {
+                return Err(overflow());
+            }
+            let remainder = dividend.checked_rem(divisor)
+                .ok_or_else(overflow)?;
+            (quotient as u64, remainder as u64)
+        } else {
+            let quotient = dividend / u128::from(divisor);
+            if quotient > u128::from(mask(size)) {
+                return Err(Stop::Fault("division overflow".to_string()));
+            }
+            (quotient as u64, (dividend % u128::from(divisor)) as u64)
+        };
+        self.set_reg(acc, quotient)?;
+        self.set_reg(ext, remainder)
+    }
+
+    /// `stos`, `lods`, `movs`, `scas` and `cmps`, repeated with `rep`
+    fn string(&mut self, insn: &Instruction) -> Result<(), Stop> {
+        use Mnemonic::*;
+
+        let m = insn.mnemonic();
+        let size = match m {
+            Stosb | Lodsb | Movsb | Scasb | Cmpsb => 1,
+            Stosw | Lodsw | Movsw | Scasw | Cmpsw => 2,
+            Stosd | Lodsd | Movsd | Scasd | Cmpsd => 4,
+            _ => 8,
+        };
+        let (acc, _) = Machine::accumulator(size);
+        let repeat = insn.has_rep_prefix() || insn.has_repne_prefix();
+        let step = if self.flags.df {
+            (size as u64).wrapping_neg()
+        } else {
+            size as u64
+        };
+        loop {
+            if repeat && self.wrap(self.regs[RCX]) == 0 {
+                break;
+            }
+            let (si, di) = (self.wrap(self.regs[RSI]), self.wrap(self.regs[RDI]));
+            match m {
+                Stosb | Stosw | Stosd | Stosq => {
+                    let value = self.reg(acc)?;
+                    self.store(di, &value.to_le_bytes()[..size])?;
+                },
+                Lodsb | Lodsw | Lodsd | Lodsq => {
+                    let value = self.load(si, size)?;
+                    self.set_reg(acc, value)?;
+                },
+                Movsb | Movsw | Movsd | Movsq => {
+                    let value = self.load(si, size)?;
+                    self.store(di, &value.to_le_bytes()[..size])?;
+                },
+                Scasb | Scasw | Scasd | Scasq => {
+                    let (a, b) = (self.reg(acc)?, self.load(di, size)?);
+                    self.sub(a, b, 0, size);
+                },
+                _ => {
+                    let (a, b) = (self.load(si, size)?, self.load(di, size)?);
+                    self.sub(a, b, 0, size);
+                },
+            }
+            if !matches!(m, Stosb | Stosw | Stosd | Stosq | Scasb | Scasw
+                    | Scasd | Scasq) {
+                self.regs[RSI] = self.wrap(si.wrapping_add(step));
+            }
+            if !matches!(m, Lodsb | Lodsw | Lodsd | Lodsq) {
+                self.regs[RDI] = self.wrap(di.wrapping_add(step));
+            }
+            if !repeat {
+                break;
+            }
+            self.regs[RCX] = self.wrap(self.regs[RCX].wrapping_sub(1));
+            let compares = matches!(m, Scasb | Scasw | Scasd | Scasq | Cmpsb
+                | Cmpsw | Cmpsd | Cmpsq);
+            if compares && (insn.has_repne_prefix() == self.flags.zf) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Argument `i` of the import being called, its return address on the
+    /// top of the stack
+    fn arg(&self, i: usize) -> Result<u64, Stop> {
+        let sp = self.regs[RSP];
+        let (regs, stack): (&[usize], u64) = match (self.arch, self.sysv) {
+            (Arch::X86, _) => (&[], sp.wrapping_add(4)),
+            (_, true) => (&[RDI, RSI, RDX, RCX, 8, 9], sp.wrapping_add(8)),
+            // Above the home space of the four arguments in registers
+            (_, false) => (&[RCX, RDX, 8, 9], sp.wrapping_add(0x28)),
+        };
+        match regs.get(i) {
+            Some(&reg) => Ok(self.regs[reg] & mask(self.width())),
+            None => {
+                let slot = (i - regs.len()) as u64 * self.width() as u64;
+                self.load(self.wrap(stack.wrapping_add(slot)), self.width())
+            },
+        }
+    }
+
+    /// Length of the NUL-terminated string at `addr`, in characters
+    fn strlen(&self, addr: u64, wide: bool) -> Result<u64, Stop> {
+        let size = if wide { 2 } else { 1 };
+        let mut len = 0;
+        while self.load(addr.wrapping_add(len * size as u64), size)? != 0 {
+            len += 1;
+            if len == MAX_COPY {
+                break;
+            }
+        }
+        Ok(len)
+    }
+
+    /// The NUL-terminated string at `addr`, up to `MAX_STRING` characters
+    /// and as much of it as is mapped
+    fn string_at(&self, addr: u64, wide: bool) -> String {
+        let size = if wide { 2 } else { 1 };
+        let units: Vec<u16> = (0..MAX_STRING as u64)
+            .map(|i| self.load(addr.wrapping_add(i * size as u64), size))
+            .take_while(|unit| matches!(unit, Ok(unit) if *unit != 0))
+            .map(|unit| unit.unwrap_or(0) as u16)
+            .collect();
+        if wide {
+            String::from_utf16_lossy(&units)
+        } else {
+            units.iter().map(|&unit| char::from(unit as u8)).collect()
+        }
+    }
+
+    /// Copy `len` bytes from `src` to `dst`, as `memmove`
+    fn copy(&mut self, dst: u64, src: u64, len: u64) -> Result<(), Stop> {
+        let mut bytes = vec![0; len.min(MAX_COPY) as usize];
+        self.memory.read(src, &mut bytes)
+            .map_err(|at| Stop::Fault(format!("read of unmapped memory at \
+                {:#x}", at)))?;
+        self.store(dst, &bytes)
+    }
+
+    /// Run the stand-in of the import `name`, then return to its caller
+    fn call_import(&mut self, name: &str) -> Result<(), Stop> {
+        let function = name.rsplit('!').next().unwrap_or(name);
+        let (kinds, stdcall) = APIS.iter()
+            .find(|(api, _, _)| *api == function)
+            .map(|&(_, kinds, stdcall)| (kinds, stdcall))
+            .ok_or_else(|| Stop::Fault(format!("{} is not emulated",
+                defang::text(name))))?;
+        let args = (0..kinds.len()).map(|i| self.arg(i))
+            .collect::<Result<Vec<u64>, Stop>>()?;
+        let shown = kinds.chars().zip(args.iter())
+            .map(|(kind, &arg)| match kind {
+                'a' | 'w' if arg != 0 => format!("\"{}\"",
+                    defang::text(&self.string_at(arg, kind == 'w'))),
+                _ => format!("{:#x}", arg),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let call = format!("{}({})", defang::text(name), shown);
+
+        let value = match self.api(function, &args) {
+            Ok(value) => value & mask(self.width()),
+            Err(stop) => {
+                self.calls.push(call);
+                return Err(stop);
+            },
+        };
+        self.calls.push(format!("{} = {:#x}", call, value));
+        self.regs[RAX] = value;
+        self.rip = self.pop()?;
+        if self.arch == Arch::X86 && stdcall && !self.sysv {
+            self.regs[RSP] = self.wrap(self.regs[RSP]
+                .wrapping_add(4 * args.len() as u64));
+        }
+        Ok(())
+    }
+
+    /// What the import `name` returns for `args`, with its effects on the
+    /// memory
+    fn api(&mut self, name: &str, args: &[u64]) -> Result<u64, Stop> {
+        let alloc = |machine: &mut Machine, size: u64| match size {
+            0..=MAX_COPY => machine.memory.alloc(size).unwrap_or(0),
+            _ => 0,
+        };
+        Ok(match name {
+            "VirtualAlloc" => match args[0] & !(PAGE - 1) {
+                0 => alloc(self, args[1]),
+                addr if args[1] <= MAX_COPY
+                        && self.memory.map(addr, args[0] - addr + args[1]) =>
+                    addr,
+                _ => 0,
+            },
+            "VirtualProtect" => {
+                // The previous protection was read, write and execute
+                if args[3] != 0 {
+                    self.store(args[3], &0x40u32.to_le_bytes())?;
+                }
+                1
+            },
+            "HeapAlloc" => alloc(self, args[2]),
+            "LocalAlloc" | "GlobalAlloc" => alloc(self, args[1]),
+            "malloc" => alloc(self, args[0]),
+            "calloc" => alloc(self, args[0].saturating_mul(args[1])),
+            "GetProcessHeap" => HEAP,
+            "VirtualFree" | "HeapFree" | "CloseHandle" | "MessageBoxA"
+                | "MessageBoxW" => 1,
+            "LoadLibraryA" | "LoadLibraryW" | "LoadLibraryExA"
+                | "LoadLibraryExW" => MODULE,
+            "GetModuleHandleA" | "GetModuleHandleW" => match args[0] {
+                0 => self.base,
+                _ => MODULE,
+            },
+            "GetProcAddress" => {
+                let function = match args[1] {
+                    ordinal @ 0..=0xffff => format!("#{}", ordinal),
+                    name => self.string_at(name, false),
+                };
+                self.stub(function)
+            },
+            "lstrlenA" | "strlen" => self.strlen(args[0], false)?,
+            "lstrlenW" | "wcslen" => self.strlen(args[0], true)?,
+            "memcpy" | "memmove" | "RtlMoveMemory" => {
+                self.copy(args[0], args[1], args[2])?;
+                args[0]
+            },
+            "memset" | "RtlZeroMemory" => {
+                let (value, len) = match name {
+                    "memset" => (args[1] as u8, args[2]),
+                    _ => (0, args[1]),
+                };
+                self.store(args[0], &vec![value; len.min(MAX_COPY) as usize])?;
+                args[0]
+            },
+            "GetTickCount" => self.steps as u64,
+            "ExitProcess" | "ExitThread" | "exit" =>
+                return Err(Stop::Exited(args[0])),
+            // Sleeping, freeing, logging: nothing to emulate
+            _ => 0,
+        })
+    }
+}
+
+/// Plugin stepping through the code emulated in the tab, with the
+/// registers, the calls to the imports and the memory last written
+#[derive(Default)]
+pub struct EmulatorView {
+    /// Rows of memory scrolled from the address followed
+    scroll: i64,
+}
+
+impl RenderPlugin for EmulatorView {
+    fn get_name(&self) -> &str {
+        "Emulator"
+    }
+
+    /// Stepping keys win over the global ones, `n` over the next match
+    fn captures_keys(&self) -> bool {
+        true
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let machine = match &ctx.emulation {
+            Some(machine) => machine,
+            None => {
+                f.render_widget(Paragraph::new("nothing emulated, select code \
+                    and run emulate"), area);
+                return;
+            },
+        };
+        let label = Style::default().fg(Color::Cyan);
+        let digits = machine.width() * 2;
+        let mut lines = Vec::new();
+
+        // The instruction about to run, or why it will not
+        let next = match machine.import_at(machine.rip) {
+            Some(name) => defang::text(name),
+            None if machine.rip == machine.ret => "caller".to_string(),
+            None => Disassembler::new(machine.arch)
+                .decode(&machine.code(), machine.rip)
+                .map_or_else(|| "?".to_string(), |insn| insn.text),
+        };
+        lines.push(Spans::from(vec![
+            Span::styled(format!("{:0w$x}  ", machine.rip, w = digits),
+                Style::default().fg(Color::Blue)),
+            Span::styled(next, Style::default().fg(Color::Yellow)),
+        ]));
+        let status = match &machine.stop {
+            Some(stop) => Span::styled(format!("stopped after {} steps: {}",
+                machine.steps, stop), Style::default().fg(Color::Red)),
+            None => Span::raw(format!("{} steps", machine.steps)),
+        };
+        lines.push(Spans::from(status));
+
+        let cell = 4 + digits + 2;
+        let per_line = (area.width as usize / cell).max(1);
+        for row in machine.registers().chunks(per_line) {
+            lines.push(Spans::from(row.iter().flat_map(|(name, value)| vec![
+                Span::styled(format!("{:<4}", name), label),
+                Span::raw(format!("{:0w$x}  ", value, w = digits)),
+            ]).collect::<Vec<_>>()));
+        }
+        let mut flags = vec![Span::styled("flags ", label)];
+        for (name, set) in machine.flags.list().iter() {
+            flags.push(match set {
+                true => Span::styled(name.to_uppercase(),
+                    Style::default().fg(Color::Yellow)),
+                false => Span::styled(*name, Style::default().fg(Color::DarkGray)),
+            });
+            flags.push(Span::raw(" "));
+        }
+        lines.push(Spans::from(flags));
+        if let Some(call) = machine.calls.last() {
+            lines.push(Spans::from(vec![Span::styled("call  ", label),
+                Span::raw(call.as_str())]));
+        }
+
+        // Memory followed, as rows of hex and characters
+        let row = ((area.width as usize).saturating_sub(digits + 2) / 4 / 4 * 4)
+            .clamp(4, 16) as u64;
+        let (followed, what) = match (machine.view, machine.written) {
+            (Some(addr), _) => (addr, "memory"),
+            (None, Some((addr, _))) => (addr, "last write"),
+            (None, None) => (machine.regs[RSP], "stack"),
+        };
+        let start = (followed / row * row)
+            .wrapping_add((self.scroll * row as i64) as u64);
+        lines.push(Spans::from(Span::styled(format!("{} at {:#x}", what,
+            followed), label)));
+        let rows = (area.height as usize).saturating_sub(lines.len());
+        for i in 0..rows as u64 {
+            let addr = start.wrapping_add(i * row);
+            let bytes = machine.memory.peek(addr, row as usize);
+            let hex: String = bytes.iter()
+                .map(|byte| byte.map_or_else(|| "?? ".to_string(),
+                    |byte| format!("{:02x} ", byte)))
+                .collect();
+            let text: String = bytes.iter()
+                .map(|byte| match byte {
+                    Some(byte) if byte.is_ascii_graphic() => char::from(*byte),
+                    _ => '.',
+                })
+                .collect();
+            lines.push(Spans::from(vec![
+                Span::styled(format!("{:0w$x}  ", addr, w = digits),
+                    Style::default().fg(Color::Blue)),
+                Span::raw(hex),
+                Span::raw(defang::text(&text)),
+            ]));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `s` emulates the next instruction, `n` steps over calls, `r` runs
+    /// until the emulation stops, `j`/`k` scroll the memory and `f` follows
+    /// the writes again. The cursor of the tab follows the code.
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let machine = match ctx.emulation.as_mut() {
+            Some(machine) => machine,
+            None => return false,
+        };
+        match key.code {
+            KeyCode::Char('s') => machine.step(),
+            KeyCode::Char('n') => machine.step_over(),
+            KeyCode::Char('r') => machine.run(MAX_STEPS),
+            KeyCode::Char('j') => {
+                self.scroll += 1;
+                return true;
+            },
+            KeyCode::Char('k') => {
+                self.scroll -= 1;
+                return true;
+            },
+            KeyCode::Char('f') => {
+                machine.view = None;
+                self.scroll = 0;
+                return true;
+            },
+            _ => return false,
+        }
+
+        self.scroll = 0;
+        let rip = machine.rip;
+        let offset = ctx.sample.as_ref()
+            .and_then(|sample| sample.image.as_ref())
+            .and_then(|image| image.offset_of(rip));
+        if let Some(offset) = offset.filter(|&offset| offset < ctx.data().len()) {
+            ctx.cursor = offset;
+            ctx.anchor = None;
+        }
+        true
+    }
+}
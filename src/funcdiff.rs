@@ -0,0 +1,485 @@
+//! Function level diff of two executables, matching the functions by the
+//! hashes of their normalized instructions and the shape of their graphs
+use std::{
+    thread,
+    collections::{HashMap},
+    sync::{mpsc, Arc},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::analysis::{self, Function};
+use crate::image::{Image};
+
+/// Functions left unmatched on each side at most compared block by block
+const MAX_FUZZY: usize = 2000;
+/// Share of common blocks for two functions to be a changed pair
+const MIN_SIMILARITY: f64 = 0.5;
+/// Instructions of both sides at most aligned line by line
+const MAX_ALIGN: usize = 1 << 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Changed,
+    Added,
+    Removed,
+    Identical,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Changed => "changed",
+            Status::Added => "added",
+            Status::Removed => "removed",
+            Status::Identical => "identical",
+        }
+    }
+}
+
+/// A function of the first sample, of the second or one of each matched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pair {
+    pub status: Status,
+    /// Index in the functions of the first sample
+    pub a: Option<usize>,
+    /// Index in the functions of the second sample
+    pub b: Option<usize>,
+}
+
+/// Instructions of the first and second function shown on a row of the
+/// side by side view, by index
+pub type Row = (Option<usize>, Option<usize>);
+
+/// Functions of two samples and how they match
+#[derive(Debug, Clone)]
+pub struct FunctionDiff {
+    pub a: Vec<Function>,
+    pub b: Vec<Function>,
+    /// Changed functions first, then the added, removed and identical ones
+    pub pairs: Vec<Pair>,
+}
+
+/// Pair the functions of `left` and `right` with the same `key`, in the
+/// order of their addresses. With `unique`, only the keys a single function
+/// has on each side pair them.
+fn pair_by<K, F>(a: &[Function], b: &[Function], left: &mut Vec<usize>,
+        right: &mut Vec<usize>, key: F, unique: bool,
+        mut pair: impl FnMut(usize, usize))
+        where K: std::hash::Hash + Eq, F: Fn(&Function) -> Option<K> {
+    let mut by_key: HashMap<K, Vec<usize>> = HashMap::new();
+    for &j in right.iter() {
+        if let Some(key) = key(&b[j]) {
+            by_key.entry(key).or_default().push(j);
+        }
+    }
+    let mut left_counts: HashMap<K, usize> = HashMap::new();
+    if unique {
+        for &i in left.iter() {
+            if let Some(key) = key(&a[i]) {
+                *left_counts.entry(key).or_default() += 1;
+            }
+        }
+    }
+    let mut matched = Vec::new();
+    for &i in left.iter() {
+        let key = match key(&a[i]) {
+            Some(key) if !unique || left_counts.get(&key) == Some(&1) => key,
+            _ => continue,
+        };
+        let candidates = match by_key.get_mut(&key) {
+            Some(candidates) if unique && candidates.len() == 1 => candidates,
+            Some(candidates) if !unique && !candidates.is_empty() => candidates,
+            _ => continue,
+        };
+        let j = candidates.remove(0);
+        pair(i, j);
+        matched.push((i, j));
+    }
+    left.retain(|i| !matched.iter().any(|(m, _)| m == i));
+    right.retain(|j| !matched.iter().any(|(_, m)| m == j));
+}
+
+/// Share of the blocks of `a` and `b` found on both sides
+fn similarity(a: &[u64], b: &[u64]) -> f64 {
+    let mut counts: HashMap<u64, isize> = HashMap::new();
+    for hash in a {
+        *counts.entry(*hash).or_default() += 1;
+    }
+    let mut common = 0;
+    for hash in b {
+        if let Some(count) = counts.get_mut(hash).filter(|count| **count > 0) {
+            *count -= 1;
+            common += 1;
+        }
+    }
+    2.0 * common as f64 / (a.len() + b.len()).max(1) as f64
+}
+
+impl FunctionDiff {
+    /// Match the functions `a` of the first sample with the functions `b`
+    /// of the second: by name, then by hash, then by a shape only one
+    /// function has on each side and finally by the share of identical
+    /// blocks
+    pub fn new(a: Vec<Function>, b: Vec<Function>) -> FunctionDiff {
+        let mut pairs = Vec::new();
+        let mut left: Vec<usize> = (0..a.len()).collect();
+        let mut right: Vec<usize> = (0..b.len()).collect();
+        let status = |i: usize, j: usize| if a[i].hash() == b[j].hash() {
+            Status::Identical } else { Status::Changed };
+        let mut pair = |status: Status, i: usize, j: usize|
+            pairs.push(Pair { status, a: Some(i), b: Some(j) });
+
+        pair_by(&a, &b, &mut left, &mut right,
+            |f| Some(f.name.clone()).filter(|_| f.is_named()), false,
+            |i, j| pair(status(i, j), i, j));
+        pair_by(&a, &b, &mut left, &mut right, |f| Some(f.hash()), false,
+            |i, j| pair(Status::Identical, i, j));
+        // Small functions often share their shape, which then tells nothing
+        pair_by(&a, &b, &mut left, &mut right, |f| Some(f.shape()), true,
+            |i, j| pair(Status::Changed, i, j));
+
+        // What is left is compared block by block
+        if left.len() <= MAX_FUZZY && right.len() <= MAX_FUZZY {
+            let blocks = |f: &Function| f.blocks.iter().map(|block| block.hash())
+                .collect::<Vec<u64>>();
+            let right_blocks: Vec<Vec<u64>> = right.iter()
+                .map(|&j| blocks(&b[j])).collect();
+            let mut taken = vec![false; right.len()];
+            let mut matched = Vec::new();
+            for &i in left.iter() {
+                let left_blocks = blocks(&a[i]);
+                let best = right_blocks.iter().enumerate()
+                    .filter(|(k, _)| !taken[*k])
+                    .map(|(k, other)| (k, similarity(&left_blocks, other)))
+                    .filter(|(_, score)| *score >= MIN_SIMILARITY)
+                    .max_by(|x, y| x.1.total_cmp(&y.1));
+                if let Some((k, _)) = best {
+                    taken[k] = true;
+                    matched.push(i);
+                    pair(Status::Changed, i, right[k]);
+                }
+            }
+            left.retain(|i| !matched.contains(i));
+            right = right.into_iter().enumerate()
+                .filter(|(k, _)| !taken[*k])
+                .map(|(_, j)| j)
+                .collect();
+        }
+
+        pairs.extend(left.into_iter()
+            .map(|i| Pair { status: Status::Removed, a: Some(i), b: None }));
+        pairs.extend(right.into_iter()
+            .map(|j| Pair { status: Status::Added, a: None, b: Some(j) }));
+        let address = |pair: &Pair| (pair.a.map(|i| a[i].address),
+            pair.b.map(|j| b[j].address));
+        pairs.sort_by_key(|pair| (pair.status, address(pair)));
+
+        FunctionDiff { a, b, pairs }
+    }
+
+    /// Number of functions in each status
+    pub fn summary(&self) -> String {
+        [Status::Identical, Status::Changed, Status::Added, Status::Removed]
+            .iter()
+            .map(|&status| format!("{} {}", self.pairs.iter()
+                .filter(|pair| pair.status == status).count(), status.name()))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Instructions of a matched pair side by side, aligned on the ones
+    /// that are the same once normalized
+    pub fn align(&self, pair: &Pair) -> Vec<Row> {
+        let norms = |f: Option<&Function>| f.map(|f| f.insns()
+            .map(|insn| insn.norm).collect::<Vec<u64>>()).unwrap_or_default();
+        let a = norms(pair.a.map(|i| &self.a[i]));
+        let b = norms(pair.b.map(|j| &self.b[j]));
+
+        if a.len() * b.len() > MAX_ALIGN {
+            return (0..a.len().max(b.len()))
+                .map(|k| (Some(k).filter(|&k| k < a.len()),
+                    Some(k).filter(|&k| k < b.len())))
+                .collect();
+        }
+
+        // Longest common subsequence, from the end
+        let width = b.len() + 1;
+        let mut lcs = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let mut rows = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                rows.push((Some(i), Some(j)));
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                    || (i < a.len() && lcs[(i + 1) * width + j]
+                        >= lcs[i * width + j + 1]) {
+                rows.push((Some(i), None));
+                i += 1;
+            } else {
+                rows.push((None, Some(j)));
+                j += 1;
+            }
+        }
+        rows
+    }
+}
+
+/// State of the matching of the functions of a diff
+#[derive(Debug)]
+pub enum MatchStatus {
+    Running,
+    Done(FunctionDiff),
+    Failed(String),
+}
+
+/// Functions of both samples of a diff, found and matched in a background
+/// thread since that takes a while for big executables
+#[derive(Debug)]
+pub struct FunctionMatch {
+    pub status: MatchStatus,
+    rx: Option<mpsc::Receiver<Result<FunctionDiff, String>>>,
+}
+
+impl FunctionMatch {
+    /// Find the functions of the executables `a` and `b`, laid out as their
+    /// images tell, and match them in the background
+    pub fn start(a: (Arc<Image>, Arc<[u8]>), b: (Arc<Image>, Arc<[u8]>))
+            -> FunctionMatch {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let functions = |(image, data): &(Arc<Image>, Arc<[u8]>)|
+                analysis::functions(image, data);
+            let _ = tx.send(functions(&a).and_then(|a| Ok(FunctionDiff::new(a,
+                functions(&b)?))));
+        });
+        FunctionMatch { status: MatchStatus::Running, rx: Some(rx) }
+    }
+
+    /// Functions that cannot be matched because of `err`
+    pub fn failed(err: String) -> FunctionMatch {
+        FunctionMatch { status: MatchStatus::Failed(err), rx: None }
+    }
+
+    /// Collect the functions if they are matched
+    pub fn poll(&mut self) {
+        let diff = match self.rx.as_ref().map(|rx| rx.try_recv()) {
+            Some(Ok(diff)) => diff,
+            Some(Err(mpsc::TryRecvError::Disconnected)) =>
+                Err("analysis thread stopped".to_string()),
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+        };
+
+        self.rx = None;
+        self.status = match diff {
+            Ok(diff) => MatchStatus::Done(diff),
+            Err(err) => MatchStatus::Failed(err),
+        };
+    }
+
+    /// The functions matched, once they are
+    pub fn result(&self) -> Result<&FunctionDiff, &str> {
+        match &self.status {
+            MatchStatus::Done(diff) => Ok(diff),
+            MatchStatus::Running => Err("functions are still being matched"),
+            MatchStatus::Failed(err) => Err(err),
+        }
+    }
+}
+
+/// Plugin listing the functions of a diff by status, and the disassembly
+/// of a pair side by side
+#[derive(Default)]
+pub struct FunctionDiffView {
+    selected: usize,
+    scroll: usize,
+    /// Pair shown side by side with its aligned instructions
+    open: Option<(usize, Vec<Row>)>,
+    /// First row of the side by side view shown
+    row: usize,
+}
+
+/// One side of a row of the side by side view
+fn insn_span<'a>(function: Option<&Function>, index: Option<usize>,
+        width: usize, style: Style) -> Span<'a> {
+    let text = match (function, index) {
+        (Some(function), Some(index)) => {
+            let insn = function.insns().nth(index).unwrap();
+            format!("{:08x} {}", insn.address, insn.text)
+        },
+        _ => String::new(),
+    };
+    let text: String = text.chars().take(width).collect();
+    Span::styled(format!("{:<w$}", text, w = width), style)
+}
+
+impl FunctionDiffView {
+    fn draw_pair<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            diff: &FunctionDiff) {
+        let (index, rows) = self.open.as_ref().unwrap();
+        let pair = &diff.pairs[*index];
+        let a = pair.a.map(|i| &diff.a[i]);
+        let b = pair.b.map(|j| &diff.b[j]);
+        let width = (area.width as usize).saturating_sub(3) / 2;
+
+        let title = Style::default().add_modifier(Modifier::BOLD);
+        let name = |f: Option<&Function>| f.map_or(String::new(),
+            |f| f.name.clone());
+        let mut lines = vec![Spans::from(vec![
+            Span::styled(format!("{:<w$}", name(a), w = width), title),
+            Span::raw(" | "),
+            Span::styled(name(b), title),
+        ])];
+
+        let height = (area.height as usize).saturating_sub(1);
+        self.row = self.row.min(rows.len().saturating_sub(height));
+        for &(i, j) in rows.iter().skip(self.row).take(height) {
+            let changed = i.is_none() || j.is_none();
+            let (left, right) = match changed {
+                true => (Style::default().fg(Color::Red),
+                         Style::default().fg(Color::Green)),
+                false => (Style::default(), Style::default()),
+            };
+            lines.push(Spans::from(vec![
+                insn_span(a, i, width, left),
+                Span::raw(" | "),
+                insn_span(b, j, width, right),
+            ]));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+}
+
+impl RenderPlugin for FunctionDiffView {
+    fn get_name(&self) -> &str {
+        "Functions"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let diff = match ctx.diff.as_ref().map(|diff| diff.functions.result()) {
+            Some(Ok(diff)) => diff,
+            Some(Err(err)) => {
+                f.render_widget(Paragraph::new(Span::styled(
+                    format!("no functions: {}", err),
+                    Style::default().fg(Color::DarkGray))), area);
+                return;
+            },
+            None => {
+                f.render_widget(Paragraph::new(
+                    "no diff, see the diff command"), area);
+                return;
+            }
+        };
+        if self.open.is_some() {
+            self.draw_pair(f, area, diff);
+            return;
+        }
+
+        let mut lines = vec![Spans::from(Span::styled(diff.summary(),
+            Style::default().fg(Color::Blue)))];
+        self.selected = self.selected.min(diff.pairs.len().saturating_sub(1));
+        let rows = (area.height as usize).saturating_sub(1).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let name = |function: Option<&Function>| function.map_or(
+            "-".to_string(), |f| format!("{}@{:x}", f.name, f.address));
+        for (i, pair) in diff.pairs.iter().enumerate().skip(self.scroll)
+                .take(rows) {
+            let color = match pair.status {
+                Status::Changed => Color::Yellow,
+                Status::Added => Color::Green,
+                Status::Removed => Color::Red,
+                Status::Identical => Color::DarkGray,
+            };
+            let mut style = Style::default().fg(color);
+            if i == self.selected {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            let a = pair.a.map(|i| &diff.a[i]);
+            let b = pair.b.map(|j| &diff.b[j]);
+            let text = match a.zip(b) {
+                Some((a, b)) if a.name == b.name && a.address == b.address =>
+                    name(Some(a)),
+                _ => format!("{} -> {}", name(a), name(b)),
+            };
+            lines.push(Spans::from(vec![
+                Span::styled(format!("{:<10}", pair.status.name()), style),
+                Span::styled(text, style),
+            ]));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous function, moving the cursor of the
+    /// tab to it. `Enter` shows the selected pair side by side, `j`/`k`
+    /// scroll it and `Esc` goes back to the list.
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let diff = match ctx.diff.as_ref().map(|diff| diff.functions.result()) {
+            Some(Ok(diff)) => diff,
+            _ => return false,
+        };
+        if self.open.is_some() {
+            match key.code {
+                KeyCode::Char('j') | KeyCode::Down => self.row += 1,
+                KeyCode::Char('k') | KeyCode::Up =>
+                    self.row = self.row.saturating_sub(1),
+                KeyCode::Esc | KeyCode::Char('q') => self.open = None,
+                _ => return false,
+            }
+            return true;
+        }
+
+        let count = diff.pairs.len();
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down =>
+                self.selected = (self.selected + 1).min(count.saturating_sub(1)),
+            KeyCode::Char('k') | KeyCode::Up =>
+                self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End =>
+                self.selected = count.saturating_sub(1),
+            KeyCode::Enter => match diff.pairs.get(self.selected) {
+                Some(pair) => {
+                    self.open = Some((self.selected, diff.align(pair)));
+                    self.row = 0;
+                },
+                None => return false,
+            },
+            _ => return false,
+        }
+        // The other plugins of the tab follow the function of the first
+        // sample
+        if let Some(i) = diff.pairs.get(self.selected).and_then(|pair| pair.a) {
+            ctx.cursor = diff.a[i].offset;
+        }
+        true
+    }
+}
@@ -0,0 +1,420 @@
+//! Functions of executables, found in a background thread once a plugin
+//! needs them, and the calls between them
+use std::{
+    thread,
+    collections::{HashMap},
+    sync::{mpsc, Arc},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::analysis::{self, Function};
+use crate::app::{RenderPlugin, TabContext};
+use crate::disasm::{Flow};
+use crate::image::{Image};
+use crate::listing::{code_image};
+use crate::sample::{Sample};
+
+/// A function called by another one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Callee {
+    /// Address of the function, or of the slot of the import
+    pub va: u64,
+    pub name: String,
+    pub import: bool,
+}
+
+/// Functions of an executable, by address
+#[derive(Debug, Default)]
+pub struct FunctionIndex {
+    pub functions: Vec<Function>,
+    /// Addresses of the functions calling each function
+    callers: HashMap<u64, Vec<u64>>,
+}
+
+impl FunctionIndex {
+    /// Find the functions of the executable described by `image` in `data`
+    pub fn build(image: &Image, data: &[u8]) -> Result<FunctionIndex, String> {
+        let functions = analysis::functions(image, data)?;
+        let mut callers: HashMap<u64, Vec<u64>> = HashMap::new();
+        for function in functions.iter() {
+            for &target in function.calls.iter() {
+                callers.entry(target).or_default().push(function.address);
+            }
+        }
+        Ok(FunctionIndex { functions, callers })
+    }
+
+    /// Function starting at `address`
+    pub fn get(&self, address: u64) -> Option<&Function> {
+        self.functions.binary_search_by_key(&address, |f| f.address).ok()
+            .map(|i| &self.functions[i])
+    }
+
+    /// Function holding the instruction at `va`: one of the closest
+    /// starting before it
+    pub fn at(&self, va: u64) -> Option<&Function> {
+        let end = self.functions.partition_point(|f| f.address <= va);
+        self.functions[..end].iter().rev().take(analysis::MAX_CANDIDATES)
+            .find(|f| f.block_at(va).is_some())
+    }
+
+    /// Functions calling the one at `address`, by address
+    pub fn callers(&self, address: u64) -> Vec<&Function> {
+        let mut callers: Vec<&Function> = self.callers.get(&address)
+            .map_or(&[][..], |callers| &callers[..])
+            .iter()
+            .filter_map(|&caller| self.get(caller))
+            .collect();
+        callers.sort_by_key(|f| f.address);
+        callers
+    }
+
+    /// Functions and imports called by `function`: the functions called
+    /// directly by address, then the imports called through their slot
+    pub fn callees(&self, image: &Image, function: &Function) -> Vec<Callee> {
+        let mut callees: Vec<Callee> = function.calls.iter()
+            .map(|&va| Callee {
+                va,
+                name: self.get(va).map_or_else(|| format!("sub_{:x}", va),
+                    |f| f.name.clone()),
+                import: false,
+            })
+            .collect();
+        let mut slots: Vec<u64> = function.insns()
+            .filter(|insn| insn.flow == Flow::Call(None))
+            .filter_map(|insn| insn.mem)
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        callees.extend(slots.into_iter().filter_map(|slot| image.imports.iter()
+            .find(|import| import.slot == Some(slot))
+            .map(|import| Callee {
+                va: slot,
+                name: if import.library.is_empty() {
+                    import.name.clone()
+                } else {
+                    format!("{}!{}", import.library, import.name)
+                },
+                import: true,
+            })));
+        callees
+    }
+}
+
+/// State of the function index of a tab
+#[derive(Debug)]
+pub enum FunctionStatus {
+    /// Never analyzed, or not an executable
+    Idle,
+    Running,
+    Done(FunctionIndex),
+    Failed(String),
+}
+
+/// The function index of a tab's sample
+pub struct FunctionScan {
+    pub status: FunctionStatus,
+    /// SHA-256 of the sample analyzed, to analyze it again once it changes
+    pub sha256: String,
+    /// Contents analyzed, to analyze them again once they are edited
+    data: Option<Arc<[u8]>>,
+    rx: Option<mpsc::Receiver<Result<FunctionIndex, String>>>,
+}
+
+impl Default for FunctionScan {
+    fn default() -> Self {
+        FunctionScan { status: FunctionStatus::Idle, sha256: String::new(),
+            data: None, rx: None }
+    }
+}
+
+impl FunctionScan {
+    /// True if the functions found are not the ones of `data`, the
+    /// contents of `sample` with the edits applied
+    pub fn is_stale(&self, sample: &Sample, data: &Arc<[u8]>) -> bool {
+        sample.sha256 != self.sha256
+            || !self.data.as_ref().is_some_and(|old| Arc::ptr_eq(old, data))
+    }
+
+    /// Find the functions in `data`, the contents of `sample` with the edits
+    /// applied, in the background, dropping the previous ones. Samples which
+    /// are not executables are left idle.
+    pub fn start(&mut self, sample: &Sample, data: Arc<[u8]>) {
+        *self = FunctionScan { sha256: sample.sha256.clone(),
+            data: Some(data.clone()), ..FunctionScan::default() };
+        let image = match &sample.image {
+            Some(image) => image.clone(),
+            None => return,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(FunctionIndex::build(&image, &data));
+        });
+        self.status = FunctionStatus::Running;
+        self.rx = Some(rx);
+    }
+
+    /// Collect the functions if they are found
+    pub fn poll(&mut self) {
+        let index = match self.rx.as_ref().map(|rx| rx.try_recv()) {
+            Some(Ok(index)) => index,
+            Some(Err(mpsc::TryRecvError::Disconnected)) =>
+                Err("analysis thread stopped".to_string()),
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+        };
+
+        self.rx = None;
+        self.status = match index {
+            Ok(index) => FunctionStatus::Done(index),
+            Err(err) => FunctionStatus::Failed(err),
+        };
+    }
+
+    /// The index, once built
+    pub fn index(&self) -> Result<&FunctionIndex, &str> {
+        match &self.status {
+            FunctionStatus::Done(index) => Ok(index),
+            FunctionStatus::Running => Err("functions are still being found"),
+            FunctionStatus::Failed(err) => Err(err),
+            FunctionStatus::Idle => Err("the sample has no code to analyze"),
+        }
+    }
+}
+
+/// Move the cursor of the tab to `va`, if it is in the file
+fn go_to(ctx: &mut TabContext, va: u64) {
+    let offset = code_image(ctx).ok().and_then(|image| image.offset_of(va));
+    if let Some(offset) = offset {
+        ctx.cursor = offset.min(ctx.data().len().saturating_sub(1));
+    }
+}
+
+/// Scroll `scroll` so that `line` is in the `rows` shown
+fn scroll_to(scroll: &mut usize, line: usize, rows: usize) {
+    if line < *scroll {
+        *scroll = line;
+    } else if line >= *scroll + rows {
+        *scroll = line + 1 - rows;
+    }
+}
+
+/// Plugin listing the functions of the tab's sample
+#[derive(Default)]
+pub struct FunctionList {
+    selected: usize,
+    /// First line shown
+    scroll: usize,
+    /// Rows shown in the last draw
+    rows: usize,
+}
+
+impl RenderPlugin for FunctionList {
+    fn get_name(&self) -> &str {
+        "Functions"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let index = match code_image(ctx)
+                .and_then(|_| ctx.functions.index().map_err(str::to_string)) {
+            Ok(index) if index.functions.is_empty() =>
+                Err("no functions found".to_string()),
+            index => index,
+        };
+        let functions = match index {
+            Ok(index) => &index.functions,
+            Err(err) => {
+                f.render_widget(Paragraph::new(err), area);
+                return;
+            },
+        };
+
+        self.rows = (area.height as usize).max(1);
+        self.selected = self.selected.min(functions.len() - 1);
+        scroll_to(&mut self.scroll, self.selected, self.rows);
+        let address_style = Style::default().fg(Color::Blue);
+        let size_style = Style::default().fg(Color::DarkGray);
+        let lines: Vec<Spans> = functions.iter().enumerate()
+            .skip(self.scroll)
+            .take(self.rows)
+            .map(|(i, function)| {
+                let style = if i == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                let shape = function.shape();
+                Spans::from(vec![
+                    Span::styled(format!("{:08x} ", function.address),
+                        address_style.patch(style)),
+                    Span::styled(function.name.clone(), style),
+                    Span::styled(format!("  blocks: {}, instructions: {}",
+                        shape.blocks, shape.insns), size_style),
+                ])
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// Address of the selected function
+    fn address(&self, ctx: &TabContext) -> Option<u64> {
+        ctx.functions.index().ok()
+            .and_then(|index| index.functions.get(self.selected))
+            .map(|function| function.address)
+    }
+
+    /// `j`/`k` select the next/previous function and `Enter` moves the
+    /// cursor of the tab to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let count = ctx.functions.index()
+            .map_or(0, |index| index.functions.len());
+        let last = count.saturating_sub(1);
+        match key.code {
+            KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::PageDown => self.selected = (self.selected + self.rows)
+                .min(last),
+            KeyCode::PageUp => self.selected = self.selected
+                .saturating_sub(self.rows),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Enter => {
+                if let Some(va) = self.address(ctx) {
+                    go_to(ctx, va);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Plugin showing the functions calling the function holding the cursor
+/// of the tab, and the functions and imports it calls
+#[derive(Default)]
+pub struct CallGraph {
+    title: String,
+    /// Selected caller or callee, callers first
+    selected: usize,
+    /// Address of each caller and callee in the last draw
+    targets: Vec<u64>,
+    /// Function the selection is in
+    function: Option<u64>,
+    scroll: usize,
+}
+
+impl RenderPlugin for CallGraph {
+    fn get_name(&self) -> &str {
+        if self.title.is_empty() { "Call graph" } else { &self.title }
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        self.title.clear();
+        self.targets.clear();
+        let found = code_image(ctx).and_then(|image| {
+            let index = ctx.functions.index().map_err(str::to_string)?;
+            let va = image.va_of(ctx.cursor)
+                .ok_or("the cursor is not in a section loaded in memory")?;
+            let function = index.at(va).ok_or_else(|| format!(
+                "no function holds {:#x}", va))?;
+            Ok((image, index, function))
+        });
+        let (image, index, function) = match found {
+            Ok(found) => found,
+            Err(err) => {
+                f.render_widget(Paragraph::new(err), area);
+                return;
+            },
+        };
+        self.title = format!("Call graph {}", function.name);
+        if self.function != Some(function.address) {
+            self.function = Some(function.address);
+            self.selected = 0;
+            self.scroll = 0;
+        }
+
+        let callers = index.callers(function.address);
+        let callees = index.callees(image, function);
+        let address_style = Style::default().fg(Color::Blue);
+        let heading_style = Style::default().fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        let import_style = Style::default().fg(Color::DarkGray);
+        let mut lines = Vec::new();
+        let mut selected_line = 0;
+        let current = self.selected;
+        let mut item = |lines: &mut Vec<Spans>, targets: &mut Vec<u64>,
+                arrow: &str, va: u64, name: String, style: Style| {
+            let selected = targets.len() == current;
+            let reversed = if selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            if selected {
+                selected_line = lines.len();
+            }
+            lines.push(Spans::from(vec![
+                Span::raw(format!("  {} ", arrow)),
+                Span::styled(format!("{:08x} ", va), address_style.patch(reversed)),
+                Span::styled(name, style.patch(reversed)),
+            ]));
+            targets.push(va);
+        };
+
+        lines.push(Spans::from(Span::styled(format!("Callers ({})",
+            callers.len()), heading_style)));
+        for caller in callers.iter() {
+            item(&mut lines, &mut self.targets, "◀", caller.address,
+                caller.name.clone(), Style::default());
+        }
+        lines.push(Spans::from(Span::styled(format!("Callees ({})",
+            callees.len()), heading_style)));
+        for callee in callees {
+            let style = if callee.import { import_style } else { Style::default() };
+            item(&mut lines, &mut self.targets, "▶", callee.va, callee.name,
+                style);
+        }
+        self.selected = self.selected.min(self.targets.len().saturating_sub(1));
+
+        let rows = (area.height as usize).max(1);
+        scroll_to(&mut self.scroll, selected_line, rows);
+        let lines: Vec<Spans> = lines.into_iter().skip(self.scroll).collect();
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// Address of the selected caller or callee
+    fn address(&self, _ctx: &TabContext) -> Option<u64> {
+        self.targets.get(self.selected).copied()
+    }
+
+    /// `j`/`k` select the next/previous caller or callee and `Enter` moves
+    /// the cursor of the tab to it, to walk the call graph
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let last = self.targets.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Enter => {
+                if let Some(&va) = self.targets.get(self.selected) {
+                    go_to(ctx, va);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,373 @@
+//! Hex dump of the tab's sample with the cursor shared by the tab. Bytes
+//! can be edited in place, see `patch` for how edits are kept.
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::search::{printable};
+use crate::template::{FIELD_COLORS};
+
+/// Width of the offset column, followed by two spaces
+const OFFSET_WIDTH: usize = 8;
+/// Characters of the section names shown at most next to virtual addresses
+const MAX_NAME: usize = 8;
+
+/// How typed keys change the sample in edit mode
+#[derive(Debug, Default, Clone, Copy)]
+struct EditMode {
+    /// Insert bytes before the cursor instead of overwriting them
+    insert: bool,
+    /// Type characters in the ASCII column instead of hex digits
+    ascii: bool,
+    /// The high nibble of the byte under the cursor was typed, the next hex
+    /// digit completes the byte
+    nibble: bool,
+    /// Join the next byte typed with the last edit, to undo a run at once
+    merge: bool,
+}
+
+pub struct HexView {
+    /// First row shown
+    scroll: usize,
+    /// Bytes per row in the last draw
+    per_row: usize,
+    /// Rows shown in the last draw
+    rows: usize,
+    /// Set while editing
+    edit: Option<EditMode>,
+    /// Show the virtual addresses of the rows and their sections instead of
+    /// file offsets, for executables
+    virtual_addresses: bool,
+}
+
+impl Default for HexView {
+    fn default() -> Self {
+        HexView { scroll: 0, per_row: 16, rows: 1, edit: None,
+            virtual_addresses: false }
+    }
+}
+
+impl HexView {
+    pub fn new() -> HexView {
+        HexView::default()
+    }
+
+    /// Largest power of two bytes per row that fits in `width` columns
+    /// after an address column of `gutter` columns. Each byte takes 3
+    /// columns in hex and 1 in ASCII.
+    fn bytes_per_row(width: u16, gutter: usize) -> usize {
+        let width = width as usize;
+        let mut per_row = 16;
+        while per_row > 1 && gutter + 2 + per_row * 4 + 1 > width {
+            per_row /= 2;
+        }
+        per_row
+    }
+
+    /// Handle a key in edit mode, returns false for keys that are not
+    /// editing keys so that they move the cursor as usual
+    fn on_edit_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let (mut mode, original) = match (self.edit, &ctx.sample) {
+            (Some(mode), Some(sample)) => (mode, sample.data.clone()),
+            _ => return false,
+        };
+
+        // The cursor of the diff goes past the end for an insertion there
+        let cursor = ctx.cursor.min(ctx.data().len().saturating_sub(1));
+        let current = match ctx.data().get(cursor) {
+            Some(&byte) => byte,
+            None => return false,
+        };
+        match key.code {
+            KeyCode::Esc => {
+                self.edit = None;
+                return true;
+            },
+            KeyCode::Tab => mode = EditMode { ascii: !mode.ascii, ..mode },
+            KeyCode::Insert => mode = EditMode { insert: !mode.insert, ..mode },
+            KeyCode::Backspace => ctx.cursor = cursor.saturating_sub(1),
+            KeyCode::Char(c) if mode.ascii
+                    && (c == ' ' || c.is_ascii_graphic()) => {
+                let byte = c as u8;
+                if mode.insert {
+                    ctx.patches.insert(&original, cursor, byte, mode.merge);
+                } else {
+                    ctx.patches.overwrite(&original, cursor, byte, mode.merge);
+                }
+                ctx.cursor = cursor + 1;
+                mode.merge = true;
+            },
+            KeyCode::Char(c) if !mode.ascii && c.is_ascii_hexdigit() => {
+                let digit = c.to_digit(16).unwrap() as u8;
+                if mode.nibble {
+                    // The byte is already in the edit, complete it
+                    ctx.patches.overwrite(&original, cursor,
+                        current & 0xf0 | digit, true);
+                    ctx.cursor = cursor + 1;
+                } else if mode.insert {
+                    ctx.patches.insert(&original, cursor, digit << 4,
+                        mode.merge);
+                } else {
+                    ctx.patches.overwrite(&original, cursor,
+                        digit << 4 | current & 0x0f, mode.merge);
+                }
+                mode.nibble = !mode.nibble;
+                mode.merge = true;
+                ctx.cursor = ctx.cursor.min(ctx.data().len() - 1);
+                self.edit = Some(mode);
+                return true;
+            },
+            _ => {
+                // Moving around ends the run of edits
+                self.edit = Some(EditMode { nibble: false, merge: false,
+                    ..mode });
+                return false;
+            },
+        }
+
+        ctx.cursor = ctx.cursor.min(ctx.data().len() - 1);
+        self.edit = Some(EditMode { nibble: false, ..mode });
+        true
+    }
+}
+
+impl RenderPlugin for HexView {
+    fn get_name(&self) -> &str {
+        match self.edit {
+            None => "HexView",
+            Some(EditMode { insert: true, .. }) => "HexView [insert]",
+            Some(_) => "HexView [edit]",
+        }
+    }
+
+    /// Bytes typed in edit mode are written, not taken as commands
+    fn captures_keys(&self) -> bool {
+        self.edit.is_some()
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let data = ctx.data();
+        if data.is_empty() {
+            let text = if ctx.sample.is_some() { "empty sample" }
+                       else { "no sample" };
+            f.render_widget(Paragraph::new(text), area);
+            return;
+        }
+
+        // Virtual addresses are followed by the name of their section
+        let image = ctx.sample.as_ref().and_then(|sample| sample.image.as_deref())
+            .filter(|_| self.virtual_addresses);
+        let (va_width, name_width) = match image {
+            Some(image) => {
+                let end = image.sections.iter()
+                    .map(|section| section.va.saturating_add(section.vsize))
+                    .max().unwrap_or(0);
+                let digits = (64 - end.leading_zeros() as usize).div_ceil(4);
+                (digits.max(OFFSET_WIDTH), image.sections.iter()
+                    .map(|section| section.name.chars().count().min(MAX_NAME))
+                    .max().unwrap_or(0))
+            },
+            None => (OFFSET_WIDTH, 0),
+        };
+        let gutter = match image {
+            Some(_) => va_width + 1 + name_width,
+            None => OFFSET_WIDTH,
+        };
+        self.per_row = HexView::bytes_per_row(area.width, gutter);
+        self.rows = (area.height as usize).max(1);
+
+        // Scroll just enough to keep the cursor in view
+        let cursor = ctx.cursor.min(data.len() - 1);
+        let cursor_row = cursor / self.per_row;
+        if cursor_row < self.scroll {
+            self.scroll = cursor_row;
+        } else if cursor_row >= self.scroll + self.rows {
+            self.scroll = cursor_row + 1 - self.rows;
+        }
+
+        let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
+        // While editing, the column not typed in only underlines the cursor
+        let (hex_cursor, ascii_cursor) = match self.edit {
+            Some(EditMode { ascii: true, .. }) =>
+                (Style::default().add_modifier(Modifier::UNDERLINED),
+                 cursor_style),
+            Some(_) => (cursor_style,
+                 Style::default().add_modifier(Modifier::UNDERLINED)),
+            None => (cursor_style, cursor_style),
+        };
+        let changed_style = Style::default().fg(Color::LightRed)
+            .add_modifier(Modifier::BOLD);
+        let selection_style = Style::default().bg(Color::DarkGray);
+        let match_style = Style::default().bg(Color::Yellow).fg(Color::Black);
+        let offset_style = Style::default().fg(Color::Blue);
+        let note_style = Style::default().fg(Color::Cyan);
+        let (sel_start, sel_end) = match ctx.anchor {
+            Some(_) => ctx.selection(),
+            None => (0, 0),
+        };
+        let notes = &ctx.notes;
+
+        let mut lines = Vec::with_capacity(self.rows);
+        for row in self.scroll..self.scroll + self.rows {
+            let start = row * self.per_row;
+            if start >= data.len() {
+                break;
+            }
+            let end = (start + self.per_row).min(data.len());
+            let matches = ctx.search.overlapping(start, end);
+
+            let style_of = |offset: usize, cursor_style: Style| {
+                let style = if offset == cursor {
+                    cursor_style
+                } else if sel_start <= offset && offset < sel_end {
+                    selection_style
+                } else if matches.iter()
+                        .any(|m| m.offset <= offset && offset < m.offset + m.len) {
+                    match_style
+                } else if let Some(tag) = notes.color_at(offset) {
+                    Style::default().bg(tag.color()).fg(Color::Black)
+                } else if ctx.patches.is_changed(offset) {
+                    changed_style
+                } else if let Some(leaf) = ctx.overlay.as_ref()
+                        .and_then(|overlay| overlay.leaf_at(offset)) {
+                    // Fields of the template alternate colours
+                    Style::default()
+                        .fg(FIELD_COLORS[leaf % FIELD_COLORS.len()])
+                } else {
+                    Style::default()
+                };
+                // Labelled and bookmarked offsets stand out
+                if notes.label(offset).is_some()
+                        || notes.bookmark(offset).is_some() {
+                    style.add_modifier(Modifier::UNDERLINED)
+                } else {
+                    style
+                }
+            };
+
+            let address = match image {
+                // Rows are cut in the middle of sections, show where the
+                // first byte of the row is loaded
+                Some(image) => match image.section_of(start) {
+                    Some(section) => format!("{:0width$x} {:name$}  ",
+                        image.va_of(start).unwrap_or(0), section.name.chars()
+                            .take(MAX_NAME).collect::<String>(),
+                        width = va_width, name = name_width),
+                    None => format!("{:width$}  ", "-", width = gutter),
+                },
+                None => format!("{:0width$x}  ", start, width = OFFSET_WIDTH),
+            };
+            let mut spans = vec![Span::styled(address, offset_style)];
+            for (offset, byte) in (start..end).zip(&data[start..end]) {
+                spans.push(Span::styled(format!("{:02x}", byte),
+                    style_of(offset, hex_cursor)));
+                spans.push(Span::raw(" "));
+            }
+            // Align the ASCII column of the last row
+            spans.push(Span::raw(" ".repeat((self.per_row - (end - start)) * 3
+                + 1)));
+            for (offset, byte) in (start..end).zip(&data[start..end]) {
+                spans.push(Span::styled(printable(&[*byte]),
+                    style_of(offset, ascii_cursor)));
+            }
+
+            // Annotations of the row after the bytes, cut by the border
+            let row_notes: Vec<String> = notes.bookmarks.iter()
+                .filter(|b| start <= b.offset && b.offset < end)
+                .map(|b| format!("*{}", b.name))
+                .chain(notes.labels_in(start, end)
+                    .map(|(_, label)| format!("{}:", label)))
+                .chain(notes.comments_in(start, end)
+                    .map(|c| format!("; {}", c.text)))
+                .collect();
+            if !row_notes.is_empty() {
+                spans.push(Span::styled(format!("  {}", row_notes.join(" ")),
+                    note_style));
+            }
+            lines.push(Spans::from(spans));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// Move the cursor: `h`/`l` by a byte, `j`/`k` by a row, `PageUp`/
+    /// `PageDown` by a screen and `g`/`G` to the start/end of the sample.
+    /// `v` starts a selection at the cursor, which then follows the cursor,
+    /// and `v` again or `Esc` drops it. `a` switches the addresses of the
+    /// rows between file offsets and virtual addresses, for executables.
+    ///
+    /// `e` enters edit mode: hex digits overwrite the byte under the cursor,
+    /// `Tab` switches to typing characters in the ASCII column, `Insert`
+    /// switches between overwriting and inserting, and `Esc` leaves edit
+    /// mode. `u`/`U` undo/redo edits, except while typing in the ASCII
+    /// column.
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let len = ctx.data().len();
+        if len == 0 || key.modifiers.intersects(
+                KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return false;
+        }
+        if self.on_edit_key(key, ctx) {
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('e') if ctx.sample.is_some() => {
+                self.edit = Some(EditMode::default());
+                return true;
+            },
+            KeyCode::Char('u') | KeyCode::Char('U') => {
+                let offset = if key.code == KeyCode::Char('u') {
+                    ctx.patches.undo()
+                } else {
+                    ctx.patches.redo()
+                };
+                if let Some(offset) = offset {
+                    ctx.cursor = offset.min(ctx.data().len() - 1);
+                }
+                return true;
+            },
+            KeyCode::Char('a') => {
+                self.virtual_addresses = !self.virtual_addresses;
+                return true;
+            },
+            KeyCode::Char('v') => {
+                ctx.anchor = match ctx.anchor {
+                    Some(_) => None,
+                    None => Some(ctx.cursor),
+                };
+                return true;
+            },
+            KeyCode::Esc if ctx.anchor.is_some() => {
+                ctx.anchor = None;
+                return true;
+            },
+            _ => {},
+        }
+
+        let cursor = ctx.cursor;
+        let page = self.per_row * self.rows;
+        ctx.cursor = match key.code {
+            KeyCode::Char('h') => cursor.saturating_sub(1),
+            KeyCode::Char('l') => cursor + 1,
+            KeyCode::Char('k') => cursor.saturating_sub(self.per_row),
+            KeyCode::Char('j') => cursor + self.per_row,
+            KeyCode::PageUp => cursor.saturating_sub(page),
+            KeyCode::PageDown => cursor + page,
+            KeyCode::Char('g') | KeyCode::Home => 0,
+            KeyCode::Char('G') | KeyCode::End => len - 1,
+            _ => return false,
+        }.min(len - 1);
+
+        true
+    }
+}
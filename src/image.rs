@@ -0,0 +1,473 @@
+//! Executables as they are laid out in memory: sections with their file
+//! offsets and virtual addresses, entry point and symbols, read from the
+//! headers
+use goblin::{
+    Object,
+    pe::export::{ExportAddressTableEntry},
+    mach::{Mach, exports::{ExportInfo}, symbols::{N_STAB, N_TYPE, N_SECT}},
+    elf::{
+        header::{EM_386, EM_X86_64, EM_ARM, EM_AARCH64, EM_MIPS,
+            EM_MIPS_RS3_LE, EM_PPC, EM_PPC64, EM_RISCV},
+        program_header::{PT_LOAD, PF_X},
+        section_header::{SHT_NOBITS, SHF_EXECINSTR},
+        sym::{STB_LOCAL, STT_FUNC, STT_OBJECT},
+    },
+};
+use serde::{Serialize, Deserialize};
+
+use crate::disasm::{Arch};
+
+/// A section, or a segment of a file without section headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Offset of the contents in the file
+    pub offset: usize,
+    /// Size of the contents in the file, zero for sections only in memory
+    pub size: usize,
+    /// Virtual address the section is loaded at
+    pub va: u64,
+    /// Size of the section in memory
+    pub vsize: u64,
+    /// Holds code
+    pub exec: bool,
+}
+
+/// A named address, from the symbol tables or the exports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub va: u64,
+}
+
+/// A function or variable the executable takes from a library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// Library it comes from, empty for ELF which does not tell
+    pub library: String,
+    /// Name, or `#<ordinal>` for imports by ordinal
+    pub name: String,
+    pub ordinal: Option<u16>,
+    /// Address of the pointer the loader fills with the address of the
+    /// import, which the code goes through
+    pub slot: Option<u64>,
+}
+
+/// A function or variable the executable offers to others
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub ordinal: Option<u32>,
+    pub va: u64,
+}
+
+/// Bytes of a sample taken as code without headers, e.g. shellcode, with
+/// the architecture and the load address chosen by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawCode {
+    pub arch: Arch,
+    /// Virtual address the first byte is loaded at
+    pub base: u64,
+    /// Offset of the code in the sample
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl RawCode {
+    /// Check that the code is in a sample of `len` bytes and that its last
+    /// byte has an address
+    pub fn check(&self, len: usize) -> Result<(), String> {
+        if self.offset.checked_add(self.size).is_none_or(|end| end > len) {
+            return Err(format!("{:#x} bytes at offset {:#x} are past the end \
+                of the sample", self.size, self.offset));
+        }
+        if self.size == 0 {
+            return Err("no bytes to disassemble".to_string());
+        }
+        if self.base.checked_add(self.size as u64 - 1).is_none() {
+            return Err(format!("{:#x} bytes loaded at {:#x} go past the end \
+                of the address space", self.size, self.base));
+        }
+        Ok(())
+    }
+}
+
+/// An address typed by the user, see `Image::resolve`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    /// Offset in the file
+    Offset(usize),
+    /// Virtual address
+    Va(u64),
+    /// Virtual address relative to the load address
+    Rva(u64),
+}
+
+/// What the headers of a PE, ELF or Mach-O tell about its layout in memory
+#[derive(Debug, Clone, Default)]
+pub struct Image {
+    /// Architecture of the code, `None` if we cannot disassemble it
+    pub arch: Option<Arch>,
+    /// Preferred load address
+    pub base: u64,
+    /// Virtual address of the entry point
+    pub entry: Option<u64>,
+    /// Sections in the order of their headers
+    pub sections: Vec<Section>,
+    /// Functions named by the symbol tables or the exports
+    pub symbols: Vec<Symbol>,
+    /// Imports in the order of the import tables
+    pub imports: Vec<Import>,
+    /// Exports in the order of the export tables
+    pub exports: Vec<Export>,
+}
+
+/// Architecture of an ELF `e_machine`, for its class and byte order. ARM
+/// code is Thumb when the entry point has its lowest bit set.
+fn elf_arch(machine: u16, is_64: bool, little_endian: bool, entry: u64)
+        -> Option<Arch> {
+    match (machine, is_64, little_endian) {
+        (EM_386, _, _) => Some(Arch::X86),
+        (EM_X86_64, _, _) => Some(Arch::X64),
+        (EM_ARM, _, true) if entry & 1 != 0 => Some(Arch::Thumb),
+        (EM_ARM, _, true) => Some(Arch::Arm),
+        (EM_AARCH64, _, true) => Some(Arch::Arm64),
+        (EM_MIPS, false, false) => Some(Arch::Mips),
+        (EM_MIPS, false, true) | (EM_MIPS_RS3_LE, _, _) => Some(Arch::Mipsel),
+        (EM_MIPS, true, false) => Some(Arch::Mips64),
+        (EM_MIPS, true, true) => Some(Arch::Mips64el),
+        (EM_PPC, _, false) => Some(Arch::Ppc),
+        (EM_PPC, _, true) => Some(Arch::Ppcle),
+        (EM_PPC64, _, false) => Some(Arch::Ppc64),
+        (EM_PPC64, _, true) => Some(Arch::Ppc64le),
+        (EM_RISCV, false, true) => Some(Arch::RiscV32),
+        (EM_RISCV, true, true) => Some(Arch::RiscV64),
+        _ => None,
+    }
+}
+
+/// Architecture of a PE `Machine`
+fn pe_arch(machine: u16) -> Option<Arch> {
+    match machine {
+        0x14c => Some(Arch::X86),
+        0x8664 => Some(Arch::X64),
+        0x1c0 => Some(Arch::Arm),
+        // Thumb, and Thumb-2 for Windows on ARM
+        0x1c2 | 0x1c4 => Some(Arch::Thumb),
+        0xaa64 => Some(Arch::Arm64),
+        _ => None,
+    }
+}
+
+/// Architecture of a Mach-O `cputype`. 32-bit ARM code for Apple systems
+/// is compiled to Thumb-2.
+fn macho_arch(cputype: u32) -> Option<Arch> {
+    match cputype {
+        0x7 => Some(Arch::X86),
+        0x0100_0007 => Some(Arch::X64),
+        0xc => Some(Arch::Thumb),
+        0x12 => Some(Arch::Ppc),
+        0x0100_0012 => Some(Arch::Ppc64),
+        // arm64, and arm64_32 running the same instructions
+        0x0100_000c | 0x0200_000c => Some(Arch::Arm64),
+        _ => None,
+    }
+}
+
+impl Image {
+    /// Layout of raw code: a single executable section starting at its
+    /// entry point
+    pub fn raw(code: &RawCode) -> Image {
+        Image {
+            arch: Some(code.arch),
+            base: code.base,
+            entry: Some(code.base),
+            sections: vec![Section { name: "raw".to_string(),
+                offset: code.offset, size: code.size, va: code.base,
+                vsize: code.size as u64, exec: true }],
+            ..Image::default()
+        }
+    }
+
+    /// Read the headers of the PE, ELF or Mach-O in `data`
+    pub fn parse(data: &[u8]) -> Result<Image, String> {
+        let object = Object::parse(data).map_err(|err| err.to_string())?;
+        let mut image = Image::default();
+
+        match object {
+            Object::PE(pe) => {
+                image.arch = pe_arch(pe.header.coff_header.machine);
+                image.base = pe.image_base as u64;
+                image.entry = image.base.checked_add(pe.entry as u64);
+                for section in pe.sections.iter() {
+                    // Code or executable
+                    let exec = section.characteristics & 0x2000_0020 != 0;
+                    image.sections.push(Section {
+                        name: section.name().unwrap_or("?").to_string(),
+                        offset: section.pointer_to_raw_data as usize,
+                        size: section.size_of_raw_data as usize,
+                        va: image.base
+                            .saturating_add(u64::from(section.virtual_address)),
+                        vsize: u64::from(section.virtual_size),
+                        exec,
+                    });
+                }
+                for export in pe.exports.iter().filter(|e| e.reexport.is_none()) {
+                    if let Some(name) = export.name {
+                        image.symbols.push(Symbol { name: name.to_string(),
+                            va: image.base.saturating_add(export.rva as u64) });
+                    }
+                }
+                for import in pe.imports.iter() {
+                    // goblin names imports by ordinal `ORDINAL <n>`
+                    let by_ordinal = import.name.starts_with("ORDINAL ");
+                    let name = if by_ordinal {
+                        format!("#{}", import.ordinal)
+                    } else {
+                        import.name.to_string()
+                    };
+                    image.imports.push(Import {
+                        library: import.dll.to_string(),
+                        name,
+                        ordinal: Some(import.ordinal).filter(|_| by_ordinal),
+                        // The offset is that of the slot in the address
+                        // table, relative to the base
+                        slot: Some(image.base.saturating_add(import.offset as u64)),
+                    });
+                }
+                // Ordinals are the indexes in the address table
+                let (ordinal_base, addresses) = match &pe.export_data {
+                    Some(data) => (data.export_directory_table.ordinal_base,
+                        &data.export_address_table[..]),
+                    None => (0, &[][..]),
+                };
+                for export in pe.exports.iter().filter(|e| e.reexport.is_none()) {
+                    let ordinal = addresses.iter().position(|entry| matches!(
+                        entry, ExportAddressTableEntry::ExportRVA(rva)
+                            if *rva as usize == export.rva));
+                    image.exports.push(Export {
+                        name: export.name.unwrap_or("?").to_string(),
+                        ordinal: ordinal.map(|i| ordinal_base.saturating_add(i as u32)),
+                        va: image.base.saturating_add(export.rva as u64),
+                    });
+                }
+            },
+            Object::Elf(elf) => {
+                image.arch = elf_arch(elf.header.e_machine, elf.is_64,
+                    elf.little_endian, elf.entry);
+                // The lowest bit of the addresses of Thumb code only tells
+                // the instruction set
+                let arm = elf.header.e_machine == EM_ARM;
+                let thumb = |va: u64| if arm { va & !1 } else { va };
+                image.entry = Some(thumb(elf.entry)).filter(|&entry| entry != 0);
+                image.base = elf.program_headers.iter()
+                    .filter(|header| header.p_type == PT_LOAD)
+                    .map(|header| header.p_vaddr)
+                    .min()
+                    .unwrap_or(0);
+                for header in elf.section_headers.iter()
+                        .filter(|header| header.sh_addr != 0
+                            || header.sh_type != SHT_NOBITS) {
+                    let name = elf.shdr_strtab.get_at(header.sh_name)
+                        .unwrap_or("?");
+                    let in_file = header.sh_type != SHT_NOBITS;
+                    image.sections.push(Section {
+                        name: name.to_string(),
+                        offset: header.sh_offset as usize,
+                        size: if in_file { header.sh_size as usize } else { 0 },
+                        va: header.sh_addr,
+                        vsize: header.sh_size,
+                        exec: header.sh_flags & u64::from(SHF_EXECINSTR) != 0,
+                    });
+                }
+                // Stripped of its section headers, use the segments
+                if elf.section_headers.is_empty() {
+                    for (i, header) in elf.program_headers.iter().enumerate()
+                            .filter(|(_, header)| header.p_type == PT_LOAD) {
+                        image.sections.push(Section {
+                            name: format!("LOAD{}", i),
+                            offset: header.p_offset as usize,
+                            size: header.p_filesz as usize,
+                            va: header.p_vaddr,
+                            vsize: header.p_memsz,
+                            exec: header.p_flags & PF_X != 0,
+                        });
+                    }
+                }
+                let symbols = elf.syms.iter().map(|sym| (sym, &elf.strtab))
+                    .chain(elf.dynsyms.iter().map(|sym| (sym, &elf.dynstrtab)));
+                for (sym, strtab) in symbols {
+                    if !sym.is_function() || sym.st_value == 0
+                            || sym.st_shndx == 0 {
+                        continue;
+                    }
+                    if let Some(name) = strtab.get_at(sym.st_name)
+                            .filter(|name| !name.is_empty()) {
+                        image.symbols.push(Symbol { name: name.to_string(),
+                            va: thumb(sym.st_value) });
+                    }
+                }
+                // Imports are the undefined dynamic symbols, bound to the
+                // slots of their relocations
+                let relocs: Vec<_> = elf.pltrelocs.iter()
+                    .chain(elf.dynrelas.iter())
+                    .chain(elf.dynrels.iter())
+                    .collect();
+                for (i, sym) in elf.dynsyms.iter().enumerate() {
+                    let name = match elf.dynstrtab.get_at(sym.st_name) {
+                        Some(name) if !name.is_empty() => name.to_string(),
+                        _ => continue,
+                    };
+                    if sym.st_shndx == 0 {
+                        image.imports.push(Import {
+                            library: String::new(), name, ordinal: None,
+                            slot: relocs.iter().find(|reloc| reloc.r_sym == i)
+                                .map(|reloc| reloc.r_offset),
+                        });
+                    } else if sym.st_bind() != STB_LOCAL && matches!(
+                            sym.st_type(), STT_FUNC | STT_OBJECT) {
+                        let va = if sym.is_function() {
+                            thumb(sym.st_value)
+                        } else {
+                            sym.st_value
+                        };
+                        image.exports.push(Export { name, ordinal: None, va });
+                    }
+                }
+                // 64-bit big endian PowerPC points to the descriptors of
+                // the functions in .opd, which start with their address
+                if image.arch == Some(Arch::Ppc64) {
+                    let code = |image: &Image, va: u64| image.section_at(va)
+                        .filter(|section| section.name == ".opd")
+                        .and_then(|_| image.offset_of(va))
+                        .and_then(|offset| data.get(offset..offset + 8))
+                        .map_or(va, |bytes| u64::from_be_bytes([bytes[0],
+                            bytes[1], bytes[2], bytes[3], bytes[4], bytes[5],
+                            bytes[6], bytes[7]]));
+                    image.entry = image.entry.map(|entry| code(&image, entry));
+                    let symbols = image.symbols.iter()
+                        .map(|symbol| Symbol { va: code(&image, symbol.va),
+                            ..symbol.clone() })
+                        .collect();
+                    image.symbols = symbols;
+                }
+            },
+            Object::Mach(Mach::Binary(macho)) => {
+                image.arch = macho_arch(macho.header.cputype);
+                image.entry = Some(macho.entry).filter(|&entry| entry != 0);
+                for segment in macho.segments.iter() {
+                    if segment.name().ok() == Some("__TEXT") {
+                        image.base = segment.vmaddr;
+                    }
+                    let sections = segment.sections()
+                        .map_err(|err| err.to_string())?;
+                    for (section, _) in sections {
+                        // Zero filled sections have no contents in the file
+                        let zero_fill = matches!(section.flags & 0xff, 0x1 | 0xc);
+                        image.sections.push(Section {
+                            name: format!("{},{}",
+                                section.segname().unwrap_or("?"),
+                                section.name().unwrap_or("?")),
+                            offset: section.offset as usize,
+                            size: if zero_fill { 0 } else { section.size as usize },
+                            va: section.addr,
+                            vsize: section.size,
+                            // Pure or some instructions
+                            exec: section.flags & 0x8000_0400 != 0,
+                        });
+                    }
+                }
+                for (name, nlist) in macho.symbols().flatten() {
+                    if nlist.n_type & N_STAB != 0 || nlist.n_type & N_TYPE != N_SECT
+                            || nlist.n_value == 0 {
+                        continue;
+                    }
+                    let name = name.strip_prefix('_').unwrap_or(name);
+                    image.symbols.push(Symbol { name: name.to_string(),
+                        va: nlist.n_value });
+                }
+                for import in macho.imports().unwrap_or_default() {
+                    image.imports.push(Import {
+                        library: import.dylib.to_string(),
+                        name: import.name.strip_prefix('_')
+                            .unwrap_or(import.name).to_string(),
+                        ordinal: None,
+                        slot: Some(import.address),
+                    });
+                }
+                // Exports are relative to the start of `__TEXT`
+                for export in macho.exports().unwrap_or_default() {
+                    if let ExportInfo::Regular { address, .. } = export.info {
+                        let name = export.name.strip_prefix('_')
+                            .unwrap_or(&export.name).to_string();
+                        image.exports.push(Export { name, ordinal: None,
+                            va: image.base.saturating_add(address) });
+                    }
+                }
+            },
+            Object::Mach(Mach::Fat(_)) =>
+                return Err("fat Mach-O binaries hold several files".to_string()),
+            _ => return Err("not a PE, ELF or Mach-O file".to_string()),
+        }
+
+        // Headers can point anywhere, only keep what is in the file
+        for section in image.sections.iter_mut() {
+            section.offset = section.offset.min(data.len());
+            section.size = section.size.min(data.len() - section.offset);
+        }
+        image.symbols.sort_by_key(|symbol| symbol.va);
+        image.symbols.dedup_by_key(|symbol| symbol.va);
+
+        Ok(image)
+    }
+
+    /// Section loaded at `va`
+    pub fn section_at(&self, va: u64) -> Option<&Section> {
+        self.sections.iter()
+            .find(|section| va >= section.va && va - section.va < section.vsize)
+    }
+
+    /// File offset of the byte loaded at `va`, if it comes from the file
+    pub fn offset_of(&self, va: u64) -> Option<usize> {
+        self.sections.iter()
+            .find(|section| va >= section.va
+                && va - section.va < section.size as u64)
+            .map(|section| section.offset + (va - section.va) as usize)
+    }
+
+    /// Section whose contents are at `offset` in the file
+    pub fn section_of(&self, offset: usize) -> Option<&Section> {
+        self.sections.iter().find(|section| section.offset <= offset
+            && offset < section.offset + section.size)
+    }
+
+    /// Virtual address the byte at `offset` in the file is loaded at
+    pub fn va_of(&self, offset: usize) -> Option<u64> {
+        self.section_of(offset).and_then(|section| section.va
+            .checked_add((offset - section.offset) as u64))
+    }
+
+    /// File offset of `address`, if it is in the file
+    pub fn resolve(&self, address: Address) -> Option<usize> {
+        match address {
+            Address::Offset(offset) => Some(offset),
+            Address::Va(va) => self.offset_of(va),
+            Address::Rva(rva) => self.base.checked_add(rva)
+                .and_then(|va| self.offset_of(va)),
+        }
+    }
+
+    /// Bytes of code from `va` to the end of its section
+    pub fn code_at<'d>(&self, data: &'d [u8], va: u64) -> Option<&'d [u8]> {
+        let section = self.sections.iter().find(|section| section.exec
+            && va >= section.va && va - section.va < section.size as u64)?;
+        let start = section.offset + (va - section.va) as usize;
+        data.get(start..section.offset + section.size)
+    }
+
+    /// Name of the symbol at `va`
+    pub fn symbol(&self, va: u64) -> Option<&str> {
+        self.symbols.binary_search_by_key(&va, |symbol| symbol.va).ok()
+            .map(|i| self.symbols[i].name.as_str())
+    }
+}
@@ -0,0 +1,231 @@
+//! Data inspector: the bytes under the cursor read as common types
+use std::{
+    convert::{TryInto},
+    fmt::{Display, LowerExp},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+
+/// Characters shown at most in the string previews
+const MAX_CHARS: usize = 32;
+
+/// Seconds between 1601-01-01, the FILETIME epoch, and the Unix epoch
+const FILETIME_EPOCH: i64 = 11_644_473_600;
+
+/// Read `N` bytes at the start of `bytes`, if there are enough
+fn take<const N: usize>(bytes: &[u8]) -> Option<[u8; N]> {
+    bytes.get(..N).map(|bytes| bytes.try_into().unwrap())
+}
+
+/// Date and time of the civil calendar for seconds since the Unix epoch
+pub fn format_time(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400);
+
+    // Days to year, month and day, from Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day,
+        time / 3600, time / 60 % 60, time % 60)
+}
+
+/// MS-DOS date and time, the time in the low word and the date in the high
+/// one as in FAT and ZIP headers
+fn dos_time(value: u32) -> Option<String> {
+    let (time, date) = (value & 0xffff, value >> 16);
+    let (day, month, year) = (date & 0x1f, (date >> 5) & 0xf,
+        1980 + (date >> 9));
+    let (secs, mins, hours) = ((time & 0x1f) * 2, (time >> 5) & 0x3f,
+        time >> 11);
+    if day == 0 || month == 0 || month > 12 || hours > 23 || mins > 59
+            || secs > 59 {
+        return None;
+    }
+    Some(format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day,
+        hours, mins, secs))
+}
+
+/// A float in decimal if it is short enough, in scientific notation
+/// otherwise
+fn float<F: Into<f64> + Display + LowerExp + Copy>(value: F) -> String {
+    let abs = value.into().abs();
+    if abs == 0.0 || !abs.is_finite() || (1e-4..1e16).contains(&abs) {
+        value.to_string()
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+/// GUID in its registry form, the first three groups little endian
+fn guid(b: [u8; 16]) -> String {
+    format!("{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}}}",
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        u16::from_le_bytes([b[4], b[5]]),
+        u16::from_le_bytes([b[6], b[7]]),
+        b[8], b[9],
+        b[10..].iter().map(|b| format!("{:02X}", b)).collect::<String>())
+}
+
+/// Unsigned LEB128 varint, with the number of bytes it takes
+fn varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0u64;
+    for (i, &b) in bytes.iter().take(10).enumerate() {
+        value |= u64::from(b & 0x7f).checked_shl(7 * i as u32)?;
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// Characters up to the first NUL, escaping the control ones
+fn preview<I: Iterator<Item = char>>(chars: I) -> String {
+    let mut text = String::new();
+    for (i, c) in chars.take_while(|&c| c != '\0').enumerate() {
+        if i == MAX_CHARS {
+            text.push_str("...");
+            break;
+        }
+        match c {
+            c if c.is_control() => text.push_str(&c.escape_default()
+                .to_string()),
+            c => text.push(c),
+        }
+    }
+    format!("\"{}\"", text)
+}
+
+fn utf16(bytes: &[u8], big: bool) -> String {
+    let units = bytes.chunks_exact(2)
+        .map(|pair| if big { u16::from_be_bytes([pair[0], pair[1]]) }
+                    else { u16::from_le_bytes([pair[0], pair[1]]) });
+    preview(char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+}
+
+/// Interpretations of the bytes at the start of `bytes`, by name. The
+/// value is `None` when there are not enough bytes or they make no sense as
+/// this type.
+pub fn inspect(bytes: &[u8]) -> Vec<(&'static str, Option<String>)> {
+    /// Little and big endian values side by side
+    macro_rules! both {
+        ($ty:ty) => {
+            take(bytes).map(|raw| format!("{} / {}",
+                <$ty>::from_le_bytes(raw), <$ty>::from_be_bytes(raw)))
+        };
+        ($ty:ty, $show:expr) => {
+            take(bytes).map(|raw| format!("{} / {}",
+                $show(<$ty>::from_le_bytes(raw)),
+                $show(<$ty>::from_be_bytes(raw))))
+        };
+    }
+
+    let utf8_len = bytes.len().min(MAX_CHARS * 4);
+    let utf8 = String::from_utf8_lossy(&bytes[..utf8_len]);
+
+    vec![
+        ("u8", bytes.first().map(|b| format!("{} ({:#04x})", b, b))),
+        ("i8", bytes.first().map(|&b| (b as i8).to_string())),
+        ("u16", both!(u16)),
+        ("i16", both!(i16)),
+        ("u32", both!(u32)),
+        ("i32", both!(i32)),
+        ("u64", both!(u64)),
+        ("i64", both!(i64)),
+        ("f32", both!(f32, float)),
+        ("f64", both!(f64, float)),
+        ("unix", take(bytes).map(|raw| format_time(
+            i64::from(u32::from_le_bytes(raw))))),
+        ("filetime", take(bytes)
+            .map(|raw| u64::from_le_bytes(raw) / 10_000_000)
+            .map(|secs| format_time(secs as i64 - FILETIME_EPOCH))),
+        ("dos", take(bytes).and_then(|raw| dos_time(u32::from_le_bytes(raw)))),
+        ("guid", take(bytes).map(guid)),
+        ("ipv4", take::<4>(bytes).map(|ip| format!("{}.{}.{}.{}",
+            ip[0], ip[1], ip[2], ip[3]))),
+        ("varint", varint(bytes).map(|(value, len)|
+            format!("{} ({} bytes)", value, len))),
+        ("ascii", Some(preview(bytes.iter()
+            .map(|&b| if b.is_ascii() { b as char }
+                      else { char::REPLACEMENT_CHARACTER })))),
+        ("latin1", Some(preview(bytes.iter().map(|&b| b as char)))),
+        ("utf8", Some(preview(utf8.chars()))),
+        ("utf16le", Some(utf16(&bytes[..bytes.len().min(MAX_CHARS * 4)],
+            false))),
+        ("utf16be", Some(utf16(&bytes[..bytes.len().min(MAX_CHARS * 4)],
+            true))),
+    ]
+}
+
+/// Plugin showing the bytes at the cursor of the tab as integers, floats,
+/// timestamps and strings
+#[derive(Default)]
+pub struct Inspector {
+    /// First line shown
+    scroll: usize,
+}
+
+impl RenderPlugin for Inspector {
+    fn get_name(&self) -> &str {
+        "Inspector"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let data = ctx.data();
+        if data.is_empty() {
+            f.render_widget(Paragraph::new("no sample"), area);
+            return;
+        }
+
+        let cursor = ctx.cursor.min(data.len() - 1);
+        let rows = inspect(&data[cursor..]);
+        self.scroll = self.scroll.min(rows.len() - 1);
+
+        let mut lines = vec![Spans::from(Span::styled(
+            format!("at {:#x}, le / be", cursor),
+            Style::default().fg(Color::Blue)))];
+        for (name, value) in rows.into_iter().skip(self.scroll) {
+            let value = match value {
+                Some(value) => Span::raw(value),
+                None => Span::styled("-", Style::default().fg(Color::DarkGray)),
+            };
+            lines.push(Spans::from(vec![
+                Span::styled(format!("{:<9}", name),
+                    Style::default().fg(Color::Cyan)),
+                value,
+            ]));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` scroll the values
+    fn on_key(&mut self, key: KeyEvent, _ctx: &mut TabContext) -> bool {
+        match key.code {
+            KeyCode::Char('j') => self.scroll += 1,
+            KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            _ => return false,
+        }
+        true
+    }
+}
@@ -31,6 +31,17 @@ pub struct KeyConfig {
     /// Move to the tab on the left
     pub tab_left:           KeyEvent,
 
+    /// Open the command prompt
+    pub command:            KeyEvent,
+    /// Open the command prompt to search the sample
+    pub search:             KeyEvent,
+    /// Move the cursor to the next/previous search match
+    pub next_match:         KeyEvent,
+    pub previous_match:     KeyEvent,
+    /// Show the cross-references to the address under the cursor, or to
+    /// the item selected in the focused plugin
+    pub xrefs:              KeyEvent,
+
     /// Quit application>
     pub quit:               KeyEvent,
 }
@@ -38,47 +49,67 @@ pub struct KeyConfig {
 impl Default for KeyConfig {
     fn default() -> Self {
         Self {
-            focus_left: KeyEvent {
-                code:       KeyCode::Left,
-                modifiers:  KeyModifiers::empty()
-            },
-            focus_right: KeyEvent {
-                code:       KeyCode::Right,
-                modifiers:  KeyModifiers::empty()
-            },
-            focus_up: KeyEvent {
-                code:       KeyCode::Up,
-                modifiers:  KeyModifiers::empty()
-            },
-            focus_down: KeyEvent {
-                code:       KeyCode::Down,
-                modifiers:  KeyModifiers::empty()
-            },
-            new_plugin: KeyEvent {
-                code:       KeyCode::Char('n'),
-                modifiers:  KeyModifiers::CONTROL,
-            },
-            remove_plugin: KeyEvent {
-                code:       KeyCode::Char('r'),
-                modifiers:  KeyModifiers::CONTROL,
-            },
+            focus_left: KeyEvent::new(
+                KeyCode::Left,
+                KeyModifiers::empty(),
+            ),
+            focus_right: KeyEvent::new(
+                KeyCode::Right,
+                KeyModifiers::empty(),
+            ),
+            focus_up: KeyEvent::new(
+                KeyCode::Up,
+                KeyModifiers::empty(),
+            ),
+            focus_down: KeyEvent::new(
+                KeyCode::Down,
+                KeyModifiers::empty(),
+            ),
+            new_plugin: KeyEvent::new(
+                KeyCode::Char('n'),
+                KeyModifiers::CONTROL,
+            ),
+            remove_plugin: KeyEvent::new(
+                KeyCode::Char('r'),
+                KeyModifiers::CONTROL,
+            ),
             // TODO: add for move_*(right, up, down)
-            move_left: KeyEvent {
-                code:       KeyCode::Left,
-                modifiers:  KeyModifiers::CONTROL,
-            },
-            tab_right: KeyEvent {
-                code:       KeyCode::Right,
-                modifiers:  KeyModifiers::SHIFT,
-            },
-            tab_left: KeyEvent {
-                code:       KeyCode::Left,
-                modifiers:  KeyModifiers::SHIFT,
-            },
-            quit: KeyEvent {
-                code:       KeyCode::Char('q'),
-                modifiers:  KeyModifiers::CONTROL,
-            }
+            move_left: KeyEvent::new(
+                KeyCode::Left,
+                KeyModifiers::CONTROL,
+            ),
+            tab_right: KeyEvent::new(
+                KeyCode::Right,
+                KeyModifiers::SHIFT,
+            ),
+            tab_left: KeyEvent::new(
+                KeyCode::Left,
+                KeyModifiers::SHIFT,
+            ),
+            command: KeyEvent::new(
+                KeyCode::Char(':'),
+                KeyModifiers::empty(),
+            ),
+            search: KeyEvent::new(
+                KeyCode::Char('/'),
+                KeyModifiers::empty(),
+            ),
+            next_match: KeyEvent::new(
+                KeyCode::Char('n'),
+                KeyModifiers::empty(),
+            ),
+            previous_match: KeyEvent::new(
+                KeyCode::Char('N'),
+                KeyModifiers::SHIFT,
+            ),
+            xrefs: KeyEvent::new(
+                KeyCode::Char('x'),
+                KeyModifiers::CONTROL,
+            ),
+            quit: KeyEvent::new(
+                KeyCode::Char('q'),
+                KeyModifiers::CONTROL,
+            )
         }
     }
 }
@@ -0,0 +1,218 @@
+//! Layouts describe which plugins a tab shows and how they are arranged in
+//! the grid, without holding any plugin state.
+use std::{
+    fs,
+    error::Error,
+    collections::{BTreeMap},
+    path::{Path},
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::app::{ColumnsState, PluginsState, Plugin, FileManager};
+use crate::sample::{Format};
+use crate::hexview::{HexView};
+use crate::search::{SearchResults};
+use crate::yara::{Yara};
+use crate::annotations::{Bookmarks};
+use crate::patch::{PatchList};
+use crate::template::{TemplateTree};
+use crate::inspector::{Inspector};
+use crate::diff::{DiffView, Differences};
+use crate::funcdiff::{FunctionDiffView};
+use crate::symbols::{Symbols};
+use crate::listing::{Listing};
+use crate::cfg::{CfgView};
+use crate::functions::{FunctionList, CallGraph};
+use crate::emulator::{EmulatorView};
+
+/// The kind of a plugin, independent of its state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PluginKind {
+    FileManager,
+    HexView,
+    Parser,
+    SearchResults,
+    Yara,
+    Bookmarks,
+    Patches,
+    Template,
+    Inspector,
+    Diff,
+    Differences,
+    FunctionDiff,
+    Symbols,
+    Listing,
+    Cfg,
+    Functions,
+    CallGraph,
+    Emulator,
+}
+
+/// Arrangement of plugins in a tab: a list of columns, each holding a list
+/// of plugins from top to bottom
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    pub columns: Vec<Vec<PluginKind>>,
+}
+
+/// Names of the layouts that ship with maglab
+pub const BUILTIN_LAYOUTS: [&str; 7] =
+    ["browse", "code", "diff", "emulate", "function-diff", "hex", "triage"];
+
+/// A named layout defined by the user, e.g. in the configuration:
+///
+/// ```toml
+/// [layouts.pe-triage]
+/// columns = [["FileManager"], ["HexView", "HexView"], ["Parser"]]
+/// formats = ["PE"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutTemplate {
+    pub columns: Vec<Vec<PluginKind>>,
+    /// Sample formats for which this template is picked by default
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formats: Vec<Format>,
+}
+
+impl LayoutTemplate {
+    pub fn layout(&self) -> Layout {
+        Layout { columns: self.columns.clone() }
+    }
+}
+
+/// Templates saved from inside maglab. They live in their own file so that
+/// we never rewrite the configuration written by the user.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedLayouts {
+    #[serde(default)]
+    pub layouts: BTreeMap<String, LayoutTemplate>,
+}
+
+impl SavedLayouts {
+    /// Read the saved templates from `path`. A missing file holds no
+    /// templates.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SavedLayouts, Box<dyn Error>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(SavedLayouts::default());
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let saved = toml::from_str(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(saved)
+    }
+
+    /// Write the templates as TOML to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("{}: {}", dir.display(), err))?;
+        }
+        let text = toml::to_string(self)?;
+        fs::write(path, text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(())
+    }
+}
+
+impl Layout {
+    /// Get one of the layouts that ship with maglab by its `name`
+    pub fn builtin(name: &str) -> Option<Layout> {
+        use PluginKind::*;
+
+        let columns = match name {
+            // Look around the filesystem
+            "browse" => vec![vec![FileManager], vec![HexView]],
+            // The code of executables: their functions, each as a listing
+            // and as a graph
+            "code" => vec![vec![Functions, CallGraph], vec![Listing, HexView],
+                vec![Cfg]],
+            // Two samples side by side over the list of their differences
+            "diff" => vec![vec![Diff, Differences]],
+            // Code run step by step, with the registers and the memory
+            "emulate" => vec![vec![Listing, HexView], vec![Emulator]],
+            // Same for executables, with their functions matched
+            "function-diff" => vec![vec![FunctionDiff], vec![Diff, Differences]],
+            // Only the bytes
+            "hex" => vec![vec![HexView]],
+            // Bytes and the parsed headers side by side
+            "triage" => vec![vec![FileManager], vec![HexView], vec![Parser]],
+            _ => return None,
+        };
+
+        Some(Layout { columns })
+    }
+
+    /// Name of the builtin layout used by default for a sample of `format`
+    pub fn name_for_format(format: Format) -> &'static str {
+        match format {
+            Format::PE | Format::ELF | Format::MachO => "triage",
+            Format::Raw => "hex",
+        }
+    }
+
+    /// Get the layout of an existing grid
+    pub fn from_grid(grid: &ColumnsState) -> Layout {
+        let columns = grid.columns.iter()
+            .map(|col| col.plugins.iter().map(|p| p.kind()).collect())
+            .collect();
+
+        Layout { columns }
+    }
+
+    /// Create the plugins described by the layout. File managers start in
+    /// `dir`.
+    pub fn build<'a>(&self, dir: &Path) -> ColumnsState<'a> {
+        let columns = self.columns.iter()
+            // Empty columns cannot be focused, skip them
+            .filter(|col| !col.is_empty())
+            .map(|col| PluginsState::new(col.iter()
+                .map(|kind| match kind {
+                    PluginKind::FileManager =>
+                        Plugin::FileManager(FileManager::new(dir.to_path_buf())),
+                    PluginKind::HexView => Plugin::HexView(HexView::new()),
+                    PluginKind::Parser => Plugin::Parser,
+                    PluginKind::SearchResults =>
+                        Plugin::SearchResults(SearchResults::default()),
+                    PluginKind::Yara => Plugin::Yara(Yara::default()),
+                    PluginKind::Bookmarks =>
+                        Plugin::Bookmarks(Bookmarks::default()),
+                    PluginKind::Patches =>
+                        Plugin::Patches(PatchList::default()),
+                    PluginKind::Template =>
+                        Plugin::Template(TemplateTree::default()),
+                    PluginKind::Inspector =>
+                        Plugin::Inspector(Inspector::default()),
+                    PluginKind::Diff => Plugin::Diff(DiffView::default()),
+                    PluginKind::Differences =>
+                        Plugin::Differences(Differences::default()),
+                    PluginKind::FunctionDiff =>
+                        Plugin::FunctionDiff(FunctionDiffView::default()),
+                    PluginKind::Symbols => Plugin::Symbols(Symbols::default()),
+                    PluginKind::Listing => Plugin::Listing(Listing::default()),
+                    PluginKind::Cfg => Plugin::Cfg(CfgView::default()),
+                    PluginKind::Functions =>
+                        Plugin::Functions(FunctionList::default()),
+                    PluginKind::CallGraph =>
+                        Plugin::CallGraph(CallGraph::default()),
+                    PluginKind::Emulator =>
+                        Plugin::Emulator(EmulatorView::default()),
+                })
+                .collect()))
+            .collect::<Vec<_>>();
+
+        // A grid always holds at least one plugin
+        if columns.is_empty() {
+            return ColumnsState::new(vec![PluginsState::new(
+                vec![Plugin::HexView(HexView::new())])]);
+        }
+
+        ColumnsState::new(columns)
+    }
+}
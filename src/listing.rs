@@ -0,0 +1,279 @@
+//! Linear disassembly of the executable sections around the tab's cursor
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::annotations::{Annotations};
+use crate::app::{RenderPlugin, TabContext};
+use crate::defang;
+use crate::disasm::{Disassembler, Insn, Flow};
+use crate::image::{Image};
+use crate::xrefs::{describe_target};
+
+/// Bytes of an instruction shown at most, in hex
+const MAX_BYTES: usize = 8;
+/// Bytes of the longest instruction, looked back to find the previous one
+const MAX_INSN: u64 = 15;
+
+/// Executable of the tab's sample, if we can disassemble it
+pub fn code_image(ctx: &TabContext) -> Result<&Image, String> {
+    let sample = ctx.sample.as_ref().ok_or("no sample")?;
+    let image = sample.image.as_deref().ok_or_else(|| format!(
+        "{} has no virtual addresses", defang::name(&sample.name())))?;
+    if image.arch.is_none() {
+        return Err("cannot disassemble this architecture".to_string());
+    }
+    Ok(image)
+}
+
+/// Address of the instruction under the cursor of the tab, if it is in an
+/// executable section
+pub fn cursor_va(image: &Image, ctx: &TabContext) -> Result<u64, String> {
+    image.va_of(ctx.cursor)
+        .filter(|&va| image.code_at(ctx.data(), va).is_some())
+        .ok_or_else(|| "the cursor is not in an executable section".to_string())
+}
+
+/// Label the user put at the address of `insn`, and the comments they put
+/// on its bytes
+pub fn insn_notes<'a>(image: &Image, notes: &'a Annotations, insn: &Insn)
+        -> (Option<&'a str>, Vec<&'a str>) {
+    match image.offset_of(insn.address) {
+        Some(offset) => (notes.label(offset), notes
+            .comments_in(offset, offset + insn.len)
+            .map(|comment| comment.text.as_str())
+            .collect()),
+        None => (None, Vec::new()),
+    }
+}
+
+/// Address of the instruction before the one at `va`, found by decoding
+/// from up to `MAX_INSN` bytes before it
+fn previous(image: &Image, data: &[u8], disasm: &mut Disassembler, va: u64)
+        -> Option<u64> {
+    (1..=MAX_INSN).rev()
+        .filter_map(|back| va.checked_sub(back))
+        .find_map(|start| {
+            let (mut at, mut last) = (start, None);
+            while at < va {
+                let insn = disasm.decode(image.code_at(data, at)?, at)?;
+                last = Some(at);
+                at = insn.end();
+            }
+            last.filter(|_| at == va)
+        })
+}
+
+/// Where `insn` goes or what it uses, to follow it
+fn target(insn: &Insn) -> Option<u64> {
+    match insn.flow {
+        Flow::Call(Some(target)) | Flow::Jump(Some(target))
+            | Flow::Branch(target) => Some(target),
+        _ => insn.mem,
+    }
+}
+
+/// Plugin listing the instructions from the cursor of the tab on, one per
+/// line, with the symbols as labels
+#[derive(Default)]
+pub struct Listing {
+    /// Address of the first instruction shown
+    top: u64,
+    /// Instructions shown in the last draw
+    shown: Vec<Insn>,
+    /// Rows shown in the last draw
+    rows: usize,
+}
+
+impl Listing {
+    /// Instruction holding `va` among the ones shown
+    fn shown_at(&self, va: u64) -> Option<usize> {
+        self.shown.iter()
+            .position(|insn| insn.address <= va && va < insn.end())
+    }
+
+    /// Decode the instructions from `top` on until `rows` lines are filled,
+    /// counting the labels
+    fn decode(&self, image: &Image, data: &[u8], notes: &Annotations,
+            rows: usize) -> Vec<Insn> {
+        // Checked by code_image
+        let mut disasm = Disassembler::new(image.arch.unwrap());
+        let mut insns = Vec::new();
+        let (mut at, mut lines) = (self.top, 0);
+        while lines < rows {
+            let insn = match image.code_at(data, at)
+                    .and_then(|code| disasm.decode(code, at)) {
+                Some(insn) => insn,
+                None => break,
+            };
+            lines += 1 + usize::from(image.symbol(at).is_some())
+                + usize::from(insn_notes(image, notes, &insn).0.is_some());
+            at = insn.end();
+            insns.push(insn);
+        }
+        insns
+    }
+}
+
+impl RenderPlugin for Listing {
+    fn get_name(&self) -> &str {
+        "Listing"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let (image, va) = match code_image(ctx)
+                .and_then(|image| Ok((image, cursor_va(image, ctx)?))) {
+            Ok(found) => found,
+            Err(err) => {
+                self.shown.clear();
+                f.render_widget(Paragraph::new(err), area);
+                return;
+            },
+        };
+        let data = ctx.data();
+        self.rows = (area.height as usize).max(1);
+
+        // Scroll by a line when the cursor moves to the instruction after
+        // the last one shown, start over from it when it goes elsewhere
+        self.shown = self.decode(image, data, &ctx.notes, self.rows);
+        if va >= self.top && self.shown_at(va).is_none() {
+            if self.shown.len() > 1
+                    && self.shown.last().map(|insn| insn.end()) == Some(va) {
+                self.top = self.shown[1].address;
+            } else {
+                self.top = va;
+            }
+            self.shown = self.decode(image, data, &ctx.notes, self.rows);
+        } else if va < self.top {
+            self.top = va;
+            self.shown = self.decode(image, data, &ctx.notes, self.rows);
+        }
+
+        let address_style = Style::default().fg(Color::Blue);
+        let bytes_style = Style::default().fg(Color::DarkGray);
+        let label_style = Style::default().fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        let note_style = Style::default().fg(Color::Cyan);
+        let mut lines = Vec::with_capacity(self.rows);
+        for insn in self.shown.iter() {
+            if let Some(name) = image.symbol(insn.address) {
+                lines.push(Spans::from(Span::styled(format!("{}:", name),
+                    label_style)));
+            }
+            let (label, comments) = insn_notes(image, &ctx.notes, insn);
+            if let Some(label) = label {
+                lines.push(Spans::from(Span::styled(format!("{}:", label),
+                    note_style)));
+            }
+            let offset = image.offset_of(insn.address).unwrap_or(0);
+            let bytes = &data[offset..(offset + insn.len).min(data.len())];
+            let mut hex: String = bytes.iter().take(MAX_BYTES)
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            if bytes.len() > MAX_BYTES {
+                hex.push_str("..");
+            }
+
+            let style = if insn.address <= va && va < insn.end() {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            let mut spans = vec![
+                Span::styled(format!("{:08x}  ", insn.address),
+                    address_style.patch(style)),
+                Span::styled(format!("{:w$}  ", hex, w = 2 * MAX_BYTES + 2),
+                    bytes_style.patch(style)),
+                Span::styled(insn.text.clone(), style),
+            ];
+            let comment = match insn.flow {
+                Flow::Call(Some(target)) | Flow::Jump(Some(target))
+                    | Flow::Branch(target) => image.symbol(target)
+                        .map(|name| name.to_string()),
+                _ => insn.mem
+                    .and_then(|mem| describe_target(image, data, mem)),
+            };
+            if let Some(comment) = comment {
+                spans.push(Span::styled(format!("  ; {}", comment),
+                    bytes_style));
+            }
+            for comment in comments {
+                spans.push(Span::styled(format!("  ; {}", comment),
+                    note_style));
+            }
+            lines.push(Spans::from(spans));
+        }
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` move the cursor of the tab to the next/previous instruction,
+    /// `PageDown`/`PageUp` by a screen. `Enter` follows the jump or call
+    /// under the cursor, or goes to the data it uses.
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return false;
+        }
+        let (image, va) = match code_image(ctx)
+                .and_then(|image| Ok((image, cursor_va(image, ctx)?))) {
+            Ok(found) => found,
+            Err(_) => return false,
+        };
+        let data = ctx.data();
+        let mut disasm = Disassembler::new(image.arch.unwrap());
+        let insn = match self.shown_at(va) {
+            Some(i) => Some(self.shown[i].clone()),
+            None => disasm.decode(image.code_at(data, va).unwrap_or(&[]), va),
+        };
+        let (start, end) = insn.as_ref()
+            .map_or((va, va.wrapping_add(1)), |insn| (insn.address, insn.end()));
+
+        let steps = match key.code {
+            KeyCode::Char('j') | KeyCode::Char('k') => 1,
+            KeyCode::PageDown | KeyCode::PageUp => self.rows.max(1),
+            KeyCode::Enter => {
+                let offset = insn.as_ref().and_then(target)
+                    .and_then(|target| image.offset_of(target));
+                if let Some(offset) = offset {
+                    ctx.cursor = offset.min(data.len().saturating_sub(1));
+                }
+                return true;
+            },
+            _ => return false,
+        };
+        let mut at = start;
+        if matches!(key.code, KeyCode::Char('j') | KeyCode::PageDown) {
+            at = end;
+            for _ in 1..steps {
+                match image.code_at(data, at)
+                        .and_then(|code| disasm.decode(code, at)) {
+                    Some(insn) => at = insn.end(),
+                    None => break,
+                }
+            }
+            if image.code_at(data, at).is_none() {
+                return true;
+            }
+        } else {
+            for _ in 0..steps {
+                let shown = self.shown_at(at).filter(|&i| i > 0)
+                    .map(|i| self.shown[i - 1].address);
+                match shown.or_else(|| previous(image, data, &mut disasm, at)) {
+                    Some(previous) => at = previous,
+                    None => break,
+                }
+            }
+        }
+        if let Some(offset) = image.offset_of(at) {
+            ctx.cursor = offset.min(data.len().saturating_sub(1));
+        }
+        true
+    }
+}
@@ -4,122 +4,288 @@
  * TODO: Find a way to execute terminal commands
  */
 use std::{
+    io,
+    env,
     thread,
-    io::stdout,
+    process,
     error::Error,
+    path::{Path},
     sync::mpsc,
     time::{Duration, Instant},
 };
 
 use tui::{
-    terminal::{Terminal},
-    backend::{CrosstermBackend},
+    layout::{Rect},
 };
 
 use crossterm::{
-    terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen,
-        LeaveAlternateScreen},
-    event::{self, Event as CtEvent, EnableMouseCapture, DisableMouseCapture},
-    execute,
+    event::{self, Event as CtEvent},
 };
 
 pub mod app;
 pub mod tabs;
 pub mod keys;
+pub mod terminal;
+pub mod cli;
+pub mod config;
+pub mod sample;
+pub mod layout;
+pub mod session;
+pub mod command;
+pub mod hexview;
+pub mod search;
+pub mod yara;
+pub mod annotations;
+pub mod project;
+pub mod patch;
+pub mod template;
+pub mod inspector;
+pub mod image;
+pub mod disasm;
+pub mod riscv;
+pub mod analysis;
+pub mod funcdiff;
+pub mod diff;
+pub mod archive;
+pub mod defang;
+pub mod quarantine;
+pub mod symbols;
+pub mod xrefs;
+pub mod listing;
+pub mod cfg;
+pub mod functions;
+pub mod emulator;
+
+#[cfg(test)]
+mod tests;
 use crate::keys::{KeyConfig};
+use crate::terminal::{TerminalGuard};
 use crate::tabs::{TabsState};
-use crate::app::{App, MagLabApp, ColumnsState, PluginsState, Plugin,
-    FileManager};
+use crate::app::{App, MagLabApp};
+use crate::cli::{Args};
+use crate::config::{Config};
+use crate::sample::{Sample};
+use crate::layout::{Layout};
+use crate::session::{Session};
+use crate::project::{Project};
 
 
+/// Events sent from the input thread to the main loop
 enum Event<I> {
+    /// A key was pressed
     Input(I),
+    /// The terminal was resized to the given (columns, rows)
+    Resize(u16, u16),
+    /// Text was pasted in the terminal (requires bracketed paste)
+    Paste(String),
+    /// The terminal gained focus
+    FocusGained,
+    /// The terminal lost focus
+    FocusLost,
+    /// Polling or reading from the terminal failed, the input thread exits
+    /// after sending this
+    Error(io::Error),
     Tick,
 }
 
+/// Poll crossterm for events and forward them on `tx`, sending a `Tick`
+/// every `tick_rate`. Returns when the receiving end is dropped or when
+/// reading from the terminal fails.
+fn input_loop(tx: mpsc::Sender<Event<event::KeyEvent>>, tick_rate: Duration) {
+    // Get current time
+    let mut last_tick = Instant::now();
+
+    // Event loop
+    loop {
+        // Poll for tick rate duration, if no events, send a tick event.
+        let timeout = tick_rate
+            .checked_sub(last_tick.elapsed())
+            .unwrap_or_else(|| Duration::from_secs(0));
+
+        // Check if we have an event before the timeout
+        let polled = event::poll(timeout)
+            .and_then(|ready| if ready { event::read().map(Some) }
+                              else { Ok(None) });
+
+        let ev = match polled {
+            Ok(Some(CtEvent::Key(key))) => Some(Event::Input(key)),
+            Ok(Some(CtEvent::Resize(cols, rows))) =>
+                Some(Event::Resize(cols, rows)),
+            Ok(Some(CtEvent::Paste(text))) => Some(Event::Paste(text)),
+            Ok(Some(CtEvent::FocusGained)) => Some(Event::FocusGained),
+            Ok(Some(CtEvent::FocusLost)) => Some(Event::FocusLost),
+            // Mouse events are not handled yet
+            Ok(Some(CtEvent::Mouse(_))) | Ok(None) => None,
+            Err(err) => {
+                // Nothing more we can read, let the main loop decide what
+                // to do and stop the thread
+                let _ = tx.send(Event::Error(err));
+                return;
+            }
+        };
+
+        // Send the event to the consumer, if it is gone we are done
+        if let Some(ev) = ev {
+            if tx.send(ev).is_err() {
+                return;
+            }
+        }
+
+        // If we get a timeout, send a tick event and reset the tick
+        if last_tick.elapsed() >= tick_rate {
+            if tx.send(Event::Tick).is_err() {
+                return;
+            }
+            last_tick = Instant::now();
+        }
+    }
+}
+
+/// Build the tabs to start with: the ones of the project and the ones saved
+/// in the session, followed by one tab per sample given on the command line.
+/// The tab active when the session was saved is active again.
+fn initial_tabs<'a>(args: &Args, config: &Config, project: Option<&Project>)
+        -> Result<TabsState<'a>, Box<dyn Error>> {
+    let mut apps = Vec::new();
+    let mut index = 0;
+
+    if let Some(project) = project {
+        if let Some(session) = &project.session {
+            index = session.index;
+        }
+        apps.extend(project.tabs(config));
+    }
+
+    // A session file that does not exist yet is created when we exit
+    if let Some(session) = args.session.as_ref().filter(|p| p.exists()) {
+        let session = Session::load(session)?;
+        index = apps.len() + session.index;
+        apps.extend(session.restore());
+    }
+
+    // Layout forced on the command line or in the configuration
+    let layout_name = args.layout.as_ref().or(config.layout.as_ref());
+    let forced_layout = match layout_name {
+        Some(name) => Some(config.layout(name)
+            .ok_or_else(|| format!("unknown layout {}, expected one of: {}",
+                name, config.layout_names().join(", ")))?),
+        None => None,
+    };
+
+    for path in args.samples.iter() {
+        let sample = Sample::open(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        // Already restored from the project or the session
+        if apps.iter().filter_map(|app| app.ctx.sample.as_ref())
+                .any(|open| open.sha256 == sample.sha256) {
+            continue;
+        }
+        let layout = forced_layout.clone()
+            .unwrap_or_else(|| config.layout_for_format(sample.format));
+        let grid = layout.build(&sample.dir());
+        apps.push(App::with_sample(sample, grid));
+    }
+
+    // Nothing to analyse, start by looking around the current directory
+    if apps.is_empty() {
+        let layout = forced_layout
+            .unwrap_or_else(|| Layout::builtin("browse").unwrap());
+        apps.push(App::new("FileManager", layout.build(Path::new("."))));
+    }
+
+    let mut tabs = TabsState::new(apps);
+    // Unless the session file was edited by hand
+    if index < tabs.apps.len() {
+        tabs.index = index;
+    }
+    Ok(tabs)
+}
+
 fn main() -> Result<(), Box<dyn Error>>{
-    // Put terminal in raw mode
-    enable_raw_mode()?;
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("maglab: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if args.version {
+        println!("maglab {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    // The configuration given on the command line must exist, the default
+    // one is optional
+    let config_path = args.config.clone().or_else(Config::default_path);
+    let mut config = match (&args.config, &config_path) {
+        (Some(path), _) => Config::load(path)?,
+        (None, Some(path)) if path.exists() => Config::load(path)?,
+        _ => Config::default(),
+    };
+    // Layouts saved with `layout-save`, binary templates and API categories
+    // live next to the configuration
+    if let Some(dir) = config_path.as_ref().and_then(|path| path.parent()) {
+        config.load_saved_layouts(dir.join("layouts.toml"))?;
+        if config.templates.is_none() {
+            config.templates = Some(dir.join("templates"));
+        }
+        if config.api_categories.is_none() {
+            config.api_categories = Some(dir.join("apis.toml"));
+        }
+    }
+    if config.notes_dir.is_none() {
+        config.notes_dir = Config::default_notes_dir();
+    }
+    if args.quarantine.is_some() {
+        config.quarantine = args.quarantine.clone();
+    }
+
+    // A project keeps its own annotations and layouts
+    let project = match &args.project {
+        Some(dir) => {
+            let project = Project::open(dir)?;
+            config.notes_dir = Some(project.notes_dir());
+            config.load_saved_layouts(project.layouts_path())?;
+            Some(project)
+        },
+        None => None,
+    };
+
+    // Load everything before touching the terminal so that errors are
+    // printed on a sane screen
+    let tabs = initial_tabs(&args, &config, project.as_ref())?;
 
-    // Get a new handle to the standard output
-    let mut stdout = stdout();
-    // Give app an alternate screen
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    // Make sure a panic anywhere gives the user their terminal back
+    terminal::install_panic_hook();
 
-    // Create a new Backend
-    let backend = CrosstermBackend::new(stdout);
-    // Create a new Terminal
-    let mut terminal = Terminal::new(backend)?;
+    // Setup the terminal. It is restored when `terminal` is dropped, which
+    // also covers every early return below.
+    let mut terminal = TerminalGuard::new()?;
 
     // Setup a multiproduce-singleconsumer channel
     let (tx, rx) = mpsc::channel();
 
     // Setup a timeout tick rate
-    let tick_rate = Duration::from_millis(1000);
-
-    // Spanw a new thread that will handle the event pipeline
-    thread::spawn(move || {
-        // Get current time
-        let mut last_tick = Instant::now();
-
-        // Event loop
-        loop {
-            // Poll for tick rate duration, if no events, send a tick event.
-            let timeout = tick_rate
-                .checked_sub(last_tick.elapsed())
-                .unwrap_or_else(|| Duration::from_secs(0));
-
-            // If we get a timeout
-            if event::poll(timeout).unwrap() {
-                // Check if we have an event
-                if let CtEvent::Key(key) = event::read().unwrap() {
-                    // Send the event to the consumer
-                    tx.send(Event::Input(key)).unwrap();
-                }
-            }
-            // If we get a timeout, send a tick event and reset the tick
-            if last_tick.elapsed() >= tick_rate {
-                tx.send(Event::Tick).unwrap();
-                last_tick = Instant::now();
-            }
-        }
-    });
-
-
-    let fm = FileManager::new(".");
-
-    let plugins1 = PluginsState::new(
-        vec![Plugin::FileManager(fm), Plugin::HexView]);
-    let plugins2 = PluginsState::new(vec![Plugin::HexView]);
-    let fm = FileManager::new(".");
-    let plugins3 = PluginsState::new(
-        vec![Plugin::FileManager(fm), Plugin::HexView, Plugin::Parser]);
-    let cols1 = ColumnsState::new(vec![plugins1, plugins2, plugins3]);
-    let fm = FileManager::new(".");
-    let plugins1 = PluginsState::new(
-        vec![Plugin::FileManager(fm), Plugin::HexView]);
-    let plugins2 = PluginsState::new(vec![Plugin::HexView]);
-    let fm = FileManager::new(".");
-    let plugins3 = PluginsState::new(
-        vec![Plugin::FileManager(fm), Plugin::HexView, Plugin::Parser]);
-    let cols2 = ColumnsState::new(vec![plugins2, plugins1, plugins3]);
-    let fm = FileManager::new(".");
-    let plugins1 = PluginsState::new(
-        vec![Plugin::FileManager(fm), Plugin::HexView]);
-    let plugins2 = PluginsState::new(vec![Plugin::HexView]);
-    let fm = FileManager::new(".");
-    let plugins3 = PluginsState::new(
-        vec![Plugin::FileManager(fm), Plugin::HexView, Plugin::Parser]);
-    let cols3 = ColumnsState::new(vec![plugins3, plugins2, plugins1]);
-
-    let tabs = TabsState::new(vec![
-        app::App::new("FileManager", cols1),
-        app::App::new("MachO", cols2),
-        app::App::new("PE", cols3),
-    ]);
+    let tick_rate = args.tick_rate
+        .or_else(|| config.tick_rate.map(Duration::from_millis))
+        .unwrap_or_else(|| Duration::from_millis(1000));
+
+    // Spawn a new thread that will handle the event pipeline
+    thread::spawn(move || input_loop(tx, tick_rate));
 
     // Create a new MagLab app
     let mut mag_lab_app = MagLabApp::new("MagLab", tabs);
+    mag_lab_app.read_only = args.read_only
+        || (!args.writable && config.read_only.unwrap_or(true));
+    mag_lab_app.config = config;
+    mag_lab_app.project = project;
+    if let Err(err) = mag_lab_app.track_samples() {
+        mag_lab_app.status = Some(format!("error: {}", err));
+    }
 
     // Clear terminal output so we have a clean canvas
     terminal.clear()?;
@@ -127,53 +293,59 @@ fn main() -> Result<(), Box<dyn Error>>{
     // Initialize the keys configuration
     let key_conf = KeyConfig::init();
 
+    // Error that made us leave the main loop, if any
+    let mut exit_error: Option<Box<dyn Error>> = None;
+
     loop {
         // Draw the canvas
         terminal.draw(|f| mag_lab_app.draw(f))?;
         // Handle user input
-        match rx.recv()? {
-            Event::Input(event) => {
-                if event == key_conf.quit {
-                    mag_lab_app.should_quit = true;
-                } else if event == key_conf.tab_left {
-                    mag_lab_app.tab_left();
-                } else if event == key_conf.tab_right {
-                    mag_lab_app.tab_right();
-                } else if event == key_conf.focus_left {
-                    mag_lab_app.focus_left();
-                } else if event == key_conf.focus_right {
-                    mag_lab_app.focus_right();
-                } else if event == key_conf.focus_up {
-                    mag_lab_app.focus_up();
-                } else if event == key_conf.focus_down {
-                    mag_lab_app.focus_down();
-                } else if event == key_conf.new_plugin {
-                    mag_lab_app.add_plugin(Plugin::HexView);
-                } else if event == key_conf.remove_plugin {
-                    mag_lab_app.remove_plugin();
-                }
+        let ev = rx.recv().unwrap_or_else(|err| {
+            // The input thread is gone, there is no way to get input anymore
+            Event::Error(io::Error::new(io::ErrorKind::BrokenPipe, err))
+        });
+        match ev {
+            Event::Input(event) => mag_lab_app.on_key_event(event, &key_conf),
+
+            // Redraw right away for the new size. The loop draws again
+            // after every event, the resize clears the previous frame.
+            Event::Resize(cols, rows) => {
+                terminal.resize(Rect::new(0, 0, cols, rows))?;
+            },
+            Event::Paste(text) => mag_lab_app.on_paste(&text),
+            Event::Error(err) => {
+                exit_error = Some(err.into());
+                mag_lab_app.should_quit = true;
             },
 
-            // Currently do nothing on tick
-            Event::Tick => {},
+            // Currently do nothing on tick or focus changes
+            Event::Tick | Event::FocusGained | Event::FocusLost => {},
         };
 
 
         // Check if we should exit the app
         if mag_lab_app.should_quit {
-            // Get terminal back into normal mode
-            disable_raw_mode()?;
-            // Leave our crossterm screen
-            execute!(
-                terminal.backend_mut(),
-                LeaveAlternateScreen,
-                DisableMouseCapture,
-            )?;
-            terminal.show_cursor()?;
             break;
         }
 
     }
 
-    Ok(())
+    // Get terminal back into normal mode
+    drop(terminal);
+    // Dropping the receiver makes the input thread stop at its next send
+    drop(rx);
+
+    // Save where we left so that the session opens the same way next time
+    if let Some(path) = args.session.as_ref() {
+        Session::capture(&mag_lab_app).save(path)?;
+    }
+    if let Some(mut project) = mag_lab_app.project.take() {
+        project.capture(&mag_lab_app);
+        project.save()?;
+    }
+
+    match exit_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,416 @@
+//! Edits made to a sample in the hex view.
+//!
+//! The sample itself is never modified: edits are kept as a list of patches
+//! over its original contents, which can be undone, listed, and exported as
+//! a patched copy, an IPS patch or a JSON patch.
+use std::{
+    fs,
+    error::Error,
+    sync::{Arc},
+    path::{Path},
+};
+
+use serde::{Serialize, Deserialize};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+
+/// How an edit changes the contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EditKind {
+    /// Replace the bytes at the offset
+    Overwrite,
+    /// Insert bytes before the offset, moving the rest of the contents
+    Insert,
+}
+
+/// One edit, offsets are in the contents as they were before it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Edit {
+    pub offset: usize,
+    pub kind: EditKind,
+    /// Bytes replaced, empty for an insertion
+    #[serde(with = "hex_bytes")]
+    pub old: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub new: Vec<u8>,
+}
+
+/// Bytes as a hex string in JSON patches
+mod hex_bytes {
+    use serde::{Serializer, Deserializer, Deserialize, de::Error};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], s: S)
+            -> Result<S::Ok, S::Error> {
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        s.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D)
+            -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(d)?;
+        if !hex.len().is_multiple_of(2) {
+            return Err(D::Error::custom("odd number of hex digits"));
+        }
+        (0..hex.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// A JSON patch: the edits in order and the sample they apply to
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonPatch {
+    /// SHA-256 of the original sample
+    pub sha256: String,
+    pub edits: Vec<Edit>,
+}
+
+/// The edits of a tab's sample and the contents they give
+#[derive(Debug, Default)]
+pub struct Patches {
+    /// Edits in the order they were made
+    pub edits: Vec<Edit>,
+    /// Edits undone, the last one is redone first
+    undone: Vec<Edit>,
+    /// Contents before any edit
+    original: Option<Arc<[u8]>>,
+    /// Contents with every edit applied, `None` without edits
+    data: Option<Arc<[u8]>>,
+    /// Ranges of `data` changed by the edits, sorted
+    changed: Vec<(usize, usize)>,
+}
+
+impl Patches {
+    /// Contents with the edits applied, `None` if nothing was edited
+    pub fn data(&self) -> Option<&Arc<[u8]>> {
+        self.data.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// True if the byte at `offset` of the patched contents was edited
+    pub fn is_changed(&self, offset: usize) -> bool {
+        let i = self.changed.partition_point(|&(_, end)| end <= offset);
+        self.changed.get(i).is_some_and(|&(start, _)| start <= offset)
+    }
+
+    /// Ranges of the patched contents changed by the edits
+    pub fn changed(&self) -> &[(usize, usize)] {
+        &self.changed
+    }
+
+    /// Set the byte at `offset` of the contents to `byte`. With `merge`, the
+    /// change joins the last edit if it covers or directly follows `offset`,
+    /// so that typing a run of bytes is undone at once.
+    pub fn overwrite(&mut self, original: &Arc<[u8]>, offset: usize,
+            byte: u8, merge: bool) {
+        let current = self.contents(original);
+        let old = match current.get(offset) {
+            Some(old) => *old,
+            None => return,
+        };
+
+        match self.edits.last_mut() {
+            Some(last) if merge && last.offset <= offset
+                    && offset < last.offset + last.new.len() => {
+                last.new[offset - last.offset] = byte;
+            },
+            Some(last) if merge && last.kind == EditKind::Overwrite
+                    && offset == last.offset + last.new.len() => {
+                last.old.push(old);
+                last.new.push(byte);
+            },
+            _ => self.edits.push(Edit { offset, kind: EditKind::Overwrite,
+                old: vec![old], new: vec![byte] }),
+        }
+        self.undone.clear();
+        self.rebuild(original);
+    }
+
+    /// Insert `byte` before `offset`, merging with the last insertion like
+    /// `overwrite`
+    pub fn insert(&mut self, original: &Arc<[u8]>, offset: usize, byte: u8,
+            merge: bool) {
+        if offset > self.contents(original).len() {
+            return;
+        }
+
+        match self.edits.last_mut() {
+            Some(last) if merge && last.kind == EditKind::Insert
+                    && offset == last.offset + last.new.len() => {
+                last.new.push(byte);
+            },
+            _ => self.edits.push(Edit { offset, kind: EditKind::Insert,
+                old: Vec::new(), new: vec![byte] }),
+        }
+        self.undone.clear();
+        self.rebuild(original);
+    }
+
+    /// Undo the last edit, returns its offset
+    pub fn undo(&mut self) -> Option<usize> {
+        let edit = self.edits.pop()?;
+        let offset = edit.offset;
+        self.undone.push(edit);
+        if let Some(original) = self.original.clone() {
+            self.rebuild(&original);
+        }
+        Some(offset)
+    }
+
+    /// Redo the last edit undone, returns its offset
+    pub fn redo(&mut self) -> Option<usize> {
+        let edit = self.undone.pop()?;
+        let offset = edit.offset;
+        self.edits.push(edit);
+        if let Some(original) = self.original.clone() {
+            self.rebuild(&original);
+        }
+        Some(offset)
+    }
+
+    /// Drop every edit
+    pub fn clear(&mut self) {
+        *self = Patches::default();
+    }
+
+    /// Current contents, `original` if nothing was edited
+    fn contents<'a>(&'a self, original: &'a Arc<[u8]>) -> &'a [u8] {
+        self.data.as_deref().unwrap_or(original)
+    }
+
+    /// Apply the edits to `original` again
+    fn rebuild(&mut self, original: &Arc<[u8]>) {
+        self.original = Some(original.clone());
+        if self.edits.is_empty() {
+            self.data = None;
+            self.changed.clear();
+            return;
+        }
+
+        let data = apply(original, &self.edits);
+        let mut changed: Vec<(usize, usize)> = Vec::new();
+        for edit in self.edits.iter() {
+            let (start, end) = (edit.offset, edit.offset + edit.new.len());
+            if edit.kind == EditKind::Insert {
+                // What follows the insertion moves
+                for range in changed.iter_mut().filter(|r| r.0 >= start) {
+                    *range = (range.0 + edit.new.len(), range.1
+                        + edit.new.len());
+                }
+            }
+            changed.push((start, end));
+        }
+        changed.sort_unstable();
+        // Merge the ranges that touch
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(changed.len());
+        for (start, end) in changed {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        self.data = Some(data.into());
+        self.changed = merged;
+    }
+}
+
+/// Apply `edits` in order to `original`
+pub fn apply(original: &[u8], edits: &[Edit]) -> Vec<u8> {
+    let mut data = original.to_vec();
+    for edit in edits {
+        match edit.kind {
+            EditKind::Overwrite => {
+                let end = (edit.offset + edit.new.len()).min(data.len());
+                let start = edit.offset.min(end);
+                data[start..end].copy_from_slice(&edit.new[..end - start]);
+            },
+            EditKind::Insert => {
+                let offset = edit.offset.min(data.len());
+                data.splice(offset..offset, edit.new.iter().copied());
+            },
+        }
+    }
+    data
+}
+
+/// Largest offset an IPS record can hold
+const IPS_MAX_OFFSET: usize = 0xff_ffff;
+/// Largest record of an IPS patch
+const IPS_MAX_RECORD: usize = 0xffff;
+/// An offset that reads as the end marker of the patch
+const IPS_EOF: usize = 0x45_4f46;
+
+/// Build an IPS patch turning `original` into `patched`. IPS records only
+/// write bytes, so everything following an insertion is part of the patch.
+pub fn ips(original: &[u8], patched: &[u8]) -> Result<Vec<u8>, String> {
+    if patched.len() < original.len() {
+        return Err("IPS patches cannot shrink a file".to_string());
+    }
+
+    let mut out = b"PATCH".to_vec();
+    let mut offset = 0;
+    while offset < patched.len() {
+        if original.get(offset) == Some(&patched[offset]) {
+            offset += 1;
+            continue;
+        }
+
+        // Start one byte earlier rather than at the offset read as `EOF`
+        let mut start = offset;
+        if start == IPS_EOF {
+            start -= 1;
+        }
+        let mut end = offset + 1;
+        while end < patched.len() && end - start < IPS_MAX_RECORD
+                && original.get(end) != Some(&patched[end]) {
+            end += 1;
+        }
+        if start > IPS_MAX_OFFSET {
+            return Err(format!("change at {:#x} is past the 16 MiB an IPS \
+                patch can address", start));
+        }
+
+        out.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        out.extend_from_slice(&patched[start..end]);
+        offset = end;
+    }
+    out.extend_from_slice(b"EOF");
+
+    Ok(out)
+}
+
+/// Write the patched contents of `original` to `path`, as an IPS patch for
+/// `.ips` files, as a JSON patch for `.json` files and as a patched copy for
+/// anything else. Returns what was written.
+pub fn export(path: &Path, sha256: &str, original: &[u8], patches: &Patches)
+        -> Result<&'static str, Box<dyn Error>> {
+    let patched = apply(original, &patches.edits);
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    let (what, bytes) = match extension.as_deref() {
+        Some("ips") => ("IPS patch", ips(original, &patched)?),
+        Some("json") => {
+            let patch = JsonPatch {
+                sha256: sha256.to_string(),
+                edits: patches.edits.clone(),
+            };
+            ("JSON patch", serde_json::to_string_pretty(&patch)?.into_bytes())
+        },
+        _ => ("patched copy", patched),
+    };
+    fs::write(path, bytes)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    Ok(what)
+}
+
+/// Bytes of an edit shown in the `Patches` plugin, the others are elided
+const BYTES_SHOWN: usize = 16;
+
+/// Plugin listing the edits of the tab's sample with the bytes they replace
+#[derive(Default)]
+pub struct PatchList {
+    selected: usize,
+    /// First line shown
+    scroll: usize,
+}
+
+fn hex_line(bytes: &[u8]) -> String {
+    let mut hex: Vec<String> = bytes.iter().take(BYTES_SHOWN)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if bytes.len() > BYTES_SHOWN {
+        hex.push(format!("... {} more", bytes.len() - BYTES_SHOWN));
+    }
+    hex.join(" ")
+}
+
+impl RenderPlugin for PatchList {
+    fn get_name(&self) -> &str {
+        "Patches"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let edits = &ctx.patches.edits;
+        if edits.is_empty() {
+            f.render_widget(Paragraph::new(
+                "no edits, press e in a hex view to edit"), area);
+            return;
+        }
+
+        // Every edit takes a header line, and a line per side of the diff
+        self.selected = self.selected.min(edits.len() - 1);
+        let per_edit = 3;
+        let rows = (area.height as usize / per_edit).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let mut lines = Vec::new();
+        for (i, edit) in edits.iter().enumerate().skip(self.scroll) {
+            let mut header = Style::default().fg(Color::Blue);
+            if i == self.selected {
+                header = header.add_modifier(Modifier::REVERSED);
+            }
+            let kind = match edit.kind {
+                EditKind::Overwrite => "overwrite",
+                EditKind::Insert => "insert",
+            };
+            lines.push(Spans::from(Span::styled(format!("{:08x} {} {} bytes",
+                edit.offset, kind, edit.new.len()), header)));
+            if !edit.old.is_empty() {
+                lines.push(Spans::from(Span::styled(
+                    format!("- {}", hex_line(&edit.old)),
+                    Style::default().fg(Color::Red))));
+            }
+            lines.push(Spans::from(Span::styled(
+                format!("+ {}", hex_line(&edit.new)),
+                Style::default().fg(Color::Green))));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous edit, `Enter` moves the cursor of
+    /// the tab to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let count = ctx.patches.edits.len();
+        match key.code {
+            KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(count.saturating_sub(1));
+            },
+            KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            },
+            KeyCode::Enter => {
+                if let Some(edit) = ctx.patches.edits.get(self.selected) {
+                    ctx.cursor = edit.offset.min(ctx.data().len()
+                        .saturating_sub(1));
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,253 @@
+//! Projects bundle everything about a case in one directory, so that it can
+//! be handed from one analyst to the next:
+//!
+//! ```text
+//! case/
+//!   project.json        samples, artifacts, journal and open tabs
+//!   layouts.toml        layout templates saved with `layout-save`
+//!   samples/<sha256>    copy of every sample opened in the project
+//!   notes/<sha256>.json annotations of each sample
+//!   artifacts/<sha256>  bytes extracted from the samples
+//! ```
+//!
+//! Samples and artifacts are stored by hash, the tabs refer to the copies
+//! in the project rather than to where the samples were found.
+use std::{
+    fs,
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::app::{App, MagLabApp};
+use crate::config::{Config};
+use crate::sample::{Sample, Format};
+use crate::session::{Session};
+
+/// A sample stored in the project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectSample {
+    pub sha256: String,
+    /// File name of the sample when it was added
+    pub name: String,
+    pub format: Format,
+    /// Where the sample was added from
+    pub source: PathBuf,
+}
+
+/// Bytes extracted from a sample, e.g. a decrypted payload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    pub sha256: String,
+    pub name: String,
+    /// SHA-256 of the sample the bytes come from
+    pub parent: String,
+    /// Offset of the bytes in the parent
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// A note of the analyst about the case
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    pub author: Option<String>,
+    pub text: String,
+}
+
+/// A case: its samples, what was extracted from them, the notes of the
+/// analysts and the tabs open when it was last closed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Project {
+    pub name: String,
+    #[serde(default)]
+    pub samples: Vec<ProjectSample>,
+    #[serde(default)]
+    pub artifacts: Vec<Artifact>,
+    #[serde(default)]
+    pub journal: Vec<JournalEntry>,
+    /// Tabs open when the project was last closed, with sample paths
+    /// relative to the project directory
+    #[serde(default)]
+    pub session: Option<Session>,
+    /// Directory of the project
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+impl Project {
+    /// Open the project in `dir`, creating it if the directory holds none
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Project, Box<dyn Error>> {
+        let dir = dir.as_ref();
+        let path = dir.join("project.json");
+        if !path.exists() {
+            let name = dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "project".to_string());
+            let project = Project { name, dir: dir.to_path_buf(),
+                ..Project::default() };
+            project.save()?;
+            return Ok(project);
+        }
+
+        let text = fs::read_to_string(&path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let mut project: Project = serde_json::from_str(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        project.dir = dir.to_path_buf();
+
+        Ok(project)
+    }
+
+    /// Write `project.json`
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|err| format!("{}: {}", self.dir.display(), err))?;
+        let path = self.dir.join("project.json");
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(&path, text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(())
+    }
+
+    /// Directory of the annotations of the samples
+    pub fn notes_dir(&self) -> PathBuf {
+        self.dir.join("notes")
+    }
+
+    /// File of the layout templates saved in the project
+    pub fn layouts_path(&self) -> PathBuf {
+        self.dir.join("layouts.toml")
+    }
+
+    /// Path of the sample with the given hash, relative to the project
+    fn sample_file(sha256: &str) -> PathBuf {
+        Path::new("samples").join(sha256)
+    }
+
+    /// Path of the copy of the sample with the given hash
+    pub fn sample_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(Project::sample_file(sha256))
+    }
+
+    /// Path of the artifact with the given hash
+    pub fn artifact_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join("artifacts").join(sha256)
+    }
+
+    pub fn has_sample(&self, sha256: &str) -> bool {
+        self.samples.iter().any(|sample| sample.sha256 == sha256)
+    }
+
+    /// Copy `sample` into the project, returns false if it was already in
+    pub fn add_sample(&mut self, sample: &Sample)
+            -> Result<bool, Box<dyn Error>> {
+        if self.has_sample(&sample.sha256) {
+            return Ok(false);
+        }
+
+        write_new(&self.sample_path(&sample.sha256), &sample.data)?;
+        self.samples.push(ProjectSample {
+            sha256: sample.sha256.clone(),
+            name: sample.name(),
+            format: sample.format,
+            source: sample.path.clone(),
+        });
+        self.save()?;
+
+        Ok(true)
+    }
+
+    /// Store the bytes `offset..offset + data.len()` of `parent` as an
+    /// artifact called `name`
+    pub fn add_artifact(&mut self, parent: &Sample, offset: usize,
+            data: &[u8], name: &str) -> Result<&Artifact, Box<dyn Error>> {
+        let artifact = Sample::from_bytes(PathBuf::from(name), data.to_vec());
+        write_new(&self.artifact_path(&artifact.sha256), data)?;
+        self.artifacts.push(Artifact {
+            sha256: artifact.sha256,
+            name: name.to_string(),
+            parent: parent.sha256.clone(),
+            offset,
+            len: data.len(),
+        });
+        self.save()?;
+
+        Ok(self.artifacts.last().unwrap())
+    }
+
+    /// Add `text` to the journal, signed with the user name
+    pub fn add_note(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let author = std::env::var("USER").ok();
+        self.journal.push(JournalEntry { time, author,
+            text: text.to_string() });
+        self.save()
+    }
+
+    /// Remember the tabs of `app`, pointing them to the copies of their
+    /// samples in the project
+    pub fn capture(&mut self, app: &MagLabApp) {
+        let mut session = Session::capture(app);
+        for (tab, app) in session.tabs.iter_mut().zip(&app.tabs.apps) {
+            if let Some(sample) = &app.ctx.sample {
+                if self.has_sample(&sample.sha256) {
+                    tab.sample = Some(Project::sample_file(&sample.sha256));
+                }
+            }
+        }
+        self.session = Some(session);
+    }
+
+    /// Recreate the tabs of the project: the ones open when it was closed,
+    /// or one per sample in a project that was never closed
+    pub fn tabs<'a>(&self, config: &Config) -> Vec<App<'a>> {
+        if let Some(session) = &self.session {
+            let mut session = session.clone();
+            for tab in session.tabs.iter_mut() {
+                if let Some(path) = tab.sample.as_mut() {
+                    *path = self.dir.join(&*path);
+                }
+            }
+            return session.restore();
+        }
+
+        self.samples.iter().filter_map(|entry| {
+            let sample = Sample::open(self.sample_path(&entry.sha256)).ok()?;
+            let grid = config.layout_for_format(sample.format)
+                .build(&sample.dir());
+            let mut app = App::with_sample(sample, grid);
+            app.title = entry.name.clone();
+            Some(app)
+        }).collect()
+    }
+
+    /// One line describing the project for the status line
+    pub fn summary(&self) -> String {
+        format!("project {}: {} samples, {} artifacts, {} journal entries",
+            self.name, self.samples.len(), self.artifacts.len(),
+            self.journal.len())
+    }
+}
+
+/// Write `data` to `path` unless it exists already. Files are named after
+/// their hash, an existing file holds the same bytes.
+fn write_new(path: &Path, data: &[u8]) -> Result<(), Box<dyn Error>> {
+    if path.exists() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("{}: {}", dir.display(), err))?;
+    }
+    fs::write(path, data)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    Ok(())
+}
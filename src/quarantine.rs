@@ -0,0 +1,37 @@
+//! Quarantine: a directory holding a copy of every sample under its
+//! SHA-256, which cannot be run by accident
+use std::{
+    io,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::sample::{Sample};
+
+/// Copy `sample` to `dir` as `<sha256>`, readable but neither writable nor
+/// executable. Returns the path of the copy, which is kept if it exists.
+pub fn store(dir: &Path, sample: &Sample) -> io::Result<PathBuf> {
+    let path = dir.join(&sample.sha256);
+    if path.exists() {
+        return Ok(path);
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(&path, &sample.data)?;
+    make_read_only(&path)?;
+
+    Ok(path)
+}
+
+/// Leave read permissions only on the file at `path`
+#[cfg(unix)]
+fn make_read_only(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o444))
+}
+
+#[cfg(not(unix))]
+fn make_read_only(path: &Path) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(path, permissions)
+}
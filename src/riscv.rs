@@ -0,0 +1,448 @@
+//! Decoding RISC-V instructions, which capstone is not built with: the base
+//! integer instructions of RV32 and RV64 with the multiplications, the
+//! atomics, the control registers and the compressed instructions. Other
+//! instructions of a valid size are shown as `.insn` and fall through.
+use crate::disasm::{Insn, Flow, fnv, FNV_START, bad, Arch};
+
+/// ABI names of the integer registers
+const REGS: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+const RA: u32 = 1;
+const SP: u32 = 2;
+
+/// An operand, as shown
+#[derive(Debug, Clone, Copy)]
+enum Arg {
+    Reg(u32),
+    FReg(u32),
+    Imm(i64),
+    /// Upper immediate of `lui` and `auipc`, shown in hex
+    Upper(u32),
+    /// Target of a jump or a branch
+    Addr(u64),
+    /// `disp(base)`
+    Mem(i64, u32),
+    /// Address in a register, for the atomics
+    Ptr(u32),
+    Csr(u32),
+}
+
+/// Sign-extend the `bits` low bits of `value`
+fn sext(value: u32, bits: u32) -> i64 {
+    let shift = 32 - bits;
+    i64::from(((value << shift) as i32) >> shift)
+}
+
+/// Bits `hi..=lo` of `w`, moved to `to`
+fn bits(w: u32, hi: u32, lo: u32, to: u32) -> u32 {
+    ((w >> lo) & ((1 << (hi - lo + 1)) - 1)) << to
+}
+
+/// An instruction decoded, before it is laid out in an `Insn`
+struct Decoded {
+    mnemonic: &'static str,
+    args: Vec<Arg>,
+    flow: Flow,
+}
+
+impl Decoded {
+    fn new(mnemonic: &'static str, args: Vec<Arg>) -> Decoded {
+        Decoded { mnemonic, args, flow: Flow::Next }
+    }
+
+    fn flow(mut self, flow: Flow) -> Decoded {
+        self.flow = flow;
+        self
+    }
+}
+
+/// Decode the instruction at the start of `code`, loaded at `address`, for
+/// registers of `xlen` bits
+pub fn decode(code: &[u8], address: u64, xlen: u32) -> Insn {
+    let arch = if xlen == 32 { Arch::RiscV32 } else { Arch::RiscV64 };
+    let half = match code {
+        [lo, hi, ..] => u16::from_le_bytes([*lo, *hi]),
+        _ => return bad(arch, code, address),
+    };
+    let (decoded, len) = if half & 3 != 3 {
+        (compressed(half, address, xlen), 2)
+    } else {
+        match code.get(..4) {
+            Some(word) => (full(u32::from_le_bytes([word[0], word[1], word[2],
+                word[3]]), address, xlen), 4),
+            None => return bad(arch, code, address),
+        }
+    };
+    let decoded = match decoded {
+        Some(decoded) => decoded,
+        None => return bad(arch, code, address),
+    };
+
+    // Mnemonic and registers, leaving out immediates and targets
+    let mut norm = fnv(FNV_START, decoded.mnemonic.as_bytes());
+    let mut text = decoded.mnemonic.to_string();
+    for (i, arg) in decoded.args.iter().enumerate() {
+        text.push_str(if i == 0 { " " } else { ", " });
+        match *arg {
+            Arg::Reg(reg) | Arg::Ptr(reg) | Arg::Mem(_, reg) =>
+                norm = fnv(norm, &[reg as u8]),
+            Arg::FReg(reg) => norm = fnv(norm, &[32 + reg as u8]),
+            _ => norm = fnv(norm, &[64]),
+        }
+        match *arg {
+            Arg::Reg(reg) => text.push_str(REGS[reg as usize]),
+            Arg::FReg(reg) => text.push_str(&format!("f{}", reg)),
+            Arg::Imm(imm) => text.push_str(&imm.to_string()),
+            Arg::Upper(imm) => text.push_str(&format!("{:#x}", imm)),
+            Arg::Addr(target) => text.push_str(&format!("{:#x}", target)),
+            Arg::Mem(disp, base) =>
+                text.push_str(&format!("{}({})", disp, REGS[base as usize])),
+            Arg::Ptr(base) => text.push_str(&format!("({})", REGS[base as usize])),
+            Arg::Csr(csr) => text.push_str(&format!("{:#x}", csr)),
+        }
+    }
+    Insn { address, len, text, flow: decoded.flow, mem: None, imm: None, norm }
+}
+
+/// Flow of `jal`: a call when it links the return address
+fn jal(rd: u32, target: u64) -> Decoded {
+    match rd {
+        0 => Decoded::new("j", vec![Arg::Addr(target)])
+            .flow(Flow::Jump(Some(target))),
+        RA => Decoded::new("jal", vec![Arg::Addr(target)])
+            .flow(Flow::Call(Some(target))),
+        _ => Decoded::new("jal", vec![Arg::Reg(rd), Arg::Addr(target)])
+            .flow(Flow::Call(Some(target))),
+    }
+}
+
+/// Flow of `jalr`: a return through `ra`, a jump or a call through a
+/// register
+fn jalr(rd: u32, rs1: u32, imm: i64) -> Decoded {
+    match (rd, rs1, imm) {
+        (0, RA, 0) => Decoded::new("ret", vec![]).flow(Flow::Return),
+        (0, _, 0) => Decoded::new("jr", vec![Arg::Reg(rs1)])
+            .flow(Flow::Jump(None)),
+        (0, _, _) => Decoded::new("jr", vec![Arg::Mem(imm, rs1)])
+            .flow(Flow::Jump(None)),
+        (RA, _, 0) => Decoded::new("jalr", vec![Arg::Reg(rs1)])
+            .flow(Flow::Call(None)),
+        _ => Decoded::new("jalr", vec![Arg::Reg(rd), Arg::Mem(imm, rs1)])
+            .flow(Flow::Call(None)),
+    }
+}
+
+/// `addi`, shown as `nop`, `li` and `mv` where it is one
+fn addi(rd: u32, rs1: u32, imm: i64) -> Decoded {
+    match (rd, rs1, imm) {
+        (0, 0, 0) => Decoded::new("nop", vec![]),
+        (_, 0, _) => Decoded::new("li", vec![Arg::Reg(rd), Arg::Imm(imm)]),
+        (_, _, 0) => Decoded::new("mv", vec![Arg::Reg(rd), Arg::Reg(rs1)]),
+        _ => Decoded::new("addi", vec![Arg::Reg(rd), Arg::Reg(rs1),
+            Arg::Imm(imm)]),
+    }
+}
+
+/// A 32-bit instruction
+fn full(w: u32, address: u64, xlen: u32) -> Option<Decoded> {
+    let rd = bits(w, 11, 7, 0);
+    let rs1 = bits(w, 19, 15, 0);
+    let rs2 = bits(w, 24, 20, 0);
+    let funct3 = bits(w, 14, 12, 0);
+    let funct7 = bits(w, 31, 25, 0);
+    let imm_i = sext(w >> 20, 12);
+    let imm_s = sext(bits(w, 31, 25, 5) | bits(w, 11, 7, 0), 12);
+    let rv64 = xlen == 64;
+    let r = |mnemonic| Some(Decoded::new(mnemonic, vec![Arg::Reg(rd),
+        Arg::Reg(rs1), Arg::Reg(rs2)]));
+
+    match w & 0x7f {
+        // lui, auipc
+        0x37 => Some(Decoded::new("lui", vec![Arg::Reg(rd), Arg::Upper(w >> 12)])),
+        0x17 => Some(Decoded::new("auipc", vec![Arg::Reg(rd),
+            Arg::Upper(w >> 12)])),
+        0x6f => {
+            let offset = sext(bits(w, 31, 31, 20) | bits(w, 19, 12, 12)
+                | bits(w, 20, 20, 11) | bits(w, 30, 21, 1), 21);
+            Some(jal(rd, address.wrapping_add(offset as u64)))
+        },
+        0x67 if funct3 == 0 => Some(jalr(rd, rs1, imm_i)),
+        0x63 => {
+            let offset = sext(bits(w, 31, 31, 12) | bits(w, 7, 7, 11)
+                | bits(w, 30, 25, 5) | bits(w, 11, 8, 1), 13);
+            let target = address.wrapping_add(offset as u64);
+            let mnemonic = match funct3 {
+                0 => "beq", 1 => "bne", 4 => "blt", 5 => "bge", 6 => "bltu",
+                7 => "bgeu", _ => return None,
+            };
+            let decoded = match (mnemonic, rs2) {
+                ("beq", 0) => Decoded::new("beqz", vec![Arg::Reg(rs1),
+                    Arg::Addr(target)]),
+                ("bne", 0) => Decoded::new("bnez", vec![Arg::Reg(rs1),
+                    Arg::Addr(target)]),
+                _ => Decoded::new(mnemonic, vec![Arg::Reg(rs1), Arg::Reg(rs2),
+                    Arg::Addr(target)]),
+            };
+            Some(decoded.flow(Flow::Branch(target)))
+        },
+        0x03 => {
+            let mnemonic = match funct3 {
+                0 => "lb", 1 => "lh", 2 => "lw", 3 if rv64 => "ld", 4 => "lbu",
+                5 => "lhu", 6 if rv64 => "lwu", _ => return None,
+            };
+            Some(Decoded::new(mnemonic, vec![Arg::Reg(rd), Arg::Mem(imm_i, rs1)]))
+        },
+        0x23 => {
+            let mnemonic = match funct3 {
+                0 => "sb", 1 => "sh", 2 => "sw", 3 if rv64 => "sd",
+                _ => return None,
+            };
+            Some(Decoded::new(mnemonic, vec![Arg::Reg(rs2), Arg::Mem(imm_s, rs1)]))
+        },
+        0x07 | 0x27 => {
+            let mnemonic = match (w & 0x7f, funct3) {
+                (0x07, 2) => "flw", (0x07, 3) => "fld",
+                (0x27, 2) => "fsw", (0x27, 3) => "fsd",
+                _ => return None,
+            };
+            Some(if w & 0x7f == 0x07 {
+                Decoded::new(mnemonic, vec![Arg::FReg(rd), Arg::Mem(imm_i, rs1)])
+            } else {
+                Decoded::new(mnemonic, vec![Arg::FReg(rs2), Arg::Mem(imm_s, rs1)])
+            })
+        },
+        0x13 => {
+            let shamt = i64::from(bits(w, 25, 20, 0));
+            let shift = |mnemonic| Some(Decoded::new(mnemonic,
+                vec![Arg::Reg(rd), Arg::Reg(rs1), Arg::Imm(shamt)]));
+            let imm = |mnemonic| Some(Decoded::new(mnemonic,
+                vec![Arg::Reg(rd), Arg::Reg(rs1), Arg::Imm(imm_i)]));
+            match (funct3, w >> 26) {
+                (0, _) => Some(addi(rd, rs1, imm_i)),
+                (2, _) => imm("slti"),
+                (3, _) => imm("sltiu"),
+                (4, _) => imm("xori"),
+                (6, _) => imm("ori"),
+                (7, _) => imm("andi"),
+                (1, 0) => shift("slli"),
+                (5, 0) => shift("srli"),
+                (5, 0x10) => shift("srai"),
+                _ => None,
+            }
+        },
+        0x1b if rv64 => {
+            let shamt = i64::from(rs2);
+            let shift = |mnemonic| Some(Decoded::new(mnemonic,
+                vec![Arg::Reg(rd), Arg::Reg(rs1), Arg::Imm(shamt)]));
+            match (funct3, funct7) {
+                (0, _) if imm_i == 0 => Some(Decoded::new("sext.w",
+                    vec![Arg::Reg(rd), Arg::Reg(rs1)])),
+                (0, _) => Some(Decoded::new("addiw", vec![Arg::Reg(rd),
+                    Arg::Reg(rs1), Arg::Imm(imm_i)])),
+                (1, 0) => shift("slliw"),
+                (5, 0) => shift("srliw"),
+                (5, 0x20) => shift("sraiw"),
+                _ => None,
+            }
+        },
+        0x33 => match (funct7, funct3) {
+            (0, 0) => r("add"), (0x20, 0) => r("sub"), (0, 1) => r("sll"),
+            (0, 2) => r("slt"), (0, 3) => r("sltu"), (0, 4) => r("xor"),
+            (0, 5) => r("srl"), (0x20, 5) => r("sra"), (0, 6) => r("or"),
+            (0, 7) => r("and"),
+            (1, 0) => r("mul"), (1, 1) => r("mulh"), (1, 2) => r("mulhsu"),
+            (1, 3) => r("mulhu"), (1, 4) => r("div"), (1, 5) => r("divu"),
+            (1, 6) => r("rem"), (1, 7) => r("remu"),
+            _ => None,
+        },
+        0x3b if rv64 => match (funct7, funct3) {
+            (0, 0) => r("addw"), (0x20, 0) => r("subw"), (0, 1) => r("sllw"),
+            (0, 5) => r("srlw"), (0x20, 5) => r("sraw"),
+            (1, 0) => r("mulw"), (1, 4) => r("divw"), (1, 5) => r("divuw"),
+            (1, 6) => r("remw"), (1, 7) => r("remuw"),
+            _ => None,
+        },
+        0x2f => {
+            let mnemonic = match (funct3, w >> 27) {
+                (2, 0x02) => "lr.w", (2, 0x03) => "sc.w", (2, 0x01) => "amoswap.w",
+                (2, 0x00) => "amoadd.w", (2, 0x04) => "amoxor.w",
+                (2, 0x0c) => "amoand.w", (2, 0x08) => "amoor.w",
+                (2, 0x10) => "amomin.w", (2, 0x14) => "amomax.w",
+                (2, 0x18) => "amominu.w", (2, 0x1c) => "amomaxu.w",
+                (3, 0x02) if rv64 => "lr.d", (3, 0x03) if rv64 => "sc.d",
+                (3, 0x01) if rv64 => "amoswap.d", (3, 0x00) if rv64 => "amoadd.d",
+                (3, 0x04) if rv64 => "amoxor.d", (3, 0x0c) if rv64 => "amoand.d",
+                (3, 0x08) if rv64 => "amoor.d", (3, 0x10) if rv64 => "amomin.d",
+                (3, 0x14) if rv64 => "amomax.d", (3, 0x18) if rv64 => "amominu.d",
+                (3, 0x1c) if rv64 => "amomaxu.d",
+                _ => return None,
+            };
+            Some(if mnemonic.starts_with("lr") {
+                Decoded::new(mnemonic, vec![Arg::Reg(rd), Arg::Ptr(rs1)])
+            } else {
+                Decoded::new(mnemonic, vec![Arg::Reg(rd), Arg::Reg(rs2),
+                    Arg::Ptr(rs1)])
+            })
+        },
+        0x0f => match funct3 {
+            0 => Some(Decoded::new("fence", vec![])),
+            1 => Some(Decoded::new("fence.i", vec![])),
+            _ => None,
+        },
+        0x73 => {
+            let csr = w >> 20;
+            let mnemonic = match funct3 {
+                0 => return match w {
+                    0x0000_0073 => Some(Decoded::new("ecall", vec![])),
+                    0x0010_0073 => Some(Decoded::new("ebreak", vec![])
+                        .flow(Flow::Stop)),
+                    0x1050_0073 => Some(Decoded::new("wfi", vec![])),
+                    0x1020_0073 => Some(Decoded::new("sret", vec![])
+                        .flow(Flow::Return)),
+                    0x3020_0073 => Some(Decoded::new("mret", vec![])
+                        .flow(Flow::Return)),
+                    _ => None,
+                },
+                1 => "csrrw", 2 => "csrrs", 3 => "csrrc", 5 => "csrrwi",
+                6 => "csrrsi", 7 => "csrrci",
+                _ => return None,
+            };
+            let source = if funct3 >= 5 {
+                Arg::Imm(i64::from(rs1))
+            } else {
+                Arg::Reg(rs1)
+            };
+            Some(Decoded::new(mnemonic, vec![Arg::Reg(rd), Arg::Csr(csr), source]))
+        },
+        // Floating point and vector operations
+        0x43 | 0x47 | 0x4b | 0x4f | 0x53 | 0x57 =>
+            Some(Decoded::new(".insn", vec![Arg::Upper(w)])),
+        _ => None,
+    }
+}
+
+/// A 16-bit compressed instruction, shown as the instruction it expands to
+fn compressed(h: u16, address: u64, xlen: u32) -> Option<Decoded> {
+    let w = u32::from(h);
+    let rv64 = xlen == 64;
+    let funct3 = bits(w, 15, 13, 0);
+    // Registers x8 to x15 of the 3 bit fields
+    let rd_ = bits(w, 4, 2, 0) + 8;
+    let rs1_ = bits(w, 9, 7, 0) + 8;
+    let rd = bits(w, 11, 7, 0);
+    let rs2 = bits(w, 6, 2, 0);
+    let imm6 = sext(bits(w, 12, 12, 5) | bits(w, 6, 2, 0), 6);
+    let shamt = i64::from(bits(w, 12, 12, 5) | bits(w, 6, 2, 0));
+    // Offsets scaled by 4 and 8 of the loads and stores
+    let uimm_w = i64::from(bits(w, 12, 10, 3) | bits(w, 6, 6, 2)
+        | bits(w, 5, 5, 6));
+    let uimm_d = i64::from(bits(w, 12, 10, 3) | bits(w, 6, 5, 6));
+    let target_j = address.wrapping_add(sext(bits(w, 12, 12, 11)
+        | bits(w, 11, 11, 4) | bits(w, 10, 9, 8) | bits(w, 8, 8, 10)
+        | bits(w, 7, 7, 6) | bits(w, 6, 6, 7) | bits(w, 5, 3, 1)
+        | bits(w, 2, 2, 5), 12) as u64);
+    let target_b = address.wrapping_add(sext(bits(w, 12, 12, 8)
+        | bits(w, 11, 10, 3) | bits(w, 6, 5, 6) | bits(w, 4, 3, 1)
+        | bits(w, 2, 2, 5), 9) as u64);
+    let reg_mem = |mnemonic, rd, disp, base| Some(Decoded::new(mnemonic,
+        vec![Arg::Reg(rd), Arg::Mem(disp, base)]));
+    let freg_mem = |mnemonic, rd, disp, base| Some(Decoded::new(mnemonic,
+        vec![Arg::FReg(rd), Arg::Mem(disp, base)]));
+
+    match (w & 3, funct3) {
+        // Illegal, all zeroes
+        (0, _) if w == 0 => None,
+        (0, 0) => {
+            let imm = bits(w, 12, 11, 4) | bits(w, 10, 7, 6) | bits(w, 6, 6, 2)
+                | bits(w, 5, 5, 3);
+            if imm == 0 {
+                return None;
+            }
+            Some(Decoded::new("addi", vec![Arg::Reg(rd_), Arg::Reg(SP),
+                Arg::Imm(i64::from(imm))]))
+        },
+        (0, 1) => freg_mem("fld", rd_, uimm_d, rs1_),
+        (0, 2) => reg_mem("lw", rd_, uimm_w, rs1_),
+        (0, 3) if rv64 => reg_mem("ld", rd_, uimm_d, rs1_),
+        (0, 3) => freg_mem("flw", rd_, uimm_w, rs1_),
+        (0, 5) => Some(Decoded::new("fsd", vec![Arg::FReg(rd_),
+            Arg::Mem(uimm_d, rs1_)])),
+        (0, 6) => reg_mem("sw", rd_, uimm_w, rs1_),
+        (0, 7) if rv64 => reg_mem("sd", rd_, uimm_d, rs1_),
+        (0, 7) => Some(Decoded::new("fsw", vec![Arg::FReg(rd_),
+            Arg::Mem(uimm_w, rs1_)])),
+
+        (1, 0) => Some(addi(rd, rd, imm6)),
+        (1, 1) if rv64 => Some(Decoded::new("addiw", vec![Arg::Reg(rd),
+            Arg::Reg(rd), Arg::Imm(imm6)])),
+        (1, 1) => Some(jal(RA, target_j)),
+        (1, 2) => Some(addi(rd, 0, imm6)),
+        (1, 3) if rd == SP => {
+            let imm = sext(bits(w, 12, 12, 9) | bits(w, 6, 6, 4)
+                | bits(w, 5, 5, 6) | bits(w, 4, 3, 7) | bits(w, 2, 2, 5), 10);
+            Some(addi(SP, SP, imm))
+        },
+        (1, 3) => Some(Decoded::new("lui", vec![Arg::Reg(rd),
+            Arg::Upper((imm6 as u32) & 0xfffff)])),
+        (1, 4) => {
+            let rd = rs1_;
+            let r = |mnemonic| Some(Decoded::new(mnemonic, vec![Arg::Reg(rd),
+                Arg::Reg(rd), Arg::Reg(rd_)]));
+            match (bits(w, 11, 10, 0), bits(w, 12, 12, 0), bits(w, 6, 5, 0)) {
+                (0, _, _) => Some(Decoded::new("srli", vec![Arg::Reg(rd),
+                    Arg::Reg(rd), Arg::Imm(shamt)])),
+                (1, _, _) => Some(Decoded::new("srai", vec![Arg::Reg(rd),
+                    Arg::Reg(rd), Arg::Imm(shamt)])),
+                (2, _, _) => Some(Decoded::new("andi", vec![Arg::Reg(rd),
+                    Arg::Reg(rd), Arg::Imm(imm6)])),
+                (3, 0, 0) => r("sub"),
+                (3, 0, 1) => r("xor"),
+                (3, 0, 2) => r("or"),
+                (3, 0, 3) => r("and"),
+                (3, 1, 0) if rv64 => r("subw"),
+                (3, 1, 1) if rv64 => r("addw"),
+                _ => None,
+            }
+        },
+        (1, 5) => Some(jal(0, target_j)),
+        (1, 6) => Some(Decoded::new("beqz", vec![Arg::Reg(rs1_),
+            Arg::Addr(target_b)]).flow(Flow::Branch(target_b))),
+        (1, 7) => Some(Decoded::new("bnez", vec![Arg::Reg(rs1_),
+            Arg::Addr(target_b)]).flow(Flow::Branch(target_b))),
+
+        (2, 0) => Some(Decoded::new("slli", vec![Arg::Reg(rd), Arg::Reg(rd),
+            Arg::Imm(shamt)])),
+        (2, 1) => freg_mem("fld", rd, i64::from(bits(w, 12, 12, 5)
+            | bits(w, 6, 5, 3) | bits(w, 4, 2, 6)), SP),
+        (2, 2) if rd != 0 => reg_mem("lw", rd, i64::from(bits(w, 12, 12, 5)
+            | bits(w, 6, 4, 2) | bits(w, 3, 2, 6)), SP),
+        (2, 3) if rv64 && rd != 0 => reg_mem("ld", rd, i64::from(
+            bits(w, 12, 12, 5) | bits(w, 6, 5, 3) | bits(w, 4, 2, 6)), SP),
+        (2, 3) if !rv64 => freg_mem("flw", rd, i64::from(bits(w, 12, 12, 5)
+            | bits(w, 6, 4, 2) | bits(w, 3, 2, 6)), SP),
+        (2, 4) => match (bits(w, 12, 12, 0), rd, rs2) {
+            (0, 0, 0) => None,
+            (0, _, 0) => Some(jalr(0, rd, 0)),
+            (0, _, _) => Some(Decoded::new("mv", vec![Arg::Reg(rd),
+                Arg::Reg(rs2)])),
+            (_, 0, 0) => Some(Decoded::new("ebreak", vec![]).flow(Flow::Stop)),
+            (_, _, 0) => Some(jalr(RA, rd, 0)),
+            _ => Some(Decoded::new("add", vec![Arg::Reg(rd), Arg::Reg(rd),
+                Arg::Reg(rs2)])),
+        },
+        (2, 5) => Some(Decoded::new("fsd", vec![Arg::FReg(rs2),
+            Arg::Mem(i64::from(bits(w, 12, 10, 3) | bits(w, 9, 7, 6)), SP)])),
+        (2, 6) => reg_mem("sw", rs2, i64::from(bits(w, 12, 9, 2)
+            | bits(w, 8, 7, 6)), SP),
+        (2, 7) if rv64 => reg_mem("sd", rs2, i64::from(bits(w, 12, 10, 3)
+            | bits(w, 9, 7, 6)), SP),
+        (2, 7) => Some(Decoded::new("fsw", vec![Arg::FReg(rs2),
+            Arg::Mem(i64::from(bits(w, 12, 9, 2) | bits(w, 8, 7, 6)), SP)])),
+        _ => None,
+    }
+}
@@ -0,0 +1,139 @@
+//! Samples are the files we analyse. Each tab holds at most one sample.
+use std::{
+    io,
+    fmt,
+    fs,
+    sync::{Arc},
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+use crate::archive;
+use crate::image::{Image};
+
+/// File formats we know how to lay out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Format {
+    #[serde(alias = "pe")]
+    PE,
+    #[serde(alias = "elf")]
+    ELF,
+    #[serde(alias = "macho", alias = "Mach-O")]
+    MachO,
+    /// Anything we do not recognize
+    #[serde(alias = "raw")]
+    Raw,
+}
+
+impl Format {
+    /// Detect the format of `data` based on its magic
+    pub fn detect(data: &[u8]) -> Format {
+        match data {
+            [b'M', b'Z', ..] => Format::PE,
+            [0x7f, b'E', b'L', b'F', ..] => Format::ELF,
+            // 32 and 64-bit Mach-O in both endiannesses, plus fat binaries
+            [0xfe, 0xed, 0xfa, 0xce, ..] | [0xce, 0xfa, 0xed, 0xfe, ..]
+            | [0xfe, 0xed, 0xfa, 0xcf, ..] | [0xcf, 0xfa, 0xed, 0xfe, ..]
+            | [0xca, 0xfe, 0xba, 0xbe, ..] => Format::MachO,
+            _ => Format::Raw,
+        }
+    }
+
+    /// Formats the system can run
+    pub fn is_executable(self) -> bool {
+        matches!(self, Format::PE | Format::ELF | Format::MachO)
+    }
+
+    /// Get a format from its name as typed by the user, ignoring case
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_ascii_lowercase().as_str() {
+            "pe" => Some(Format::PE),
+            "elf" => Some(Format::ELF),
+            "macho" | "mach-o" => Some(Format::MachO),
+            "raw" => Some(Format::Raw),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Format::PE => "PE",
+            Format::ELF => "ELF",
+            Format::MachO => "Mach-O",
+            Format::Raw => "raw",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A file loaded in memory for analysis. The contents are never written
+/// back to disk.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    /// Path the sample was loaded from
+    pub path: PathBuf,
+    /// Contents of the file, shared with background workers
+    pub data: Arc<[u8]>,
+    /// Detected file format
+    pub format: Format,
+    /// SHA-256 of the contents in lowercase hex, identifies the sample
+    /// wherever it is stored
+    pub sha256: String,
+    /// Layout in memory of executables, to map file offsets to virtual
+    /// addresses
+    pub image: Option<Arc<Image>>,
+}
+
+impl Sample {
+    /// Read the sample at `path` in memory. A path going into a container,
+    /// e.g. `files.zip/a.exe`, is extracted from it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Sample> {
+        let path = path.as_ref();
+        // Keep an absolute path so sessions work from any directory
+        let (path, data) = if path.exists() {
+            let path = fs::canonicalize(path)?;
+            let data = fs::read(&path)?;
+            (path, data)
+        } else {
+            archive::read(path)?
+        };
+
+        Ok(Sample::from_bytes(path, data))
+    }
+
+    /// Create a sample from `data` already in memory, `path` tells where it
+    /// came from
+    pub fn from_bytes<D: Into<Arc<[u8]>>>(path: PathBuf, data: D) -> Sample {
+        let data = data.into();
+        let format = Format::detect(&data);
+        let sha256 = Sha256::digest(&data).iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let image = if format.is_executable() {
+            Image::parse(&data).ok().map(Arc::new)
+        } else {
+            None
+        };
+
+        Sample { path, data, format, sha256, image }
+    }
+
+    /// Name of the file, used as the title of the tab holding the sample
+    pub fn name(&self) -> String {
+        self.path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// Directory holding the sample, where the file managers of its tab start
+    pub fn dir(&self) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+}
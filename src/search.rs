@@ -0,0 +1,403 @@
+//! Searching the sample of a tab for byte patterns, text or regexes.
+//!
+//! Every query is compiled to a byte regex and run in a background thread
+//! over the sample. Results are collected by the tab on every draw.
+use std::{
+    thread,
+    sync::{Arc, mpsc},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use regex::bytes::{Regex, RegexBuilder};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::defang;
+
+/// Stop collecting matches past this many, a `.` regex would otherwise
+/// hold one match per byte of the sample
+pub const MAX_MATCHES: usize = 100_000;
+
+/// Number of matches sent at once by the search thread
+const BATCH: usize = 1024;
+
+/// A query, as typed after `search`:
+///
+/// * `hex 4D 5A ?? 00`: bytes in hex, `??` matches any byte and `4?` any
+///   byte with a high nibble of 4
+/// * `text MZ`: ASCII text, also the default when no kind is given
+/// * `utf16 kernel32`: UTF-16LE text
+/// * `re \x4d\x5a.{2}`: a byte regex
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Hex(Vec<(u8, u8)>),
+    Text(String),
+    Utf16(String),
+    Regex(String),
+}
+
+impl Pattern {
+    /// Parse a query typed by the user
+    pub fn parse(query: &str) -> Result<Pattern, String> {
+        let query = query.trim();
+        let (kind, rest) = match query.find(char::is_whitespace) {
+            Some(pos) => (&query[..pos], query[pos..].trim_start()),
+            None => (query, ""),
+        };
+
+        let pattern = match kind {
+            "hex" => Pattern::Hex(parse_hex(rest)?),
+            "text" => Pattern::Text(rest.to_string()),
+            "utf16" => Pattern::Utf16(rest.to_string()),
+            "re" => Pattern::Regex(rest.to_string()),
+            // No kind given, search for the whole query as text
+            _ => Pattern::Text(query.to_string()),
+        };
+
+        match &pattern {
+            Pattern::Hex(bytes) if bytes.is_empty() =>
+                Err("empty hex pattern".to_string()),
+            Pattern::Text(s) | Pattern::Utf16(s) | Pattern::Regex(s)
+                if s.is_empty() => Err("empty pattern".to_string()),
+            _ => Ok(pattern),
+        }
+    }
+
+    /// Compile the pattern to a byte regex
+    pub fn compile(&self) -> Result<Regex, String> {
+        let source = match self {
+            Pattern::Hex(bytes) => bytes.iter()
+                .map(|&(value, mask)| masked_byte_class(value, mask))
+                .collect(),
+            Pattern::Text(text) => text.bytes().map(escape_byte).collect(),
+            Pattern::Utf16(text) => text.encode_utf16()
+                .flat_map(|unit| unit.to_le_bytes())
+                .map(escape_byte)
+                .collect(),
+            Pattern::Regex(re) => re.clone(),
+        };
+
+        RegexBuilder::new(&source)
+            // Match bytes, not UTF-8 code points, and let `.` match newlines
+            .unicode(false)
+            .dot_matches_new_line(true)
+            .build()
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Parse space separated hex bytes into (value, mask) pairs. Spaces between
+/// bytes are optional: `4D5A??00` works as well.
+fn parse_hex(text: &str) -> Result<Vec<(u8, u8)>, String> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace())
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {}", text));
+    }
+
+    digits.chunks(2).map(|pair| {
+        let mut value = 0u8;
+        let mut mask = 0u8;
+        for &digit in pair {
+            value <<= 4;
+            mask <<= 4;
+            if digit != '?' {
+                let nibble = digit.to_digit(16)
+                    .ok_or_else(|| format!("invalid hex digit {}", digit))?;
+                value |= nibble as u8;
+                mask |= 0xf;
+            }
+        }
+        Ok((value, mask))
+    }).collect()
+}
+
+fn escape_byte(byte: u8) -> String {
+    format!("\\x{:02x}", byte)
+}
+
+/// Regex matching the bytes equal to `value` on the bits set in `mask`
+fn masked_byte_class(value: u8, mask: u8) -> String {
+    match mask {
+        0xff => escape_byte(value),
+        0x00 => ".".to_string(),
+        _ => {
+            let class: String = (0..=255u8)
+                .filter(|byte| byte & mask == value & mask)
+                .map(escape_byte)
+                .collect();
+            format!("[{}]", class)
+        }
+    }
+}
+
+/// A match in the sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// Messages from the search thread
+enum Message {
+    Matches(Vec<Match>),
+    /// The search is over, with `true` if it stopped at `MAX_MATCHES`
+    Done(bool),
+}
+
+/// The last search run in a tab and its results
+#[derive(Default)]
+pub struct Search {
+    /// The query as typed by the user
+    pub query: Option<String>,
+    /// Matches found so far, sorted by offset and never overlapping
+    pub matches: Vec<Match>,
+    /// Index of the selected match
+    pub current: Option<usize>,
+    /// The search thread is still running
+    pub running: bool,
+    /// The search stopped at `MAX_MATCHES`
+    pub truncated: bool,
+    /// Receiving end of the search thread
+    rx: Option<mpsc::Receiver<Message>>,
+    /// Set to stop the search thread
+    cancel: Arc<AtomicBool>,
+}
+
+impl Search {
+    /// Start searching `data` for `query` in the background, dropping the
+    /// results of the previous search
+    pub fn start(&mut self, query: &str, data: Arc<[u8]>) -> Result<(), String> {
+        let regex = Pattern::parse(query)?.compile()?;
+
+        self.stop();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        *self = Search {
+            query: Some(query.trim().to_string()),
+            running: true,
+            rx: Some(rx),
+            cancel: cancel.clone(),
+            ..Search::default()
+        };
+
+        thread::spawn(move || search_thread(regex, data, tx, cancel));
+
+        Ok(())
+    }
+
+    /// Stop the running search, keeping the results found so far
+    pub fn stop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.rx = None;
+        self.running = false;
+    }
+
+    /// Collect the results sent by the search thread since the last call
+    pub fn poll(&mut self) {
+        let rx = match self.rx.as_ref() {
+            Some(rx) => rx,
+            None => return,
+        };
+
+        loop {
+            match rx.try_recv() {
+                Ok(Message::Matches(matches)) => self.matches.extend(matches),
+                Ok(Message::Done(truncated)) => {
+                    self.truncated = truncated;
+                    self.running = false;
+                    self.rx = None;
+                    break;
+                },
+                Err(mpsc::TryRecvError::Empty) => break,
+                // The thread is gone without telling us it was done
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.running = false;
+                    self.rx = None;
+                    break;
+                },
+            }
+        }
+    }
+
+    /// Matches overlapping the bytes in `start..end`
+    pub fn overlapping(&self, start: usize, end: usize) -> &[Match] {
+        // Matches never overlap so both their starts and ends are sorted
+        let first = self.matches
+            .partition_point(|m| m.offset + m.len <= start);
+        let last = self.matches.partition_point(|m| m.offset < end);
+        &self.matches[first..last.max(first)]
+    }
+
+    /// Select the first match after `offset`, wrapping around to the first
+    /// one. Returns the offset of the selected match.
+    pub fn next(&mut self, offset: usize) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let index = self.matches.partition_point(|m| m.offset <= offset);
+        let index = if index == self.matches.len() { 0 } else { index };
+        self.select(index)
+    }
+
+    /// Select the last match before `offset`, wrapping around to the last
+    /// one. Returns the offset of the selected match.
+    pub fn previous(&mut self, offset: usize) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        let index = self.matches.partition_point(|m| m.offset < offset);
+        let index = if index == 0 { self.matches.len() - 1 } else { index - 1 };
+        self.select(index)
+    }
+
+    /// Select the match at `index`, returning its offset
+    pub fn select(&mut self, index: usize) -> Option<usize> {
+        let m = self.matches.get(index)?;
+        self.current = Some(index);
+        Some(m.offset)
+    }
+
+    /// One line summary of the search
+    pub fn summary(&self) -> String {
+        match &self.query {
+            None => "no search".to_string(),
+            Some(query) => format!("{}: {}{} matches{}", query,
+                self.matches.len(),
+                if self.truncated { "+" } else { "" },
+                if self.running { ", searching..." } else { "" }),
+        }
+    }
+}
+
+fn search_thread(regex: Regex, data: Arc<[u8]>, tx: mpsc::Sender<Message>,
+        cancel: Arc<AtomicBool>) {
+    let mut batch = Vec::with_capacity(BATCH);
+    let mut found = 0;
+
+    for m in regex.find_iter(&data) {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        // Empty matches would highlight nothing
+        if m.start() == m.end() {
+            continue;
+        }
+        if found == MAX_MATCHES {
+            let _ = tx.send(Message::Matches(batch));
+            let _ = tx.send(Message::Done(true));
+            return;
+        }
+
+        batch.push(Match { offset: m.start(), len: m.end() - m.start() });
+        found += 1;
+        if batch.len() == BATCH {
+            let full = std::mem::replace(&mut batch, Vec::with_capacity(BATCH));
+            if tx.send(Message::Matches(full)).is_err() {
+                return;
+            }
+        }
+    }
+
+    let _ = tx.send(Message::Matches(batch));
+    let _ = tx.send(Message::Done(false));
+}
+
+/// Plugin listing the matches of the tab's search with the bytes around them
+#[derive(Default)]
+pub struct SearchResults {
+    /// First match shown
+    scroll: usize,
+}
+
+/// Number of bytes shown for each match
+const CONTEXT: usize = 16;
+
+impl RenderPlugin for SearchResults {
+    fn get_name(&self) -> &str {
+        "Search"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let search = &ctx.search;
+        let data = ctx.data();
+        let rows = (area.height as usize).saturating_sub(1);
+
+        // Keep the selected match in view
+        let current = search.current.unwrap_or(0);
+        if current < self.scroll {
+            self.scroll = current;
+        } else if rows > 0 && current >= self.scroll + rows {
+            self.scroll = current + 1 - rows;
+        }
+
+        let mut lines = vec![Spans::from(Span::styled(search.summary(),
+            Style::default().fg(Color::Yellow)))];
+
+        let match_style = Style::default().fg(Color::Red);
+        let shown = search.matches.iter().enumerate()
+            .skip(self.scroll)
+            .take(rows);
+        for (i, m) in shown {
+            let end = (m.offset + CONTEXT.max(m.len)).min(data.len());
+            let bytes = &data[m.offset.min(end)..end];
+
+            let mut spans = vec![Span::raw(format!("{:08x}  ", m.offset))];
+            for (j, byte) in bytes.iter().enumerate() {
+                let style = if j < m.len { match_style }
+                            else { Style::default() };
+                spans.push(Span::styled(format!("{:02x} ", byte), style));
+            }
+            spans.push(Span::raw(format!(" {}",
+                defang::text(&printable(bytes)))));
+
+            let mut line = Spans::from(spans);
+            if search.current == Some(i) {
+                for span in line.0.iter_mut() {
+                    span.style = span.style.add_modifier(Modifier::REVERSED);
+                }
+            }
+            lines.push(line);
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous match and move the cursor to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let search = &mut ctx.search;
+        let last = search.matches.len().saturating_sub(1);
+        let index = match (key.code, search.current) {
+            (KeyCode::Char('j'), Some(i)) => (i + 1).min(last),
+            (KeyCode::Char('k'), Some(i)) => i.saturating_sub(1),
+            (KeyCode::Char('j'), None) | (KeyCode::Char('k'), None) => 0,
+            (KeyCode::Char('g'), _) => 0,
+            (KeyCode::Char('G'), _) => last,
+            _ => return false,
+        };
+
+        if let Some(offset) = search.select(index) {
+            ctx.cursor = offset;
+        }
+        true
+    }
+}
+
+/// Bytes as ASCII, with `.` for anything not printable
+pub fn printable(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char }
+                  else { '.' })
+        .collect()
+}
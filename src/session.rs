@@ -0,0 +1,124 @@
+//! Sessions save the open tabs, their samples and layouts so that maglab
+//! opens where the user left it
+use std::{
+    fs,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::app::{App, MagLabApp};
+use crate::image::{RawCode};
+use crate::layout::{Layout};
+use crate::sample::{Sample};
+
+/// State of a single tab
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TabSession {
+    pub title: String,
+    /// Path of the sample opened in the tab
+    pub sample: Option<PathBuf>,
+    pub layout: Layout,
+    /// Focused column
+    pub column: usize,
+    /// Focused plugin in each column
+    pub lines: Vec<usize>,
+    /// Offset of the cursor in the sample
+    #[serde(default)]
+    pub cursor: usize,
+    /// Last search run in the tab, run again on restore
+    #[serde(default)]
+    pub search: Option<String>,
+    /// Bytes of the sample disassembled as headerless code
+    #[serde(default)]
+    pub raw: Option<RawCode>,
+}
+
+/// State of all the tabs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Active tab
+    pub index: usize,
+    pub tabs: Vec<TabSession>,
+}
+
+impl Session {
+    /// Read a session from the JSON file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Session, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        let session = serde_json::from_str(&text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(session)
+    }
+
+    /// Write the session as JSON to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(path, text)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        Ok(())
+    }
+
+    /// Capture the current state of `app`
+    pub fn capture(app: &MagLabApp) -> Session {
+        let tabs = app.tabs.apps.iter().map(|tab| TabSession {
+            title: tab.title.clone(),
+            sample: tab.ctx.sample.as_ref().map(|sample| sample.path.clone()),
+            cursor: tab.ctx.cursor,
+            search: tab.ctx.search.query.clone(),
+            raw: tab.ctx.raw,
+            layout: Layout::from_grid(&tab.grid),
+            column: tab.grid.index,
+            lines: tab.grid.columns.iter().map(|col| col.index).collect(),
+        }).collect();
+
+        Session { index: app.tabs.index, tabs }
+    }
+
+    /// Recreate the tabs of the session. Samples that cannot be read anymore
+    /// are left out but their tab is kept.
+    pub fn restore<'a>(&self) -> Vec<App<'a>> {
+        self.tabs.iter().map(|tab| {
+            let sample = tab.sample.as_ref()
+                .and_then(|path| Sample::open(path).ok());
+            let dir = sample.as_ref()
+                .map(|sample| sample.dir())
+                .unwrap_or_else(|| PathBuf::from("."));
+            let mut grid = tab.layout.build(&dir);
+
+            // Restore the focus, as long as it still points to a plugin
+            for (col, &line) in grid.columns.iter_mut().zip(&tab.lines) {
+                if line < col.plugins.len() {
+                    col.index = line;
+                }
+            }
+            if tab.column < grid.columns.len() {
+                grid.index = tab.column;
+            }
+
+            let mut app = match sample {
+                Some(sample) => App::with_sample(sample, grid),
+                None => App::new(&tab.title, grid),
+            };
+            app.title = tab.title.clone();
+            app.ctx.cursor = tab.cursor.min(app.ctx.data().len()
+                .saturating_sub(1));
+            // As long as the sample still holds the code
+            let len = app.ctx.data().len();
+            if let Some(code) = tab.raw.filter(|code| code.check(len).is_ok()) {
+                app.ctx.set_raw(Some(code));
+            }
+            if let (Some(query), Some(data)) = (&tab.search, app.ctx.contents()) {
+                // The query was valid when it was saved
+                let _ = app.ctx.search.start(query, data);
+            }
+            app
+        }).collect()
+    }
+}
@@ -0,0 +1,394 @@
+//! Imports and exports of executables, with the APIs malware likes tagged
+//! by what they are used for.
+//!
+//! The categories come from a TOML file mapping each category to the APIs
+//! in it, which extends the builtin mapping:
+//!
+//! ```toml
+//! injection = ["VirtualAllocEx", "WriteProcessMemory"]
+//! keylogging = ["GetAsyncKeyState", "SetWindowsHookEx"]
+//! ```
+//!
+//! Names are matched ignoring case, leading underscores and the `A`/`W`
+//! suffixes of the ANSI and wide variants of Windows APIs.
+use std::{
+    fs,
+    error::Error,
+    collections::{BTreeMap, HashMap},
+    path::{Path},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::xrefs::{Xref, describe};
+use crate::image::{Image};
+use crate::sample::{Sample};
+
+/// APIs of each category that ship with maglab
+const BUILTIN: &str = r#"
+injection = [
+    "VirtualAllocEx", "VirtualProtectEx", "WriteProcessMemory",
+    "ReadProcessMemory", "CreateRemoteThread", "CreateRemoteThreadEx",
+    "NtCreateThreadEx", "RtlCreateUserThread", "QueueUserAPC",
+    "NtQueueApcThread", "SetThreadContext", "NtUnmapViewOfSection",
+    "ZwUnmapViewOfSection", "NtMapViewOfSection", "NtWriteVirtualMemory",
+    "process_vm_writev", "task_for_pid", "mach_vm_write",
+]
+anti-debug = [
+    "IsDebuggerPresent", "CheckRemoteDebuggerPresent",
+    "NtQueryInformationProcess", "NtSetInformationThread",
+    "OutputDebugString", "GetTickCount", "QueryPerformanceCounter",
+    "ptrace", "sysctl",
+]
+networking = [
+    "WSAStartup", "socket", "connect", "bind", "listen", "accept", "send",
+    "recv", "sendto", "recvfrom", "gethostbyname", "getaddrinfo",
+    "InternetOpen", "InternetOpenUrl", "InternetConnect", "InternetReadFile",
+    "HttpOpenRequest", "HttpSendRequest", "URLDownloadToFile", "WinHttpOpen",
+    "WinHttpConnect", "WinHttpSendRequest", "curl_easy_perform",
+]
+crypto = [
+    "CryptAcquireContext", "CryptGenKey", "CryptDeriveKey", "CryptImportKey",
+    "CryptEncrypt", "CryptDecrypt", "CryptCreateHash", "CryptHashData",
+    "CryptProtectData", "CryptUnprotectData", "BCryptOpenAlgorithmProvider",
+    "BCryptGenerateSymmetricKey", "BCryptEncrypt", "BCryptDecrypt",
+    "EVP_EncryptInit_ex", "EVP_DecryptInit_ex", "AES_set_encrypt_key",
+    "CCCrypt",
+]
+persistence = [
+    "RegCreateKey", "RegCreateKeyEx", "RegSetValue", "RegSetValueEx",
+    "CreateService", "ChangeServiceConfig", "StartService",
+    "SetWindowsHookEx", "CopyFile", "MoveFileEx", "SHGetFolderPath",
+]
+"#;
+
+/// Which APIs fall in which category
+#[derive(Debug, Clone, Default)]
+pub struct ApiCategories {
+    /// Category by normalized name
+    by_name: HashMap<String, String>,
+}
+
+/// Name of an API as it is looked up
+fn normalize(name: &str) -> String {
+    name.trim_start_matches('_').to_ascii_lowercase()
+}
+
+impl ApiCategories {
+    /// The builtin mapping extended by the file at `path`, if there is one.
+    /// APIs listed in the file take the category it gives them.
+    pub fn load(path: Option<&Path>) -> Result<ApiCategories, Box<dyn Error>> {
+        let mut categories = ApiCategories::default();
+        categories.add("builtin", BUILTIN)?;
+
+        if let Some(path) = path.filter(|path| path.is_file()) {
+            let text = fs::read_to_string(path)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+            categories.add(&path.display().to_string(), &text)?;
+        }
+
+        Ok(categories)
+    }
+
+    /// Add the mapping in `text`, `source` names it in errors
+    pub fn add(&mut self, source: &str, text: &str) -> Result<(), String> {
+        let mapping: BTreeMap<String, Vec<String>> = toml::from_str(text)
+            .map_err(|err| format!("{}: {}", source, err))?;
+        for (category, names) in mapping {
+            for name in names {
+                self.by_name.insert(normalize(&name), category.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Category of the API called `name`
+    pub fn category(&self, name: &str) -> Option<&str> {
+        let normalized = normalize(name);
+        if let Some(category) = self.by_name.get(&normalized) {
+            return Some(category);
+        }
+        // ANSI and wide variants, e.g. `CreateServiceW`
+        match name.strip_suffix('A').or_else(|| name.strip_suffix('W')) {
+            Some(_) => self.by_name.get(&normalized[..normalized.len() - 1])
+                .map(|category| category.as_str()),
+            None => None,
+        }
+    }
+}
+
+/// An import or an export, as listed by the `Symbols` plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub import: bool,
+    /// Library of an import, when known
+    pub library: String,
+    pub name: String,
+    pub ordinal: Option<u32>,
+    /// Slot of an import or address of an export
+    pub va: Option<u64>,
+    pub category: Option<String>,
+}
+
+/// Imports and exports of the sample of a tab
+#[derive(Debug)]
+pub struct SymbolTable {
+    /// SHA-256 of the sample listed, to list it again once it changes
+    pub sha256: String,
+    /// Imports then exports, or why there are none
+    pub entries: Result<Vec<Entry>, String>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        SymbolTable { sha256: String::new(), entries: Ok(Vec::new()) }
+    }
+}
+
+impl SymbolTable {
+    /// List the imports and exports of `sample`
+    pub fn new(sample: &Sample, categories: &ApiCategories) -> SymbolTable {
+        let entries = match &sample.image {
+            Some(image) => Ok(SymbolTable::entries(image, categories)),
+            None => Err("not a PE, ELF or Mach-O file".to_string()),
+        };
+        SymbolTable { sha256: sample.sha256.clone(), entries }
+    }
+
+    fn entries(image: &Image, categories: &ApiCategories) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = image.imports.iter()
+            .map(|import| Entry {
+                import: true,
+                library: import.library.clone(),
+                name: import.name.clone(),
+                ordinal: import.ordinal.map(u32::from),
+                va: import.slot,
+                category: categories.category(&import.name)
+                    .map(|category| category.to_string()),
+            })
+            .collect();
+        entries.extend(image.exports.iter().map(|export| Entry {
+            import: false,
+            library: String::new(),
+            name: export.name.clone(),
+            ordinal: export.ordinal,
+            va: Some(export.va),
+            category: categories.category(&export.name)
+                .map(|category| category.to_string()),
+        }));
+        entries
+    }
+}
+
+/// Plugin listing the imports and exports of the tab's sample
+#[derive(Default)]
+pub struct Symbols {
+    /// Index of the selected entry among the ones shown
+    selected: usize,
+    /// Cross-reference of the selected entry the cursor was moved to
+    xref: Option<usize>,
+    /// Only show the entries with a category
+    categorized: bool,
+    /// First line shown
+    scroll: usize,
+}
+
+impl Symbols {
+    /// Instructions using the import `entry`, through its slot or through
+    /// a stub jumping through it
+    fn xrefs(entry: &Entry, ctx: &TabContext) -> Result<Vec<Xref>, String> {
+        let index = ctx.xrefs.index()?;
+        Ok(entry.va.map(|va| index.refs(va)).unwrap_or_default())
+    }
+
+    /// Entries shown, all or only the categorized ones
+    fn shown<'t>(&self, ctx: &'t TabContext) -> Vec<&'t Entry> {
+        match &ctx.symbols.entries {
+            Ok(entries) => entries.iter()
+                .filter(|entry| !self.categorized || entry.category.is_some())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl RenderPlugin for Symbols {
+    fn get_name(&self) -> &str {
+        if self.categorized { "Symbols [categorized]" } else { "Symbols" }
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let message = match (&ctx.sample, &ctx.symbols.entries) {
+            (None, _) => Some("no sample".to_string()),
+            (Some(_), Err(err)) => Some(err.clone()),
+            (Some(_), Ok(_)) if self.shown(ctx).is_empty() =>
+                Some(if self.categorized { "no categorized imports or exports" }
+                     else { "no imports or exports" }.to_string()),
+            _ => None,
+        };
+        if let Some(message) = message {
+            f.render_widget(Paragraph::new(message), area);
+            return;
+        }
+
+        let shown = self.shown(ctx);
+        self.selected = self.selected.min(shown.len() - 1);
+        let address_style = Style::default().fg(Color::Blue);
+        let library_style = Style::default().fg(Color::DarkGray);
+        let category_style = Style::default().fg(Color::LightRed)
+            .add_modifier(Modifier::BOLD);
+
+        // The cross-references of the selected import follow it
+        let mut lines = Vec::with_capacity(shown.len());
+        let mut selected_line = 0;
+        for (i, entry) in shown.iter().enumerate() {
+            let mut spans = vec![
+                Span::raw(if entry.import { "I " } else { "E " }),
+                Span::styled(match entry.va {
+                    Some(va) => format!("{:08x} ", va),
+                    None => format!("{:8} ", "-"),
+                }, address_style),
+            ];
+            if !entry.library.is_empty() {
+                spans.push(Span::styled(format!("{}!", entry.library),
+                    library_style));
+            }
+            spans.push(Span::raw(entry.name.clone()));
+            if let (Some(ordinal), false) = (entry.ordinal,
+                    entry.name.starts_with('#')) {
+                spans.push(Span::styled(format!(" #{}", ordinal),
+                    library_style));
+            }
+            if let Some(category) = &entry.category {
+                spans.push(Span::styled(format!(" [{}]", category),
+                    category_style));
+            }
+            if i != self.selected {
+                lines.push(Spans::from(spans));
+                continue;
+            }
+
+            for span in spans.iter_mut() {
+                span.style = span.style.add_modifier(Modifier::REVERSED);
+            }
+            lines.push(Spans::from(spans));
+            selected_line = lines.len() - 1;
+            if !entry.import {
+                continue;
+            }
+            let xrefs = match Symbols::xrefs(entry, ctx) {
+                Ok(xrefs) if xrefs.is_empty() => Err("no references in the \
+                    code".to_string()),
+                xrefs => xrefs,
+            };
+            let xrefs = match xrefs {
+                Ok(xrefs) => xrefs,
+                Err(err) => {
+                    lines.push(Spans::from(Span::styled(
+                        format!("    {}", err), library_style)));
+                    continue;
+                },
+            };
+            // Only executables have an index
+            let sample = ctx.sample.as_ref().unwrap();
+            let image = sample.image.as_deref().unwrap();
+            for (j, xref) in xrefs.iter().enumerate() {
+                let style = if self.xref == Some(j) {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                lines.push(Spans::from(vec![
+                    Span::raw("    "),
+                    Span::styled(format!("{:08x} ", xref.from),
+                        address_style.patch(style)),
+                    Span::styled(describe(image, &sample.data, xref), style),
+                ]));
+                if self.xref == Some(j) {
+                    selected_line = lines.len() - 1;
+                }
+            }
+        }
+
+        let rows = (area.height as usize).max(1);
+        if selected_line < self.scroll {
+            self.scroll = selected_line;
+        } else if selected_line >= self.scroll + rows {
+            self.scroll = selected_line + 1 - rows;
+        }
+        let shown: Vec<Spans> = lines.into_iter().skip(self.scroll).collect();
+        f.render_widget(Paragraph::new(shown), area);
+    }
+
+    /// Address of the selected symbol
+    fn address(&self, ctx: &TabContext) -> Option<u64> {
+        self.shown(ctx).get(self.selected).and_then(|entry| entry.va)
+    }
+
+    /// `j`/`k` select the next/previous symbol and `Enter` moves the cursor
+    /// of the tab to its address. `x`/`X` move the cursor to the next/
+    /// previous instruction using the selected import. `c` only shows the
+    /// symbols with a category, or all of them again.
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        let shown = self.shown(ctx);
+        let last = shown.len().saturating_sub(1);
+        let selected = self.selected;
+        match key.code {
+            KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.selected = last,
+            KeyCode::Char('c') => {
+                self.categorized = !self.categorized;
+                self.selected = 0;
+                self.scroll = 0;
+            },
+            KeyCode::Enter => {
+                let image = ctx.sample.as_ref()
+                    .and_then(|sample| sample.image.as_deref());
+                let offset = shown.get(self.selected)
+                    .and_then(|entry| entry.va)
+                    .zip(image)
+                    .and_then(|(va, image)| image.offset_of(va));
+                if let Some(offset) = offset {
+                    ctx.cursor = offset.min(ctx.data().len()
+                        .saturating_sub(1));
+                }
+            },
+            KeyCode::Char('x') | KeyCode::Char('X') => {
+                let xrefs = match shown.get(self.selected)
+                        .map(|entry| Symbols::xrefs(entry, ctx)) {
+                    Some(Ok(xrefs)) if !xrefs.is_empty() => xrefs,
+                    _ => return true,
+                };
+                let count = xrefs.len();
+                let xref = match (self.xref, key.code) {
+                    (None, KeyCode::Char('x')) => 0,
+                    (None, _) => count - 1,
+                    (Some(xref), KeyCode::Char('x')) => (xref + 1) % count,
+                    (Some(xref), _) => (xref + count - 1) % count,
+                };
+                self.xref = Some(xref);
+                ctx.cursor = xrefs[xref].offset.min(ctx.data().len()
+                    .saturating_sub(1));
+                return true;
+            },
+            _ => return false,
+        }
+        if self.selected != selected || key.code == KeyCode::Char('c') {
+            self.xref = None;
+        }
+        true
+    }
+}
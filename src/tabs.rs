@@ -28,6 +28,12 @@ impl<'a> TabsState<'a> {
         }
     }
 
+    /// Add `app` as the last tab and focus it
+    pub fn add_tab(&mut self, app: App<'a>) {
+        self.apps.push(app);
+        self.index = self.apps.len() - 1;
+    }
+
     /// Remove the current focused tab
     /// Returns true if it is the last tab remainning in the app
     pub fn remove_tab(&mut self) -> bool {
@@ -40,11 +46,11 @@ impl<'a> TabsState<'a> {
         self.apps.remove(self.index);
         // If the tab was on the last index, we go to the previous one,
         // otherwise we keep the index
-        if self.index == self.apps.len() - 1 {
+        if self.index == self.apps.len() {
             self.previous();
         }
 
-        return false
+        false
     }
 }
 
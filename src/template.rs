@@ -0,0 +1,930 @@
+//! Binary templates: structures described in a small C-like language and
+//! decoded at an offset of the sample.
+//!
+//! ```text
+//! # Configuration blob of some family
+//! struct config {
+//!     char[4] magic;
+//!     u16le version;
+//!     u8 flags;
+//!     if (flags & 1) {
+//!         u16be port;
+//!     } else {
+//!         u8[2] reserved;
+//!     }
+//!     u32le count;
+//!     server[count] servers;
+//! }
+//!
+//! struct server {
+//!     u8 len;
+//!     char name[len];
+//! }
+//! ```
+//!
+//! Integers are `u8` to `u64` and `i8` to `i64`, floats `f32` and `f64`,
+//! all little endian unless suffixed with `be`. `char[n]` is a string and
+//! `u8[n]` a byte string. Any type, including other structures, can be
+//! repeated with `[n]` after the type or after the name, `n` being an
+//! expression of the fields decoded so far. `if`/`else` decode fields
+//! depending on the values of the previous ones.
+//!
+//! Templates are read from the `.tpl` files of the templates directory,
+//! after the ones that ship with maglab.
+use std::{
+    fs,
+    error::Error,
+    collections::{BTreeMap, HashMap},
+    path::{Path},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{RenderPlugin, TabContext};
+
+/// Templates that ship with maglab
+const BUILTIN: &str = r#"
+# DOS header at the start of PE files
+struct mz_header {
+    char[2] e_magic;
+    u16 e_cblp;
+    u16 e_cp;
+    u16 e_crlc;
+    u16 e_cparhdr;
+    u16 e_minalloc;
+    u16 e_maxalloc;
+    u16 e_ss;
+    u16 e_sp;
+    u16 e_csum;
+    u16 e_ip;
+    u16 e_cs;
+    u16 e_lfarlc;
+    u16 e_ovno;
+    u16[4] e_res;
+    u16 e_oemid;
+    u16 e_oeminfo;
+    u16[10] e_res2;
+    u32 e_lfanew;
+}
+
+# Identification and header of little endian ELF files
+struct elf_header {
+    char[4] ei_magic;
+    u8 ei_class;
+    u8 ei_data;
+    u8 ei_version;
+    u8 ei_osabi;
+    u8 ei_abiversion;
+    u8[7] ei_pad;
+    u16 e_type;
+    u16 e_machine;
+    u32 e_version;
+    if (ei_class == 2) {
+        u64 e_entry;
+        u64 e_phoff;
+        u64 e_shoff;
+    } else {
+        u32 e_entry;
+        u32 e_phoff;
+        u32 e_shoff;
+    }
+    u32 e_flags;
+    u16 e_ehsize;
+    u16 e_phentsize;
+    u16 e_phnum;
+    u16 e_shentsize;
+    u16 e_shnum;
+    u16 e_shstrndx;
+}
+"#;
+
+/// Elements of an array decoded at most
+const MAX_ELEMENTS: usize = 65536;
+/// Fields decoded at most in one overlay
+const MAX_FIELDS: usize = 1 << 20;
+/// Structures nested at most
+const MAX_DEPTH: usize = 32;
+/// Bytes of a string or byte string shown in its value
+const MAX_SHOWN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+    U8, U16, U32, U64,
+    I8, I16, I32, I64,
+    F32, F64,
+}
+
+impl Scalar {
+    fn size(self) -> usize {
+        match self {
+            Scalar::U8 | Scalar::I8 => 1,
+            Scalar::U16 | Scalar::I16 => 2,
+            Scalar::U32 | Scalar::I32 | Scalar::F32 => 4,
+            Scalar::U64 | Scalar::I64 | Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Scalar(Scalar, /* big endian */ bool),
+    Char,
+    Struct(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(i128),
+    Field(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Field { ty: Type, name: String, count: Option<Expr> },
+    If { cond: Expr, then: Vec<Item>, otherwise: Vec<Item> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Struct {
+    items: Vec<Item>,
+}
+
+/// A field decoded from the sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    /// Type as written in the template
+    pub ty: String,
+    pub offset: usize,
+    pub len: usize,
+    /// Decoded value, `None` for structures and arrays
+    pub value: Option<String>,
+    pub children: Vec<Field>,
+}
+
+/// A template decoded at an offset of the sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub root: Field,
+    /// Ranges of the fields with a value, sorted, to colour the hex view
+    pub leaves: Vec<(usize, usize)>,
+    /// Why decoding stopped early, if it did
+    pub error: Option<String>,
+}
+
+impl Overlay {
+    /// Index of the field with a value holding `offset`
+    pub fn leaf_at(&self, offset: usize) -> Option<usize> {
+        let i = self.leaves.partition_point(|&(_, end)| end <= offset);
+        self.leaves.get(i)
+            .filter(|&&(start, _)| start <= offset)
+            .map(|_| i)
+    }
+}
+
+/// A set of templates by name
+#[derive(Debug, Default)]
+pub struct Templates {
+    structs: BTreeMap<String, Struct>,
+}
+
+impl Templates {
+    /// The builtin templates followed by the ones in the `.tpl` files of
+    /// `dir`, if given
+    pub fn load(dir: Option<&Path>) -> Result<Templates, Box<dyn Error>> {
+        let mut templates = Templates::default();
+        templates.add("builtin", BUILTIN)?;
+
+        let dir = match dir {
+            Some(dir) if dir.is_dir() => dir,
+            _ => return Ok(templates),
+        };
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .map_err(|err| format!("{}: {}", dir.display(), err))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "tpl"))
+            .collect();
+        files.sort();
+        for file in files {
+            let text = fs::read_to_string(&file)
+                .map_err(|err| format!("{}: {}", file.display(), err))?;
+            templates.add(&file.display().to_string(), &text)?;
+        }
+
+        Ok(templates)
+    }
+
+    /// Parse the templates in `text`, `source` names it in errors.
+    /// Templates replace the ones with the same name.
+    pub fn add(&mut self, source: &str, text: &str) -> Result<(), String> {
+        let structs = Parser::new(text).file()
+            .map_err(|(line, err)| format!("{}:{}: {}", source, line, err))?;
+        self.structs.extend(structs);
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.structs.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Decode the template `name` at `offset` of `data`
+    pub fn apply(&self, name: &str, data: &[u8], offset: usize)
+            -> Result<Overlay, String> {
+        if !self.structs.contains_key(name) {
+            return Err(format!("unknown template {}, expected one of: {}",
+                name, self.names().join(", ")));
+        }
+
+        let mut decoder = Decoder {
+            templates: self, data, fields: 0, scopes: Vec::new(),
+            leaves: Vec::new(),
+        };
+        let mut root = Field {
+            name: name.to_string(), ty: name.to_string(), offset, len: 0,
+            value: None, children: Vec::new(),
+        };
+        let error = decoder.decode_struct(name, offset, &mut root, 0).err();
+        let mut leaves = decoder.leaves;
+        leaves.sort_unstable();
+
+        Ok(Overlay { root, leaves, error })
+    }
+}
+
+/// Tokens of the template language
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(i128),
+    Punct(&'static str),
+}
+
+const PUNCTS: [&str; 28] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>",
+    "{", "}", "[", "]", "(", ")", ";", "<", ">", "&", "|", "^", "+", "-",
+    "*", "/", "%", "!", "~", ",",
+];
+
+/// Split `text` into tokens with their line numbers
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, (usize, String)> {
+    let mut tokens = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        // Comments run to the end of the line
+        let line = line.split('#').next().unwrap_or("");
+        let line = line.split("//").next().unwrap_or("");
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            let c = rest.chars().next().unwrap();
+            if c.is_ascii_alphabetic() || c == '_' {
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric()
+                    && c != '_').unwrap_or(rest.len());
+                tokens.push((line_no, Token::Ident(rest[..end].to_string())));
+                rest = &rest[end..];
+            } else if c.is_ascii_digit() {
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let word = &rest[..end];
+                let num = match word.strip_prefix("0x") {
+                    Some(hex) => i128::from_str_radix(hex, 16),
+                    None => word.parse(),
+                }.map_err(|_| (line_no, format!("invalid number {}", word)))?;
+                tokens.push((line_no, Token::Num(num)));
+                rest = &rest[end..];
+            } else if let Some(punct) = PUNCTS.iter()
+                    .find(|p| rest.starts_with(**p)) {
+                tokens.push((line_no, Token::Punct(punct)));
+                rest = &rest[punct.len()..];
+            } else {
+                return Err((line_no, format!("unexpected character {}", c)));
+            }
+            rest = rest.trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of template files
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Error of the tokenizer, reported when parsing starts
+    error: Option<(usize, String)>,
+}
+
+type ParseResult<T> = Result<T, (usize, String)>;
+
+impl Parser {
+    fn new(text: &str) -> Parser {
+        match tokenize(text) {
+            Ok(tokens) => Parser { tokens, pos: 0, error: None },
+            Err(err) => Parser { tokens: Vec::new(), pos: 0, error: Some(err) },
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or_else(|| self.tokens.last())
+            .map(|(line, _)| *line)
+            .unwrap_or(1)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn error<T>(&self, msg: String) -> ParseResult<T> {
+        Err((self.line(), msg))
+    }
+
+    /// Consume the punctuation `punct` if it comes next
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> ParseResult<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.error(format!("expected {}", punct))
+        }
+    }
+
+    fn ident(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            },
+            _ => self.error("expected a name".to_string()),
+        }
+    }
+
+    fn file(&mut self) -> ParseResult<Vec<(String, Struct)>> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let mut structs: Vec<(String, Struct)> = Vec::new();
+        while self.peek().is_some() {
+            if self.ident()? != "struct" {
+                self.pos -= 1;
+                return self.error("expected struct".to_string());
+            }
+            let name = self.ident()?;
+            if structs.iter().any(|(other, _)| *other == name) {
+                return self.error(format!("struct {} defined twice", name));
+            }
+            let items = self.block()?;
+            self.eat(";");
+            structs.push((name, Struct { items }));
+        }
+        Ok(structs)
+    }
+
+    fn block(&mut self) -> ParseResult<Vec<Item>> {
+        self.expect("{")?;
+        let mut items = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                return self.error("expected }".to_string());
+            }
+            items.push(self.item()?);
+        }
+        Ok(items)
+    }
+
+    fn item(&mut self) -> ParseResult<Item> {
+        let word = self.ident()?;
+        if word == "if" {
+            self.expect("(")?;
+            let cond = self.expr()?;
+            self.expect(")")?;
+            let then = self.block()?;
+            let otherwise = if matches!(self.peek(),
+                    Some(Token::Ident(w)) if w == "else") {
+                self.pos += 1;
+                if matches!(self.peek(), Some(Token::Ident(w)) if w == "if") {
+                    vec![self.item()?]
+                } else {
+                    self.block()?
+                }
+            } else {
+                Vec::new()
+            };
+            return Ok(Item::If { cond, then, otherwise });
+        }
+
+        let ty = self.ty(&word)?;
+        // The count goes after the type or after the name
+        let mut count = self.count()?;
+        let name = self.ident()?;
+        if count.is_none() {
+            count = self.count()?;
+        }
+        self.expect(";")?;
+        Ok(Item::Field { ty, name, count })
+    }
+
+    fn count(&mut self) -> ParseResult<Option<Expr>> {
+        if !self.eat("[") {
+            return Ok(None);
+        }
+        let count = self.expr()?;
+        self.expect("]")?;
+        Ok(Some(count))
+    }
+
+    fn ty(&mut self, word: &str) -> ParseResult<Type> {
+        let (base, big) = match word.strip_suffix("be") {
+            Some(base) => (base, true),
+            None => (word.strip_suffix("le").unwrap_or(word), false),
+        };
+        let scalar = match base {
+            "u8" => Scalar::U8, "u16" => Scalar::U16,
+            "u32" => Scalar::U32, "u64" => Scalar::U64,
+            "i8" => Scalar::I8, "i16" => Scalar::I16,
+            "i32" => Scalar::I32, "i64" => Scalar::I64,
+            "f32" => Scalar::F32, "f64" => Scalar::F64,
+            "char" if base == word => return Ok(Type::Char),
+            _ => return Ok(Type::Struct(word.to_string())),
+        };
+        Ok(Type::Scalar(scalar, big))
+    }
+
+    /// Binary operators from the loosest to the tightest
+    const LEVELS: [&'static [&'static str]; 9] = [
+        &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="],
+        &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"],
+    ];
+
+    fn expr(&mut self) -> ParseResult<Expr> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> ParseResult<Expr> {
+        let next = |p: &mut Parser| if level + 1 < Parser::LEVELS.len() {
+            p.binary(level + 1)
+        } else {
+            p.product()
+        };
+        let mut lhs = next(self)?;
+        while let Some(op) = Parser::LEVELS[level].iter()
+                .find(|op| matches!(self.peek(),
+                    Some(Token::Punct(p)) if p == *op)) {
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(next(self)?));
+        }
+        Ok(lhs)
+    }
+
+    fn product(&mut self) -> ParseResult<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = ["*", "/", "%"].iter()
+                .find(|op| matches!(self.peek(),
+                    Some(Token::Punct(p)) if p == *op)) {
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        for op in ["!", "-", "~"] {
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        match self.peek().cloned() {
+            Some(Token::Num(num)) => {
+                self.pos += 1;
+                Ok(Expr::Num(num))
+            },
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(Expr::Field(name))
+            },
+            _ => self.error("expected a number or a field".to_string()),
+        }
+    }
+}
+
+/// Decodes templates over the sample
+struct Decoder<'a> {
+    templates: &'a Templates,
+    data: &'a [u8],
+    /// Fields decoded so far
+    fields: usize,
+    /// Integer fields of the structures being decoded, innermost last
+    scopes: Vec<HashMap<String, i128>>,
+    leaves: Vec<(usize, usize)>,
+}
+
+impl<'a> Decoder<'a> {
+    fn lookup(&self, name: &str) -> Result<i128, String> {
+        self.scopes.iter().rev()
+            .find_map(|scope| scope.get(name).copied())
+            .ok_or_else(|| format!("no integer field {} decoded before", name))
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i128, String> {
+        Ok(match expr {
+            Expr::Num(num) => *num,
+            Expr::Field(name) => self.lookup(name)?,
+            Expr::Unary(op, value) => {
+                let value = self.eval(value)?;
+                match *op {
+                    "!" => (value == 0) as i128,
+                    "-" => value.wrapping_neg(),
+                    _ => !value,
+                }
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+                match *op {
+                    "||" => (lhs != 0 || rhs != 0) as i128,
+                    "&&" => (lhs != 0 && rhs != 0) as i128,
+                    "|" => lhs | rhs,
+                    "^" => lhs ^ rhs,
+                    "&" => lhs & rhs,
+                    "==" => (lhs == rhs) as i128,
+                    "!=" => (lhs != rhs) as i128,
+                    "<" => (lhs < rhs) as i128,
+                    ">" => (lhs > rhs) as i128,
+                    "<=" => (lhs <= rhs) as i128,
+                    ">=" => (lhs >= rhs) as i128,
+                    "<<" => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                    ">>" => lhs.checked_shr(rhs as u32).unwrap_or(0),
+                    "+" => lhs.wrapping_add(rhs),
+                    "-" => lhs.wrapping_sub(rhs),
+                    "*" => lhs.wrapping_mul(rhs),
+                    _ if rhs == 0 => return Err("division by zero".into()),
+                    "/" => lhs.checked_div(rhs)
+                        .ok_or("division overflow")?,
+                    _ => lhs.checked_rem(rhs).ok_or("division overflow")?,
+                }
+            },
+        })
+    }
+
+    /// Bytes `offset..offset + len` of the sample
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], String> {
+        offset.checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| format!("{} bytes at {:#x} are past the end of \
+                the sample", len, offset))
+    }
+
+    fn count_field(&mut self) -> Result<(), String> {
+        self.fields += 1;
+        if self.fields > MAX_FIELDS {
+            return Err(format!("more than {} fields", MAX_FIELDS));
+        }
+        Ok(())
+    }
+
+    /// Decode the structure `name` at `offset` into the children of `node`
+    fn decode_struct(&mut self, name: &str, offset: usize, node: &mut Field,
+            depth: usize) -> Result<(), String> {
+        let def = self.templates.structs.get(name)
+            .ok_or_else(|| format!("unknown type {}", name))?;
+        if depth > MAX_DEPTH {
+            return Err(format!("structures nested more than {} deep",
+                MAX_DEPTH));
+        }
+
+        self.scopes.push(HashMap::new());
+        let mut end = offset;
+        let result = self.decode_items(&def.items, &mut end, node, depth);
+        self.scopes.pop();
+        node.len = end - offset;
+        result
+    }
+
+    fn decode_items(&mut self, items: &[Item], offset: &mut usize,
+            node: &mut Field, depth: usize) -> Result<(), String> {
+        for item in items {
+            match item {
+                Item::If { cond, then, otherwise } => {
+                    let items = if self.eval(cond)? != 0 { then }
+                                else { otherwise };
+                    self.decode_items(items, offset, node, depth)?;
+                },
+                Item::Field { ty, name, count } => {
+                    let count = count.as_ref()
+                        .map(|count| self.eval(count))
+                        .transpose()?;
+                    // Keep what was decoded of a field that failed
+                    let (field, result) = self.decode_field(ty, name, count,
+                        *offset, depth);
+                    *offset += field.len;
+                    node.children.push(field);
+                    result?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode a field, with the part decoded before an error if there is
+    /// one
+    fn decode_field(&mut self, ty: &Type, name: &str, count: Option<i128>,
+            offset: usize, depth: usize) -> (Field, Result<(), String>) {
+        let ty_name = |count: Option<i128>| {
+            let base = match ty {
+                Type::Scalar(scalar, big) => format!("{:?}{}",
+                    scalar, if *big { "be" } else { "" }).to_lowercase(),
+                Type::Char => "char".to_string(),
+                Type::Struct(name) => name.clone(),
+            };
+            match count {
+                Some(count) => format!("{}[{}]", base, count),
+                None => base,
+            }
+        };
+        let mut field = Field {
+            name: name.to_string(), ty: ty_name(count), offset, len: 0,
+            value: None, children: Vec::new(),
+        };
+        if let Err(err) = self.count_field() {
+            return (field, Err(err));
+        }
+
+        let count = match count {
+            Some(count) if count < 0 || count > MAX_ELEMENTS as i128 =>
+                return (field, Err(format!("{}: invalid count {}", name,
+                    count))),
+            Some(count) => count as usize,
+            None => return self.decode_one(ty, field, depth),
+        };
+
+        // Strings and byte strings are shown as a single value
+        let string = match ty {
+            Type::Char => Some(true),
+            Type::Scalar(Scalar::U8, _) => Some(false),
+            _ => None,
+        };
+        if let Some(text) = string {
+            let bytes = match self.bytes(offset, count) {
+                Ok(bytes) => bytes,
+                Err(err) => return (field, Err(err)),
+            };
+            field.len = count;
+            field.value = Some(if text { quote(bytes) } else { hex(bytes) });
+            self.leaves.push((offset, offset + count));
+            return (field, Ok(()));
+        }
+
+        let mut end = offset;
+        for i in 0..count {
+            let element = Field {
+                name: format!("[{}]", i), ty: ty_name(None), offset: end,
+                len: 0, value: None, children: Vec::new(),
+            };
+            let (element, result) = self.decode_one(ty, element, depth);
+            end += element.len;
+            field.children.push(element);
+            if let Err(err) = result {
+                field.len = end - offset;
+                return (field, Err(err));
+            }
+        }
+        field.len = end - offset;
+        (field, Ok(()))
+    }
+
+    fn decode_one(&mut self, ty: &Type, mut field: Field, depth: usize)
+            -> (Field, Result<(), String>) {
+        let offset = field.offset;
+        match ty {
+            Type::Struct(name) => {
+                let result = self.decode_struct(name, offset, &mut field,
+                    depth + 1);
+                (field, result)
+            },
+            Type::Char => {
+                let bytes = match self.bytes(offset, 1) {
+                    Ok(bytes) => bytes,
+                    Err(err) => return (field, Err(err)),
+                };
+                field.len = 1;
+                field.value = Some(quote(bytes));
+                self.leaves.push((offset, offset + 1));
+                (field, Ok(()))
+            },
+            Type::Scalar(scalar, big) => {
+                let bytes = match self.bytes(offset, scalar.size()) {
+                    Ok(bytes) => bytes,
+                    Err(err) => return (field, Err(err)),
+                };
+                let (value, text) = scalar_value(*scalar, *big, bytes);
+                if let Some(value) = value {
+                    self.scopes.last_mut().unwrap()
+                        .insert(field.name.clone(), value);
+                }
+                field.len = bytes.len();
+                field.value = Some(text);
+                self.leaves.push((offset, offset + bytes.len()));
+                (field, Ok(()))
+            },
+        }
+    }
+}
+
+/// Decode a scalar, returns its value as an integer if it is one and as
+/// text
+fn scalar_value(scalar: Scalar, big: bool, bytes: &[u8])
+        -> (Option<i128>, String) {
+    let mut raw = [0u8; 8];
+    if big {
+        raw[8 - bytes.len()..].copy_from_slice(bytes);
+        raw.reverse();
+    } else {
+        raw[..bytes.len()].copy_from_slice(bytes);
+    }
+    let unsigned = u64::from_le_bytes(raw);
+    let bits = bytes.len() * 8;
+    // Sign extend from the size of the scalar
+    let signed = ((unsigned << (64 - bits)) as i64) >> (64 - bits);
+
+    match scalar {
+        Scalar::F32 => (None, f32::from_bits(unsigned as u32).to_string()),
+        Scalar::F64 => (None, f64::from_bits(unsigned).to_string()),
+        Scalar::I8 | Scalar::I16 | Scalar::I32 | Scalar::I64 =>
+            (Some(signed as i128), signed.to_string()),
+        _ => (Some(unsigned as i128), format!("{} ({:#x})", unsigned,
+            unsigned)),
+    }
+}
+
+/// `bytes` as a quoted string, escaping what is not printable
+fn quote(bytes: &[u8]) -> String {
+    let mut text: String = bytes.iter().take(MAX_SHOWN)
+        .map(|&b| match b {
+            b'"' | b'\\' => format!("\\{}", b as char),
+            b' '..=b'~' => (b as char).to_string(),
+            0 => "\\0".to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect();
+    if bytes.len() > MAX_SHOWN {
+        text.push_str("...");
+    }
+    format!("\"{}\"", text)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut text: Vec<String> = bytes.iter().take(MAX_SHOWN / 4)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if bytes.len() > MAX_SHOWN / 4 {
+        text.push("...".to_string());
+    }
+    text.join(" ")
+}
+
+/// Colours of the fields in the hex view, one after the other
+pub const FIELD_COLORS: [Color; 4] =
+    [Color::Cyan, Color::Green, Color::Magenta, Color::Yellow];
+
+/// Plugin showing the tree of fields decoded by the template applied to the
+/// tab, highlighting the field under the cursor
+#[derive(Default)]
+pub struct TemplateTree {
+    selected: usize,
+    /// First line shown
+    scroll: usize,
+    /// Offset of each line in the last draw
+    offsets: Vec<usize>,
+    /// Cursor of the tab in the last draw, the selection follows it
+    cursor: Option<usize>,
+}
+
+/// Flatten the tree under `field` into `lines` with their depth
+fn flatten<'a>(field: &'a Field, depth: usize,
+        lines: &mut Vec<(usize, &'a Field)>) {
+    lines.push((depth, field));
+    for child in field.children.iter() {
+        flatten(child, depth + 1, lines);
+    }
+}
+
+impl RenderPlugin for TemplateTree {
+    fn get_name(&self) -> &str {
+        "Template"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let overlay = match &ctx.overlay {
+            Some(overlay) => overlay,
+            None => {
+                f.render_widget(Paragraph::new(
+                    "no template applied, see the template command"), area);
+                return;
+            }
+        };
+
+        let mut fields = Vec::new();
+        flatten(&overlay.root, 0, &mut fields);
+        self.offsets = fields.iter().map(|(_, field)| field.offset).collect();
+
+        // Select the innermost field under the cursor when it moves
+        if self.cursor != Some(ctx.cursor) {
+            self.cursor = Some(ctx.cursor);
+            if let Some(i) = fields.iter().rposition(|(_, field)| {
+                    field.offset <= ctx.cursor
+                        && ctx.cursor < field.offset + field.len }) {
+                self.selected = i;
+            }
+        }
+        self.selected = self.selected.min(fields.len() - 1);
+
+        let mut rows = (area.height as usize).max(1);
+        if overlay.error.is_some() {
+            rows = rows.saturating_sub(1).max(1);
+        }
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        let mut lines: Vec<Spans> = Vec::new();
+        if let Some(err) = &overlay.error {
+            lines.push(Spans::from(Span::styled(err.clone(),
+                Style::default().fg(Color::Red))));
+        }
+        for (i, (depth, field)) in fields.iter().enumerate()
+                .skip(self.scroll).take(rows) {
+            let color = overlay.leaf_at(field.offset)
+                .filter(|_| field.value.is_some())
+                .map(|leaf| FIELD_COLORS[leaf % FIELD_COLORS.len()])
+                .unwrap_or(Color::White);
+            let mut spans = vec![
+                Span::styled(format!("{:08x} ", field.offset),
+                    Style::default().fg(Color::Blue)),
+                Span::raw("  ".repeat(*depth)),
+                Span::styled(field.name.clone(), Style::default().fg(color)),
+                Span::styled(format!(" {}", field.ty),
+                    Style::default().fg(Color::DarkGray)),
+            ];
+            if let Some(value) = &field.value {
+                spans.push(Span::raw(format!(" = {}", value)));
+            }
+            if i == self.selected {
+                for span in spans.iter_mut() {
+                    span.style = span.style.add_modifier(Modifier::REVERSED);
+                }
+            }
+            lines.push(Spans::from(spans));
+        }
+
+        f.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// `j`/`k` select the next/previous field, `Enter` moves the cursor of
+    /// the tab to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        match key.code {
+            KeyCode::Char('j') => {
+                self.selected = (self.selected + 1)
+                    .min(self.offsets.len().saturating_sub(1));
+            },
+            KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            },
+            KeyCode::Enter => {
+                if let Some(&offset) = self.offsets.get(self.selected) {
+                    ctx.cursor = offset.min(ctx.data().len()
+                        .saturating_sub(1));
+                    // Keep the selection on this field rather than on the
+                    // innermost one at the offset
+                    self.cursor = Some(ctx.cursor);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,172 @@
+//! Terminal setup and teardown. The terminal is put back in its normal mode
+//! whenever we leave: on a clean quit, on an error bubbling out of `main` and
+//! on a panic.
+use std::{
+    io::{self, Write, Stdout, stdout},
+    fs::{self, File, OpenOptions},
+    path::{PathBuf},
+    thread,
+    panic::{self, PanicHookInfo},
+    backtrace::{Backtrace},
+    ops::{Deref, DerefMut},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tui::{
+    terminal::{Terminal},
+    backend::{CrosstermBackend},
+};
+
+use crossterm::{
+    terminal::{enable_raw_mode, disable_raw_mode, EnterAlternateScreen,
+        LeaveAlternateScreen},
+    event::{EnableMouseCapture, DisableMouseCapture, EnableBracketedPaste,
+        DisableBracketedPaste, EnableFocusChange, DisableFocusChange},
+    cursor::{Show},
+    execute,
+};
+
+/// Owns the `Terminal` we draw on and restores the user's terminal when it
+/// goes out of scope
+pub struct TerminalGuard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl TerminalGuard {
+    /// Put the terminal in raw mode on an alternate screen and capture mouse,
+    /// paste and focus events
+    pub fn new() -> io::Result<TerminalGuard> {
+        // Put terminal in raw mode
+        enable_raw_mode()?;
+
+        // Get a new handle to the standard output
+        let mut stdout = stdout();
+        // Give app an alternate screen. If this fails halfway we still want
+        // the raw mode gone.
+        if let Err(err) = execute!(stdout, EnterAlternateScreen,
+                EnableMouseCapture, EnableBracketedPaste, EnableFocusChange) {
+            restore();
+            return Err(err);
+        }
+
+        // Create a new Backend and a new Terminal on top of it
+        let backend = CrosstermBackend::new(stdout);
+        match Terminal::new(backend) {
+            Ok(terminal) => Ok(TerminalGuard { terminal }),
+            Err(err) => {
+                restore();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Deref for TerminalGuard {
+    type Target = Terminal<CrosstermBackend<Stdout>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for TerminalGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Get the terminal back into normal mode. Errors are ignored since this is
+/// called while we are already leaving, possibly because of an error.
+pub fn restore() {
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableBracketedPaste,
+        DisableFocusChange,
+        Show,
+    );
+}
+
+/// Path of the log file where crash reports are appended:
+/// `$XDG_STATE_HOME/maglab/crash.log` or `$HOME/.local/state/maglab/crash.log`
+pub fn crash_log_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local").join("state")))
+        .map(|dir| dir.join("maglab").join("crash.log"))
+}
+
+/// Open the crash log, or a new file of the temporary directory nobody
+/// else can have made when there is no home directory
+fn open_crash_log() -> io::Result<(PathBuf, File)> {
+    if let Some(path) = crash_log_path() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let log = OpenOptions::new().create(true).append(true).open(&path)?;
+        return Ok((path, log));
+    }
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let path = std::env::temp_dir().join(format!("maglab-crash-{}-{}.log",
+        std::process::id(), timestamp));
+    let log = OpenOptions::new().write(true).create_new(true).open(&path)?;
+    Ok((path, log))
+}
+
+/// Install a panic hook that restores the terminal before anything gets
+/// printed and appends a crash report to the crash log. Background threads
+/// only leave a report: the interface keeps running and tells their work
+/// stopped.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() != Some("main") {
+            let _ = open_crash_log()
+                .and_then(|(_, mut log)| write_crash_report(&mut log, info));
+            return;
+        }
+        restore();
+
+        match open_crash_log()
+                .and_then(|(path, mut log)| write_crash_report(&mut log, info)
+                    .map(|()| path)) {
+            Ok(path) => eprintln!("maglab crashed, report written to {}",
+                path.display()),
+            Err(err) => eprintln!("maglab crashed, could not write report: {}",
+                err),
+        }
+
+        // Let the default hook print the panic message as usual
+        default_hook(info);
+    }));
+}
+
+/// Append the panic message, where it happened and a backtrace to `log`
+fn write_crash_report(log: &mut File, info: &PanicHookInfo) -> io::Result<()> {
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let thread = thread::current();
+
+    writeln!(log, "==== maglab {} crash report ====", env!("CARGO_PKG_VERSION"))?;
+    writeln!(log, "time: {} (seconds since UNIX epoch)", timestamp)?;
+    writeln!(log, "os: {} {}", std::env::consts::OS, std::env::consts::ARCH)?;
+    writeln!(log, "thread: {}", thread.name().unwrap_or("<unnamed>"))?;
+    writeln!(log, "panic: {}", info)?;
+    writeln!(log, "backtrace:\n{}", Backtrace::force_capture())?;
+    writeln!(log)?;
+
+    Ok(())
+}
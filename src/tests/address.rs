@@ -0,0 +1,102 @@
+//! Moving between file offsets and virtual addresses of executables
+use std::{
+    convert::{TryInto},
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::image::{Address};
+use crate::layout::{Layout};
+use crate::sample::{Sample};
+
+use super::{Harness, key, elf, elf_for, TEXT_VA};
+
+/// A harness with one tab holding `data` in a hex view
+fn harness(name: &str, data: Vec<u8>) -> Harness {
+    let sample = Sample::from_bytes(PathBuf::from(name), data);
+    let grid = Layout::builtin("hex").unwrap().build(".".as_ref());
+    Harness::new(vec![App::with_sample(sample, grid)])
+}
+
+#[test]
+fn offsets_map_to_virtual_addresses_and_back() {
+    let sample = Sample::from_bytes(PathBuf::from("a.elf"),
+        elf(&[0x90; 0x20]));
+    let image = sample.image.as_deref().unwrap();
+    assert_eq!(image.section_of(0x50).unwrap().name, ".text");
+    assert_eq!(image.va_of(0x50), Some(TEXT_VA + 0x10));
+    assert_eq!(image.va_of(0x10), None);
+    assert_eq!(image.resolve(Address::Va(TEXT_VA + 0x1f)), Some(0x5f));
+    assert_eq!(image.resolve(Address::Va(TEXT_VA + 0x20)), None);
+    // Loaded without program headers, the base is 0
+    assert_eq!(image.resolve(Address::Rva(TEXT_VA)), Some(0x40));
+    assert_eq!(image.resolve(Address::Offset(7)), Some(7));
+
+    let sample = Sample::from_bytes(PathBuf::from("a.bin"), &b"data"[..]);
+    assert!(sample.image.is_none());
+}
+
+#[test]
+fn goto_accepts_offsets_and_virtual_addresses() {
+    let mut h = harness("a.elf", elf(&[0x90; 0x20]));
+    h.command("goto va 0x401010");
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x50);
+    assert_eq!(h.app.status.as_deref(),
+        Some("offset 0x50, va 0x401010 in .text"));
+    h.command("goto rva 4198405");
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x45);
+    h.command("goto 0x10");
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x10);
+    assert_eq!(h.app.status.as_deref(), Some("offset 0x10"));
+
+    h.command("goto va 0x402000");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: the address is not in the file"));
+    h.command("goto 0x10000");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: offset 0x10000 is past the end of the sample"));
+    h.command("goto pa 0x10");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: usage: goto [va|rva] <address>"));
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x10);
+
+    let mut h = harness("a.bin", b"data".to_vec());
+    h.command("goto va 0x401000");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: a[.]bin has no virtual addresses"));
+}
+
+#[test]
+fn hex_view_shows_virtual_addresses_with_sections() {
+    let mut h = harness("a.elf", elf(&[0x90; 0x20]));
+    h.command("goto va 0x401008");
+    h.press(&[key('a')]);
+    h.assert_snapshot("hexview_virtual_addresses");
+}
+
+#[test]
+fn sections_at_the_top_of_the_address_space_do_not_overflow() {
+    // .text starts 0x100 bytes below 2^64 and holds 0x200
+    const TOP: u64 = 0xffff_ffff_ffff_ff00;
+    let mut data = elf_for(0x3e, TOP, &[0x90; 0x200]);
+    let shoff = u64::from_le_bytes(data[40..48].try_into().unwrap()) as usize;
+    data[shoff + 64 + 16..shoff + 64 + 24].copy_from_slice(&TOP.to_le_bytes());
+    let sample = Sample::from_bytes(PathBuf::from("a.elf"), data.clone());
+    let image = sample.image.as_deref().unwrap();
+    assert_eq!(image.section_at(TOP + 0xff).unwrap().name, ".text");
+    assert!(image.section_at(TOP - 1).is_none());
+    assert_eq!(image.resolve(Address::Va(u64::MAX)), Some(64 + 0xff));
+    assert_eq!(image.va_of(64 + 0xff), Some(u64::MAX));
+    assert_eq!(image.va_of(64 + 0x100), None);
+    assert_eq!(image.code_at(&sample.data, u64::MAX).map(<[u8]>::len),
+        Some(0x101));
+
+    let mut h = harness("a.elf", data);
+    h.wait_for_xrefs();
+    h.command("goto va 0xfffffffffffffff0");
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 64 + 0xf0);
+    h.command("goto 0x150");
+    assert_eq!(h.app.status.as_deref(), Some("offset 0x150"));
+    h.press(&[key('a')]);
+    h.draw();
+}
@@ -0,0 +1,122 @@
+//! Annotating samples and getting the annotations back
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::app::{App, ColumnsState, PluginsState, Plugin, FileManager};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::annotations::{Annotations, TagColor, NoteKind};
+
+use super::{Harness, key, enter};
+
+/// Empty notes directory for the test called `name`
+fn notes_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-notes-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn edits_replace_and_remove_annotations() {
+    let mut notes = Annotations::new("00");
+
+    notes.set_comment(0, 4, "header");
+    notes.set_comment(0, 4, "magic");
+    notes.set_comment(2, 3, "byte");
+    assert_eq!(notes.comments.len(), 2);
+    assert_eq!(notes.comments[0].text, "magic");
+    notes.set_comment(2, 3, "");
+    assert_eq!(notes.comments.len(), 1);
+
+    // The last tag wins, removing clears everything overlapping
+    notes.set_color(0, 8, Some(TagColor::Red));
+    notes.set_color(4, 6, Some(TagColor::Blue));
+    assert_eq!(notes.color_at(5), Some(TagColor::Blue));
+    assert_eq!(notes.color_at(7), Some(TagColor::Red));
+    notes.set_color(5, 6, None);
+    assert_eq!(notes.color_at(0), None);
+    assert!(notes.colors.is_empty());
+
+    notes.set_bookmark(8, "config");
+    notes.set_label(8, "cfg");
+    let kinds: Vec<NoteKind> = notes.notes().iter()
+        .map(|note| note.kind)
+        .collect();
+    assert_eq!(kinds, vec![NoteKind::Comment, NoteKind::Bookmark,
+        NoteKind::Label]);
+}
+
+#[test]
+fn notes_come_back_when_reopening_from_the_file_manager() {
+    let dir = notes_dir("reopen");
+    let fm = FileManager::new(Path::new("src/tests/fixtures/dir"));
+    let grid = ColumnsState::new(vec![
+        PluginsState::new(vec![Plugin::FileManager(fm)]),
+    ]);
+    let mut h = Harness::new(vec![App::new("Files", grid)]);
+    h.app.config.notes_dir = Some(dir.clone());
+
+    // Open sample.bin in a new tab
+    h.press(&[enter()]);
+    assert_eq!(h.app.tabs.apps.len(), 2);
+    assert_eq!(h.app.tabs.index, 1);
+    assert_eq!(h.app.status.as_deref(), Some("opened sample[.]bin"));
+
+    h.command("bookmark greeting");
+    h.press(&[key('l')]);
+    h.command("label second");
+    h.press(&[key('v'), key('l'), key('l')]);
+    h.command("comment the   middle");
+    assert_eq!(h.app.status.as_deref(), Some("commented 3 bytes at 0x1"));
+    h.press(&[key('v'), key('l')]);
+    h.command("color green");
+    h.assert_snapshot("annotations_hexview");
+
+    let sha256 = h.app.tabs.apps[1].ctx.sample.as_ref().unwrap()
+        .sha256.clone();
+    assert!(Annotations::path_in(&dir, &sha256).exists());
+
+    // Close the tab and open the sample again
+    let keys = KeyConfig::init();
+    h.press(&[keys.remove_plugin]);
+    assert_eq!(h.app.tabs.apps.len(), 1);
+    h.press(&[enter()]);
+    assert_eq!(h.app.status.as_deref(),
+        Some("opened sample[.]bin, 3 annotations"));
+    let notes = &h.app.tabs.apps[1].ctx.notes;
+    assert_eq!(notes.label(1), Some("second"));
+    assert_eq!(notes.bookmark(0).unwrap().name, "greeting");
+    assert_eq!(notes.comments[0].text, "the   middle");
+    assert_eq!(notes.color_at(4), Some(TagColor::Green));
+
+    // Opening it once more goes back to its tab
+    h.press(&[keys.tab_left, enter()]);
+    assert_eq!(h.app.tabs.apps.len(), 2);
+    assert_eq!(h.app.tabs.index, 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn bookmarks_plugin_jumps_to_notes() {
+    let sample = Sample::from_bytes(PathBuf::from("sample.bin"),
+        vec![0u8; 0x40]);
+    let layout = Layout { columns: vec![
+        vec![PluginKind::Bookmarks], vec![PluginKind::HexView]] };
+    let mut app = App::with_sample(sample, layout.build(".".as_ref()));
+    app.ctx.notes = Annotations::new(&app.ctx.sample.as_ref().unwrap().sha256);
+    app.ctx.notes.set_bookmark(0x20, "config");
+    app.ctx.notes.set_label(0x10, "decrypt");
+    app.ctx.notes.set_comment(0x30, 0x38, "key");
+    let mut h = Harness::new(vec![app]);
+    h.assert_snapshot("bookmarks");
+
+    h.press(&[key('j'), enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x20);
+    h.press(&[key('G'), enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x30);
+}
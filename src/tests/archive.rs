@@ -0,0 +1,271 @@
+//! Browsing containers and opening the files inside them as samples
+use std::{
+    fs,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::app::{App, ColumnsState, PluginsState, Plugin, FileManager};
+use crate::archive::{self, Archive, Kind};
+use crate::keys::{KeyConfig};
+use crate::sample::{Sample, Format};
+
+use super::{Harness, key, enter};
+
+const DROPPER: &[u8] = b"MZ\x90\0dropper";
+
+/// Empty directory for the test called `name`
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-archive-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A ZIP with a plain file, a file encrypted with the usual password and a
+/// tar.gz holding another file
+fn zip() -> Vec<u8> {
+    let mut tar = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(7);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, "stage2/payload.bin", &b"payload"[..])
+        .unwrap();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(),
+        flate2::Compression::default());
+    gz.write_all(&tar.into_inner().unwrap()).unwrap();
+    let targz = gz.finish().unwrap();
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("docs/readme.txt", options).unwrap();
+    zip.write_all(b"read me").unwrap();
+    zip.start_file("bin/dropper.exe", options
+        .with_aes_encryption(zip::AesMode::Aes256, archive::PASSWORD)).unwrap();
+    zip.write_all(DROPPER).unwrap();
+    zip.start_file("inner.tar.gz", options).unwrap();
+    zip.write_all(&targz).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+/// A cabinet holding `files` in one MSZIP folder
+fn cab(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let contents: Vec<u8> = files.iter().flat_map(|(_, data)| data.to_vec())
+        .collect();
+    let mut block = b"CK".to_vec();
+    block.extend(miniz_oxide::deflate::compress_to_vec(&contents, 6));
+
+    let mut table = Vec::new();
+    let mut offset = 0u32;
+    for (name, data) in files {
+        table.extend((data.len() as u32).to_le_bytes());
+        table.extend(offset.to_le_bytes());
+        table.extend([0; 6]);
+        table.extend(0x20u16.to_le_bytes());
+        table.extend(name.as_bytes());
+        table.push(0);
+        offset += data.len() as u32;
+    }
+
+    let files_at = 36 + 8;
+    let data_at = files_at + table.len();
+    let mut cab = b"MSCF\0\0\0\0".to_vec();
+    cab.extend(((data_at + 8 + block.len()) as u32).to_le_bytes());
+    cab.extend([0; 4]);
+    cab.extend((files_at as u32).to_le_bytes());
+    cab.extend([0, 0, 0, 0, 3, 1, 1, 0]);
+    cab.extend((files.len() as u16).to_le_bytes());
+    cab.extend([0; 6]);
+    // The folder, its one data block follows the files
+    cab.extend((data_at as u32).to_le_bytes());
+    cab.extend([1, 0, 1, 0]);
+    cab.extend(table);
+    cab.extend([0; 4]);
+    cab.extend((block.len() as u16).to_le_bytes());
+    cab.extend((contents.len() as u16).to_le_bytes());
+    cab.extend(block);
+    cab
+}
+
+/// Directory record of an ISO image
+fn iso_record(name: &[u8], sector: u32, size: u32, dir: bool) -> Vec<u8> {
+    let len = 33 + name.len() + (name.len() + 1) % 2;
+    let mut record = vec![0; len];
+    record[0] = len as u8;
+    record[2..6].copy_from_slice(&sector.to_le_bytes());
+    record[6..10].copy_from_slice(&sector.to_be_bytes());
+    record[10..14].copy_from_slice(&size.to_le_bytes());
+    record[14..18].copy_from_slice(&size.to_be_bytes());
+    record[25] = if dir { 2 } else { 0 };
+    record[32] = name.len() as u8;
+    record[33..33 + name.len()].copy_from_slice(name);
+    record
+}
+
+/// An ISO image with `SETUP/RUN.EXE` in it
+fn iso() -> Vec<u8> {
+    let sector = |n: usize| n * 2048;
+    let mut iso = vec![0; sector(21)];
+    iso[sector(16)] = 1;
+    iso[sector(16) + 1..sector(16) + 6].copy_from_slice(b"CD001");
+    let root = iso_record(&[0], 18, 2048, true);
+    iso[sector(16) + 156..sector(16) + 190].copy_from_slice(&root);
+    iso[sector(17)] = 255;
+    iso[sector(17) + 1..sector(17) + 6].copy_from_slice(b"CD001");
+
+    let dir = [iso_record(&[0], 18, 2048, true),
+        iso_record(&[1], 18, 2048, true),
+        iso_record(b"SETUP", 19, 2048, true)].concat();
+    iso[sector(18)..sector(18) + dir.len()].copy_from_slice(&dir);
+    let dir = [iso_record(&[0], 19, 2048, true),
+        iso_record(&[1], 18, 2048, true),
+        iso_record(b"RUN.EXE;1", 20, DROPPER.len() as u32, false)].concat();
+    iso[sector(19)..sector(19) + dir.len()].copy_from_slice(&dir);
+    iso[sector(20)..sector(20) + DROPPER.len()].copy_from_slice(DROPPER);
+    iso
+}
+
+fn names(archive: &Archive) -> Vec<&str> {
+    archive.entries().iter().map(|entry| entry.name.as_str()).collect()
+}
+
+#[test]
+fn opens_files_in_encrypted_and_nested_containers() {
+    let dir = temp_dir("nested");
+    fs::write(dir.join("mail.zip"), zip()).unwrap();
+
+    let zip = Archive::open(&dir.join("mail.zip")).unwrap();
+    assert_eq!(names(&zip), vec!["bin", "bin/dropper.exe", "docs",
+        "docs/readme.txt", "inner.tar.gz"]);
+    assert!(zip.entry("bin/dropper.exe").unwrap().encrypted);
+    assert!(zip.entry("bin").unwrap().dir);
+
+    let sample = Sample::open(dir.join("mail.zip/bin/dropper.exe")).unwrap();
+    assert_eq!(&sample.data[..], DROPPER);
+    assert_eq!(sample.format, Format::PE);
+    assert_eq!(sample.name(), "dropper.exe");
+    assert!(sample.path.ends_with("mail.zip/bin/dropper.exe"));
+
+    let sample = Sample::open(
+        dir.join("mail.zip/inner.tar.gz/stage2/payload.bin")).unwrap();
+    assert_eq!(&sample.data[..], b"payload");
+    let (_, targz, inner) = archive::locate(
+        &dir.join("mail.zip/inner.tar.gz/stage2")).unwrap();
+    assert_eq!((targz.kind, inner.as_str()), (Kind::TarGz, "stage2"));
+
+    assert!(Sample::open(dir.join("mail.zip/docs/missing.txt")).is_err());
+    assert!(Sample::open(dir.join("mail.zip/docs/readme.txt/x")).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reads_7z_cab_ole_and_iso() {
+    let mut sevenz = sevenz_rust::SevenZWriter::new(Cursor::new(Vec::new()))
+        .unwrap();
+    let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+    entry.name = "payload/stage2.bin".to_string();
+    entry.has_stream = true;
+    sevenz.push_archive_entry(entry, Some(&b"stage two"[..])).unwrap();
+    let sevenz = Archive::from_bytes(sevenz.finish().unwrap().into_inner())
+        .unwrap();
+    assert_eq!(names(&sevenz), vec!["payload", "payload/stage2.bin"]);
+    assert_eq!(sevenz.read("payload/stage2.bin").unwrap(), b"stage two");
+
+    let cab = Archive::from_bytes(cab(&[("setup\\run.exe", DROPPER),
+        ("readme.txt", b"hello hello hello")])).unwrap();
+    assert_eq!(cab.kind, Kind::Cab);
+    assert_eq!(names(&cab), vec!["readme.txt", "setup", "setup/run.exe"]);
+    assert_eq!(cab.read("setup/run.exe").unwrap(), DROPPER);
+    assert_eq!(cab.read("readme.txt").unwrap(), b"hello hello hello");
+
+    let mut ole = cfb::CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+    ole.create_storage("/Macros").unwrap();
+    ole.create_stream("/Macros/Module1").unwrap()
+        .write_all(b"Sub AutoOpen()").unwrap();
+    ole.create_stream("/\x05SummaryInformation").unwrap();
+    ole.flush().unwrap();
+    let ole = Archive::from_bytes(ole.into_inner().into_inner()).unwrap();
+    assert_eq!(names(&ole), vec!["Macros", "Macros/Module1",
+        "[5]SummaryInformation"]);
+    assert_eq!(ole.read("Macros/Module1").unwrap(), b"Sub AutoOpen()");
+
+    let iso = Archive::from_bytes(iso()).unwrap();
+    assert_eq!(names(&iso), vec!["SETUP", "SETUP/RUN.EXE"]);
+    assert_eq!(iso.read("SETUP/RUN.EXE").unwrap(), DROPPER);
+    assert!(iso.read("SETUP").is_err());
+
+    assert!(Archive::from_bytes(DROPPER.to_vec()).is_err());
+}
+
+#[test]
+fn iso_directories_are_listed_once() {
+    // Both directories of the root share their records, which point back up
+    // to the root
+    let mut iso = iso();
+    let dir = [iso_record(&[0], 18, 2048, true),
+        iso_record(&[1], 18, 2048, true),
+        iso_record(b"A", 19, 2048, true),
+        iso_record(b"B", 19, 2048, true)].concat();
+    iso[18 * 2048..18 * 2048 + dir.len()].copy_from_slice(&dir);
+    let dir = [iso_record(&[0], 19, 2048, true),
+        iso_record(&[1], 18, 2048, true),
+        iso_record(b"RUN.EXE;1", 20, DROPPER.len() as u32, false),
+        iso_record(b"UP", 18, 2048, true)].concat();
+    iso[19 * 2048..19 * 2048 + dir.len()].copy_from_slice(&dir);
+
+    let iso = Archive::from_bytes(iso).unwrap();
+    assert_eq!(names(&iso), vec!["A", "B", "B/RUN.EXE", "B/UP"]);
+    assert_eq!(iso.read("B/RUN.EXE").unwrap(), DROPPER);
+}
+
+#[test]
+fn file_manager_browses_containers() {
+    let dir = temp_dir("browse");
+    fs::write(dir.join("mail.zip"), zip()).unwrap();
+
+    let fm = FileManager::new(dir.clone());
+    let grid = ColumnsState::new(vec![
+        PluginsState::new(vec![Plugin::FileManager(fm)]),
+    ]);
+    let mut h = Harness::new(vec![App::new("Files", grid)]);
+
+    // Into the ZIP, then its `bin` directory
+    h.press(&[enter(), enter()]);
+    h.assert_snapshot("file_manager_archive");
+
+    h.press(&[enter()]);
+    assert_eq!(h.app.tabs.apps.len(), 2);
+    assert_eq!(h.app.tabs.apps[1].title, "dropper.exe");
+    assert_eq!(h.app.status.as_deref(), Some("opened dropper[.]exe"));
+
+    // Back to the top of the ZIP, into the tar.gz in it
+    h.app.tabs.index = 0;
+    let backspace = KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE);
+    h.press(&[backspace, key('j'), key('j'), enter(), enter(), enter()]);
+    assert_eq!(h.app.tabs.apps.len(), 3);
+    assert_eq!(&h.app.tabs.apps[2].ctx.sample.as_ref().unwrap().data[..],
+        b"payload");
+
+    // `o` opens the container itself
+    h.app.tabs.index = 0;
+    h.press(&[backspace, backspace, backspace, key('o')]);
+    assert_eq!(h.app.tabs.apps[3].title, "mail.zip");
+
+    // Samples inside containers are not written back
+    h.app.tabs.index = 1;
+    h.app.read_only = false;
+    let keys = KeyConfig::init();
+    h.press(&[keys.focus_right, key('e'), key('0'), key('0')]);
+    h.command("patch-write");
+    assert_eq!(h.app.status.as_deref(), Some("error: the sample is inside a \
+        container, use patch-export to write a patched copy"));
+
+    assert!(archive::is_container(&dir.join("mail.zip")));
+    assert!(!archive::is_container(Path::new(
+        "src/tests/fixtures/dir/sample.bin")));
+    fs::remove_dir_all(&dir).unwrap();
+}
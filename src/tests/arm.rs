@@ -0,0 +1,158 @@
+//! Disassembling ARM, Thumb and AArch64 code
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::analysis::{self};
+use crate::disasm::{Arch, Disassembler, Flow};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+
+use super::{Harness, key, elf_for, TEXT_VA};
+
+/// AArch64 function calling another and returning early on zero
+const ARM64: &[u8] = &[
+    0xfd, 0x7b, 0xbf, 0xa9,     // stp x29, x30, [sp, #-0x10]!
+    0xfd, 0x03, 0x00, 0x91,     // mov x29, sp
+    0x60, 0x00, 0x00, 0xb4,     // cbz x0, 0x401014
+    0x58, 0x00, 0x00, 0x58,     // ldr x24, 0x401018
+    0x03, 0x00, 0x00, 0x94,     // bl 0x40101c
+    0xfd, 0x7b, 0xc1, 0xa8,     // ldp x29, x30, [sp], #0x10
+    0xc0, 0x03, 0x5f, 0xd6,     // ret
+    0x1f, 0x20, 0x03, 0xd5,     // callee: nop
+    0xc0, 0x03, 0x5f, 0xd6,     // ret
+];
+
+/// Decode `code` with `arch` from `TEXT_VA`, into the text and flow of
+/// each instruction
+fn decode(arch: Arch, code: &[u8]) -> Vec<(String, Flow)> {
+    let mut disasm = Disassembler::new(arch);
+    let mut insns = Vec::new();
+    let mut at = 0;
+    while let Some(insn) = disasm.decode(&code[at..], TEXT_VA + at as u64) {
+        at += insn.len;
+        insns.push((insn.text, insn.flow));
+    }
+    insns
+}
+
+#[test]
+fn arm64_branches_calls_and_returns_are_followed() {
+    let insns = decode(Arch::Arm64, ARM64);
+    assert_eq!(insns[2], ("cbz x0, #0x401014".to_string(),
+        Flow::Branch(TEXT_VA + 0x14)));
+    assert_eq!(insns[4], ("bl #0x40101c".to_string(),
+        Flow::Call(Some(TEXT_VA + 0x1c))));
+    assert_eq!(insns[6].1, Flow::Return);
+    assert!(insns[..2].iter().all(|(_, flow)| *flow == Flow::Next));
+
+    // Literals are memory operands
+    let insn = Disassembler::new(Arch::Arm64).decode(&ARM64[12..],
+        TEXT_VA + 12).unwrap();
+    assert_eq!((insn.mem, insn.imm), (Some(TEXT_VA + 0x14), None));
+
+    // Branches on a condition and through registers, traps
+    let insns = decode(Arch::Arm64, &[
+        0x41, 0xff, 0xff, 0x54,         // b.ne 0x400fe8
+        0x00, 0x02, 0x1f, 0xd6,         // br x16
+        0x00, 0x00, 0x20, 0xd4,         // brk #0
+        0xff, 0xff, 0xff, 0xff,         // (bad)
+    ]);
+    let flows: Vec<Flow> = insns.into_iter().map(|(_, flow)| flow).collect();
+    assert_eq!(flows, vec![Flow::Branch(TEXT_VA - 0x18), Flow::Jump(None),
+        Flow::Stop, Flow::Stop]);
+}
+
+#[test]
+fn arm_and_thumb_returns_and_literals_are_decoded() {
+    let insns = decode(Arch::Arm, &[
+        0x10, 0x40, 0x2d, 0xe9,         // push {r4, lr}
+        0x04, 0x00, 0x9f, 0xe5,         // ldr r0, [pc, #4]
+        0x00, 0x00, 0x50, 0xe3,         // cmp r0, #0
+        0x00, 0x00, 0x00, 0x0a,         // beq 0x401014
+        0xfa, 0xff, 0xff, 0xeb,         // bl 0x401000
+        0x10, 0x80, 0xbd, 0xe8,         // pop {r4, pc}
+        0x1e, 0xff, 0x2f, 0xe1,         // bx lr
+        0x1e, 0xff, 0x2f, 0x01,         // bxeq lr
+    ]);
+    let flows: Vec<Flow> = insns.iter().map(|(_, flow)| *flow).collect();
+    assert_eq!(flows, vec![Flow::Next, Flow::Next, Flow::Next,
+        Flow::Branch(TEXT_VA + 0x14), Flow::Call(Some(TEXT_VA)),
+        Flow::Return, Flow::Return, Flow::Next]);
+    assert_eq!(insns[0].0, "push {r4, lr}");
+
+    // `pc` reads 8 bytes ahead in A32, 4 bytes ahead and aligned in Thumb
+    let insn = Disassembler::new(Arch::Arm)
+        .decode(&[0x04, 0x00, 0x9f, 0xe5], TEXT_VA + 4).unwrap();
+    assert_eq!(insn.mem, Some(TEXT_VA + 0x10));
+    let insn = Disassembler::new(Arch::Thumb)
+        .decode(&[0x01, 0x48], TEXT_VA + 2).unwrap();
+    assert_eq!((insn.text.as_str(), insn.len), ("ldr r0, [pc, #4]", 2));
+    assert_eq!(insn.mem, Some(TEXT_VA + 8));
+    // And wraps around at the top of the address space
+    let insn = Disassembler::new(Arch::Arm)
+        .decode(&[0x04, 0x00, 0x9f, 0xe5], u64::MAX - 3).unwrap();
+    assert_eq!(insn.mem, Some(8));
+    let insn = Disassembler::new(Arch::Thumb)
+        .decode(&[0x01, 0x48], u64::MAX - 1).unwrap();
+    assert_eq!(insn.mem, Some(4));
+
+    let insns = decode(Arch::Thumb, &[
+        0x80, 0xb5,                     // push {r7, lr}
+        0x08, 0xb1,                     // cbz r0, 0x401008
+        0x01, 0x20,                     // movs r0, #1
+        0x80, 0xbd,                     // pop {r7, pc}
+        0x70, 0x47,                     // bx lr
+    ]);
+    let flows: Vec<Flow> = insns.into_iter().map(|(_, flow)| flow).collect();
+    assert_eq!(flows, vec![Flow::Next, Flow::Branch(TEXT_VA + 8), Flow::Next,
+        Flow::Return, Flow::Return]);
+}
+
+#[test]
+fn architecture_comes_from_the_headers() {
+    let sample = Sample::from_bytes(PathBuf::from("a.out"),
+        elf_for(183, TEXT_VA, ARM64));
+    let image = sample.image.as_deref().unwrap();
+    assert_eq!(image.arch, Some(Arch::Arm64));
+    let functions: Vec<(u64, String)> = analysis::functions(image,
+            &sample.data).unwrap()
+        .into_iter()
+        .map(|f| (f.address - TEXT_VA, f.name))
+        .collect();
+    assert_eq!(functions, vec![(0x0, "entry".to_string()),
+        (0x1c, "sub_40101c".to_string())]);
+
+    // The lowest bit of the entry point of ARM code tells it is Thumb
+    let image = Sample::from_bytes(PathBuf::from("a.out"),
+        elf_for(40, TEXT_VA | 1, &[0x70, 0x47])).image.unwrap();
+    assert_eq!((image.arch, image.entry), (Some(Arch::Thumb), Some(TEXT_VA)));
+    let image = Sample::from_bytes(PathBuf::from("a.out"),
+        elf_for(40, TEXT_VA, &[0x1e, 0xff, 0x2f, 0xe1])).image.unwrap();
+    assert_eq!(image.arch, Some(Arch::Arm));
+}
+
+#[test]
+fn raw_code_is_disassembled_with_the_architecture_chosen() {
+    let layout = Layout { columns: vec![vec![PluginKind::Listing]] };
+    let sample = Sample::from_bytes(PathBuf::from("shellcode.bin"), ARM64);
+    let mut h = Harness::new(vec![
+        App::with_sample(sample, layout.build(".".as_ref()))]);
+    h.command("arch");
+    assert_eq!(h.app.status.as_deref(), Some("cannot disassemble \
+        shellcode[.]bin, choose an architecture with arch <name>"));
+
+    h.command("arch aarch64");
+    assert_eq!(h.app.status.as_deref(),
+        Some("disassembling shellcode[.]bin as arm64 code"));
+    h.wait_for_xrefs();
+    h.press(&[key('j'), key('j')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 8);
+    h.assert_snapshot("listing_arm64");
+
+    h.command("arch mips16");
+    assert_eq!(h.app.status.as_deref(), Some("error: unknown architecture \
+        mips16, expected one of x86-16, x86, x64, arm, thumb, arm64, mips, mipsel, \
+        mips64, mips64el, ppc, ppcle, ppc64, ppc64le, riscv32, riscv64"));
+}
@@ -0,0 +1,161 @@
+//! Control flow graphs and the linear disassembly they follow
+use std::{
+    path::{PathBuf},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::annotations::{Annotations};
+use crate::app::{App};
+use crate::analysis::{self};
+use crate::cfg::{Graph, Zoom};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+
+use super::{Harness, key, enter, elf, TEXT_VA};
+
+/// A function branching in two and joining again, then a loop it calls
+const TEXT: &[u8] = &[
+    0x85, 0xc9,                     // test ecx, ecx
+    0x74, 0x0c,                     // je 0x401010
+    0xe8, 0x17, 0, 0, 0,            // call loop
+    0xb8, 0x01, 0, 0, 0,            // mov eax, 1
+    0xeb, 0x05,                     // jmp 0x401015
+    0xb8, 0x02, 0, 0, 0,            // mov eax, 2
+    0xc3,                           // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0x31, 0xc0,                     // loop: xor eax, eax
+    0xff, 0xc0,                     // inc eax
+    0x39, 0xc8,                     // cmp eax, ecx
+    0x75, 0xfa,                     // jne 0x401022
+    0xc3,                           // ret
+];
+
+fn sample() -> Sample {
+    Sample::from_bytes(PathBuf::from("a.out"), elf(TEXT))
+}
+
+/// Offset in the file of the byte at `va`
+fn offset(va: u64) -> usize {
+    64 + (va - TEXT_VA) as usize
+}
+
+fn tab() -> KeyEvent {
+    KeyEvent::new(KeyCode::Tab, KeyModifiers::empty())
+}
+
+#[test]
+fn function_holding_an_address_is_found_from_the_closest_start() {
+    let sample = sample();
+    let image = sample.image.as_deref().unwrap();
+    let starts = [TEXT_VA, TEXT_VA + 0x20];
+
+    let function = analysis::function_at(image, &sample.data, &starts,
+        TEXT_VA + 0x24).unwrap();
+    assert_eq!((function.address, function.name.as_str()),
+        (TEXT_VA + 0x20, "sub_401020"));
+    assert_eq!(function.block_at(TEXT_VA + 0x25), Some(1));
+
+    let function = analysis::function_at(image, &sample.data, &starts,
+        TEXT_VA + 0x10).unwrap();
+    assert_eq!((function.address, function.name.as_str()), (TEXT_VA, "entry"));
+
+    // Without a start before it, the function starts at the address
+    let function = analysis::function_at(image, &sample.data, &[],
+        TEXT_VA + 0x22).unwrap();
+    assert_eq!(function.address, TEXT_VA + 0x22);
+    assert!(analysis::function_at(image, &sample.data, &[], 0x1000).is_none());
+}
+
+#[test]
+fn blocks_are_laid_out_in_layers_from_the_entry() {
+    let sample = sample();
+    let image = sample.image.as_deref().unwrap();
+    let layers = |va| {
+        let function = analysis::function_at(image, &sample.data, &[], va)
+            .unwrap();
+        let graph = Graph::new(&function, Zoom::Full, image,
+            &Annotations::default());
+        let nodes = graph.nodes.iter()
+            .map(|node| (node.address - TEXT_VA, node.layer))
+            .collect::<Vec<_>>();
+        (nodes, graph)
+    };
+
+    let (nodes, graph) = layers(TEXT_VA);
+    assert_eq!(nodes, vec![(0x0, 0), (0x4, 1), (0x10, 1), (0x15, 2)]);
+    assert_eq!(graph.edges.len(), 4);
+    // The taken branch goes right, next to the block the call returns to
+    assert!(graph.nodes[2].x > graph.nodes[1].x);
+    assert_eq!(graph.nodes[1].y, graph.nodes[2].y);
+
+    // The loop goes back up through a lane right of the blocks
+    let (nodes, graph) = layers(TEXT_VA + 0x20);
+    assert_eq!(nodes, vec![(0x20, 0), (0x22, 1), (0x28, 2)]);
+    let back = graph.edges.iter().find(|edge| edge.from == edge.to).unwrap();
+    let right = graph.nodes.iter().map(|node| node.x + node.width).max();
+    assert!(back.points.iter().any(|&(x, _)| Some(x) > right));
+}
+
+#[test]
+fn graph_follows_the_cursor_and_moves_it_to_the_blocks_selected() {
+    let layout = Layout { columns: vec![vec![PluginKind::Cfg]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample(), layout.build(".".as_ref()))]);
+    h.wait_for_xrefs();
+
+    h.command("goto va 0x401004");
+    h.assert_snapshot("cfg");
+    h.press(&[tab()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, offset(TEXT_VA + 0x10));
+    h.press(&[tab(), tab()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, offset(TEXT_VA));
+
+    h.press(&[key('-'), key('-')]);
+    h.assert_snapshot("cfg_overview");
+
+    // The loop is found from the call to it
+    h.command("goto va 0x401024");
+    h.press(&[key('+'), key('+')]);
+    h.assert_snapshot("cfg_loop");
+    h.command("goto 0");
+    h.assert_snapshot("cfg_not_code");
+}
+
+#[test]
+fn listing_moves_the_cursor_by_instruction_and_follows_branches() {
+    let layout = Layout { columns: vec![vec![PluginKind::Listing]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample(), layout.build(".".as_ref()))]);
+    h.wait_for_xrefs();
+
+    h.command("goto va 0x401000");
+    h.press(&[key('j'), key('j')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, offset(TEXT_VA + 4));
+    h.assert_snapshot("listing");
+    h.press(&[enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, offset(TEXT_VA + 0x20));
+    h.press(&[key('k')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, offset(TEXT_VA + 0x1f));
+    h.press(&[key('j'), key('j'), key('j'), key('k')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, offset(TEXT_VA + 0x22));
+}
+
+#[test]
+fn labels_and_comments_of_the_user_show_in_the_disassembly() {
+    let layout = Layout { columns: vec![vec![PluginKind::Listing]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample(), layout.build(".".as_ref()))]);
+    h.wait_for_xrefs();
+
+    h.command("goto va 0x401010");
+    h.command("label no_handle");
+    h.command("goto va 0x401000");
+    h.command("comment handle given?");
+    h.assert_snapshot("listing_annotated");
+
+    let layout = Layout { columns: vec![vec![PluginKind::Cfg]] };
+    h.app.tabs.apps[0].grid = layout.build(".".as_ref());
+    h.draw();
+    h.assert_snapshot("cfg_annotated");
+}
@@ -0,0 +1,137 @@
+//! Comparing two samples
+use std::{
+    fs,
+    path::{PathBuf},
+};
+
+use crate::app::{App, ColumnsState, PluginsState, Plugin, FileManager};
+use crate::diff::{self, Diff, Hunk};
+use crate::keys::{KeyConfig};
+use crate::layout::{PluginKind};
+use crate::sample::{Sample};
+
+use super::{Harness, key, enter, elf};
+
+/// Check that `hunks` cover both sides in order and that the equal ones are
+/// equal
+fn check(a: &[u8], b: &[u8], hunks: &[Hunk]) {
+    let (mut i, mut j) = (0, 0);
+    for hunk in hunks {
+        assert_eq!((hunk.a.start, hunk.b.start), (i, j));
+        if hunk.equal {
+            assert_eq!(a[hunk.a.clone()], b[hunk.b.clone()]);
+        }
+        i = hunk.a.end;
+        j = hunk.b.end;
+    }
+    assert_eq!((i, j), (a.len(), b.len()));
+}
+
+/// Bytes that do not repeat, like code or compressed data
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491u32;
+    (0..len).map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (state >> 16) as u8
+    }).collect()
+}
+
+#[test]
+fn diff_aligns_insertions_deletions_and_replacements() {
+    let (a, b) = (&b"hello world"[..], &b"hello, wide world"[..]);
+    let hunks = diff::diff(a, b);
+    check(a, b, &hunks);
+    assert_eq!(hunks[1].kind(), "insert");
+    assert_eq!(hunks[1].b, 5..11);
+
+    // Big enough to be aligned on common blocks first
+    let a = noise(200_000);
+    let mut b = a.clone();
+    b.splice(150_000..150_010, noise(4));
+    b.splice(50_000..50_000, vec![0xcc; 100]);
+    b.truncate(b.len() - 5);
+    let hunks = diff::diff(&a, &b);
+    check(&a, &b, &hunks);
+    let kinds: Vec<&str> = hunks.iter().filter(|hunk| !hunk.equal)
+        .map(|hunk| hunk.kind())
+        .collect();
+    assert_eq!(kinds, vec!["insert", "replace", "delete"]);
+    assert_eq!(hunks[1].b, 50_000..50_100);
+
+    assert!(diff::diff(&a, &a).iter().all(|hunk| hunk.equal));
+}
+
+#[test]
+fn diff_summarizes_the_changed_sections() {
+    let a = Sample::from_bytes(PathBuf::from("a"), elf(b"\x90\x90\xc3"));
+    let b = Sample::from_bytes(PathBuf::from("b"), elf(b"\x90\xcc\xcc\xc3"));
+    let diff = Diff::new(&a, b);
+    let changes: Vec<(&str, &str)> = diff.sections.as_ref().unwrap().iter()
+        .map(|change| (change.name.as_str(), change.change.as_str()))
+        .collect();
+    assert_eq!(changes, vec![
+        (".text", "changed, 2 bytes, size 0x3 -> 0x4"),
+        (".shstrtab", "unchanged")]);
+
+    let raw = Sample::from_bytes(PathBuf::from("c"), &b"abc"[..]);
+    assert!(Diff::new(&a, raw).sections.is_err());
+}
+
+#[test]
+fn picking_two_files_opens_a_diff_tab() {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-diff-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("a.bin"), b"MZ\x90\0header\0\0\0\0code").unwrap();
+    fs::write(dir.join("b.bin"), b"MZ\x90\0header\x01\0\0\0\0\0new code")
+        .unwrap();
+
+    let fm = FileManager::new(dir.clone());
+    let grid = ColumnsState::new(vec![
+        PluginsState::new(vec![Plugin::FileManager(fm)]),
+    ]);
+    let mut h = Harness::new(vec![App::new("Files", grid)]);
+    h.press(&[key('d')]);
+    assert!(h.app.status.as_deref().unwrap().starts_with("picked"));
+    h.press(&[key('j'), key('d')]);
+    assert_eq!(h.app.tabs.apps.len(), 2);
+    assert_eq!(h.app.tabs.apps[1].title, "a.bin vs b.bin");
+    assert_eq!(h.app.status.as_deref(),
+        Some("2 differences, 0 bytes on the left, 6 on the right"));
+
+    // Jump from one difference to the next
+    h.press(&[key(']')]);
+    assert_eq!(h.app.tabs.apps[1].ctx.cursor, 10);
+    h.press(&[key(']')]);
+    assert_eq!(h.app.tabs.apps[1].ctx.cursor, 14);
+    h.assert_snapshot("diff");
+
+    // Comparing the tab with itself finds nothing
+    h.command("diff-tab 2");
+    assert_eq!(h.app.status.as_deref(), Some("identical"));
+    h.command("diff-tab 9");
+    assert_eq!(h.app.status.as_deref(), Some("error: no sample in tab 9"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn empty_samples_and_insertions_at_the_end_keep_the_cursor_in_the_sample() {
+    let sample = |name: &str, data: &[u8]| Sample::from_bytes(
+        PathBuf::from(name), data.to_vec());
+    let mut h = Harness::with_layouts(&[&[&[PluginKind::HexView]]]);
+    h.app.open_diff(sample("a.bin", b""), sample("b.bin", b""));
+    h.draw();
+    h.press(&[key(']'), key('G')]);
+    assert_eq!(h.app.tabs.apps[1].ctx.cursor, 0);
+
+    // The inserted bytes start right after the last one on the left
+    h.app.open_diff(sample("a.bin", b"ab"), sample("b.bin", b"abcd"));
+    h.draw();
+    h.press(&[key(']')]);
+    assert_eq!(h.app.tabs.apps[2].ctx.cursor, 1);
+    h.app.tabs.apps[2].ctx.cursor = 0;
+    h.press(&[KeyConfig::init().focus_down, enter()]);
+    assert_eq!(h.app.tabs.apps[2].ctx.cursor, 1);
+}
@@ -0,0 +1,195 @@
+//! Disassembling the MIPS, PowerPC and RISC-V code of embedded devices
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::analysis::{self};
+use crate::disasm::{Arch, Disassembler, Flow};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+
+use super::{Harness, elf_with, TEXT_VA};
+
+/// Big endian MIPS: a branch and a call, each followed by its delay slot,
+/// then the function called
+const MIPS: &[u32] = &[
+    0x27bd_ffe0,    // addiu $sp, $sp, -0x20
+    0x1080_0003,    // beqz $a0, 0x401014
+    0x0000_0000,    // nop
+    0x0c10_0408,    // jal 0x401020
+    0x27bd_0020,    // addiu $sp, $sp, 0x20
+    0x03e0_0008,    // jr $ra
+    0x0000_0000,    // nop
+    0x0000_0000,    // nop
+    0x03e0_0008,    // jr $ra
+    0x0000_0000,    // nop
+];
+
+/// Big endian 32-bit PowerPC calling a function unless r3 is zero
+const PPC: &[u32] = &[
+    0x9421_fff0,    // stwu r1, -0x10(r1)
+    0x7c08_02a6,    // mflr r0
+    0x2c03_0000,    // cmpwi r3, 0
+    0x4182_000c,    // beq 0x401018
+    0x4800_0011,    // bl 0x401020
+    0x6000_0000,    // nop
+    0x3821_0010,    // addi r1, r1, 0x10
+    0x4e80_0020,    // blr
+    0x4e80_0020,    // blr
+];
+
+/// RV64GC calling a function unless a0 is zero, mostly compressed
+const RISCV: &[u8] = &[
+    0x41, 0x11,                 // addi sp, sp, -16
+    0x06, 0xe4,                 // sd ra, 8(sp)
+    0x01, 0xc5,                 // beqz a0, 0x40100c
+    0xef, 0x00, 0xa0, 0x01,     // jal 0x401020
+    0x01, 0x00,                 // nop
+    0xa2, 0x60,                 // ld ra, 8(sp)
+    0x41, 0x01,                 // addi sp, sp, 16
+    0x82, 0x80,                 // ret
+    0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01, 0x00,
+    0x01, 0x00,
+    0x05, 0x45,                 // li a0, 1
+    0x82, 0x80,                 // ret
+];
+
+fn words(words: &[u32], big_endian: bool) -> Vec<u8> {
+    words.iter()
+        .flat_map(|&word| if big_endian {
+            word.to_be_bytes()
+        } else {
+            word.to_le_bytes()
+        })
+        .collect()
+}
+
+fn mips() -> Sample {
+    Sample::from_bytes(PathBuf::from("mirai.mips"),
+        elf_with(8, false, true, TEXT_VA, &words(MIPS, true)))
+}
+
+/// Address and name of the functions found in `sample`
+fn functions(sample: &Sample) -> Vec<(u64, String)> {
+    analysis::functions(sample.image.as_deref().unwrap(), &sample.data)
+        .unwrap()
+        .into_iter()
+        .map(|f| (f.address - TEXT_VA, f.name))
+        .collect()
+}
+
+#[test]
+fn architecture_and_byte_order_come_from_the_elf_header() {
+    let arch = |machine, is_64, big_endian| Sample::from_bytes(
+            PathBuf::from("a.out"),
+            elf_with(machine, is_64, big_endian, TEXT_VA, &[0; 4]))
+        .image.and_then(|image| image.arch);
+    assert_eq!(arch(8, false, true), Some(Arch::Mips));
+    assert_eq!(arch(8, false, false), Some(Arch::Mipsel));
+    assert_eq!(arch(8, true, true), Some(Arch::Mips64));
+    assert_eq!(arch(8, true, false), Some(Arch::Mips64el));
+    assert_eq!(arch(20, false, true), Some(Arch::Ppc));
+    assert_eq!(arch(21, true, true), Some(Arch::Ppc64));
+    assert_eq!(arch(21, true, false), Some(Arch::Ppc64le));
+    assert_eq!(arch(243, false, false), Some(Arch::RiscV32));
+    assert_eq!(arch(243, true, false), Some(Arch::RiscV64));
+}
+
+#[test]
+fn mips_delay_slots_stay_in_the_blocks_of_their_jumps() {
+    let sample = mips();
+    assert_eq!(functions(&sample), vec![(0x0, "entry".to_string()),
+        (0x20, "sub_401020".to_string())]);
+
+    let image = sample.image.as_deref().unwrap();
+    let function = analysis::function_at(image, &sample.data, &[], TEXT_VA)
+        .unwrap();
+    let blocks: Vec<(u64, usize, Vec<u64>)> = function.blocks.iter()
+        .map(|block| (block.start - TEXT_VA, block.insns.len(),
+            block.succs.iter().map(|succ| succ - TEXT_VA).collect()))
+        .collect();
+    assert_eq!(blocks, vec![(0x0, 3, vec![0x14, 0xc]), (0xc, 2, vec![0x14]),
+        (0x14, 2, vec![])]);
+    assert_eq!(function.blocks[0].exit().flow, Flow::Branch(TEXT_VA + 0x14));
+
+    // The same code little endian
+    let mut disasm = Disassembler::new(Arch::Mipsel);
+    let insn = disasm.decode(&words(&MIPS[3..4], false), TEXT_VA + 0xc)
+        .unwrap();
+    assert_eq!(insn.flow, Flow::Call(Some(TEXT_VA + 0x20)));
+}
+
+#[test]
+fn powerpc_branches_calls_and_returns_are_followed() {
+    let mut disasm = Disassembler::new(Arch::Ppc);
+    let code = words(PPC, true);
+    let flows: Vec<(String, Flow)> = (3..8)
+        .map(|i| disasm.decode(&code[i * 4..], TEXT_VA + i as u64 * 4).unwrap())
+        .map(|insn| (insn.text, insn.flow))
+        .collect();
+    assert_eq!(flows, vec![
+        ("beq 0x401018".to_string(), Flow::Branch(TEXT_VA + 0x18)),
+        ("bl 0x401020".to_string(), Flow::Call(Some(TEXT_VA + 0x20))),
+        ("nop".to_string(), Flow::Next),
+        ("addi r1, r1, 0x10".to_string(), Flow::Next),
+        ("blr".to_string(), Flow::Return),
+    ]);
+
+    // Little endian 64-bit, calling through the count register
+    let insn = Disassembler::new(Arch::Ppc64le)
+        .decode(&0x4e80_0421u32.to_le_bytes(), TEXT_VA).unwrap();
+    assert_eq!((insn.text.as_str(), insn.flow), ("bctrl", Flow::Call(None)));
+
+    let sample = Sample::from_bytes(PathBuf::from("mirai.ppc"),
+        elf_with(20, false, true, TEXT_VA, &code));
+    assert_eq!(functions(&sample), vec![(0x0, "entry".to_string()),
+        (0x20, "sub_401020".to_string())]);
+}
+
+#[test]
+fn riscv_compressed_and_full_instructions_are_decoded() {
+    let mut disasm = Disassembler::new(Arch::RiscV64);
+    let mut insns = Vec::new();
+    let mut at = 0;
+    while at < 0x12 {
+        let insn = disasm.decode(&RISCV[at..], TEXT_VA + at as u64).unwrap();
+        at += insn.len;
+        insns.push((insn.text, insn.flow));
+    }
+    let texts: Vec<&str> = insns.iter().map(|(text, _)| text.as_str())
+        .collect();
+    assert_eq!(texts, vec!["addi sp, sp, -16", "sd ra, 8(sp)",
+        "beqz a0, 0x40100c", "jal 0x401020", "nop", "ld ra, 8(sp)",
+        "addi sp, sp, 16", "ret"]);
+    assert_eq!(insns[2].1, Flow::Branch(TEXT_VA + 0xc));
+    assert_eq!(insns[3].1, Flow::Call(Some(TEXT_VA + 0x20)));
+    assert_eq!(insns[7].1, Flow::Return);
+
+    // Full instructions, on 32 bits
+    let mut disasm = Disassembler::new(Arch::RiscV32);
+    let mut text = |word: u32| disasm.decode(&word.to_le_bytes(), TEXT_VA)
+        .unwrap().text;
+    assert_eq!(text(0xfe01_0113), "addi sp, sp, -32");
+    assert_eq!(text(0x0001_2537), "lui a0, 0x12");
+    assert_eq!(text(0x00c5_a503), "lw a0, 12(a1)");
+    assert_eq!(text(0x02b5_0533), "mul a0, a0, a1");
+    assert_eq!(text(0x0000_0073), "ecall");
+    // ld is 64-bit only
+    assert_eq!(text(0x0085_b503), "(bad)");
+
+    let sample = Sample::from_bytes(PathBuf::from("mirai.riscv64"),
+        elf_with(243, true, false, TEXT_VA, RISCV));
+    assert_eq!(functions(&sample), vec![(0x0, "entry".to_string()),
+        (0x20, "sub_401020".to_string())]);
+}
+
+#[test]
+fn graph_of_mips_code_shows_the_delay_slots() {
+    let layout = Layout { columns: vec![vec![PluginKind::Cfg]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(mips(), layout.build(".".as_ref()))]);
+    h.wait_for_xrefs();
+    h.command("goto va 0x401000");
+    h.assert_snapshot("cfg_mips");
+}
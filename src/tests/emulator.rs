@@ -0,0 +1,276 @@
+//! Emulating code to watch it decrypt strings or unpack itself
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::keys::{KeyConfig};
+use crate::disasm::{Arch};
+use crate::emulator::{Machine, Memory, Stop};
+use crate::image::{Image, Import, RawCode};
+use crate::layout::{Layout};
+use crate::sample::{Format, Sample};
+
+use super::{Harness, key, pe, PE_TEXT_VA};
+
+/// Decrypts the name of a library XORed with 0x55, then loads it
+const DECRYPT: &[u8] = &[
+    0x48, 0x8d, 0x0d, 0xf9, 0x00, 0x00, 0x00,   // lea rcx, [0x140001100]
+    0x31, 0xc0,                                 // xor eax, eax
+    0x80, 0x34, 0x01, 0x55,                     // xor byte [rcx+rax], 0x55
+    0xff, 0xc0,                                 // inc eax
+    0x83, 0xf8, 0x0a,                           // cmp eax, 0xa
+    0x75, 0xf5,                                 // jne 0x140001009
+    0x48, 0x83, 0xec, 0x28,                     // sub rsp, 0x28
+    0xff, 0x15, 0xe2, 0x07, 0x00, 0x00,         // call [LoadLibraryA]
+    0x48, 0x83, 0xc4, 0x28,                     // add rsp, 0x28
+    0xc3,                                       // ret
+];
+
+/// x86 code loaded at `BASE`, in a buffer of 0x100 bytes
+const BASE: u64 = 0x1000;
+
+fn raw(arch: Arch, code: &[u8]) -> (Image, Vec<u8>) {
+    let mut data = code.to_vec();
+    data.resize(0x100, 0);
+    let image = Image::raw(&RawCode { arch, base: BASE, offset: 0,
+        size: data.len() });
+    (image, data)
+}
+
+fn run(code: &[u8]) -> Machine {
+    let (image, data) = raw(Arch::X86, code);
+    let mut machine = Machine::new(&image, &data, Format::Raw, BASE, None)
+        .unwrap();
+    machine.run(100);
+    machine
+}
+
+#[test]
+fn string_is_decrypted_and_the_library_loaded() {
+    let mut text = DECRYPT.to_vec();
+    text.resize(0x100, 0xcc);
+    text.extend(b"ws2_32.dll".iter().map(|byte| byte ^ 0x55));
+    let sample = Sample::from_bytes(PathBuf::from("a.exe"),
+        pe(&text, "KERNEL32.dll", &["LoadLibraryA"]));
+    let mut h = Harness::new(vec![App::with_sample(sample,
+        Layout::builtin("emulate").unwrap().build(".".as_ref()))]);
+    h.app.tabs.apps[0].ctx.cursor = 0x200;
+    h.command("emulate");
+    assert_eq!(h.app.status.as_deref(),
+        Some("emulating x64 code from 0x140001000 until it returns"));
+
+    // Stepping moves the cursor along
+    h.press(&[KeyConfig::init().focus_right, key('s'), key('s'), key('s')]);
+    let ctx = &h.app.tabs.apps[0].ctx;
+    let machine = ctx.emulation.as_ref().unwrap();
+    assert_eq!(machine.rip, PE_TEXT_VA + 0xd);
+    assert_eq!(machine.register("rcx"), Some(PE_TEXT_VA + 0x100));
+    assert_eq!(ctx.cursor, 0x20d);
+
+    h.press(&[key('r')]);
+    let machine = h.app.tabs.apps[0].ctx.emulation.as_ref().unwrap();
+    assert_eq!(machine.stop, Some(Stop::Returned));
+    assert_eq!(machine.calls, vec![
+        "KERNEL32.dll!LoadLibraryA(\"ws2_32.dll\") = 0x70000000".to_string()]);
+    let mut name = [0; 10];
+    machine.memory.read(PE_TEXT_VA + 0x100, &mut name).unwrap();
+    assert_eq!(&name, b"ws2_32.dll");
+    h.assert_snapshot("emulator_x64");
+
+    h.command("emulate-mem rsp");
+    let machine = h.app.tabs.apps[0].ctx.emulation.as_ref().unwrap();
+    assert_eq!(machine.view, machine.register("rsp"));
+    h.command("emulate-mem eip");
+    assert_eq!(h.app.status.as_deref(), Some("error: invalid address eip"));
+}
+
+#[test]
+fn calls_are_stepped_over_with_a_key() {
+    let text = [
+        0xe8, 0x01, 0x00, 0x00, 0x00,   // call 0x140001006
+        0xc3,                           // ret
+        0x90,                           // nop
+        0xc3,                           // ret
+    ];
+    let sample = Sample::from_bytes(PathBuf::from("a.exe"),
+        pe(&text, "KERNEL32.dll", &["ExitProcess"]));
+    let mut h = Harness::new(vec![App::with_sample(sample,
+        Layout::builtin("emulate").unwrap().build(".".as_ref()))]);
+    h.app.tabs.apps[0].ctx.cursor = 0x200;
+    h.command("emulate");
+    h.press(&[KeyConfig::init().focus_right, key('n')]);
+    let machine = h.app.tabs.apps[0].ctx.emulation.as_ref().unwrap();
+    assert_eq!((machine.rip, machine.steps), (PE_TEXT_VA + 5, 3));
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x205);
+}
+
+#[test]
+fn arithmetic_and_string_instructions_are_emulated() {
+    let machine = run(&[
+        0xb8, 0x64, 0x00, 0x00, 0x00,   // mov eax, 100
+        0x31, 0xd2,                     // xor edx, edx
+        0xb9, 0x07, 0x00, 0x00, 0x00,   // mov ecx, 7
+        0xf7, 0xf1,                     // div ecx
+        0x6a, 0xff,                     // push -1
+        0x5b,                           // pop ebx
+        0x83, 0xc3, 0x02,               // add ebx, 2
+        0x0f, 0x92, 0xc2,               // setb dl
+        0xd1, 0xe0,                     // shl eax, 1
+        0xbf, 0x80, 0x10, 0x00, 0x00,   // mov edi, 0x1080
+        0xb9, 0x04, 0x00, 0x00, 0x00,   // mov ecx, 4
+        0x50,                           // push eax
+        0xb0, 0x41,                     // mov al, 0x41
+        0xf3, 0xaa,                     // rep stosb
+        0x58,                           // pop eax
+        0xc3,                           // ret
+    ]);
+    assert_eq!(machine.stop, Some(Stop::Returned));
+    let regs: Vec<Option<u64>> = ["eax", "ebx", "ecx", "edx", "edi"].iter()
+        .map(|name| machine.register(name))
+        .collect();
+    assert_eq!(regs, vec![Some(28), Some(1), Some(0), Some(1), Some(0x1084)]);
+    let mut written = [0; 5];
+    machine.memory.read(0x1080, &mut written).unwrap();
+    assert_eq!(&written, b"AAAA\0");
+    assert_eq!(machine.written, Some((0x1083, 1)));
+}
+
+#[test]
+fn imports_are_stubbed_and_stepped_over() {
+    let (mut image, data) = raw(Arch::X86, &[
+        0x6a, 0x40,                         // push 0x40
+        0x68, 0x00, 0x30, 0x00, 0x00,       // push 0x3000
+        0x68, 0x00, 0x10, 0x00, 0x00,       // push 0x1000
+        0x6a, 0x00,                         // push 0
+        0xff, 0x15, 0xf0, 0x10, 0x00, 0x00, // call [0x10f0]
+        0xc3,                               // ret
+    ]);
+    image.imports.push(Import { library: "KERNEL32.dll".to_string(),
+        name: "VirtualAlloc".to_string(), ordinal: None, slot: Some(0x10f0) });
+    let mut machine = Machine::new(&image, &data, Format::PE, BASE, None)
+        .unwrap();
+    let esp = machine.register("esp").unwrap();
+    machine.run(4);
+    machine.step_over();
+    assert_eq!(machine.rip, BASE + 0x14);
+    let memory = machine.register("eax").unwrap();
+    assert_eq!(machine.calls, vec![format!("KERNEL32.dll!VirtualAlloc(0x0, \
+        0x1000, 0x3000, 0x40) = {:#x}", memory)]);
+    assert!(machine.memory.is_mapped(memory));
+    // The import removed its arguments from the stack
+    assert_eq!(machine.register("esp"), Some(esp));
+
+    machine.step();
+    assert_eq!(machine.stop, Some(Stop::Returned));
+}
+
+#[test]
+fn emulation_stops_where_it_cannot_go_on() {
+    let fault = |code: &[u8]| match run(code).stop {
+        Some(Stop::Fault(err)) => err,
+        stop => panic!("no fault: {:?}", stop),
+    };
+    // mov eax, [0x90000000]
+    assert_eq!(fault(&[0xa1, 0x00, 0x00, 0x00, 0x90]),
+        "read of unmapped memory at 0x90000000");
+    // movaps xmm0, xmm1
+    assert_eq!(fault(&[0x0f, 0x28, 0xc1]), "cannot emulate movaps");
+    // int 0x80
+    assert_eq!(fault(&[0xcd, 0x80]), "system calls are not emulated");
+    // The quotient of the most negative dividend by -1 does not fit
+    let (image, data) = raw(Arch::X64, &[
+        0x48, 0xba, 0, 0, 0, 0, 0, 0, 0, 0x80,  // mov rdx, 1 << 63
+        0x31, 0xc0,                             // xor eax, eax
+        0x48, 0x83, 0xc9, 0xff,                 // or rcx, -1
+        0x48, 0xf7, 0xf9,                       // idiv rcx
+    ]);
+    let mut machine = Machine::new(&image, &data, Format::Raw, BASE, None)
+        .unwrap();
+    machine.run(100);
+    assert_eq!(machine.stop, Some(Stop::Fault("division overflow".to_string())));
+
+    // The end of the selection
+    let (image, data) = raw(Arch::X86, &[0x90, 0x90, 0xc3]);
+    let mut machine = Machine::new(&image, &data, Format::Raw, BASE,
+        Some(BASE + 2)).unwrap();
+    machine.run(100);
+    assert_eq!((machine.stop, machine.steps), (Some(Stop::End), 2));
+
+    let (image, data) = raw(Arch::Arm, &[]);
+    assert_eq!(Machine::new(&image, &data, Format::Raw, BASE, None).err()
+        .as_deref(), Some("cannot emulate arm code, only x86 and x64"));
+}
+
+#[test]
+fn huge_sections_are_mapped_without_allocating_them() {
+    let (mut image, data) = raw(Arch::X86, &[
+        0xa1, 0x00, 0x00, 0x00, 0x80,       // mov eax, [0x80000000]
+        0xa3, 0x00, 0x00, 0x00, 0xf0,       // mov [0xf0000000], eax
+        0xc3,                               // ret
+    ]);
+    // Nearly all of the 4 GiB 32-bit code reaches
+    image.sections[0].vsize = 0xf000_0000;
+    let mut machine = Machine::new(&image, &data, Format::Raw, BASE, None)
+        .unwrap();
+    machine.memory.write(0x8000_0000, &[0x2a, 0, 0, 0]).unwrap();
+    machine.run(100);
+    assert_eq!(machine.stop, Some(Stop::Returned));
+    let mut value = [0; 8];
+    machine.memory.read(0xf000_0000 - 4, &mut value).unwrap();
+    assert_eq!(value, [0, 0, 0, 0, 0x2a, 0, 0, 0]);
+    assert!(!machine.memory.is_mapped(0xf000_1000));
+    assert_eq!(machine.memory.peek(0x4000_0000, 1), vec![Some(0)]);
+}
+
+#[test]
+fn stack_pointer_wraps_around_the_address_space() {
+    // Code in the last 0x100 bytes of the address space
+    const TOP: u64 = 0xffff_ffff_ffff_ff00;
+    let mut data = vec![
+        0x48, 0xc7, 0xc4, 0xf8, 0xff, 0xff, 0xff,   // mov rsp, -8
+        0x58,                                       // pop rax
+        0x48, 0xc7, 0xc4, 0xf0, 0xff, 0xff, 0xff,   // mov rsp, -16
+        0xc2, 0x08, 0x00,                           // ret 8
+    ];
+    data.resize(0x100, 0);
+    let image = Image::raw(&RawCode { arch: Arch::X64, base: TOP, offset: 0,
+        size: data.len() });
+    let mut machine = Machine::new(&image, &data, Format::Raw, TOP, None)
+        .unwrap();
+    machine.run(2);
+    assert_eq!((&machine.stop, machine.register("rsp")), (&None, Some(0)));
+    machine.run(2);
+    assert_eq!((machine.rip, machine.register("rsp")), (0, Some(0)));
+
+    // Stepping over a call that cannot push its return address
+    let (image, data) = raw(Arch::X64, &[
+        0x48, 0xc7, 0xc4, 0xff, 0xff, 0xff, 0xff,   // mov rsp, -1
+        0xe8, 0x00, 0x00, 0x00, 0x00,               // call next
+    ]);
+    let mut machine = Machine::new(&image, &data, Format::Raw, BASE, None)
+        .unwrap();
+    machine.step();
+    machine.step_over();
+    assert!(matches!(machine.stop, Some(Stop::Fault(_))), "{:?}",
+        machine.stop);
+}
+
+#[test]
+fn memory_of_the_host_used_is_bounded() {
+    let (mut image, data) = raw(Arch::X86, &[
+        0xbf, 0x00, 0x00, 0x10, 0x00,       // mov edi, 0x100000
+        0x89, 0x07,                         // mov [edi], eax
+        0x81, 0xc7, 0x00, 0x10, 0x00, 0x00, // add edi, 0x1000
+        0xeb, 0xf6,                         // jmp 0x1005
+    ]);
+    image.sections[0].vsize = 0xf000_0000;
+    let mut machine = Machine::new(&image, &data, Format::Raw, BASE, None)
+        .unwrap();
+    machine.run(1_000_000);
+    assert_eq!(machine.stop, Some(Stop::Fault("out of memory".to_string())));
+
+    // Mapping ends too, however little each region holds
+    let mut memory = Memory::default();
+    assert!((0..0x10_0000u64).any(|i| !memory.map(i << 16, 1)));
+}
@@ -0,0 +1,148 @@
+//! Disassembling executables and matching their functions
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::layout::{Layout, PluginKind};
+use crate::analysis::{self};
+use crate::disasm::{Arch, Disassembler, Flow};
+use crate::funcdiff::{FunctionDiff, Status};
+use crate::image::{Image};
+use crate::sample::{Sample};
+
+use super::{Harness, key, enter, elf, TEXT_VA};
+
+/// Three functions called from the entry point
+const OLD: &[u8] = &[
+    0xe8, 0x0b, 0, 0, 0,            // call f1
+    0xe8, 0x16, 0, 0, 0,            // call f2
+    0xc3,                           // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0xb8, 0x01, 0, 0, 0,            // f1: mov eax, 1
+    0xc3,                           // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0x85, 0xff,                     // f2: test edi, edi
+    0x74, 0x02,                     // je ret
+    0x31, 0xc0,                     // xor eax, eax
+    0xc3,                           // ret
+];
+
+/// The same with another constant in f1, a line more in f2 moved further
+/// and a new function
+const NEW: &[u8] = &[
+    0xe8, 0x0b, 0, 0, 0,            // call f1
+    0xe8, 0x26, 0, 0, 0,            // call f2
+    0xe8, 0x11, 0, 0, 0,            // call f3
+    0xc3,                           // ret
+    0xb8, 0x02, 0, 0, 0,            // f1: mov eax, 2
+    0xc3,                           // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0x31, 0xc0,                     // f3: xor eax, eax
+    0xc3,                           // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0xcc,
+    0x85, 0xff,                     // f2: test edi, edi
+    0x74, 0x04,                     // je ret
+    0x31, 0xc0,                     // xor eax, eax
+    0xff, 0xc0,                     // inc eax
+    0xc3,                           // ret
+];
+
+fn functions(text: &[u8]) -> Vec<analysis::Function> {
+    let data = elf(text);
+    let image = Image::parse(&data).unwrap();
+    analysis::functions(&image, &data).unwrap()
+}
+
+#[test]
+fn functions_are_found_from_the_entry_point() {
+    let mut disasm = Disassembler::new(Arch::X64);
+    let insn = disasm.decode(&OLD[..5], TEXT_VA).unwrap();
+    assert_eq!(insn.text, "call 0x401010");
+    assert_eq!(insn.flow, Flow::Call(Some(TEXT_VA + 0x10)));
+    // Constants do not change the normalized instruction
+    let other = disasm.decode(&NEW[16..21], TEXT_VA).unwrap();
+    assert_eq!(disasm.decode(&OLD[16..21], TEXT_VA).unwrap().norm, other.norm);
+    assert_eq!(disasm.decode(&[0xcc], TEXT_VA).unwrap().flow, Flow::Stop);
+
+    let functions = functions(OLD);
+    let names: Vec<&str> = functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["entry", "sub_401010", "sub_401020"]);
+    assert_eq!(functions[0].calls, vec![TEXT_VA + 0x10, TEXT_VA + 0x20]);
+    assert_eq!(functions[2].offset, 64 + 0x20);
+
+    // test/je, xor then ret, the jump skipping the xor
+    let f2 = &functions[2];
+    let blocks: Vec<(u64, Vec<u64>)> = f2.blocks.iter()
+        .map(|block| (block.start - TEXT_VA, block.succs.iter()
+            .map(|succ| succ - TEXT_VA).collect()))
+        .collect();
+    assert_eq!(blocks, vec![(0x20, vec![0x26, 0x24]), (0x24, vec![0x26]),
+        (0x26, vec![])]);
+}
+
+#[test]
+fn functions_are_matched_by_name_hash_shape_and_blocks() {
+    let diff = FunctionDiff::new(functions(OLD), functions(NEW));
+    assert_eq!(diff.summary(), "1 identical, 2 changed, 1 added, 0 removed");
+
+    let pairs: Vec<(Status, Option<u64>, Option<u64>)> = diff.pairs.iter()
+        .map(|pair| (pair.status,
+            pair.a.map(|i| diff.a[i].address - TEXT_VA),
+            pair.b.map(|j| diff.b[j].address - TEXT_VA)))
+        .collect();
+    assert_eq!(pairs, vec![
+        (Status::Changed, Some(0), Some(0)),
+        (Status::Changed, Some(0x20), Some(0x30)),
+        (Status::Added, None, Some(0x20)),
+        (Status::Identical, Some(0x10), Some(0x10)),
+    ]);
+
+    // The line added in f2 is the only one without a match
+    let rows = diff.align(&diff.pairs[1]);
+    assert_eq!(rows, vec![(Some(0), Some(0)), (Some(1), Some(1)),
+        (Some(2), Some(2)), (None, Some(3)), (Some(3), Some(4))]);
+}
+
+#[test]
+fn shapes_shared_by_several_functions_do_not_pair_them() {
+    let text = |f1: &[u8], f2: &[u8]| {
+        let mut text = vec![
+            0xe8, 0x0b, 0, 0, 0,        // call f1
+            0xe8, 0x16, 0, 0, 0,        // call f2
+            0xc3,                       // ret
+        ];
+        text.resize(0x10, 0xcc);
+        text.extend_from_slice(f1);
+        text.resize(0x20, 0xcc);
+        text.extend_from_slice(f2);
+        text
+    };
+    // Two instructions in a block for all of them
+    let old = text(&[0xb8, 1, 0, 0, 0, 0xc3],   // mov eax, 1; ret
+        &[0xb9, 1, 0, 0, 0, 0xc3]);             // mov ecx, 1; ret
+    let new = text(&[0x31, 0xc0, 0xc3],         // xor eax, eax; ret
+        &[0x31, 0xc9, 0xc3]);                   // xor ecx, ecx; ret
+    let diff = FunctionDiff::new(functions(&old), functions(&new));
+    assert_eq!(diff.summary(), "1 identical, 0 changed, 2 added, 2 removed");
+}
+
+#[test]
+fn changed_functions_are_shown_side_by_side() {
+    let old = Sample::from_bytes(PathBuf::from("old"), elf(OLD));
+    let new = Sample::from_bytes(PathBuf::from("new"), elf(NEW));
+    let layout = Layout { columns: vec![vec![PluginKind::HexView]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(old, layout.build(".".as_ref())),
+        App::with_sample(new, layout.build(".".as_ref())),
+    ]);
+    h.command("diff-tab 2").wait_for_diff();
+    assert_eq!(h.app.tabs.apps[2].title, "old vs new");
+    h.press(&[key('j')]);
+    assert_eq!(h.app.tabs.apps[2].ctx.cursor, 64 + 0x20);
+    h.assert_snapshot("function_diff");
+
+    h.press(&[enter()]);
+    h.assert_snapshot("function_diff_pair");
+}
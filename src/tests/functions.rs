@@ -0,0 +1,95 @@
+//! Finding the functions of executables and the calls between them
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::keys::{KeyConfig};
+use crate::analysis::{self};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+
+use super::{Harness, key, enter, pe, PE_TEXT_VA};
+
+/// The entry calls a function calling an import and the stub of another
+/// import. A function nothing calls follows.
+const TEXT: &[u8] = &[
+    0xe8, 0x0b, 0, 0, 0,                    // call 0x140001010
+    0xe8, 0x26, 0, 0, 0,                    // call 0x140001030 (stub)
+    0xc3,                                   // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0xff, 0x15, 0xea, 0x07, 0x00, 0x00,     // call [ExitProcess]
+    0xc3,                                   // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0x55,                                   // push rbp
+    0x48, 0x89, 0xe5,                       // mov rbp, rsp
+    0x31, 0xc0,                             // xor eax, eax
+    0x5d,                                   // pop rbp
+    0xc3,                                   // ret
+    0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc, 0xcc,
+    0xff, 0x25, 0xd2, 0x07, 0x00, 0x00,     // jmp [Sleep]
+];
+
+fn sample() -> Sample {
+    Sample::from_bytes(PathBuf::from("a.exe"),
+        pe(TEXT, "KERNEL32.dll", &["ExitProcess", "Sleep"]))
+}
+
+#[test]
+fn functions_are_found_from_calls_and_prologues_and_named_after_imports() {
+    let sample = sample();
+    let image = sample.image.as_deref().unwrap();
+    let functions: Vec<(u64, String)> = analysis::functions(image,
+            &sample.data).unwrap()
+        .into_iter()
+        .map(|f| (f.address - PE_TEXT_VA, f.name))
+        .collect();
+    assert_eq!(functions, vec![
+        (0x0, "entry".to_string()),
+        (0x10, "sub_140001010".to_string()),
+        (0x20, "sub_140001020".to_string()),
+        (0x30, "j_Sleep".to_string()),
+    ]);
+}
+
+#[test]
+fn call_graph_shows_the_callers_and_callees_of_the_function_listed() {
+    let layout = Layout { columns: vec![
+        vec![PluginKind::Functions, PluginKind::CallGraph]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample(), layout.build(".".as_ref()))]);
+    h.wait_for_functions();
+    h.assert_snapshot("functions");
+
+    // Going to a function shows its calls
+    h.press(&[key('j'), enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x210);
+    h.press(&[KeyConfig::init().focus_down]);
+    h.assert_snapshot("call_graph");
+
+    // Walk up to the caller
+    h.press(&[enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x200);
+    h.press(&[key('j'), enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x230);
+}
+
+#[test]
+fn functions_are_found_again_in_the_edited_code() {
+    let layout = Layout { columns: vec![vec![PluginKind::Functions]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample(), layout.build(".".as_ref()))]);
+    h.wait_for_functions();
+
+    // Returning before the call to the stub leaves it uncalled
+    let ctx = &mut h.app.tabs.apps[0].ctx;
+    let original = ctx.sample.as_ref().unwrap().data.clone();
+    ctx.patches.overwrite(&original, 0x205, 0xc3, false);
+    h.draw();
+    h.wait_for_functions();
+    let index = h.app.tabs.apps[0].ctx.functions.index().unwrap();
+    let functions: Vec<u64> = index.functions.iter()
+        .map(|f| f.address - PE_TEXT_VA)
+        .collect();
+    assert_eq!(functions, vec![0x0, 0x10, 0x20]);
+}
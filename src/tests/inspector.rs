@@ -0,0 +1,74 @@
+//! Reading the bytes under the cursor in the data inspector
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::inspector::{inspect, format_time};
+
+use super::{Harness, key};
+
+fn value(bytes: &[u8], name: &str) -> Option<String> {
+    inspect(bytes).into_iter()
+        .find(|(row, _)| *row == name)
+        .unwrap().1
+}
+
+#[test]
+fn inspector_reads_numbers_times_and_strings() {
+    let bytes = [0x01, 0x02, 0x03, 0x04];
+    assert_eq!(value(&bytes, "u16").as_deref(), Some("513 / 258"));
+    assert_eq!(value(&bytes, "ipv4").as_deref(), Some("1.2.3.4"));
+    assert_eq!(value(&bytes, "u64"), None);
+    assert_eq!(value(&1.5f32.to_be_bytes(), "f32").as_deref(),
+        Some("6.8965e-41 / 1.5"));
+    assert_eq!(value(&[0xff], "i8").as_deref(), Some("-1"));
+    assert_eq!(value(&[0xe5, 0x8e, 0x26], "varint").as_deref(),
+        Some("624485 (3 bytes)"));
+    assert_eq!(value(&[0x80], "varint"), None);
+
+    assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_time(951_782_400), "2000-02-29 00:00:00 UTC");
+    assert_eq!(value(&0x5f5e_1000u32.to_le_bytes(), "unix").as_deref(),
+        Some("2020-09-13 12:26:40 UTC"));
+    // 2000-01-01 in 100ns since 1601
+    assert_eq!(value(&125_911_584_000_000_000u64.to_le_bytes(), "filetime")
+        .as_deref(), Some("2000-01-01 00:00:00 UTC"));
+    // 2021-06-15 13:45:30
+    let dos = (((2021 - 1980) << 9 | 6 << 5 | 15) << 16)
+        | (13 << 11 | 45 << 5 | 15);
+    assert_eq!(value(&(dos as u32).to_le_bytes(), "dos").as_deref(),
+        Some("2021-06-15 13:45:30"));
+    assert_eq!(value(&[0; 4], "dos"), None);
+
+    let guid = [0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66,
+        0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+    assert_eq!(value(&guid, "guid").as_deref(),
+        Some("{00112233-4455-6677-8899-AABBCCDDEEFF}"));
+
+    assert_eq!(value(b"ab\ncd\0ef", "ascii").as_deref(), Some("\"ab\\ncd\""));
+    assert_eq!(value("h\u{e9}".as_bytes(), "utf8").as_deref(),
+        Some("\"h\u{e9}\""));
+    assert_eq!(value(b"h\0i\0\0\0", "utf16le").as_deref(), Some("\"hi\""));
+    assert_eq!(value(b"\0h\0i", "utf16be").as_deref(), Some("\"hi\""));
+}
+
+#[test]
+fn inspector_follows_the_hex_view_cursor() {
+    let sample = Sample::from_bytes(PathBuf::from("sample.bin"),
+        &b"\x7f\0\0\x01MZ\x90\0"[..]);
+    let layout = Layout { columns: vec![
+        vec![PluginKind::HexView], vec![PluginKind::Inspector]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample, layout.build(".".as_ref()))]);
+    h.press(&[key('l'), key('l'), key('l'), key('l')]);
+
+    // Scrolling the inspector does not move the cursor
+    let keys = KeyConfig::init();
+    h.press(&[keys.focus_right, key('j'), key('j')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 4);
+    h.assert_snapshot("inspector");
+}
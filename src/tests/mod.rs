@@ -0,0 +1,545 @@
+//! Headless tests: drive `MagLabApp` with keys like the main loop does and
+//! check what gets drawn on a `TestBackend`.
+//!
+//! Screens are compared against the text files in `src/tests/snapshots`.
+//! A missing snapshot fails the test. Run the tests with
+//! `MAGLAB_UPDATE_SNAPSHOTS=1` to write the missing snapshots, or to
+//! overwrite them after a change in rendering, and review the diff.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+mod search;
+mod yara;
+mod annotations;
+mod project;
+mod patch;
+mod template;
+mod inspector;
+mod diff;
+mod funcdiff;
+mod archive;
+mod safety;
+mod address;
+mod symbols;
+mod xrefs;
+mod cfg;
+mod functions;
+mod arm;
+mod embedded;
+mod shellcode;
+mod emulator;
+
+use tui::{
+    terminal::{Terminal},
+    backend::{TestBackend},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::app::{App, MagLabApp, ColumnsState, PluginsState, Plugin,
+    FileManager};
+use crate::tabs::{TabsState};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::config::{Config};
+use crate::xrefs::{XrefStatus};
+use crate::funcdiff::{MatchStatus};
+use crate::functions::{FunctionStatus};
+
+/// Size of the fake terminal
+const WIDTH: u16 = 60;
+const HEIGHT: u16 = 16;
+
+/// A `MagLabApp` rendered on a fake terminal
+pub struct Harness {
+    app: MagLabApp<'static>,
+    keys: KeyConfig,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    pub fn new(apps: Vec<App<'static>>) -> Harness {
+        Harness::with_config(apps, Config::default())
+    }
+
+    /// Set the configuration before the first draw, like `main` does
+    pub fn with_config(apps: Vec<App<'static>>, config: Config) -> Harness {
+        let backend = TestBackend::new(WIDTH, HEIGHT);
+        let mut app = MagLabApp::new("MagLab", TabsState::new(apps));
+        app.config = config;
+        let mut harness = Harness {
+            app,
+            keys: KeyConfig::init(),
+            terminal: Terminal::new(backend).unwrap(),
+        };
+        harness.draw();
+        harness
+    }
+
+    /// One tab per layout, titled after its position
+    fn with_layouts(layouts: &[&[&[PluginKind]]]) -> Harness {
+        let apps = layouts.iter().enumerate().map(|(i, columns)| {
+            let layout = Layout {
+                columns: columns.iter().map(|col| col.to_vec()).collect(),
+            };
+            App::new(&format!("Tab{}", i), layout.build(Path::new(".")))
+        }).collect();
+        Harness::new(apps)
+    }
+
+    pub fn draw(&mut self) {
+        let app = &mut self.app;
+        self.terminal.draw(|f| app.draw(f)).unwrap();
+    }
+
+    /// Handle each key and redraw after it, like the main loop
+    pub fn press(&mut self, keys: &[KeyEvent]) -> &mut Harness {
+        for &key in keys {
+            self.app.on_key_event(key, &self.keys);
+            if self.app.should_quit {
+                break;
+            }
+            self.draw();
+        }
+        self
+    }
+
+    /// Wait for the cross-references of the current tab to be indexed,
+    /// then redraw
+    pub fn wait_for_xrefs(&mut self) -> &mut Harness {
+        let start = Instant::now();
+        let xrefs = &mut self.app.tabs.apps[self.app.tabs.index].ctx.xrefs;
+        while matches!(xrefs.status, XrefStatus::Running) {
+            assert!(start.elapsed() < Duration::from_secs(5),
+                "indexing hangs");
+            xrefs.poll();
+        }
+        self.draw();
+        self
+    }
+
+    /// Wait for the functions of the current tab to be found, then redraw
+    pub fn wait_for_functions(&mut self) -> &mut Harness {
+        let start = Instant::now();
+        let functions = &mut self.app.tabs.apps[self.app.tabs.index].ctx
+            .functions;
+        while matches!(functions.status, FunctionStatus::Running) {
+            assert!(start.elapsed() < Duration::from_secs(5),
+                "analysis hangs");
+            functions.poll();
+        }
+        self.draw();
+        self
+    }
+
+    /// Wait for the functions of the diff in the current tab to be matched,
+    /// then redraw
+    pub fn wait_for_diff(&mut self) -> &mut Harness {
+        let start = Instant::now();
+        let diff = self.app.tabs.apps[self.app.tabs.index].ctx.diff.as_mut()
+            .expect("not a diff tab");
+        while matches!(diff.functions.status, MatchStatus::Running) {
+            assert!(start.elapsed() < Duration::from_secs(5),
+                "matching hangs");
+            diff.functions.poll();
+        }
+        self.draw();
+        self
+    }
+
+    /// Type `text` as individual key presses
+    pub fn type_text(&mut self, text: &str) -> &mut Harness {
+        let keys: Vec<KeyEvent> = text.chars().map(key).collect();
+        self.press(&keys)
+    }
+
+    /// Type `line` in the prompt and run it
+    pub fn command(&mut self, line: &str) -> &mut Harness {
+        let open = self.keys.command;
+        self.press(&[open]).type_text(line);
+        self.press(&[enter()])
+    }
+
+    /// Text of the last frame, without trailing spaces
+    fn screen(&self) -> String {
+        let buffer = self.terminal.backend().buffer();
+        let width = buffer.area.width as usize;
+        buffer.content.chunks(width)
+            .map(|line| {
+                let line: String = line.iter()
+                    .map(|cell| cell.symbol.as_str())
+                    .collect();
+                line.trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n") + "\n"
+    }
+
+    /// Compare the last frame with the snapshot called `name`, or write it
+    /// when `MAGLAB_UPDATE_SNAPSHOTS` is set
+    pub fn assert_snapshot(&self, name: &str) {
+        let path = snapshot_path(name);
+        let screen = self.screen();
+        let update = std::env::var_os("MAGLAB_UPDATE_SNAPSHOTS").is_some();
+
+        if update {
+            fs::write(&path, &screen).unwrap();
+            return;
+        }
+
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(err) => panic!("cannot read snapshot {}: {}, run with \
+                MAGLAB_UPDATE_SNAPSHOTS=1 to write it\n--- got\n{}",
+                path.display(), err, screen),
+        };
+        assert!(expected == screen,
+            "screen does not match snapshot {}\n--- expected\n{}--- got\n{}",
+            path.display(), expected, screen);
+    }
+
+    /// Number of plugins in each column of the current tab
+    fn shape(&self) -> Vec<usize> {
+        let tab = &self.app.tabs.apps[self.app.tabs.index];
+        tab.grid.columns.iter().map(|col| col.plugins.len()).collect()
+    }
+
+    /// Focused (column, plugin) in the current tab
+    fn focus(&self) -> (usize, usize) {
+        let grid = &self.app.tabs.apps[self.app.tabs.index].grid;
+        (grid.index, grid.columns[grid.index].index)
+    }
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src").join("tests").join("snapshots")
+        .join(format!("{}.txt", name))
+}
+
+pub fn key(c: char) -> KeyEvent {
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::empty())
+}
+
+pub fn enter() -> KeyEvent {
+    KeyEvent::new(KeyCode::Enter, KeyModifiers::empty())
+}
+
+/// Address `.text` is loaded at in the ELF files made by `elf`
+pub const TEXT_VA: u64 = 0x401000;
+
+/// A little endian x86-64 ELF entered at the start of its `.text`, which
+/// holds `text`
+pub fn elf(text: &[u8]) -> Vec<u8> {
+    elf_for(0x3e, TEXT_VA, text)
+}
+
+/// A little endian 64-bit ELF for the `e_machine` given, entered at
+/// `entry`, whose `.text` holds `text`
+pub fn elf_for(machine: u16, entry: u64, text: &[u8]) -> Vec<u8> {
+    elf_with(machine, true, false, entry, text)
+}
+
+/// An ELF for the `e_machine` given, of 64 or 32 bits and big or little
+/// endian, entered at `entry`. Its `.text` holds `text` right after the
+/// header, which is 64 bytes long, or 52 for 32 bits.
+pub fn elf_with(machine: u16, is_64: bool, big_endian: bool, entry: u64,
+        text: &[u8]) -> Vec<u8> {
+    let half = |data: &mut Vec<u8>, value: u16| data.extend_from_slice(
+        &if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    let word = |data: &mut Vec<u8>, value: u32| data.extend_from_slice(
+        &if big_endian { value.to_be_bytes() } else { value.to_le_bytes() });
+    // Addresses, offsets and the flags and sizes of the sections
+    let long = |data: &mut Vec<u8>, value: u64| if is_64 {
+        data.extend_from_slice(&if big_endian { value.to_be_bytes() }
+            else { value.to_le_bytes() });
+    } else {
+        word(data, value as u32);
+    };
+    let header = if is_64 { 64 } else { 52 };
+    let names = b"\0.text\0.shstrtab\0";
+    let shoff = (header + text.len() + names.len()).next_multiple_of(8);
+
+    let mut data = vec![0x7f, b'E', b'L', b'F', if is_64 { 2 } else { 1 },
+        if big_endian { 2 } else { 1 }, 1];
+    data.resize(16, 0);
+    half(&mut data, 2);
+    half(&mut data, machine);
+    word(&mut data, 1);
+    long(&mut data, entry);
+    long(&mut data, 0);
+    long(&mut data, shoff as u64);
+    word(&mut data, 0);
+    let shentsize = if is_64 { 64 } else { 40 };
+    for &value in [header as u16, 56, 0, shentsize, 3, 2].iter() {
+        half(&mut data, value);
+    }
+    data.extend_from_slice(text);
+    data.extend_from_slice(names);
+    data.resize(shoff, 0);
+
+    // Null section, .text (allocated and executable) then .shstrtab
+    let sections = [(0u32, 0u32, 0u64, 0u64, 0usize, 0usize),
+        (1, 1, 6, TEXT_VA, header, text.len()),
+        (7, 3, 0, 0, header + text.len(), names.len())];
+    for &(name, kind, flags, va, offset, size) in sections.iter() {
+        word(&mut data, name);
+        word(&mut data, kind);
+        long(&mut data, flags);
+        long(&mut data, va);
+        long(&mut data, offset as u64);
+        long(&mut data, size as u64);
+        data.resize(data.len() + if is_64 { 24 } else { 16 }, 0);
+    }
+    data
+}
+
+/// Load address of the PE files made by `pe`
+pub const PE_BASE: u64 = 0x1_4000_0000;
+/// Address `.text` is loaded at in the PE files made by `pe`
+pub const PE_TEXT_VA: u64 = PE_BASE + 0x1000;
+/// Address of the import address table of the PE files made by `pe`, which
+/// holds a slot of 8 bytes per import
+pub const PE_IAT_VA: u64 = PE_BASE + 0x1800;
+
+/// A 64-bit PE entered at the start of its only section `.text`, which
+/// holds `text` followed by the import tables. `imports` are taken from
+/// `library`, by ordinal for names like `#7`.
+pub fn pe(text: &[u8], library: &str, imports: &[&str]) -> Vec<u8> {
+    const SECTION_RVA: usize = 0x1000;
+    const IAT: usize = 0x800;
+    const DESCRIPTORS: usize = 0x900;
+    const ILT: usize = 0x940;
+    const NAMES: usize = 0xa00;
+    let mut section = vec![0u8; 0x1000];
+    section[..text.len()].copy_from_slice(text);
+
+    let mut names = NAMES;
+    let mut put = |section: &mut Vec<u8>, bytes: &[u8]| {
+        let at = names;
+        section[at..at + bytes.len()].copy_from_slice(bytes);
+        names += (bytes.len() + 1).next_multiple_of(2);
+        (SECTION_RVA + at) as u32
+    };
+    let dll = put(&mut section, library.as_bytes());
+    for (i, name) in imports.iter().enumerate() {
+        let entry = match name.strip_prefix('#') {
+            Some(ordinal) => (1 << 63) | ordinal.parse::<u64>().unwrap(),
+            None => {
+                let hint_name = [&[0, 0][..], name.as_bytes()].concat();
+                u64::from(put(&mut section, &hint_name))
+            },
+        };
+        for table in [IAT, ILT].iter() {
+            section[table + 8 * i..table + 8 * i + 8]
+                .copy_from_slice(&entry.to_le_bytes());
+        }
+    }
+    for (i, value) in [(ILT + SECTION_RVA) as u32, 0, 0, dll,
+            (IAT + SECTION_RVA) as u32].iter().enumerate() {
+        section[DESCRIPTORS + 4 * i..DESCRIPTORS + 4 * i + 4]
+            .copy_from_slice(&value.to_le_bytes());
+    }
+
+    let mut data = vec![0u8; 0x80];
+    data[..2].copy_from_slice(b"MZ");
+    data[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+    data.extend_from_slice(b"PE\0\0");
+    // COFF header: x86-64, one section, executable
+    for half in [0x8664u16, 1].iter() {
+        data.extend_from_slice(&half.to_le_bytes());
+    }
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(&0xf0u16.to_le_bytes());
+    data.extend_from_slice(&0x22u16.to_le_bytes());
+    // Optional header of a PE32+
+    data.extend_from_slice(&0x20bu16.to_le_bytes());
+    data.extend_from_slice(&[0; 14]);
+    for word in [0x1000u32, 0x1000].iter() {
+        data.extend_from_slice(&word.to_le_bytes());
+    }
+    data.extend_from_slice(&PE_BASE.to_le_bytes());
+    for word in [0x1000u32, 0x200].iter() {
+        data.extend_from_slice(&word.to_le_bytes());
+    }
+    data.extend_from_slice(&[0; 16]);
+    for word in [0x2000u32, 0x200, 0].iter() {
+        data.extend_from_slice(&word.to_le_bytes());
+    }
+    data.extend_from_slice(&3u16.to_le_bytes());
+    data.extend_from_slice(&[0; 2 + 32 + 4]);
+    data.extend_from_slice(&16u32.to_le_bytes());
+    // Data directories, only the imports
+    data.extend_from_slice(&[0; 8]);
+    data.extend_from_slice(&((SECTION_RVA + DESCRIPTORS) as u32).to_le_bytes());
+    data.extend_from_slice(&40u32.to_le_bytes());
+    data.extend_from_slice(&[0; 14 * 8]);
+    // Section header: code, executable, readable and writable
+    data.extend_from_slice(b".text\0\0\0");
+    for word in [0x1000u32, SECTION_RVA as u32, 0x1000, 0x200].iter() {
+        data.extend_from_slice(&word.to_le_bytes());
+    }
+    data.extend_from_slice(&[0; 12]);
+    data.extend_from_slice(&0xe000_0020u32.to_le_bytes());
+    data.resize(0x200, 0);
+    data.extend_from_slice(&section);
+    data
+}
+
+use PluginKind::{HexView, Parser};
+
+#[test]
+fn draws_grid_with_focus_on_first_plugin() {
+    let h = Harness::with_layouts(&[&[&[HexView, Parser], &[HexView]]]);
+    h.assert_snapshot("grid_initial");
+}
+
+#[test]
+fn focus_moves_and_wraps_around() {
+    let mut h = Harness::with_layouts(&[&[&[HexView, Parser], &[HexView]]]);
+    let keys = KeyConfig::init();
+
+    h.press(&[keys.focus_down]);
+    assert_eq!(h.focus(), (0, 1));
+    // Going down from the last plugin of a column wraps to the top
+    h.press(&[keys.focus_down]);
+    assert_eq!(h.focus(), (0, 0));
+    // Going left from the first column wraps to the last one
+    h.press(&[keys.focus_left]);
+    assert_eq!(h.focus(), (1, 0));
+    h.press(&[keys.focus_right]);
+    assert_eq!(h.focus(), (0, 0));
+    h.press(&[keys.focus_up]);
+    assert_eq!(h.focus(), (0, 1));
+    h.assert_snapshot("grid_focus_second_line");
+}
+
+#[test]
+fn new_plugin_goes_to_the_next_column() {
+    let mut h = Harness::with_layouts(&[&[&[Parser], &[HexView]]]);
+    let keys = KeyConfig::init();
+
+    // From the first column, the plugin is added on top of the second one
+    h.press(&[keys.new_plugin]);
+    assert_eq!(h.shape(), vec![1, 2]);
+    assert_eq!(h.focus(), (1, 0));
+    // From the last column, a new column is created
+    h.press(&[keys.new_plugin]);
+    assert_eq!(h.shape(), vec![1, 2, 1]);
+    assert_eq!(h.focus(), (2, 0));
+    h.assert_snapshot("grid_new_plugins");
+}
+
+#[test]
+fn removing_the_last_plugin_of_a_column_removes_the_column() {
+    let mut h = Harness::with_layouts(
+        &[&[&[HexView], &[Parser, HexView], &[Parser]]]);
+    let keys = KeyConfig::init();
+
+    // Last column: the focus goes to the previous one
+    h.press(&[keys.focus_left, keys.remove_plugin]);
+    assert_eq!(h.shape(), vec![1, 2]);
+    assert_eq!(h.focus(), (1, 0));
+
+    // First column: the focus stays on the first column
+    h.press(&[keys.focus_right, keys.remove_plugin]);
+    assert_eq!(h.shape(), vec![2]);
+    assert_eq!(h.focus(), (0, 0));
+    h.assert_snapshot("grid_after_removing_columns");
+}
+
+#[test]
+fn removing_the_last_plugin_of_a_line_focuses_the_previous_one() {
+    let mut h = Harness::with_layouts(&[&[&[HexView, Parser, HexView]]]);
+    let keys = KeyConfig::init();
+
+    h.press(&[keys.focus_up, keys.remove_plugin]);
+    assert_eq!(h.shape(), vec![2]);
+    assert_eq!(h.focus(), (0, 1));
+    // Removing in the middle keeps the index
+    h.press(&[keys.focus_up, keys.remove_plugin]);
+    assert_eq!(h.shape(), vec![1]);
+    assert_eq!(h.focus(), (0, 0));
+}
+
+#[test]
+fn removing_the_only_plugin_removes_the_tab() {
+    let mut h = Harness::with_layouts(&[
+        &[&[HexView]],
+        &[&[Parser]],
+        &[&[HexView]],
+    ]);
+    let keys = KeyConfig::init();
+
+    // Remove the last tab while it is focused
+    h.press(&[keys.tab_left, keys.remove_plugin]);
+    assert_eq!(h.app.tabs.apps.len(), 2);
+    assert_eq!(h.app.tabs.index, 1);
+    assert!(!h.app.should_quit);
+    h.assert_snapshot("tabs_after_removing_last");
+
+    // Remove the first tab, the focus stays on the first one
+    h.press(&[keys.tab_right, keys.remove_plugin]);
+    assert_eq!(h.app.tabs.apps.len(), 1);
+    assert_eq!(h.app.tabs.index, 0);
+    assert_eq!(h.app.tabs.apps[0].title, "Tab1");
+
+    // Removing the last plugin of the last tab quits
+    h.press(&[keys.remove_plugin]);
+    assert!(h.app.should_quit);
+}
+
+#[test]
+fn tabs_wrap_around() {
+    let mut h = Harness::with_layouts(&[&[&[HexView]], &[&[Parser]]]);
+    let keys = KeyConfig::init();
+
+    h.press(&[keys.tab_right]);
+    assert_eq!(h.app.tabs.index, 1);
+    h.press(&[keys.tab_right]);
+    assert_eq!(h.app.tabs.index, 0);
+    h.press(&[keys.tab_left]);
+    assert_eq!(h.app.tabs.index, 1);
+    h.assert_snapshot("tabs_second_selected");
+}
+
+#[test]
+fn quit_key_quits() {
+    let mut h = Harness::with_layouts(&[&[&[HexView]]]);
+    let keys = KeyConfig::init();
+
+    h.press(&[keys.quit]);
+    assert!(h.app.should_quit);
+}
+
+#[test]
+fn prompt_applies_a_layout() {
+    let mut h = Harness::with_layouts(&[&[&[HexView]]]);
+    let keys = KeyConfig::init();
+
+    h.press(&[keys.command]).type_text("layout hex");
+    h.assert_snapshot("prompt_typing");
+
+    // Keys go to the prompt while it is open
+    assert_eq!(h.shape(), vec![1]);
+    h.press(&[enter()]);
+    assert!(h.app.prompt.is_none());
+    assert_eq!(h.app.status.as_deref(), Some("layout hex applied"));
+
+    h.press(&[keys.command]).type_text("layout nope");
+    h.press(&[enter()]);
+    assert!(h.app.status.as_deref().unwrap().starts_with("error"));
+}
+
+#[test]
+fn file_manager_lists_its_directory() {
+    let fm = FileManager::new(Path::new("src/tests/fixtures/dir"));
+    let grid = ColumnsState::new(vec![
+        PluginsState::new(vec![Plugin::FileManager(fm)]),
+    ]);
+    let h = Harness::new(vec![App::new("Files", grid)]);
+    h.assert_snapshot("file_manager");
+}
@@ -0,0 +1,157 @@
+//! Editing samples in the hex view and exporting the edits
+use std::{
+    fs,
+    sync::{Arc},
+    path::{PathBuf},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::app::{App};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::patch::{self, Patches, EditKind, JsonPatch};
+
+use super::{Harness, key};
+
+fn press(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::empty())
+}
+
+#[test]
+fn edits_are_undone_and_track_changed_ranges() {
+    let original: Arc<[u8]> = Arc::from(&b"0123456789"[..]);
+    let mut patches = Patches::default();
+
+    patches.overwrite(&original, 2, b'a', false);
+    patches.overwrite(&original, 3, b'b', true);
+    patches.insert(&original, 0, b'X', false);
+    patches.insert(&original, 1, b'Y', true);
+    assert_eq!(patches.edits.len(), 2);
+    assert_eq!(&patches.data().unwrap()[..], b"XY01ab456789");
+    // The overwritten bytes moved with the insertion
+    assert_eq!(patches.changed(), &[(0, 2), (4, 6)]);
+    assert!(patches.is_changed(5));
+    assert!(!patches.is_changed(6));
+
+    assert_eq!(patches.undo(), Some(0));
+    assert_eq!(&patches.data().unwrap()[..], b"01ab456789");
+    assert_eq!(patches.undo(), Some(2));
+    assert!(patches.data().is_none());
+    assert_eq!(patches.undo(), None);
+    assert_eq!(patches.redo(), Some(2));
+    assert_eq!(&patches.data().unwrap()[..], b"01ab456789");
+
+    // A new edit forgets what was undone
+    patches.overwrite(&original, 9, b'z', false);
+    assert_eq!(patches.redo(), None);
+}
+
+#[test]
+fn ips_patches_write_the_changed_runs() {
+    let original = vec![0u8; 8];
+    let mut patched = original.clone();
+    patched[1] = 1;
+    patched[2] = 2;
+    patched[6] = 6;
+    patched.push(9);
+
+    let ips = patch::ips(&original, &patched).unwrap();
+    assert_eq!(ips, [
+        &b"PATCH"[..],
+        &[0, 0, 1, 0, 2, 1, 2],
+        &[0, 0, 6, 0, 1, 6],
+        &[0, 0, 8, 0, 1, 9],
+        &b"EOF"[..],
+    ].concat());
+
+    // A change at the offset spelling EOF starts one byte earlier
+    let original = vec![0u8; 0x45_4f48];
+    let mut patched = original.clone();
+    patched[0x45_4f46] = 1;
+    let ips = patch::ips(&original, &patched).unwrap();
+    assert_eq!(&ips[5..10], &[0x45, 0x4f, 0x45, 0, 2]);
+}
+
+#[test]
+fn hex_view_edits_and_exports_the_sample() {
+    let sample = Sample::from_bytes(PathBuf::from("sample.bin"),
+        &b"hello\n"[..]);
+    let layout = Layout { columns: vec![
+        vec![PluginKind::HexView], vec![PluginKind::Patches]] };
+    let app = App::with_sample(sample, layout.build(".".as_ref()));
+    let mut h = Harness::new(vec![app]);
+
+    // Overwrite in hex, then in ASCII, then insert
+    h.type_text("e4");
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b"Hello\n");
+    h.type_text("1");
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b"Aello\n");
+    h.press(&[press(KeyCode::Tab)]).type_text("EL");
+    h.press(&[press(KeyCode::Insert)]).type_text("--");
+    h.press(&[press(KeyCode::Esc)]);
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b"AEL--lo\n");
+    assert_eq!(h.app.tabs.apps[0].ctx.patches.edits.len(), 2);
+    h.assert_snapshot("patch_edits");
+
+    // The sample itself is untouched
+    let ctx = &h.app.tabs.apps[0].ctx;
+    assert_eq!(&ctx.sample.as_ref().unwrap().data[..], b"hello\n");
+
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-patch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let copy = dir.join("patched.bin");
+    h.command(&format!("patch-export {}", copy.display()));
+    assert_eq!(fs::read(&copy).unwrap(), b"AEL--lo\n");
+    let json = dir.join("patch.json");
+    h.command(&format!("patch-export {}", json.display()));
+    let patch: JsonPatch = serde_json::from_slice(&fs::read(&json).unwrap())
+        .unwrap();
+    assert_eq!(patch.edits[1].kind, EditKind::Insert);
+    assert_eq!(patch::apply(b"hello\n", &patch.edits), b"AEL--lo\n");
+    fs::remove_dir_all(&dir).unwrap();
+
+    // Undo the insertion, then everything
+    h.press(&[key('u')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b"AELlo\n");
+    h.command("undo");
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b"hello\n");
+    h.command("redo");
+    assert_eq!(h.app.status.as_deref(), Some("redid the edit at 0x0"));
+}
+
+#[test]
+fn edits_past_the_end_go_to_the_last_byte() {
+    let sample = Sample::from_bytes(PathBuf::from("sample.bin"), &b"ab"[..]);
+    let layout = Layout { columns: vec![vec![PluginKind::HexView]] };
+    let mut h = Harness::new(vec![App::with_sample(sample,
+        layout.build(".".as_ref()))]);
+    // Where the diff leaves the cursor on an insertion at the end
+    h.app.tabs.apps[0].ctx.cursor = 2;
+    h.type_text("e4");
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b"aB");
+
+    let sample = Sample::from_bytes(PathBuf::from("empty.bin"), &b""[..]);
+    let layout = Layout { columns: vec![vec![PluginKind::HexView]] };
+    let mut h = Harness::new(vec![App::with_sample(sample,
+        layout.build(".".as_ref()))]);
+    h.type_text("e4");
+    assert!(h.app.tabs.apps[0].ctx.data().is_empty());
+}
+
+#[test]
+fn characters_typed_in_edit_mode_are_not_commands() {
+    let sample = Sample::from_bytes(PathBuf::from("sample.bin"),
+        &b"........"[..]);
+    let layout = Layout { columns: vec![vec![PluginKind::HexView]] };
+    let mut h = Harness::new(vec![App::with_sample(sample,
+        layout.build(".".as_ref()))]);
+    h.press(&[key('e'), press(KeyCode::Tab)]).type_text(":/nN");
+    assert_eq!(h.app.tabs.apps[0].ctx.data(), b":/nN....");
+    assert!(h.app.prompt.is_none());
+
+    // Out of edit mode they are commands again
+    h.press(&[press(KeyCode::Esc), key(':')]);
+    assert_eq!(h.app.prompt.as_deref(), Some(""));
+}
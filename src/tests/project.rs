@@ -0,0 +1,128 @@
+//! Projects: adding samples, artifacts and notes, and reopening the case
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::app::{App, ColumnsState, PluginsState, Plugin, FileManager};
+use crate::cli::{Args};
+use crate::config::{Config};
+use crate::layout::{PluginKind};
+use crate::project::{Project};
+use crate::sample::{Sample};
+use crate::session::{Session};
+
+use super::{Harness, key};
+
+const SAMPLE: &str = "src/tests/fixtures/dir/sample.bin";
+
+/// Empty directory for the project of the test called `name`
+fn project_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-project-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+/// A harness with a file manager tab working in the project in `dir`
+fn harness(dir: &Path) -> Harness {
+    let fm = FileManager::new(Path::new("src/tests/fixtures/dir"));
+    let grid = ColumnsState::new(vec![
+        PluginsState::new(vec![Plugin::FileManager(fm)]),
+    ]);
+    let mut h = Harness::new(vec![App::new("Files", grid)]);
+    let project = Project::open(dir).unwrap();
+    h.app.config.notes_dir = Some(project.notes_dir());
+    h.app.project = Some(project);
+    h
+}
+
+#[test]
+fn project_is_handed_over_with_its_tabs_and_notes() {
+    let dir = project_dir("handover");
+    let mut h = harness(&dir);
+
+    h.command(&format!("open {}", SAMPLE));
+    h.command("bookmark greeting");
+    h.command("note   looks like a test file");
+    h.press(&[key('v'), key('l'), key('l'), key('l'), key('l')]);
+    h.command("extract hello.txt");
+    assert!(h.app.status.as_deref().unwrap()
+        .starts_with("extracted 5 bytes as hello.txt"));
+    h.command("project");
+    assert_eq!(h.app.status.as_deref(), Some(&*format!(
+        "project {}: 1 samples, 1 artifacts, 1 journal entries",
+        dir.file_name().unwrap().to_string_lossy())));
+
+    // Close the project like the main loop does on exit
+    let mut project = h.app.project.take().unwrap();
+    project.capture(&h.app);
+    project.save().unwrap();
+
+    let project = Project::open(&dir).unwrap();
+    let sha256 = &project.samples[0].sha256;
+    assert_eq!(project.samples[0].name, "sample.bin");
+    assert_eq!(fs::read(project.sample_path(sha256)).unwrap(), b"hello\n");
+    assert_eq!(fs::read(project.artifact_path(&project.artifacts[0].sha256))
+        .unwrap(), b"hello");
+    assert_eq!(project.journal[0].text, "looks like a test file");
+
+    // The tabs come back, the sample read from the project
+    let tabs = project.tabs(&Config::default());
+    assert_eq!(tabs.len(), 2);
+    assert_eq!(tabs[1].title, "sample.bin");
+    let sample = tabs[1].ctx.sample.as_ref().unwrap();
+    assert!(sample.path.starts_with(fs::canonicalize(&dir).unwrap()));
+
+    let config = Config { notes_dir: Some(project.notes_dir()),
+        ..Config::default() };
+    let h = Harness::with_config(tabs, config);
+    let notes = &h.app.tabs.apps[1].ctx.notes;
+    assert_eq!(notes.bookmark(0).unwrap().name, "greeting");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn session_reopens_on_the_tab_that_was_active() {
+    let dir = project_dir("session");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("session.json");
+    let mut h = harness(&dir);
+    h.command(&format!("open {}", SAMPLE));
+    assert_eq!(h.app.tabs.index, 1);
+    Session::capture(&h.app).save(&path).unwrap();
+
+    let args = Args { session: Some(path), ..Args::default() };
+    let tabs = crate::initial_tabs(&args, &Config::default(), None).unwrap();
+    assert_eq!((tabs.apps.len(), tabs.index), (2, 1));
+    assert_eq!(tabs.apps[1].title, "sample.bin");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn project_never_closed_opens_one_tab_per_sample() {
+    let dir = project_dir("samples");
+    let mut project = Project::open(&dir).unwrap();
+    let sample = Sample::from_bytes(PathBuf::from("a.bin"), &b"MZ"[..]);
+    assert!(project.add_sample(&sample).unwrap());
+    assert!(!project.add_sample(&sample).unwrap());
+    let sample = Sample::from_bytes(PathBuf::from("b.bin"), &b"\x7fELF"[..]);
+    project.add_sample(&sample).unwrap();
+
+    let project = Project::open(&dir).unwrap();
+    let titles: Vec<String> = project.tabs(&Config::default()).iter()
+        .map(|tab| tab.title.clone())
+        .collect();
+    assert_eq!(titles, vec!["a.bin", "b.bin"]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn project_commands_need_a_project() {
+    let mut h = Harness::with_layouts(&[&[&[PluginKind::HexView]]]);
+    h.command("note hello");
+    assert!(h.app.status.as_deref().unwrap().starts_with("error: no project"));
+}
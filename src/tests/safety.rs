@@ -0,0 +1,112 @@
+//! Keeping samples from being modified, run or clicked by accident
+use std::{
+    fs,
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::config::{Config};
+use crate::defang;
+use crate::layout::{Layout};
+use crate::sample::{Sample};
+
+use super::{Harness};
+
+const SAMPLE: &str = "src/tests/fixtures/dir/sample.bin";
+
+/// Empty directory for the test called `name`
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-safety-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A harness with one tab holding the sample at `path`
+fn harness(path: &str, config: Config) -> Harness {
+    let sample = Sample::open(path).unwrap();
+    let grid = Layout::builtin("hex").unwrap().build(&sample.dir());
+    Harness::with_config(vec![App::with_sample(sample, grid)], config)
+}
+
+#[test]
+fn names_urls_and_addresses_are_defanged() {
+    assert_eq!(defang::name("invoice.pdf.exe"), "invoice[.]pdf[.]exe");
+    assert_eq!(defang::name("photo\u{202e}gpj.scr"),
+        "photo<U+202E>gpj[.]scr");
+    assert_eq!(defang::name("a\nb"), "a<U+000A>b");
+
+    assert_eq!(defang::text("GET https://evil.example.com/a.php?x=1 now"),
+        "GET hxxps[://]evil[.]example[.]com/a.php?x=1 now");
+    assert_eq!(defang::text("HTTP://c2.net"), "hxxp[://]c2[.]net");
+    assert_eq!(defang::text("ftp://10.0.0.1/drop"),
+        "fxp[://]10[.]0[.]0[.]1/drop");
+    assert_eq!(defang::text("beacon to 192.168.1.20:443"),
+        "beacon to 192[.]168[.]1[.]20:443");
+    assert_eq!(defang::text("version 1.2.3 of kernel32.dll"),
+        "version 1.2.3 of kernel32.dll");
+}
+
+#[test]
+fn samples_are_read_only_and_quarantined() {
+    let dir = temp_dir("quarantine");
+    let config = Config { quarantine: Some(dir.clone()),
+        ..Config::default() };
+    let mut h = harness(SAMPLE, config);
+
+    h.command("patch-write");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: samples are read-only"));
+
+    h.command("quarantine");
+    let sample = h.app.tabs.apps[0].ctx.sample.clone().unwrap();
+    let copy = dir.join(&sample.sha256);
+    assert_eq!(h.app.status.as_deref(),
+        Some(&*format!("quarantined as {}", copy.display())));
+    assert_eq!(fs::read(&copy).unwrap(), &sample.data[..]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&copy).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o444);
+    }
+
+    // Samples opened later are quarantined right away
+    fs::write(dir.join("other.bin"), b"other").unwrap();
+    h.command(&format!("open {}", dir.join("other.bin").display()));
+    let other = h.app.tabs.apps[1].ctx.sample.clone().unwrap();
+    assert!(dir.join(&other.sha256).exists());
+
+    let mut h = harness(SAMPLE, Config::default());
+    h.command("quarantine");
+    assert!(h.app.status.as_deref().unwrap()
+        .starts_with("error: no quarantine directory"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn executables_are_only_opened_with_external_programs_when_forced() {
+    let dir = temp_dir("open-with");
+    let exe = dir.join("dropper.exe");
+    fs::write(&exe, b"MZ\x90\0dropper").unwrap();
+
+    let mut h = harness(exe.to_str().unwrap(), Config::default());
+    h.command("open-with true");
+    assert_eq!(h.app.status.as_deref(), Some("error: dropper[.]exe is a PE \
+        executable, run open-with! to open it anyway"));
+    h.command("open-with! true");
+    assert_eq!(h.app.status.as_deref(),
+        Some("opened dropper[.]exe with true"));
+
+    let mut h = harness(SAMPLE, Config::default());
+    h.command("open-with true --flag");
+    assert_eq!(h.app.status.as_deref(),
+        Some("opened sample[.]bin with true"));
+    h.command("open-with");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: usage: open-with[!] <program> [arg]..."));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,117 @@
+//! Search patterns, the search thread and how results are shown
+use std::{
+    sync::{Arc},
+    path::{PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::app::{App};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::search::{Pattern, Search, Match};
+
+use super::{Harness, enter};
+
+fn sample(data: &[u8]) -> Sample {
+    Sample::from_bytes(PathBuf::from("sample.bin"), data)
+}
+
+/// Run `query` over `data` and wait for the search thread to finish
+fn search(query: &str, data: &[u8]) -> Vec<Match> {
+    let mut search = Search::default();
+    search.start(query, Arc::from(data)).unwrap();
+    wait(&mut search);
+    search.matches
+}
+
+fn wait(search: &mut Search) {
+    let start = Instant::now();
+    while search.running {
+        assert!(start.elapsed() < Duration::from_secs(5), "search hangs");
+        search.poll();
+    }
+}
+
+fn offsets(matches: &[Match]) -> Vec<usize> {
+    matches.iter().map(|m| m.offset).collect()
+}
+
+#[test]
+fn parses_query_kinds() {
+    assert_eq!(Pattern::parse("hex 4D 5A ?? 0?").unwrap(),
+        Pattern::Hex(vec![(0x4d, 0xff), (0x5a, 0xff), (0, 0), (0, 0xf0)]));
+    assert_eq!(Pattern::parse("hex 4d5a").unwrap(),
+        Pattern::Hex(vec![(0x4d, 0xff), (0x5a, 0xff)]));
+    assert_eq!(Pattern::parse("utf16 ab").unwrap(),
+        Pattern::Utf16("ab".to_string()));
+    assert_eq!(Pattern::parse("re a.b").unwrap(),
+        Pattern::Regex("a.b".to_string()));
+    // Without a kind the whole query is text
+    assert_eq!(Pattern::parse("kernel32 dll").unwrap(),
+        Pattern::Text("kernel32 dll".to_string()));
+
+    assert!(Pattern::parse("hex 4D 5").is_err());
+    assert!(Pattern::parse("hex 4G").is_err());
+    assert!(Pattern::parse("hex").is_err());
+}
+
+#[test]
+fn finds_hex_with_wildcards() {
+    let data = b"MZ\x90\x00..MZ\x00\x00..MZ\x90\x01";
+    assert_eq!(offsets(&search("hex 4D 5A ?? 00", data)), vec![0, 6]);
+    // Nibble wildcard
+    assert_eq!(offsets(&search("hex 4D 5A 9? ??", data)), vec![0, 12]);
+}
+
+#[test]
+fn finds_text_utf16_and_regex() {
+    let data = b"a\x00b\x00c\x00 abc \x00ABC";
+    assert_eq!(offsets(&search("text abc", data)), vec![7]);
+    assert_eq!(search("utf16 abc", data), vec![Match { offset: 0, len: 6 }]);
+    // Regexes work on bytes, `.` matches anything including NUL
+    assert_eq!(offsets(&search(r"re (?i)a.c", data)), vec![7, 12]);
+    assert_eq!(offsets(&search(r"re \x00[A-Z]", data)), vec![11]);
+}
+
+#[test]
+fn next_and_previous_wrap_around() {
+    let data = b"xx..xx..xx";
+    let mut search = Search::default();
+    search.start("xx", Arc::from(&data[..])).unwrap();
+    wait(&mut search);
+
+    assert_eq!(search.next(0), Some(4));
+    assert_eq!(search.next(4), Some(8));
+    assert_eq!(search.next(8), Some(0));
+    assert_eq!(search.previous(0), Some(8));
+    assert_eq!(search.previous(5), Some(4));
+    assert_eq!(search.current, Some(1));
+}
+
+#[test]
+fn results_are_highlighted_and_navigated() {
+    let data: Vec<u8> = (0..64u8).map(|i| if i % 20 == 3 { b'M' } else { i })
+        .collect();
+    let layout = Layout { columns: vec![
+        vec![PluginKind::HexView], vec![PluginKind::SearchResults]] };
+    let app = App::with_sample(sample(&data), layout.build(".".as_ref()));
+    let mut h = Harness::new(vec![app]);
+    let keys = KeyConfig::init();
+
+    h.press(&[keys.search]).type_text("text M");
+    h.press(&[enter()]);
+    wait(&mut h.app.tabs.apps[0].ctx.search);
+
+    h.press(&[keys.next_match]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 3);
+    h.press(&[keys.next_match, keys.next_match]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 43);
+    h.press(&[keys.previous_match]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 23);
+    h.assert_snapshot("search_results");
+
+    // Selecting in the results list moves the cursor
+    h.press(&[keys.focus_right, super::key('j')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 43);
+}
@@ -0,0 +1,156 @@
+//! Disassembling headerless code: shellcode, boot sectors, blobs carved out
+//! of other files
+use std::{
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::analysis::{self};
+use crate::disasm::{Arch, Disassembler};
+use crate::image::{Address, RawCode};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::session::{Session};
+use crate::xrefs::{self, XrefKind};
+
+use super::{Harness, elf};
+
+const SAMPLE: &str = "src/tests/fixtures/dir/sample.bin";
+
+/// x86 shellcode pushing the address of its string and calling a function
+const SHELLCODE: &[u8] = &[
+    0x68, 0x0c, 0x10, 0x40, 0x00,   // push 0x40100c
+    0xe8, 0x01, 0x00, 0x00, 0x00,   // call 0x40100b
+    0xc3,                           // ret
+    0xc3,                           // ret
+];
+
+/// The shellcode and its string, hidden after the header of an image
+fn dropper() -> Sample {
+    let mut data = b"GIF89a".to_vec();
+    data.resize(0x10, 0);
+    data.extend_from_slice(SHELLCODE);
+    data.extend_from_slice(b"hello world\0");
+    Sample::from_bytes(PathBuf::from("dropper.gif"), data)
+}
+
+#[test]
+fn selection_is_disassembled_at_the_base_chosen() {
+    let layout = Layout { columns: vec![vec![PluginKind::Listing]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(dropper(), layout.build(".".as_ref()))]);
+    let ctx = &mut h.app.tabs.apps[0].ctx;
+    ctx.anchor = Some(0x10);
+    ctx.cursor = 0x27;
+    h.command("raw x86 0x401000");
+    assert_eq!(h.app.status.as_deref(), Some("disassembling 0x18 bytes of \
+        dropper[.]gif at offset 0x10 as x86 code loaded at 0x401000"));
+    let ctx = &h.app.tabs.apps[0].ctx;
+    assert_eq!((ctx.cursor, ctx.anchor), (0x10, None));
+
+    h.wait_for_xrefs();
+    let ctx = &h.app.tabs.apps[0].ctx;
+    let image = ctx.sample.as_ref().unwrap().image.as_deref().unwrap();
+    assert_eq!(image.resolve(Address::Va(0x40100c)), Some(0x1c));
+    assert_eq!(xrefs::describe_target(image, ctx.data(), 0x40100c).as_deref(),
+        Some("\"hello world\""));
+    let index = ctx.xrefs.index().unwrap();
+    let xrefs = |va: u64| index.to(va).iter()
+        .map(|xref| (xref.from, xref.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(xrefs(0x40100c), vec![(0x401000, XrefKind::Data)]);
+    assert_eq!(xrefs(0x40100b), vec![(0x401005, XrefKind::Call)]);
+    let functions: Vec<(u64, String)> = analysis::functions(image,
+            ctx.data()).unwrap()
+        .into_iter()
+        .map(|f| (f.address, f.name))
+        .collect();
+    assert_eq!(functions, vec![(0x401000, "entry".to_string()),
+        (0x40100b, "sub_40100b".to_string())]);
+    h.assert_snapshot("listing_raw");
+
+    h.command("arch");
+    assert_eq!(h.app.status.as_deref(), Some("0x18 bytes of dropper[.]gif at \
+        offset 0x10 are x86 code loaded at 0x401000"));
+    // Another architecture keeps the bytes and the base
+    h.command("arch x64");
+    assert_eq!(h.app.tabs.apps[0].ctx.raw, Some(RawCode { arch: Arch::X64,
+        base: 0x401000, offset: 0x10, size: 0x18 }));
+}
+
+#[test]
+fn raw_code_is_remembered_in_the_session() {
+    let layout = Layout { columns: vec![vec![PluginKind::HexView]] };
+    let mut h = Harness::new(vec![App::with_sample(
+        Sample::open(SAMPLE).unwrap(), layout.build(".".as_ref()))]);
+    h.command("raw 8086 0x7c00");
+
+    let text = serde_json::to_string(&Session::capture(&h.app)).unwrap();
+    assert!(text.contains(r#""raw":{"arch":"x86-16","base":31744"#));
+    let session: Session = serde_json::from_str(&text).unwrap();
+    let tabs = session.restore();
+    let ctx = &tabs[0].ctx;
+    assert_eq!(ctx.raw, Some(RawCode { arch: Arch::X86_16, base: 0x7c00,
+        offset: 0, size: 6 }));
+    let image = ctx.sample.as_ref().unwrap().image.as_deref().unwrap();
+    assert_eq!((image.arch, image.entry), (Some(Arch::X86_16), Some(0x7c00)));
+
+    // Crafted sessions cannot put the code outside the sample or past the
+    // end of the address space
+    for raw in [r#""base":31744,"offset":18446744073709551615,"size":6"#,
+            r#""base":18446744073709551615,"offset":0,"size":6"#].iter() {
+        let text = text.replace(r#""base":31744,"offset":0,"size":6"#, raw);
+        let session: Session = serde_json::from_str(&text).unwrap();
+        assert_eq!(session.restore()[0].ctx.raw, None);
+    }
+}
+
+#[test]
+fn raw_code_replaces_the_headers_until_cleared() {
+    // 16-bit code carried in the `.text` of an ELF, right after its header
+    let text = [
+        0xbe, 0x05, 0x7c,               // mov si, 0x7C05
+        0xcd, 0x10,                     // int 0x10
+        b'b', b'o', b'o', b't', 0,
+    ];
+    let insn = Disassembler::new(Arch::X86_16).decode(&text, 0x7c00).unwrap();
+    assert_eq!((insn.text.as_str(), insn.imm), ("mov si, 0x7C05",
+        Some(0x7c05)));
+
+    let layout = Layout { columns: vec![vec![PluginKind::HexView]] };
+    let mut h = Harness::new(vec![App::with_sample(
+        Sample::from_bytes(PathBuf::from("a.out"), elf(&text)),
+        layout.build(".".as_ref()))]);
+    let ctx = &mut h.app.tabs.apps[0].ctx;
+    ctx.anchor = Some(64);
+    ctx.cursor = 64 + text.len() - 1;
+    h.command("raw x86-16 0x7c00");
+    let image = |h: &Harness| h.app.tabs.apps[0].ctx.sample.as_ref().unwrap()
+        .image.clone().unwrap();
+    assert_eq!(image(&h).arch, Some(Arch::X86_16));
+    assert_eq!(image(&h).resolve(Address::Va(0x7c05)), Some(64 + 5));
+
+    h.command("raw-clear");
+    assert_eq!(h.app.status.as_deref(),
+        Some("a[.]out laid out from its ELF headers"));
+    assert_eq!(image(&h).arch, Some(Arch::X64));
+    assert!(image(&h).sections.iter().any(|section| section.name == ".text"));
+    h.command("raw-clear");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: a[.]out is not disassembled as raw code"));
+    h.command("raw");
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: usage: raw <arch> [base]"));
+
+    // The code has to fit below 2^64, and wraps around when it ends there
+    let size = h.app.tabs.apps[0].ctx.data().len() as u64;
+    h.command(&format!("raw x64 {:#x}", 0u64.wrapping_sub(size) + 1));
+    assert_eq!(h.app.status, Some(format!("error: {:#x} bytes loaded at {:#x} \
+        go past the end of the address space", size,
+        0u64.wrapping_sub(size) + 1)));
+    h.command(&format!("raw x64 {:#x}", 0u64.wrapping_sub(size)));
+    assert_eq!(image(&h).resolve(Address::Va(u64::MAX)),
+        Some(size as usize - 1));
+    let insn = Disassembler::new(Arch::X64).decode(&[0x90], u64::MAX).unwrap();
+    assert_eq!(insn.end(), 0);
+}
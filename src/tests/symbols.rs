@@ -0,0 +1,99 @@
+//! Listing the imports and exports of executables
+use std::{
+    fs,
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::config::{Config};
+use crate::image::{Import};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::symbols::{ApiCategories};
+
+use super::{Harness, key, pe, PE_TEXT_VA, PE_IAT_VA};
+
+/// Calls `VirtualAllocEx` through its slot, and `CreateFileW` through a stub
+/// jumping through its slot
+const TEXT: &[u8] = &[
+    // call qword ptr [rip+0x7fa] (VirtualAllocEx)
+    0xff, 0x15, 0xfa, 0x07, 0x00, 0x00,
+    // call 0x140001010 (stub)
+    0xe8, 0x05, 0x00, 0x00, 0x00,
+    // ret
+    0xc3, 0xcc, 0xcc, 0xcc, 0xcc,
+    // jmp qword ptr [rip+0x7f2] (CreateFileW)
+    0xff, 0x25, 0xf2, 0x07, 0x00, 0x00,
+];
+
+#[test]
+fn apis_are_categorized_from_an_editable_mapping() {
+    let mut categories = ApiCategories::load(None).unwrap();
+    assert_eq!(categories.category("VirtualAllocEx"), Some("injection"));
+    assert_eq!(categories.category("RegSetValueExW"), Some("persistence"));
+    assert_eq!(categories.category("internetopena"), None);
+    assert_eq!(categories.category("InternetOpenA"), Some("networking"));
+    assert_eq!(categories.category("_ptrace"), Some("anti-debug"));
+    assert_eq!(categories.category("CreateFileW"), None);
+
+    categories.add("test", "files = [\"CreateFile\"]\n\
+        evasion = [\"GetTickCount\"]").unwrap();
+    assert_eq!(categories.category("CreateFileW"), Some("files"));
+    assert_eq!(categories.category("GetTickCount"), Some("evasion"));
+    assert!(categories.add("test", "files = 1").unwrap_err()
+        .starts_with("test: "));
+}
+
+#[test]
+fn pe_imports_have_their_library_ordinal_and_slot() {
+    let sample = Sample::from_bytes(PathBuf::from("a.exe"),
+        pe(TEXT, "KERNEL32.dll", &["VirtualAllocEx", "CreateFileW", "#7"]));
+    let image = sample.image.as_deref().unwrap();
+    let import = |name: &str, ordinal, slot| Import {
+        library: "KERNEL32.dll".to_string(), name: name.to_string(), ordinal,
+        slot: Some(slot) };
+    assert_eq!(image.imports, vec![
+        import("VirtualAllocEx", None, PE_IAT_VA),
+        import("CreateFileW", None, PE_IAT_VA + 8),
+        import("#7", Some(7), PE_IAT_VA + 16),
+    ]);
+}
+
+#[test]
+fn symbols_show_categories_and_cross_references() {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-symbols-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mapping = dir.join("apis.toml");
+    fs::write(&mapping, "files = [\"CreateFile\"]").unwrap();
+
+    let sample = Sample::from_bytes(PathBuf::from("a.exe"),
+        pe(TEXT, "KERNEL32.dll", &["VirtualAllocEx", "CreateFileW", "#7"]));
+    let layout = Layout { columns: vec![
+        vec![PluginKind::Symbols, PluginKind::HexView]] };
+    let config = Config { api_categories: Some(mapping),
+        ..Config::default() };
+    let mut h = Harness::with_config(vec![
+        App::with_sample(sample, layout.build(".".as_ref()))], config);
+
+    h.wait_for_xrefs();
+    let index = h.app.tabs.apps[0].ctx.xrefs.index().unwrap();
+    let xrefs = |slot: u64| index.refs(slot).iter()
+        .map(|xref| xref.from)
+        .collect::<Vec<_>>();
+    assert_eq!(xrefs(PE_IAT_VA), vec![PE_TEXT_VA]);
+    assert_eq!(xrefs(PE_IAT_VA + 8), vec![PE_TEXT_VA + 6, PE_TEXT_VA + 0x10]);
+    assert!(xrefs(PE_IAT_VA + 16).is_empty());
+
+    // The cursor follows the references of the selected import
+    h.press(&[key('j'), key('x')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x206);
+    h.press(&[key('x')]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x210);
+    h.assert_snapshot("symbols");
+
+    h.press(&[key('c')]);
+    h.assert_snapshot("symbols_categorized");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
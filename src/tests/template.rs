@@ -0,0 +1,141 @@
+//! Decoding binary templates over samples
+use std::{
+    fs,
+    path::{PathBuf},
+};
+
+use crate::app::{App};
+use crate::config::{Config};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::template::{Templates, Field};
+
+use super::{Harness, key, enter};
+
+const CONFIG: &str = r#"
+# Configuration blob
+struct config {
+    char[4] magic;
+    u16le version;
+    u8 flags;
+    if (flags & 1) {
+        u16be port;
+    } else {
+        u8[2] reserved;
+    }
+    u8 count;
+    server[count] servers;
+}
+
+struct server {
+    u8 len;
+    char name[len];
+}
+"#;
+
+const DATA: &[u8] = b"CFG1\x02\x00\x01\x1f\x90\x02\x03abc\x02de";
+
+fn child<'a>(field: &'a Field, name: &str) -> &'a Field {
+    field.children.iter().find(|child| child.name == name).unwrap()
+}
+
+#[test]
+fn templates_decode_conditionals_arrays_and_structures() {
+    let mut templates = Templates::default();
+    templates.add("test", CONFIG).unwrap();
+
+    let overlay = templates.apply("config", DATA, 0).unwrap();
+    assert_eq!(overlay.error, None);
+    let root = &overlay.root;
+    assert_eq!(root.len, DATA.len());
+    assert_eq!(child(root, "magic").value.as_deref(), Some("\"CFG1\""));
+    assert_eq!(child(root, "version").value.as_deref(), Some("2 (0x2)"));
+    assert_eq!(child(root, "port").value.as_deref(), Some("8080 (0x1f90)"));
+    assert!(root.children.iter().all(|field| field.name != "reserved"));
+
+    let servers = child(root, "servers");
+    assert_eq!(servers.ty, "server[2]");
+    assert_eq!(servers.children.len(), 2);
+    assert_eq!(child(&servers.children[1], "name").value.as_deref(),
+        Some("\"de\""));
+    assert_eq!(servers.children[1].offset, 14);
+
+    // Every field with a value is coloured
+    assert_eq!(overlay.leaves.len(), 9);
+    assert_eq!(overlay.leaf_at(7), overlay.leaf_at(8));
+    assert_eq!(overlay.leaf_at(DATA.len()), None);
+}
+
+#[test]
+fn template_errors_say_where() {
+    let mut templates = Templates::default();
+    let err = templates.add("bad.tpl", "struct a {\n  u8 x;\n  u8 y\n}\n")
+        .unwrap_err();
+    assert!(err.starts_with("bad.tpl:4: "), "{}", err);
+
+    let err = templates.apply("a", DATA, 0).unwrap_err();
+    assert!(err.starts_with("unknown template a"), "{}", err);
+
+    // What was decoded before running out of data is kept
+    templates.add("test", CONFIG).unwrap();
+    let overlay = templates.apply("config", &DATA[..12], 0).unwrap();
+    assert!(overlay.error.is_some());
+    let servers = child(&overlay.root, "servers");
+    assert_eq!(servers.children.len(), 1);
+    assert_eq!(child(&servers.children[0], "len").value.as_deref(),
+        Some("3 (0x3)"));
+
+    // So is what was decoded before an expression fails
+    templates.add("overflow.tpl", "struct overflow {\n  u8 x;\n  \
+        u8 y[(-1 << 127) / -1];\n}\n").unwrap();
+    let overlay = templates.apply("overflow", DATA, 0).unwrap();
+    assert!(overlay.error.as_deref().unwrap().contains("division overflow"),
+        "{:?}", overlay.error);
+    assert_eq!(overlay.root.children.len(), 1);
+
+    // Builtin templates are always there
+    let templates = Templates::load(None).unwrap();
+    assert!(templates.names().contains(&"mz_header"));
+}
+
+#[test]
+fn template_command_overlays_the_hex_view() {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-templates-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("config.tpl"), CONFIG).unwrap();
+
+    let mut data = vec![0u8; 4];
+    data.extend_from_slice(DATA);
+    let sample = Sample::from_bytes(PathBuf::from("sample.bin"), data);
+    let layout = Layout { columns: vec![
+        vec![PluginKind::Template], vec![PluginKind::HexView]] };
+    let app = App::with_sample(sample, layout.build(".".as_ref()));
+    let config = Config { templates: Some(dir.clone()), ..Config::default() };
+    let mut h = Harness::with_config(vec![app], config);
+
+    h.command("template nope");
+    assert!(h.app.status.as_deref().unwrap()
+        .starts_with("error: unknown template nope"));
+    h.command("template config 0x4");
+    assert_eq!(h.app.status.as_deref(), Some("config decoded at 0x4, 17 bytes"));
+
+    // The tree follows the cursor of the hex view
+    let keys = KeyConfig::init();
+    h.press(&[keys.focus_right]);
+    for _ in 0..9 {
+        h.press(&[key('l')]);
+    }
+    h.assert_snapshot("template");
+
+    // Enter in the tree moves the cursor to the selected field
+    h.press(&[keys.focus_left, key('j'), key('j'), enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0xb);
+
+    h.command("template-clear");
+    assert!(h.app.tabs.apps[0].ctx.overlay.is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
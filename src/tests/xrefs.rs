@@ -0,0 +1,128 @@
+//! Cross-references between code, data and strings
+use std::{
+    path::{PathBuf},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::app::{App};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::xrefs::{XrefIndex, XrefKind};
+
+use super::{Harness, key, enter, pe, PE_TEXT_VA, PE_IAT_VA};
+
+/// Loads a string, calls a function calling an import and itself
+fn text() -> Vec<u8> {
+    let mut text = vec![
+        // lea rcx, [rip+0xf9] (the string)
+        0x48, 0x8d, 0x0d, 0xf9, 0x00, 0x00, 0x00,
+        // call 0x140001010
+        0xe8, 0x04, 0x00, 0x00, 0x00,
+        // ret
+        0xc3, 0xcc, 0xcc, 0xcc,
+        // call qword ptr [rip+0x7ea] (ExitProcess)
+        0xff, 0x15, 0xea, 0x07, 0x00, 0x00,
+        // call 0x140001010
+        0xe8, 0xf5, 0xff, 0xff, 0xff,
+        // ret
+        0xc3,
+    ];
+    text.resize(0x100, 0xcc);
+    text.extend_from_slice(b"hello, world\0");
+    text
+}
+
+fn sample() -> Sample {
+    Sample::from_bytes(PathBuf::from("a.exe"),
+        pe(&text(), "KERNEL32.dll", &["ExitProcess"]))
+}
+
+#[test]
+fn index_holds_calls_data_and_imports() {
+    let sample = sample();
+    let index = XrefIndex::build(sample.image.as_deref().unwrap(),
+        &sample.data);
+    let xrefs = |va: u64| index.to(va).iter()
+        .map(|xref| (xref.from - PE_TEXT_VA, xref.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(xrefs(PE_TEXT_VA + 0x10),
+        vec![(0x7, XrefKind::Call), (0x16, XrefKind::Call)]);
+    assert_eq!(xrefs(PE_TEXT_VA + 0x100), vec![(0x0, XrefKind::Data)]);
+    assert_eq!(xrefs(PE_IAT_VA), vec![(0x10, XrefKind::Call)]);
+    assert_eq!(index.to(PE_TEXT_VA + 0x10)[1].offset, 0x216);
+}
+
+#[test]
+fn popup_lists_the_references_to_the_cursor_and_jumps_to_them() {
+    let grid = Layout::builtin("hex").unwrap().build(".".as_ref());
+    let mut h = Harness::new(vec![App::with_sample(sample(), grid)]);
+    h.wait_for_xrefs();
+    let keys = KeyConfig::init();
+
+    h.command("goto 0x10");
+    h.press(&[keys.xrefs]);
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: the cursor is not in a section loaded in memory"));
+
+    h.command("goto va 0x140001100");
+    h.press(&[keys.xrefs]);
+    h.assert_snapshot("xrefs_string");
+    // The popup takes the keys until it is closed
+    h.press(&[enter()]);
+    assert!(h.app.xref_popup.is_none());
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x200);
+
+    h.command("goto va 0x140001010");
+    h.press(&[keys.xrefs, key('j')]);
+    h.assert_snapshot("xrefs_function");
+    h.press(&[KeyEvent::new(KeyCode::Esc, KeyModifiers::empty())]);
+    assert!(h.app.xref_popup.is_none());
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x210);
+
+    h.command("goto va 0x140001011");
+    h.press(&[keys.xrefs]);
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: no cross-references to 0x140001011"));
+}
+
+#[test]
+fn plugins_show_the_references_to_their_selection() {
+    let layout = Layout { columns: vec![
+        vec![PluginKind::Symbols, PluginKind::HexView]] };
+    let mut h = Harness::new(vec![
+        App::with_sample(sample(), layout.build(".".as_ref()))]);
+    h.wait_for_xrefs();
+
+    h.press(&[KeyConfig::init().xrefs]);
+    h.assert_snapshot("xrefs_import");
+    h.press(&[enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 0x210);
+
+    let sample = Sample::from_bytes(PathBuf::from("a.bin"), &b"data"[..]);
+    let grid = Layout::builtin("hex").unwrap().build(".".as_ref());
+    let mut h = Harness::new(vec![App::with_sample(sample, grid)]);
+    h.press(&[KeyConfig::init().xrefs]);
+    assert_eq!(h.app.status.as_deref(),
+        Some("error: no virtual addresses in this tab"));
+}
+
+#[test]
+fn references_are_indexed_again_in_the_edited_code() {
+    let grid = Layout::builtin("hex").unwrap().build(".".as_ref());
+    let mut h = Harness::new(vec![App::with_sample(sample(), grid)]);
+    h.wait_for_xrefs();
+
+    // Returning instead of the recursive call
+    let ctx = &mut h.app.tabs.apps[0].ctx;
+    let original = ctx.sample.as_ref().unwrap().data.clone();
+    ctx.patches.overwrite(&original, 0x216, 0xc3, false);
+    h.draw();
+    h.wait_for_xrefs();
+    let index = h.app.tabs.apps[0].ctx.xrefs.index().unwrap();
+    let callers: Vec<u64> = index.to(PE_TEXT_VA + 0x10).iter()
+        .map(|xref| xref.from - PE_TEXT_VA)
+        .collect();
+    assert_eq!(callers, vec![0x7]);
+}
@@ -0,0 +1,106 @@
+//! Scanning samples with YARA rules and showing the matches
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{KeyEvent, KeyCode, KeyModifiers};
+
+use crate::app::{App};
+use crate::keys::{KeyConfig};
+use crate::layout::{Layout, PluginKind};
+use crate::sample::{Sample};
+use crate::yara::{self, ScanStatus, StringHits};
+
+use super::{Harness, enter};
+
+const RULES: &str = "src/tests/fixtures/yara";
+
+fn data() -> Vec<u8> {
+    let mut data = b"MZ\x90\x00 this PROGRAM cannot be run ".to_vec();
+    data.extend("kernel32".encode_utf16().flat_map(|u| u.to_le_bytes()));
+    data
+}
+
+#[test]
+fn compiles_a_directory_and_reports_broken_files() {
+    let report = yara::compile_and_scan(Path::new(RULES), &data()).unwrap();
+
+    assert_eq!(report.files, 2);
+    assert_eq!(report.errors.len(), 1);
+    assert!(report.errors[0].starts_with("broken.yar: "));
+
+    let names: Vec<&str> = report.rules.iter()
+        .map(|rule| rule.name.as_str()).collect();
+    assert_eq!(names, vec!["mz_header", "wide_kernel32"]);
+
+    let mz = &report.rules[0];
+    assert_eq!(mz.namespace, "mz.yar");
+    assert_eq!(mz.tags, vec!["pe", "header"]);
+    assert_eq!(mz.meta, vec![
+        ("author".to_string(), "\"maglab\"".to_string()),
+        ("score".to_string(), "40".to_string()),
+    ]);
+    assert_eq!(mz.strings, vec![
+        StringHits { name: "$mz".to_string(), matches: vec![(0, 2)] },
+        StringHits { name: "$stub".to_string(), matches: vec![(5, 12)] },
+    ]);
+    assert_eq!(report.rules[1].namespace,
+        Path::new("nested").join("wide.yara").display().to_string());
+}
+
+#[test]
+fn missing_rules_directory_fails() {
+    assert!(yara::compile_and_scan(Path::new("no/such/dir"), b"").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_to_directories_are_not_followed() {
+    let dir = std::env::temp_dir()
+        .join(format!("maglab-yara-{}-loop", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(Path::new(RULES).join("mz.yar"), dir.join("mz.yar")).unwrap();
+    std::os::unix::fs::symlink(&dir, dir.join("loop")).unwrap();
+
+    let report = yara::compile_and_scan(&dir, &data()).unwrap();
+    assert_eq!(report.files, 1);
+    assert_eq!(report.rules[0].name, "mz_header");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plugin_jumps_to_matches() {
+    let data = data();
+    let sample = Sample::from_bytes(PathBuf::from("sample.exe"), &data[..]);
+    let layout = Layout { columns: vec![
+        vec![PluginKind::Yara], vec![PluginKind::HexView]] };
+    let app = App::with_sample(sample, layout.build(".".as_ref()));
+    let mut h = Harness::new(vec![app]);
+    h.app.config.yara_rules = Some(PathBuf::from(RULES));
+
+    // Showing the plugin starts the scan
+    h.draw();
+    let start = Instant::now();
+    while !matches!(h.app.tabs.apps[0].ctx.yara.status, ScanStatus::Done(_)) {
+        assert!(start.elapsed() < Duration::from_secs(10), "scan hangs");
+        h.draw();
+    }
+    h.draw();
+    h.assert_snapshot("yara_matches");
+
+    let down = KeyEvent::new(KeyCode::Char('j'), KeyModifiers::empty());
+    h.press(&[down, enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 5);
+    h.press(&[down, enter()]);
+    assert_eq!(h.app.tabs.apps[0].ctx.cursor, 32);
+
+    // Rescanning on demand
+    let keys = KeyConfig::init();
+    h.press(&[keys.command]).type_text("yara");
+    h.press(&[enter()]);
+    assert!(h.app.status.as_deref().unwrap().starts_with("scanning"));
+}
@@ -0,0 +1,359 @@
+//! Cross-references: which instructions call, jump to, read or take the
+//! address of each address of an executable.
+//!
+//! The index is built in a background thread when a sample is opened, by
+//! decoding the executable sections from start to end. Any plugin can then
+//! show the cross-references to the address under the cursor, or to the
+//! item it has selected, in a popup.
+use std::{
+    thread,
+    collections::{HashMap},
+    sync::{mpsc, Arc},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Block, Borders, BorderType, Clear, Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use crate::app::{TabContext};
+use crate::defang;
+use crate::disasm::{Disassembler, Insn, Flow};
+use crate::image::{Image};
+use crate::sample::{Sample};
+use crate::search::{printable};
+
+/// Characters at least for the bytes at an address to be shown as a string
+const MIN_STRING: usize = 4;
+/// Characters of a string shown at most in the title of the popup
+const MAX_STRING: usize = 32;
+
+/// How an instruction uses an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XrefKind {
+    /// Calls it, directly or through a pointer stored there
+    Call,
+    /// Jumps or branches to it
+    Jump,
+    /// Jumps through a pointer stored there, like the stubs of imports
+    Thunk,
+    /// Reads, writes or takes the address of data
+    Data,
+}
+
+impl XrefKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            XrefKind::Call => "call",
+            XrefKind::Jump => "jump",
+            XrefKind::Thunk => "thunk",
+            XrefKind::Data => "data",
+        }
+    }
+}
+
+/// An instruction using an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xref {
+    /// Address of the instruction
+    pub from: u64,
+    /// Offset of the instruction in the file
+    pub offset: usize,
+    pub kind: XrefKind,
+}
+
+/// Call `f` with the offset in the file of each instruction of the
+/// executable sections, decoded one after the other
+pub fn sweep(image: &Image, data: &[u8], mut f: impl FnMut(usize, Insn)) {
+    let mut disasm = match image.arch {
+        Some(arch) => Disassembler::new(arch),
+        None => return,
+    };
+    for section in image.sections.iter().filter(|section| section.exec) {
+        let code = &data[section.offset..section.offset + section.size];
+        let mut at = 0;
+        while let Some(insn) = disasm.decode(&code[at..],
+                section.va.wrapping_add(at as u64)) {
+            let offset = section.offset + at;
+            at += insn.len;
+            f(offset, insn);
+        }
+    }
+}
+
+/// Text of the instruction of `xref`
+pub fn describe(image: &Image, data: &[u8], xref: &Xref) -> String {
+    image.arch
+        .and_then(|arch| Disassembler::new(arch)
+            .decode(data.get(xref.offset..).unwrap_or(&[]), xref.from))
+        .map_or_else(|| "?".to_string(), |insn| insn.text)
+}
+
+/// Cross-references of an executable by the address they use
+#[derive(Debug, Default)]
+pub struct XrefIndex {
+    to: HashMap<u64, Vec<Xref>>,
+}
+
+impl XrefIndex {
+    /// Index the instructions of the executable described by `image` in
+    /// `data`. Immediates are only taken as addresses when they fall in a
+    /// section.
+    pub fn build(image: &Image, data: &[u8]) -> XrefIndex {
+        let mut index = XrefIndex::default();
+        sweep(image, data, |offset, insn| {
+            let mut add = |to: u64, kind| index.to.entry(to).or_default()
+                .push(Xref { from: insn.address, offset, kind });
+            match insn.flow {
+                Flow::Call(Some(target)) => add(target, XrefKind::Call),
+                Flow::Jump(Some(target)) | Flow::Branch(target) =>
+                    add(target, XrefKind::Jump),
+                _ => {},
+            }
+            if let Some(mem) = insn.mem {
+                add(mem, match insn.flow {
+                    Flow::Call(None) => XrefKind::Call,
+                    Flow::Jump(None) => XrefKind::Thunk,
+                    _ => XrefKind::Data,
+                });
+            }
+            if let Some(imm) = insn.imm
+                    .filter(|&imm| image.section_at(imm).is_some()) {
+                add(imm, XrefKind::Data);
+            }
+        });
+        index
+    }
+
+    /// Instructions using `va` directly
+    pub fn to(&self, va: u64) -> &[Xref] {
+        self.to.get(&va).map_or(&[], |xrefs| &xrefs[..])
+    }
+
+    /// Addresses called by some instruction, the starts of functions
+    pub fn calls(&self) -> impl Iterator<Item = u64> + '_ {
+        self.to.iter()
+            .filter(|(_, xrefs)| xrefs.iter()
+                .any(|xref| xref.kind == XrefKind::Call))
+            .map(|(&va, _)| va)
+    }
+
+    /// Instructions using `va`, followed through the thunks jumping
+    /// through it: the calls to the stub of an import are references to the
+    /// import
+    pub fn refs(&self, va: u64) -> Vec<Xref> {
+        let mut refs = self.to(va).to_vec();
+        for thunk in self.to(va).iter()
+                .filter(|xref| xref.kind == XrefKind::Thunk) {
+            refs.extend_from_slice(self.to(thunk.from));
+        }
+        refs.sort_by_key(|xref| xref.from);
+        refs.dedup();
+        refs
+    }
+}
+
+/// State of the cross-reference index of a tab
+#[derive(Debug)]
+pub enum XrefStatus {
+    /// Never indexed, or not an executable
+    Idle,
+    Running,
+    Done(XrefIndex),
+    Failed(String),
+}
+
+/// The cross-reference index of a tab's sample
+pub struct XrefScan {
+    pub status: XrefStatus,
+    /// SHA-256 of the sample indexed, to index it again once it changes
+    pub sha256: String,
+    /// Contents indexed, to index them again once they are edited
+    data: Option<Arc<[u8]>>,
+    rx: Option<mpsc::Receiver<XrefIndex>>,
+}
+
+impl Default for XrefScan {
+    fn default() -> Self {
+        XrefScan { status: XrefStatus::Idle, sha256: String::new(), data: None,
+            rx: None }
+    }
+}
+
+impl XrefScan {
+    /// True if the index is not the one of `data`, the contents of `sample`
+    /// with the edits applied
+    pub fn is_stale(&self, sample: &Sample, data: &Arc<[u8]>) -> bool {
+        sample.sha256 != self.sha256
+            || !self.data.as_ref().is_some_and(|old| Arc::ptr_eq(old, data))
+    }
+
+    /// Index `data`, the contents of `sample` with the edits applied, in
+    /// the background, dropping the previous index. Samples which are not
+    /// executables are left idle.
+    pub fn start(&mut self, sample: &Sample, data: Arc<[u8]>) {
+        *self = XrefScan { sha256: sample.sha256.clone(),
+            data: Some(data.clone()), ..XrefScan::default() };
+        let image = match &sample.image {
+            Some(image) => image.clone(),
+            None => return,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(XrefIndex::build(&image, &data));
+        });
+        self.status = XrefStatus::Running;
+        self.rx = Some(rx);
+    }
+
+    /// Collect the index if it is built
+    pub fn poll(&mut self) {
+        let index = match self.rx.as_ref().map(|rx| rx.try_recv()) {
+            Some(Ok(index)) => Ok(index),
+            Some(Err(mpsc::TryRecvError::Disconnected)) =>
+                Err("indexing thread stopped".to_string()),
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+        };
+
+        self.rx = None;
+        self.status = match index {
+            Ok(index) => XrefStatus::Done(index),
+            Err(err) => XrefStatus::Failed(err),
+        };
+    }
+
+    /// The index, once built
+    pub fn index(&self) -> Result<&XrefIndex, &str> {
+        match &self.status {
+            XrefStatus::Done(index) => Ok(index),
+            XrefStatus::Running =>
+                Err("cross-references are still being indexed"),
+            XrefStatus::Failed(err) => Err(err),
+            XrefStatus::Idle => Err("the sample has no code to index"),
+        }
+    }
+}
+
+/// What is at `va`: a symbol, an import or a string
+pub fn describe_target(image: &Image, data: &[u8], va: u64) -> Option<String> {
+    if let Some(name) = image.symbol(va) {
+        return Some(name.to_string());
+    }
+    if let Some(import) = image.imports.iter()
+            .find(|import| import.slot == Some(va)) {
+        return Some(if import.library.is_empty() {
+            import.name.clone()
+        } else {
+            format!("{}!{}", import.library, import.name)
+        });
+    }
+    let start = image.offset_of(va)?;
+    let len = data[start..].iter().take(MAX_STRING)
+        .take_while(|&&byte| byte == b' ' || byte.is_ascii_graphic())
+        .count();
+    if len < MIN_STRING {
+        return None;
+    }
+    Some(format!("\"{}\"", defang::text(&printable(&data[start..start + len]))))
+}
+
+/// Popup listing the cross-references to an address, over the plugins of
+/// the tab
+pub struct XrefPopup {
+    title: String,
+    /// Offset and text of each instruction
+    lines: Vec<(usize, String)>,
+    selected: usize,
+    /// First line shown
+    scroll: usize,
+}
+
+impl XrefPopup {
+    /// List the cross-references to `va` in the sample of `ctx`
+    pub fn new(ctx: &TabContext, va: u64) -> Result<XrefPopup, String> {
+        let sample = ctx.sample.as_ref().ok_or("no sample in this tab")?;
+        let image = sample.image.as_deref().ok_or_else(|| format!(
+            "{} has no virtual addresses", defang::name(&sample.name())))?;
+        let refs = ctx.xrefs.index()?.refs(va);
+        if refs.is_empty() {
+            return Err(format!("no cross-references to {:#x}", va));
+        }
+
+        let mut title = format!("Xrefs to {:#x}", va);
+        if let Some(target) = describe_target(image, &sample.data, va) {
+            title.push(' ');
+            title.push_str(&target);
+        }
+        let lines = refs.iter()
+            .map(|xref| (xref.offset, format!("{:08x} {:5} {}", xref.from,
+                xref.kind.name(), describe(image, &sample.data, xref))))
+            .collect();
+        Ok(XrefPopup { title, lines, selected: 0, scroll: 0 })
+    }
+
+    /// Draw the popup in the middle of `area`
+    pub fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect) {
+        let width = (area.width * 4 / 5).max(area.width.min(20));
+        let height = (self.lines.len() as u16 + 2)
+            .min(area.height * 4 / 5).max(area.height.min(3));
+        let popup = Rect::new(area.x + (area.width - width) / 2,
+            area.y + (area.height - height) / 2, width, height);
+
+        let rows = (height as usize).saturating_sub(2).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+        let lines: Vec<Spans> = self.lines.iter().enumerate()
+            .skip(self.scroll)
+            .take(rows)
+            .map(|(i, (_, text))| {
+                let style = if i == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                Spans::from(Span::styled(text.clone(), style))
+            })
+            .collect();
+
+        let block = Block::default()
+            .title(self.title.as_str())
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .border_style(Style::default().fg(Color::Yellow));
+        f.render_widget(Clear, popup);
+        f.render_widget(Paragraph::new(lines).block(block), popup);
+    }
+
+    /// `j`/`k` select the next/previous reference, `Enter` moves the cursor
+    /// of the tab to it and `Esc` closes the popup. Returns false once the
+    /// popup is closed.
+    pub fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.selected =
+                (self.selected + 1).min(self.lines.len() - 1),
+            KeyCode::Char('k') | KeyCode::Up =>
+                self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End =>
+                self.selected = self.lines.len() - 1,
+            KeyCode::Enter => {
+                ctx.cursor = self.lines[self.selected].0
+                    .min(ctx.data().len().saturating_sub(1));
+                return false;
+            },
+            KeyCode::Esc | KeyCode::Char('q') => return false,
+            _ => {},
+        }
+        true
+    }
+}
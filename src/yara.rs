@@ -0,0 +1,372 @@
+//! Scanning the tab's sample with YARA rules.
+//!
+//! Rules are read from every `.yar` and `.yara` file of a directory and
+//! compiled with boreal, a YARA engine written in Rust. Compiling and
+//! scanning happen in a background thread, the results are collected by the
+//! tab on every draw.
+use std::{
+    fs,
+    thread,
+    sync::{Arc, mpsc},
+    path::{Path, PathBuf},
+};
+
+use tui::{
+    terminal::{Frame},
+    backend::{Backend},
+    text::{Span, Spans},
+    style::{Style, Color, Modifier},
+    layout::{Rect},
+    widgets::{Paragraph},
+};
+
+use crossterm::event::{KeyEvent, KeyCode};
+
+use boreal::{Compiler, Scanner, MetadataValue};
+
+use crate::app::{RenderPlugin, TabContext};
+use crate::search::{printable};
+use crate::defang;
+
+/// A string of a rule and where it matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringHits {
+    /// Name of the string, with its `$`
+    pub name: String,
+    /// Offset and length of each match
+    pub matches: Vec<(usize, usize)>,
+}
+
+/// A rule that matched the sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleMatch {
+    /// Namespace of the rule, the path of its file in the rules directory
+    pub namespace: String,
+    pub name: String,
+    pub tags: Vec<String>,
+    /// Metadata as (name, value) pairs, in the order of the rule
+    pub meta: Vec<(String, String)>,
+    pub strings: Vec<StringHits>,
+}
+
+/// Outcome of compiling a rules directory and scanning a sample with it
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Number of rule files compiled
+    pub files: usize,
+    /// Files that failed to compile and compiler warnings
+    pub errors: Vec<String>,
+    pub rules: Vec<RuleMatch>,
+}
+
+/// Find the rule files under `dir`, sorted so that namespaces are stable
+fn rule_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = fs::read_dir(&dir)
+            .map_err(|err| format!("{}: {}", dir.display(), err))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Symbolic links to directories are not followed, they can loop
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                dirs.push(path);
+            } else if matches!(path.extension().and_then(|e| e.to_str()),
+                    Some("yar") | Some("yara")) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Compile every rule file under `dir`. Files that fail to compile are
+/// reported in the errors and left out, so that one broken rule does not
+/// prevent using the rest of the set.
+pub fn compile_dir(dir: &Path) -> Result<(Scanner, ScanReport), String> {
+    let mut report = ScanReport::default();
+    let mut compiler = Compiler::new();
+
+    for file in rule_files(dir)? {
+        let namespace = file.strip_prefix(dir).unwrap_or(&file)
+            .display().to_string();
+        // Check the file alone first, a failed file would otherwise leave
+        // part of its rules in the compiler
+        let mut check = Compiler::new();
+        if let Err(err) = check.add_rules_file(&file) {
+            report.errors.push(format!("{}: {}", namespace, err));
+            continue;
+        }
+        match compiler.add_rules_file_in_namespace(&file, &namespace) {
+            Ok(status) => {
+                report.files += 1;
+                report.errors.extend(status.warnings()
+                    .map(|warn| format!("{}: warning: {}", namespace, warn)));
+            },
+            Err(err) => report.errors.push(format!("{}: {}", namespace, err)),
+        }
+    }
+
+    Ok((compiler.finalize(), report))
+}
+
+/// Scan `data` with `scanner`, turning the results into owned matches
+pub fn scan(scanner: &Scanner, data: &[u8]) -> Result<Vec<RuleMatch>, String> {
+    let result = scanner.scan_mem(data).map_err(|(err, _)| err.to_string())?;
+
+    Ok(result.rules.iter().filter(|rule| rule.matched).map(|rule| RuleMatch {
+        namespace: rule.namespace.to_string(),
+        name: rule.name.to_string(),
+        tags: rule.tags.iter()
+            .map(|tag| scanner.get_string_symbol(*tag).to_string())
+            .collect(),
+        meta: rule.metadatas.iter().map(|meta| {
+            let value = match meta.value {
+                MetadataValue::Bytes(bytes) => format!("\"{}\"",
+                    String::from_utf8_lossy(scanner.get_bytes_symbol(bytes))),
+                MetadataValue::Integer(value) => value.to_string(),
+                MetadataValue::Boolean(value) => value.to_string(),
+            };
+            (scanner.get_string_symbol(meta.name).to_string(), value)
+        }).collect(),
+        strings: rule.matches.iter().map(|string| StringHits {
+            name: format!("${}", string.name),
+            matches: string.matches.iter()
+                .map(|m| (m.base + m.offset, m.length))
+                .collect(),
+        }).collect(),
+    }).collect())
+}
+
+/// Compile the rules in `dir` and scan `data` with them
+pub fn compile_and_scan(dir: &Path, data: &[u8]) -> Result<ScanReport, String> {
+    let (scanner, mut report) = compile_dir(dir)?;
+    report.rules = scan(&scanner, data)?;
+    Ok(report)
+}
+
+/// State of the YARA scan of a tab
+#[derive(Debug)]
+pub enum ScanStatus {
+    /// Never scanned
+    Idle,
+    Running,
+    Done(ScanReport),
+    Failed(String),
+}
+
+/// The YARA scan of a tab's sample
+pub struct YaraScan {
+    pub status: ScanStatus,
+    /// Directory the rules were read from
+    pub rules_dir: Option<PathBuf>,
+    rx: Option<mpsc::Receiver<Result<ScanReport, String>>>,
+}
+
+impl Default for YaraScan {
+    fn default() -> Self {
+        YaraScan { status: ScanStatus::Idle, rules_dir: None, rx: None }
+    }
+}
+
+impl YaraScan {
+    /// Compile the rules in `dir` and scan `data` in the background,
+    /// dropping the results of the previous scan
+    pub fn start(&mut self, dir: PathBuf, data: Arc<[u8]>) {
+        let (tx, rx) = mpsc::channel();
+        let thread_dir = dir.clone();
+        thread::spawn(move || {
+            let _ = tx.send(compile_and_scan(&thread_dir, &data));
+        });
+
+        *self = YaraScan {
+            status: ScanStatus::Running,
+            rules_dir: Some(dir),
+            rx: Some(rx),
+        };
+    }
+
+    /// Collect the results of the scan if it is done
+    pub fn poll(&mut self) {
+        let result = match self.rx.as_ref().map(|rx| rx.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(mpsc::TryRecvError::Disconnected)) =>
+                Err("scan thread stopped".to_string()),
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+        };
+
+        self.rx = None;
+        self.status = match result {
+            Ok(report) => ScanStatus::Done(report),
+            Err(err) => ScanStatus::Failed(err),
+        };
+    }
+
+    /// True if no scan was ever started
+    pub fn is_idle(&self) -> bool {
+        matches!(self.status, ScanStatus::Idle)
+    }
+}
+
+/// A line shown by the `Yara` plugin
+enum Line {
+    Text(Spans<'static>),
+    /// A match of a string, selecting it moves the cursor to `offset`
+    Hit { text: Spans<'static>, offset: usize },
+}
+
+/// Matches shown under each string, the others are summarized
+const MAX_HITS_SHOWN: usize = 32;
+
+/// Plugin showing the rules matching the tab's sample, their tags, metadata
+/// and where their strings matched
+#[derive(Default)]
+pub struct Yara {
+    /// Index of the selected match among the lines with a match
+    selected: usize,
+    /// First line shown
+    scroll: usize,
+    /// Number of matches in the last draw
+    hits: usize,
+}
+
+impl Yara {
+    fn lines(scan: &YaraScan, data: &[u8]) -> Vec<Line> {
+        let title = Style::default().fg(Color::Yellow);
+        let error = Style::default().fg(Color::Red);
+        let dim = Style::default().fg(Color::DarkGray);
+
+        let report = match &scan.status {
+            ScanStatus::Idle => return vec![Line::Text(Spans::from(
+                "no scan yet, set yara_rules in the configuration or run \
+                 `yara <dir>`"))],
+            ScanStatus::Running => return vec![Line::Text(Spans::from(
+                "scanning..."))],
+            ScanStatus::Failed(err) => return vec![Line::Text(Spans::from(
+                Span::styled(err.clone(), error)))],
+            ScanStatus::Done(report) => report,
+        };
+
+        let mut lines = vec![Line::Text(Spans::from(Span::styled(
+            format!("{} rules matched, {} files", report.rules.len(),
+                report.files), title)))];
+        for err in report.errors.iter() {
+            lines.push(Line::Text(Spans::from(Span::styled(err.clone(),
+                error))));
+        }
+
+        for rule in report.rules.iter() {
+            let mut spans = vec![Span::styled(rule.name.clone(),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))];
+            if !rule.tags.is_empty() {
+                spans.push(Span::raw(format!(" : {}", rule.tags.join(" "))));
+            }
+            spans.push(Span::styled(format!("  ({})", rule.namespace), dim));
+            lines.push(Line::Text(Spans::from(spans)));
+
+            for (name, value) in rule.meta.iter() {
+                lines.push(Line::Text(Spans::from(Span::styled(
+                    format!("  {} = {}", name, defang::text(value)), dim))));
+            }
+            for string in rule.strings.iter() {
+                for &(offset, len) in string.matches.iter()
+                        .take(MAX_HITS_SHOWN) {
+                    let end = (offset + len.min(16)).min(data.len());
+                    let bytes = data.get(offset..end).unwrap_or(&[]);
+                    lines.push(Line::Hit {
+                        text: Spans::from(format!("  {} @ {:#x} [{}] {}",
+                            string.name, offset, len,
+                            defang::text(&printable(bytes)))),
+                        offset,
+                    });
+                }
+                if string.matches.len() > MAX_HITS_SHOWN {
+                    lines.push(Line::Text(Spans::from(Span::styled(
+                        format!("  {} ... {} more", string.name,
+                            string.matches.len() - MAX_HITS_SHOWN), dim))));
+                }
+            }
+        }
+
+        lines
+    }
+}
+
+impl RenderPlugin for Yara {
+    fn get_name(&self) -> &str {
+        "Yara"
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<B>, area: Rect,
+            ctx: &TabContext) {
+        let lines = Yara::lines(&ctx.yara, ctx.data());
+        self.hits = lines.iter()
+            .filter(|line| matches!(line, Line::Hit { .. }))
+            .count();
+        self.selected = self.selected.min(self.hits.saturating_sub(1));
+
+        // Find the line of the selected match and keep it in view
+        let mut hit = 0;
+        let mut selected_line = None;
+        let mut text = Vec::with_capacity(lines.len());
+        for (i, line) in lines.into_iter().enumerate() {
+            match line {
+                Line::Text(spans) => text.push(spans),
+                Line::Hit { text: mut spans, .. } => {
+                    if hit == self.selected {
+                        selected_line = Some(i);
+                        for span in spans.0.iter_mut() {
+                            span.style = span.style
+                                .add_modifier(Modifier::REVERSED);
+                        }
+                    }
+                    hit += 1;
+                    text.push(spans);
+                },
+            }
+        }
+
+        let rows = (area.height as usize).max(1);
+        if let Some(line) = selected_line {
+            if line < self.scroll {
+                self.scroll = line;
+            } else if line >= self.scroll + rows {
+                self.scroll = line + 1 - rows;
+            }
+        }
+        self.scroll = self.scroll.min(text.len().saturating_sub(1));
+
+        let shown: Vec<Spans> = text.into_iter().skip(self.scroll).collect();
+        f.render_widget(Paragraph::new(shown), area);
+    }
+
+    /// `j`/`k` select the next/previous string match, `Enter` moves the
+    /// cursor of the tab to it
+    fn on_key(&mut self, key: KeyEvent, ctx: &mut TabContext) -> bool {
+        match key.code {
+            KeyCode::Char('j') => {
+                self.selected = (self.selected + 1)
+                    .min(self.hits.saturating_sub(1));
+            },
+            KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            },
+            KeyCode::Enter => {
+                let offset = Yara::lines(&ctx.yara, ctx.data()).iter()
+                    .filter_map(|line| match line {
+                        Line::Hit { offset, .. } => Some(*offset),
+                        Line::Text(_) => None,
+                    })
+                    .nth(self.selected);
+                if let Some(offset) = offset {
+                    ctx.cursor = offset;
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}